@@ -20,6 +20,8 @@ impl From<&tx::TxPrefix> for external::TxPrefix {
 
         tx_prefix.set_tombstone_block(source.tombstone_block);
 
+        tx_prefix.set_version(source.version());
+
         tx_prefix
     }
 }
@@ -41,12 +43,14 @@ impl TryFrom<&external::TxPrefix> for tx::TxPrefix {
             outputs.push(tx_out);
         }
 
-        let tx_prefix = tx::TxPrefix {
+        let mut tx_prefix = tx::TxPrefix {
             inputs,
             outputs,
             fee: source.get_fee(),
             tombstone_block: source.get_tombstone_block(),
+            version: None,
         };
+        tx_prefix.set_version(source.get_version());
         Ok(tx_prefix)
     }
 }
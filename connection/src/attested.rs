@@ -0,0 +1,85 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A handle which can only be obtained by attesting a connection.
+
+use crate::traits::AttestedConnection;
+use grpcio::Error as GrpcError;
+use std::{ops::Deref, result::Result as StdResult};
+
+/// A connection which has been attested.
+///
+/// An `Attested` can only be made by attesting the connection, so methods
+/// which need an attested session can be put on it, and code which takes one
+/// cannot run before attestation. If a call made through it fails and the
+/// connection deattests itself, the next call attests a new session first.
+///
+/// Unlike `ScopedAttestation`, the session is left open when this is dropped.
+pub struct Attested<'a, C: AttestedConnection> {
+    conn: &'a mut C,
+}
+
+impl<'a, C: AttestedConnection> Attested<'a, C> {
+    /// Attest the given connection, unless it already holds a session.
+    pub fn new(conn: &'a mut C) -> StdResult<Self, C::Error> {
+        if !conn.is_attested() {
+            let _verification_report = conn.attest()?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Make a call over the attested session, as
+    /// `AttestedConnection::attested_call` does.
+    pub fn call<T>(
+        &mut self,
+        func: impl FnOnce(&mut C) -> StdResult<T, GrpcError>,
+    ) -> StdResult<T, C::Error> {
+        self.conn.attested_call(func)
+    }
+
+    /// The underlying connection, which is attested again first if a failed
+    /// call tore down its session.
+    pub(crate) fn session_conn(&mut self) -> StdResult<&mut C, C::Error> {
+        if !self.conn.is_attested() {
+            let _verification_report = self.conn.attest()?;
+        }
+        Ok(self.conn)
+    }
+}
+
+impl<'a, C: AttestedConnection> Deref for Attested<'a, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.conn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{test_client_uri, MockAttestedConnection};
+
+    #[test]
+    // Obtaining the handle should attest only when there is no session, and
+    // a lost session should be replaced before the handle uses it again.
+    fn attested_reattests_only_when_needed() {
+        let mut conn = MockAttestedConnection::new(test_client_uri(1));
+
+        let attested = conn.attested().unwrap();
+        assert!(attested.is_attested());
+        assert_eq!(attested.attest_calls, 1);
+
+        let mut attested = conn.attested().unwrap();
+        assert_eq!(attested.attest_calls, 1);
+
+        attested
+            .call(|conn| {
+                conn.deattest();
+                Ok(())
+            })
+            .unwrap();
+        assert!(!attested.is_attested());
+        assert!(attested.session_conn().unwrap().is_attested());
+        assert_eq!(attested.attest_calls, 2);
+    }
+}
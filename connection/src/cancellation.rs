@@ -0,0 +1,134 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A flag which cancels the retries of connection calls, so that a service
+//! shutting down need not wait out its backoff schedules.
+
+use displaydoc::Display;
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The call was cancelled before it succeeded
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub struct Cancelled;
+
+/// A one-way flag which, once set, stops retryable calls before their next
+/// attempt.
+///
+/// A call waiting between attempts is woken as soon as the token is
+/// cancelled. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl CancellationToken {
+    /// Create a token which has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every call using this token. This cannot be undone.
+    pub fn cancel(&self) {
+        let (lock, condvar) = &*self.cancelled;
+        *lock.lock().expect("CancellationToken lock poisoned") = true;
+        condvar.notify_all();
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        let (lock, _condvar) = &*self.cancelled;
+        *lock.lock().expect("CancellationToken lock poisoned")
+    }
+
+    /// Sleep for `duration`, or until the token is cancelled if that is
+    /// sooner. Returns true if the token was cancelled.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let (lock, condvar) = &*self.cancelled;
+        let mut cancelled = lock.lock().expect("CancellationToken lock poisoned");
+        while !*cancelled {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            cancelled = condvar
+                .wait_timeout(cancelled, deadline - now)
+                .expect("CancellationToken lock poisoned")
+                .0;
+        }
+        *cancelled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::{Error, RetryError},
+        sync::_retry_with_stats,
+    };
+    use retry::OperationResult;
+    use std::thread;
+
+    #[test]
+    // Cancelling should wake a sleeping clone at once.
+    fn cancel_wakes_sleepers() {
+        let token = CancellationToken::new();
+        assert!(!token.is_cancelled());
+        assert!(!token.sleep(Duration::from_millis(1)));
+
+        let clone = token.clone();
+        let start = Instant::now();
+        let sleeper = thread::spawn(move || clone.sleep(Duration::from_secs(60)));
+        thread::sleep(Duration::from_millis(20));
+        token.cancel();
+
+        assert!(sleeper.join().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    // A retry loop should stop during its backoff once cancelled, without
+    // making another attempt.
+    fn cancel_stops_retries() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let start = Instant::now();
+        let mut attempts = 0;
+        let result =
+            _retry_with_stats(None, Some(&token), vec![Duration::from_secs(60); 5], || {
+                attempts += 1;
+                canceller.cancel();
+                OperationResult::<(), _>::Retry(Error::NotFound)
+            });
+
+        match result {
+            Err(RetryError::Operation {
+                error: Error::Cancelled,
+                tries: 1,
+                ..
+            }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(attempts, 1);
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // An already cancelled token prevents any attempt at all.
+        let result = _retry_with_stats(None, Some(&token), Vec::new(), || {
+            attempts += 1;
+            OperationResult::<(), Error>::Ok(())
+        });
+        match result {
+            Err(RetryError::Operation {
+                error: Error::Cancelled,
+                tries: 0,
+                ..
+            }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(attempts, 1);
+    }
+}
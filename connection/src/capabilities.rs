@@ -0,0 +1,29 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Optional APIs a consensus node may or may not serve, depending on its
+//! version.
+
+use bitflags::bitflags;
+
+bitflags! {
+    /// The optional APIs a consensus node declares that it serves.
+    ///
+    /// Nodes report these as the bits of a `GetCapabilities` response, so
+    /// existing bits must never be reassigned.
+    pub struct PeerCapabilities: u64 {
+        /// `GetSignedBlocks`, returning blocks with their signatures.
+        const SIGNED_BLOCKS = 1 << 0;
+        /// `GetBlocksWithProofs`, returning blocks with membership proofs for
+        /// their outputs.
+        const BLOCKS_WITH_PROOFS = 1 << 1;
+        /// A minimum fee reported by `GetLastBlockInfo`.
+        const MINIMUM_FEE = 1 << 2;
+        /// `GetPendingTxHashes`, listing the transactions in the mempool.
+        const PENDING_TX_HASHES = 1 << 3;
+        /// `GetCompressedBlocks`, returning blocks compressed with gzip or
+        /// zstd.
+        const COMPRESSED_BLOCKS = 1 << 4;
+        /// `CheckKeyImage`, reporting the block a key image was spent in.
+        const KEY_IMAGES = 1 << 5;
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Compression of block responses, for nodes which serve
+//! `GetCompressedBlocks`.
+//!
+//! A compressed response holds a `BlocksResponse`, serialized and then
+//! compressed with an algorithm the client said it accepts.
+
+use crate::error::{Error, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use mc_consensus_api::consensus_common::{BlockCompression, BlocksResponse};
+use protobuf::Message;
+use std::io::{Read, Write};
+
+/// The algorithms a client accepts for compressed blocks, in order of
+/// preference.
+pub const ACCEPTED_BLOCK_COMPRESSION: &[BlockCompression] =
+    &[BlockCompression::Zstd, BlockCompression::Gzip];
+
+/// The largest serialized response a compressed response may expand to, so
+/// that a small malicious response cannot exhaust the client's memory.
+const MAX_DECOMPRESSED_BLOCKS_SIZE: u64 = 256 * 1024 * 1024;
+
+/// The zstd compression level. Level 3 is zstd's default, and is fast enough
+/// to compress responses as they are served.
+const ZSTD_LEVEL: i32 = 3;
+
+/// The algorithm a node should compress a response with, given those the
+/// client accepts in order of preference. Falls back to no compression if the
+/// client accepts none.
+pub fn negotiate_block_compression(accepted: &[BlockCompression]) -> BlockCompression {
+    accepted
+        .iter()
+        .copied()
+        .find(|compression| *compression != BlockCompression::Uncompressed)
+        .unwrap_or(BlockCompression::Uncompressed)
+}
+
+/// Serialize a blocks response and compress it with `compression`.
+pub fn compress_blocks(
+    response: &BlocksResponse,
+    compression: BlockCompression,
+) -> Result<Vec<u8>> {
+    let bytes = response
+        .write_to_bytes()
+        .map_err(|err| Error::Compression(err.to_string()))?;
+
+    match compression {
+        BlockCompression::Uncompressed => Ok(bytes),
+        BlockCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(&bytes)
+                .and_then(|_| encoder.finish())
+                .map_err(|err| Error::Compression(err.to_string()))
+        }
+        BlockCompression::Zstd => zstd::stream::encode_all(&bytes[..], ZSTD_LEVEL)
+            .map_err(|err| Error::Compression(err.to_string())),
+    }
+}
+
+/// Decompress and parse a blocks response compressed with `compression`.
+pub fn decompress_blocks(compression: BlockCompression, bytes: &[u8]) -> Result<BlocksResponse> {
+    let decoder: Box<dyn Read + '_> = match compression {
+        BlockCompression::Uncompressed => Box::new(bytes),
+        BlockCompression::Gzip => Box::new(GzDecoder::new(bytes)),
+        BlockCompression::Zstd => Box::new(
+            zstd::stream::read::Decoder::new(bytes)
+                .map_err(|err| Error::Compression(err.to_string()))?,
+        ),
+    };
+
+    // Read one byte past the limit, to tell a response at the limit from one
+    // beyond it.
+    let mut decompressed = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_BLOCKS_SIZE + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|err| Error::Compression(err.to_string()))?;
+    if decompressed.len() as u64 > MAX_DECOMPRESSED_BLOCKS_SIZE {
+        return Err(Error::Compression(format!(
+            "response expands to more than {} bytes",
+            MAX_DECOMPRESSED_BLOCKS_SIZE
+        )));
+    }
+
+    BlocksResponse::parse_from_bytes(&decompressed)
+        .map_err(|err| Error::Compression(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mc_consensus_api::blockchain;
+    use mc_transaction_core::{Block, BlockContents, BLOCK_VERSION};
+    use protobuf::RepeatedField;
+
+    fn blocks_response(len: usize) -> BlocksResponse {
+        let mut blocks = vec![Block::new_origin_block(&[])];
+        while blocks.len() < len {
+            let block = Block::new_with_parent(
+                BLOCK_VERSION,
+                blocks.last().unwrap(),
+                &Default::default(),
+                &BlockContents::default(),
+            );
+            blocks.push(block);
+        }
+
+        let mut response = BlocksResponse::new();
+        response.set_blocks(RepeatedField::from_vec(
+            blocks.iter().map(blockchain::Block::from).collect(),
+        ));
+        response
+    }
+
+    #[test]
+    // Blocks should survive every algorithm unchanged, and the compressed
+    // algorithms should shrink them.
+    fn compressed_blocks_round_trip() {
+        let response = blocks_response(100);
+        let uncompressed = compress_blocks(&response, BlockCompression::Uncompressed).unwrap();
+
+        for compression in &[
+            BlockCompression::Uncompressed,
+            BlockCompression::Gzip,
+            BlockCompression::Zstd,
+        ] {
+            let bytes = compress_blocks(&response, *compression).unwrap();
+            if *compression != BlockCompression::Uncompressed {
+                assert!(bytes.len() < uncompressed.len(), "{:?}", compression);
+            }
+            assert_eq!(decompress_blocks(*compression, &bytes).unwrap(), response);
+        }
+
+        // Bytes compressed with one algorithm are rejected by another.
+        let bytes = compress_blocks(&response, BlockCompression::Zstd).unwrap();
+        match decompress_blocks(BlockCompression::Gzip, &bytes) {
+            Err(Error::Compression(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // The node should use the client's first choice of compression, and not
+    // compress for clients which accept none.
+    fn negotiation_follows_client_preference() {
+        assert_eq!(
+            negotiate_block_compression(ACCEPTED_BLOCK_COMPRESSION),
+            BlockCompression::Zstd
+        );
+        assert_eq!(
+            negotiate_block_compression(&[BlockCompression::Gzip, BlockCompression::Zstd]),
+            BlockCompression::Gzip
+        );
+        assert_eq!(
+            negotiate_block_compression(&[]),
+            BlockCompression::Uncompressed
+        );
+    }
+}
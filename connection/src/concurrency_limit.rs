@@ -0,0 +1,104 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A limit on the number of operations in flight on a connection, so that a
+//! busy peer turns callers away instead of queueing them without bound.
+
+use displaydoc::Display;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// The connection is already running as many operations as it allows
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub struct ConnectionBusy;
+
+/// A counting semaphore which never blocks.
+///
+/// At most `max_in_flight` permits are held at once. Once they are all taken,
+/// `try_acquire` fails until one is dropped. Clones share the same permits.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimit {
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: usize,
+}
+
+impl ConcurrencyLimit {
+    /// Create a limit allowing at most `max_in_flight` concurrent operations.
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight,
+        }
+    }
+
+    /// The maximum number of permits held at once.
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    /// The number of permits currently held.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Take a permit, returning `None` if they are all held.
+    pub fn try_acquire(&self) -> Option<ConcurrencyPermit> {
+        let max_in_flight = self.max_in_flight;
+        self.in_flight
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |in_flight| {
+                if in_flight < max_in_flight {
+                    Some(in_flight + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()?;
+
+        Some(ConcurrencyPermit {
+            in_flight: self.in_flight.clone(),
+        })
+    }
+}
+
+/// A permit taken from a `ConcurrencyLimit`, which is returned when dropped.
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Permits past the limit should be refused until one is returned.
+    fn limit_refuses_excess_permits() {
+        let limit = ConcurrencyLimit::new(2);
+
+        let first = limit.try_acquire().unwrap();
+        let _second = limit.try_acquire().unwrap();
+        assert!(limit.try_acquire().is_none());
+        assert_eq!(limit.in_flight(), 2);
+
+        drop(first);
+        assert_eq!(limit.in_flight(), 1);
+        assert!(limit.try_acquire().is_some());
+    }
+
+    #[test]
+    // Clones should draw from the same permits.
+    fn clones_share_permits() {
+        let limit = ConcurrencyLimit::new(1);
+        let clone = limit.clone();
+
+        let _permit = clone.try_acquire().unwrap();
+        assert!(limit.try_acquire().is_none());
+    }
+}
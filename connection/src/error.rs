@@ -4,13 +4,20 @@
 
 pub use retry::Error as RetryError;
 
-use crate::traits::AttestationError;
+use crate::{cancellation::Cancelled, concurrency_limit::ConnectionBusy, traits::AttestationError};
 use displaydoc::Display;
-use grpcio::Error as GrpcError;
-use mc_consensus_api::{consensus_common::ProposeTxResult, ConversionError};
+use grpcio::{Error as GrpcError, RpcStatusCode};
+use mc_common::ResponderId;
+use mc_consensus_api::{consensus_common::ProposeTxResponse, ConversionError};
 use mc_crypto_noise::CipherError;
-use mc_transaction_core::validation::TransactionValidationError;
-use std::{array::TryFromSliceError, convert::TryInto, result::Result as StdResult};
+use mc_transaction_core::{validation::TransactionValidationError, BlockID, BlockIndex};
+use std::{
+    array::TryFromSliceError,
+    convert::{TryFrom, TryInto},
+    error::Error as StdError,
+    result::Result as StdResult,
+    time::Duration,
+};
 
 pub type Result<T> = StdResult<T, Error>;
 pub type RetryResult<T> = StdResult<T, RetryError<Error>>;
@@ -20,6 +27,8 @@ pub type RetryResult<T> = StdResult<T, RetryError<Error>>;
 pub enum Error {
     /// The requested range was too large
     RequestTooLarge,
+    /// The requested range {0}..{1} ends before it starts
+    InvalidRange(u64, u64),
     /// Not found
     NotFound,
     /// Could not convert gRPC type to working type: {0}
@@ -32,14 +41,202 @@ pub enum Error {
     Attestation(Box<dyn AttestationError + 'static>),
     /// Transaction validation failure: {0}
     TransactionValidation(TransactionValidationError),
+    /// The peer does not support this API: {0}
+    UnsupportedByPeer(String),
+    /// Block {0} is missing its signature
+    MissingBlockSignature(BlockIndex),
+    /// Block {0} has an invalid signature
+    InvalidBlockSignature(BlockIndex),
+    /// Block {0} was not signed by a trusted signing key
+    UnexpectedSigner(BlockIndex),
+    /// Block {0} is beyond the end of the peer's ledger
+    BeyondLedger(BlockIndex),
+    /// The operation did not complete within {0:?}
+    Timeout(Duration),
+    /// A single call did not complete within its call deadline of {0:?}
+    CallTimeout(Duration),
+    /// The connection is already running as many operations as it allows
+    Busy,
+    /// The call was cancelled before it succeeded
+    Cancelled,
+    /// Could not connect to {0}
+    ConnectFailed(String),
+    /// No block height was reported by at least {0} peers: {1:?}
+    NoBlockHeightQuorum(usize, Vec<(ResponderId, Option<BlockIndex>)>),
+    /// No block height was reported by peers with a total trust of {0}: {1:?}
+    NoTrustedBlockHeight(f64, Vec<(ResponderId, Option<BlockIndex>)>),
+    /// Block IDs were needed from {0} peers, but only {1} reported them
+    NoBlockIdQuorum(usize, usize),
+    /// Every peer failed: {0:?}
+    AllPeersFailed(Vec<(ResponderId, Error)>),
+    /// Peers disagree on the ID of block {0}
+    ForkDetected(BlockIndex),
+    /// Block {0} is not on the peer's chain
+    ForkFromKnownBlock(BlockID),
+    /// Block {0} is not among the peer's last {1} blocks
+    BlockIdSearchLimit(BlockID, u64),
+    /// The returned blocks do not form a chain: {0}
+    ChainValidation(ChainValidationError),
+    /// Could not compress or decompress blocks: {0}
+    Compression(String),
     /// Other error: {0}
     Other(String),
+    /// {operation} failed on peer {responder_id}: {source}
+    Peer {
+        /// The peer the call was made to.
+        responder_id: ResponderId,
+        /// The name of the call.
+        operation: &'static str,
+        /// The error the call failed with.
+        source: Box<Error>,
+    },
+}
+
+/// The first point at which a list of blocks fails to form a chain
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum ChainValidationError {
+    /// Expected block {expected} to follow block {previous}, but found block
+    /// {found}
+    NonConsecutiveIndex {
+        previous: BlockIndex,
+        expected: BlockIndex,
+        found: BlockIndex,
+    },
+    /// The parent ID of block {0} does not match the ID of the block before it
+    ParentMismatch(BlockIndex),
+    /// Expected the chain to start at block {expected}, but found block {found}
+    UnexpectedFirstIndex {
+        expected: BlockIndex,
+        found: BlockIndex,
+    },
+    /// The ID of block {0} does not match its contents
+    InvalidBlockId(BlockIndex),
+}
+
+/// An enumeration of errors which can occur while building a connection manager
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum ConnectionManagerError {
+    /// Could not create a responder ID from the URIs: {0:?}
+    InvalidUris(Vec<String>),
+    /// The URIs {0:?} do not use a supported scheme, expected one of {1:?}
+    UnsupportedSchemes(Vec<String>, Vec<String>),
 }
 
 impl Error {
+    /// Wrap this error with the peer and call it came from. An error which
+    /// already names its peer is returned unchanged.
+    pub fn with_peer(self, responder_id: ResponderId, operation: &'static str) -> Self {
+        match self {
+            Error::Peer { .. } => self,
+            source => Error::Peer {
+                responder_id,
+                operation,
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// The error underneath any peer context, for matching on what went
+    /// wrong regardless of where.
+    pub fn without_context(&self) -> &Error {
+        match self {
+            Error::Peer { source, .. } => source.without_context(),
+            err => err,
+        }
+    }
+
+    /// The peer the error came from, if known.
+    pub fn responder_id(&self) -> Option<&ResponderId> {
+        match self {
+            Error::Peer { responder_id, .. } => Some(responder_id),
+            _ => None,
+        }
+    }
+
     /// Policy decision, whether the call should be retried.
     pub fn should_retry(&self) -> bool {
-        matches!(self, Error::Grpc(_) | Error::Attestation(_))
+        match self {
+            Error::Peer { source, .. } => source.should_retry(),
+            Error::Grpc(_) => true,
+            Error::Attestation(err) => err.is_retriable(),
+            // A single call ran past its deadline, and a later attempt may be
+            // quicker. A whole operation which ran out of time is not retried.
+            Error::CallTimeout(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the call failed because its deadline passed.
+    pub fn is_deadline_exceeded(&self) -> bool {
+        match self {
+            Error::Peer { source, .. } => source.is_deadline_exceeded(),
+            Error::Timeout(_) | Error::CallTimeout(_) => true,
+            Error::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::DEADLINE_EXCEEDED
+            }
+            Error::Attestation(err) => err.is_deadline_exceeded(),
+            _ => false,
+        }
+    }
+
+    /// Whether the peer rejected the call because it already holds what was
+    /// submitted.
+    pub fn is_already_exists(&self) -> bool {
+        match self {
+            Error::Peer { source, .. } => source.is_already_exists(),
+            Error::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::ALREADY_EXISTS
+            }
+            Error::Attestation(err) => err.is_already_exists(),
+            _ => false,
+        }
+    }
+
+    /// Whether the peer rejected the call because the client has made too
+    /// many.
+    pub fn is_rate_limited(&self) -> bool {
+        match self {
+            Error::Peer { source, .. } => source.is_rate_limited(),
+            Error::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::RESOURCE_EXHAUSTED
+            }
+            Error::Attestation(err) => err.is_rate_limited(),
+            _ => false,
+        }
+    }
+
+    /// Whether the peer does not implement the call.
+    pub fn is_unimplemented(&self) -> bool {
+        match self {
+            Error::Peer { source, .. } => source.is_unimplemented(),
+            Error::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::UNIMPLEMENTED
+            }
+            Error::Attestation(err) => err.is_unimplemented(),
+            _ => false,
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Peer { source, .. } => Some(source.as_ref()),
+            Error::Grpc(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConnectionBusy> for Error {
+    fn from(_src: ConnectionBusy) -> Self {
+        Error::Busy
+    }
+}
+
+impl From<Cancelled> for Error {
+    fn from(_src: Cancelled) -> Self {
+        Error::Cancelled
     }
 }
 
@@ -72,6 +269,12 @@ impl TryInto<GrpcError> for Error {
     }
 }
 
+impl From<ChainValidationError> for Error {
+    fn from(src: ChainValidationError) -> Self {
+        Error::ChainValidation(src)
+    }
+}
+
 impl From<ConversionError> for Error {
     fn from(src: ConversionError) -> Self {
         Error::Conversion(src)
@@ -84,9 +287,9 @@ impl From<TryFromSliceError> for Error {
     }
 }
 
-impl From<ProposeTxResult> for Error {
-    fn from(src: ProposeTxResult) -> Self {
-        src.try_into()
+impl From<&ProposeTxResponse> for Error {
+    fn from(src: &ProposeTxResponse) -> Self {
+        TransactionValidationError::try_from(src)
             .map(Self::TransactionValidation)
             .unwrap_or_else(|err| Error::Other(err.into()))
     }
@@ -0,0 +1,111 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A moving average of a connection's call latencies, so that callers can
+//! prefer faster peers.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The weight given to each new sample. Higher values follow changes in
+/// latency more quickly, and are more sensitive to outliers.
+pub const DEFAULT_LATENCY_SMOOTHING: f64 = 0.2;
+
+/// The least latency recorded for a failed call, so that a peer which fails
+/// quickly does not seem fast.
+pub const DEFAULT_LATENCY_FAILURE_PENALTY: Duration = Duration::from_secs(5);
+
+/// An exponentially weighted moving average of call latencies.
+///
+/// Clones share the same average.
+#[derive(Clone)]
+pub struct LatencyTracker {
+    average: Arc<Mutex<Option<Duration>>>,
+    smoothing: f64,
+    failure_penalty: Duration,
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_LATENCY_SMOOTHING)
+    }
+}
+
+impl LatencyTracker {
+    /// Create a tracker with no samples, which gives each new sample the
+    /// weight `smoothing`, between 0 and 1.
+    pub fn new(smoothing: f64) -> Self {
+        Self {
+            average: Arc::new(Mutex::new(None)),
+            smoothing: smoothing.max(0.0).min(1.0),
+            failure_penalty: DEFAULT_LATENCY_FAILURE_PENALTY,
+        }
+    }
+
+    /// Record failed calls as taking at least `failure_penalty`, instead of
+    /// `DEFAULT_LATENCY_FAILURE_PENALTY`.
+    pub fn with_failure_penalty(mut self, failure_penalty: Duration) -> Self {
+        self.failure_penalty = failure_penalty;
+        self
+    }
+
+    /// Add a sample to the average. The first sample becomes the average.
+    pub fn record(&self, latency: Duration) {
+        let mut average = self.average.lock().expect("LatencyTracker lock poisoned");
+        *average = Some(match *average {
+            Some(average) => Duration::from_secs_f64(
+                average.as_secs_f64()
+                    + self.smoothing * (latency.as_secs_f64() - average.as_secs_f64()),
+            ),
+            None => latency,
+        });
+    }
+
+    /// Add a sample for a call which failed after `latency`, counting it as
+    /// taking at least the failure penalty. A call which timed out counts as
+    /// taking as long as it waited.
+    pub fn record_failure(&self, latency: Duration) {
+        self.record(latency.max(self.failure_penalty));
+    }
+
+    /// The average latency, or `None` if nothing has been recorded.
+    pub fn average(&self) -> Option<Duration> {
+        *self.average.lock().expect("LatencyTracker lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // The average should start at the first sample, move part way towards
+    // each later one, and be shared between clones.
+    fn average_follows_samples() {
+        let tracker = LatencyTracker::new(0.5);
+        assert_eq!(tracker.average(), None);
+
+        tracker.record(Duration::from_millis(100));
+        assert_eq!(tracker.average(), Some(Duration::from_millis(100)));
+
+        let clone = tracker.clone();
+        clone.record(Duration::from_millis(200));
+        assert_eq!(tracker.average(), Some(Duration::from_millis(150)));
+
+        tracker.record(Duration::from_millis(50));
+        assert_eq!(tracker.average(), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    // A failure should count as taking at least the penalty, or as long as
+    // the call waited if that is longer.
+    fn failures_are_penalized() {
+        let tracker = LatencyTracker::new(1.0).with_failure_penalty(Duration::from_secs(1));
+        tracker.record_failure(Duration::from_millis(1));
+        assert_eq!(tracker.average(), Some(Duration::from_secs(1)));
+
+        tracker.record_failure(Duration::from_secs(3));
+        assert_eq!(tracker.average(), Some(Duration::from_secs(3)));
+    }
+}
@@ -2,27 +2,72 @@
 
 //! Connection support
 
+mod attested;
+mod cancellation;
+mod capabilities;
+mod compression;
+mod concurrency_limit;
 mod credentials;
 mod error;
+mod latency;
 mod manager;
+mod metrics;
+mod rate_limit;
+mod retry_budget;
+mod retry_policy;
+mod scoped;
+mod self_healing;
 mod sync;
 mod thick;
 mod traits;
+mod verify;
+
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
 
 pub use self::{
+    attested::Attested,
+    cancellation::{CancellationToken, Cancelled},
+    capabilities::PeerCapabilities,
+    compression::{
+        compress_blocks, decompress_blocks, negotiate_block_compression, ACCEPTED_BLOCK_COMPRESSION,
+    },
+    concurrency_limit::{ConcurrencyLimit, ConcurrencyPermit, ConnectionBusy},
     credentials::{
         AnyCredentialsError, AnyCredentialsProvider, AuthenticationError, CredentialsProvider,
         CredentialsProviderError, HardcodedCredentialsProvider, TokenBasicCredentialsProvider,
     },
-    error::{Error, Result, RetryError, RetryResult},
-    manager::ConnectionManager,
-    sync::SyncConnection,
+    error::{ChainValidationError, ConnectionManagerError, Error, Result, RetryError, RetryResult},
+    latency::{LatencyTracker, DEFAULT_LATENCY_FAILURE_PENALTY, DEFAULT_LATENCY_SMOOTHING},
+    manager::{
+        find_ledger_gaps, ConnectionManager, ManagerDiagnostics, PeerDiagnostics, ProposeTxOutcome,
+        QuorumResult, DEFAULT_CONFIRMATION_POLL_INTERVAL, DEFAULT_FASTEST_FETCH_TIMEOUT,
+        DEFAULT_RATE_LIMITED_RETRY_DELAY,
+    },
+    metrics::{ConnectionMetrics, NoopConnectionMetrics},
+    rate_limit::RateLimit,
+    retry_budget::RetryBudget,
+    retry_policy::{
+        RetryPolicy, DEFAULT_RETRY_INITIAL_DELAY, DEFAULT_RETRY_MAX_ATTEMPTS,
+        DEFAULT_RETRY_MAX_DELAY,
+    },
+    scoped::ScopedAttestation,
+    self_healing::{
+        ConnectFn, PeerDiscovery, SelfHealingConnectionManager, DEFAULT_HEALTH_CHECK_INTERVAL,
+        DEFAULT_MAX_HEALTH_CHECK_FAILURES,
+    },
+    sync::{CallError, SyncConnection},
     thick::{ThickClient, ThickClientAttestationError},
     traits::{
-        AttestationError, AttestedConnection, BlockInfo, BlockchainConnection, Connection,
-        RetryableBlockchainConnection, RetryableUserTxConnection, UserTxConnection,
+        AsyncBlockchainConnection, AsyncUserTxConnection, AttestationError, AttestedConnection,
+        BlockInfo, BlockchainConnection, Connection, ConnectionFuture, FeeConnection,
+        IdempotencyKey, MempoolConnection, ProposeTxResponse, RetrySuccess,
+        RetryableBlockchainConnection, RetryableFeeConnection, RetryableUserTxConnection,
+        UserTxConnection,
     },
+    verify::{validate_block_chain, verify_block_signatures, verify_trusted_block_signatures},
 };
 
 pub use mc_common::trace_time as _trace_time;
 pub use retry as _retry;
+pub use sync::_retry_with_stats;
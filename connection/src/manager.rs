@@ -2,73 +2,381 @@
 
 //! Common connection manager implementation
 
-use crate::{sync::SyncConnection, traits::Connection};
+use crate::{
+    cancellation::CancellationToken,
+    concurrency_limit::ConcurrencyLimit,
+    error::{ConnectionManagerError, Error, Result, RetryError, RetryResult},
+    rate_limit::RateLimit,
+    retry_budget::RetryBudget,
+    retry_policy::RetryPolicy,
+    sync::{CallError, SyncConnection, _retry_with_stats},
+    traits::{
+        AttestedConnection, BlockchainConnection, Connection, IdempotencyKey, ProposeTxResponse,
+        RetrySuccess, UserTxConnection,
+    },
+    verify::{check_blocks_cover, check_range, validate_block_chain},
+};
 use mc_common::{
-    logger::{o, Logger},
-    ResponderId,
+    logger::{log, o, Logger},
+    HashMap, LruCache, ResponderId,
 };
+use mc_transaction_core::{ring_signature::KeyImage, tx::Tx, Block, BlockID, BlockIndex};
 use mc_util_uri::ConnectionUri;
+use retry::OperationResult;
 use std::{
     collections::BTreeMap,
-    sync::{Arc, RwLock, RwLockReadGuard},
+    fmt::{Display, Formatter, Result as FmtResult},
+    ops::Range,
+    result::Result as StdResult,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+/// Default maximum number of blocks held by `fetch_blocks_cached`.
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = 1_000;
+
+/// Default time `await_tx_confirmation` waits between rounds of polling.
+pub const DEFAULT_CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default time `propose_tx_all` waits before resubmitting to a peer which
+/// rejected a transaction for exceeding its rate limit.
+pub const DEFAULT_RATE_LIMITED_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Default time each peer raced by `fetch_blocks_fastest` has to respond.
+pub const DEFAULT_FASTEST_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The number of times `propose_tx_all` resubmits to a rate limited peer
+/// before reporting the rejection as a failure.
+const MAX_RATE_LIMITED_RETRIES: usize = 10;
+
+/// The number of peers `fetch_blocks_fastest` races against each other.
+const FASTEST_FETCH_PEERS: usize = 3;
+
 struct ConnectionManagerInner<C: Connection> {
     /// Map of responder id -> retryable connection.
     id_to_conn: BTreeMap<ResponderId, SyncConnection<C>>,
+    /// The retry budget shared by every connection, if any.
+    retry_budget: Option<RetryBudget>,
+    /// The limit on each connection's concurrent operations, if any.
+    max_in_flight: Option<usize>,
+    /// The token cancelling each connection's retries, if any.
+    cancellation: Option<CancellationToken>,
+    /// The time `await_tx_confirmation` waits between rounds of polling.
+    confirmation_poll_interval: Duration,
+    /// The minimum time between `propose_tx_all` submissions to each peer,
+    /// if any.
+    propose_interval: Option<Duration>,
+    /// The rate limit of each peer `propose_tx_all` has submitted to.
+    propose_rate_limits: BTreeMap<ResponderId, RateLimit>,
+    /// The time `propose_tx_all` waits before resubmitting to a rate limited
+    /// peer.
+    rate_limited_retry_delay: Duration,
+    /// The time each peer raced by `fetch_blocks_fastest` has to respond.
+    fastest_fetch_timeout: Duration,
+    /// The logger each connection's logger is derived from.
+    logger: Logger,
+}
+
+impl<C: Connection> ConnectionManagerInner<C> {
+    /// Wrap `conn` for use by the manager, applying the retry budget,
+    /// concurrency limit and cancellation token given to the manager's
+    /// existing connections.
+    fn sync_conn(&self, conn: C) -> SyncConnection<C> {
+        let name = conn.to_string();
+        let mut sync_conn =
+            SyncConnection::new(conn, self.logger.new(o!("mc.peers.peer_name" => name)));
+        if let Some(retry_budget) = self.retry_budget.as_ref() {
+            sync_conn = sync_conn.with_retry_budget(retry_budget.clone());
+        }
+        if let Some(max_in_flight) = self.max_in_flight {
+            sync_conn = sync_conn.with_concurrency_limit(ConcurrencyLimit::new(max_in_flight));
+        }
+        if let Some(cancellation) = self.cancellation.as_ref() {
+            sync_conn = sync_conn.with_cancellation(cancellation.clone());
+        }
+        sync_conn
+    }
 }
 
+/// Blocks fetched by `fetch_blocks_cached`, or `None` if caching is disabled.
+type BlockCache = Option<LruCache<BlockIndex, Block>>;
+
 /// A connection manager manages a list of peers it is connected to.
+///
+/// A thread which panics while holding one of the manager's locks does not
+/// make the manager unusable: the lock is recovered, and a warning logged the
+/// first time. The connection map is only changed by single inserts and
+/// removals, so it cannot be left half-updated.
 pub struct ConnectionManager<C: Connection> {
     inner: Arc<RwLock<ConnectionManagerInner<C>>>,
+    block_cache: Arc<Mutex<BlockCache>>,
+    /// Logs recovery from poisoned locks.
+    logger: Logger,
+    /// Whether recovery from a poisoned lock has been logged.
+    poison_reported: Arc<AtomicBool>,
 }
 
 impl<C: Connection> Clone for ConnectionManager<C> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            block_cache: self.block_cache.clone(),
+            logger: self.logger.clone(),
+            poison_reported: self.poison_reported.clone(),
         }
     }
 }
 
 /// A collection of connections
 impl<C: Connection> ConnectionManager<C> {
+    /// Create a new connection manager.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any connection's URI cannot produce a responder ID. Use
+    /// `try_new` to handle that case instead.
     pub fn new(conns: Vec<C>, logger: Logger) -> Self {
-        Self {
-            inner: Arc::new(RwLock::new(ConnectionManagerInner {
-                id_to_conn: conns
-                    .into_iter()
-                    .map(|conn| {
-                        let name = conn.to_string();
-                        let responder_id = conn.uri().responder_id().unwrap_or_else(|_| {
-                            panic!(
-                                "Could not create responder_id from {:?}",
-                                conn.uri().to_string()
-                            )
-                        });
-                        let sync_conn =
-                            SyncConnection::new(conn, logger.new(o!("mc.peers.peer_name" => name)));
-                        (responder_id, sync_conn)
-                    })
-                    .collect(),
-            })),
+        Self::try_new(conns, logger).expect("Could not create connection manager")
+    }
+
+    /// Create a new connection manager, failing if any connection's URI does
+    /// not use one of its supported schemes exactly, or cannot produce a
+    /// responder ID.
+    ///
+    /// Every offending URI is reported in the returned error, not just the
+    /// first.
+    pub fn try_new(conns: Vec<C>, logger: Logger) -> StdResult<Self, ConnectionManagerError> {
+        let mut inner = ConnectionManagerInner {
+            id_to_conn: BTreeMap::new(),
+            retry_budget: None,
+            max_in_flight: None,
+            cancellation: None,
+            confirmation_poll_interval: DEFAULT_CONFIRMATION_POLL_INTERVAL,
+            propose_interval: None,
+            propose_rate_limits: BTreeMap::new(),
+            rate_limited_retry_delay: DEFAULT_RATE_LIMITED_RETRY_DELAY,
+            fastest_fetch_timeout: DEFAULT_FASTEST_FETCH_TIMEOUT,
+            logger: logger.clone(),
+        };
+        let mut invalid_uris = Vec::new();
+        let mut unsupported_uris = Vec::new();
+        let mut supported_schemes = Vec::new();
+
+        for conn in conns {
+            let uri = conn.uri();
+            if !uri.has_supported_scheme() {
+                // Report the URI as written, since Display normalizes the scheme.
+                unsupported_uris.push(uri.url().to_string());
+                supported_schemes = uri
+                    .supported_schemes()
+                    .iter()
+                    .map(|scheme| format!("{}://", scheme))
+                    .collect();
+                continue;
+            }
+
+            match uri.responder_id() {
+                Ok(responder_id) => {
+                    let sync_conn = inner.sync_conn(conn);
+                    inner.id_to_conn.insert(responder_id, sync_conn);
+                }
+                Err(_) => invalid_uris.push(uri.to_string()),
+            }
+        }
+
+        if !unsupported_uris.is_empty() {
+            return Err(ConnectionManagerError::UnsupportedSchemes(
+                unsupported_uris,
+                supported_schemes,
+            ));
+        }
+
+        if !invalid_uris.is_empty() {
+            return Err(ConnectionManagerError::InvalidUris(invalid_uris));
+        }
+
+        Ok(Self {
+            inner: Arc::new(RwLock::new(inner)),
+            block_cache: Arc::new(Mutex::new(Some(LruCache::new(DEFAULT_BLOCK_CACHE_SIZE)))),
+            logger,
+            poison_reported: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Hold at most `block_cache_size` blocks for `fetch_blocks_cached`, in a
+    /// new cache which only this manager and its later clones share. A size
+    /// of zero disables the cache.
+    pub fn with_block_cache_size(mut self, block_cache_size: usize) -> Self {
+        let block_cache = if block_cache_size > 0 {
+            Some(LruCache::new(block_cache_size))
+        } else {
+            None
+        };
+        self.block_cache = Arc::new(Mutex::new(block_cache));
+        self
+    }
+
+    /// Share `retry_budget` between all of this manager's connections, so
+    /// that their retries are limited in total rather than per call.
+    pub fn with_retry_budget(self, retry_budget: RetryBudget) -> Self {
+        {
+            let mut inner = self.write();
+            for conn in inner.id_to_conn.values_mut() {
+                *conn = conn.clone().with_retry_budget(retry_budget.clone());
+            }
+            inner.retry_budget = Some(retry_budget);
         }
+        self
+    }
+
+    /// Cancel the retries of all of this manager's connections once `token`
+    /// is cancelled.
+    pub fn with_cancellation(self, token: CancellationToken) -> Self {
+        {
+            let mut inner = self.write();
+            for conn in inner.id_to_conn.values_mut() {
+                *conn = conn.clone().with_cancellation(token.clone());
+            }
+            inner.cancellation = Some(token);
+        }
+        self
+    }
+
+    /// Allow at most `max_in_flight` concurrent operations on each of this
+    /// manager's connections. Each connection has its own limit.
+    pub fn with_concurrency_limit(self, max_in_flight: usize) -> Self {
+        {
+            let mut inner = self.write();
+            for conn in inner.id_to_conn.values_mut() {
+                *conn = conn
+                    .clone()
+                    .with_concurrency_limit(ConcurrencyLimit::new(max_in_flight));
+            }
+            inner.max_in_flight = Some(max_in_flight);
+        }
+        self
+    }
+
+    /// Wait `interval` between the rounds of polling made by
+    /// `await_tx_confirmation`, instead of
+    /// `DEFAULT_CONFIRMATION_POLL_INTERVAL`.
+    pub fn with_confirmation_poll_interval(self, interval: Duration) -> Self {
+        self.write().confirmation_poll_interval = interval;
+        self
+    }
+
+    /// Space out the submissions `propose_tx_all` makes to each peer so that
+    /// they start at least `interval` apart. Different peers are still
+    /// submitted to in parallel.
+    pub fn with_propose_rate_limit(self, interval: Duration) -> Self {
+        {
+            let mut inner = self.write();
+            inner.propose_interval = Some(interval);
+            inner.propose_rate_limits.clear();
+        }
+        self
+    }
+
+    /// Wait `delay` before resubmitting to a peer which rejected a
+    /// transaction for exceeding its rate limit, instead of
+    /// `DEFAULT_RATE_LIMITED_RETRY_DELAY`.
+    pub fn with_rate_limited_retry_delay(self, delay: Duration) -> Self {
+        self.write().rate_limited_retry_delay = delay;
+        self
+    }
+
+    /// Give each peer raced by `fetch_blocks_fastest` `timeout` to respond,
+    /// instead of `DEFAULT_FASTEST_FETCH_TIMEOUT`.
+    pub fn with_fastest_fetch_timeout(self, timeout: Duration) -> Self {
+        self.write().fastest_fetch_timeout = timeout;
+        self
     }
 
     fn read(&self) -> RwLockReadGuard<ConnectionManagerInner<C>> {
-        self.inner.read().expect("ConnectionManager lock poisoned")
+        self.inner
+            .read()
+            .unwrap_or_else(|err| self.recover("ConnectionManager", err))
+    }
+
+    fn write(&self) -> RwLockWriteGuard<ConnectionManagerInner<C>> {
+        self.inner
+            .write()
+            .unwrap_or_else(|err| self.recover("ConnectionManager", err))
+    }
+
+    fn block_cache(&self) -> MutexGuard<BlockCache> {
+        self.block_cache
+            .lock()
+            .unwrap_or_else(|err| self.recover("ConnectionManager block cache", err))
+    }
+
+    /// Take the guard from a lock poisoned by a panicked thread, logging a
+    /// warning the first time.
+    fn recover<G>(&self, lock_name: &str, err: PoisonError<G>) -> G {
+        if !self.poison_reported.swap(true, Ordering::SeqCst) {
+            log::warn!(
+                self.logger,
+                "{} lock was poisoned by a panicked thread, recovering",
+                lock_name
+            );
+        }
+        err.into_inner()
     }
 
-    /// Retrieve a vector of all the connection URLs owned by this manager.
+    /// Retrieve a vector of all the connection URLs owned by this manager,
+    /// sorted by responder ID.
     pub fn responder_ids(&self) -> Vec<ResponderId> {
         self.read().id_to_conn.keys().cloned().collect()
     }
 
-    /// Retrieve an array of synchronous connection supports.
+    /// Retrieve an array of synchronous connection supports, sorted by
+    /// responder ID.
+    ///
+    /// The order is the same on every call and every run, whatever order the
+    /// connections were given or added in, so callers may rely on it when
+    /// trying peers in turn.
     pub fn conns(&self) -> Vec<SyncConnection<C>> {
         self.read().id_to_conn.values().cloned().collect()
     }
 
+    /// The connection whose calls have taken the least time on average, as
+    /// measured by `SyncConnection::average_latency`.
+    ///
+    /// Connections with no measurement yet are chosen first, so that each
+    /// peer is tried before it is judged. Failed calls count against a peer,
+    /// so one which keeps failing is not chosen over a working one. Ties go
+    /// to the first in responder ID order. Returns `None` if there are no
+    /// connections.
+    pub fn fastest_connection(&self) -> Option<SyncConnection<C>> {
+        self.conns().into_iter().min_by_key(latency_rank)
+    }
+
+    /// Ping every peer in parallel, reporting each round trip, or `None` if
+    /// the peer failed or cannot be pinged.
+    ///
+    /// Successful pings are recorded in each connection's latency average, so
+    /// this can be used to measure peers before `fastest_connection` is
+    /// needed.
+    pub fn ping_all(&self) -> Vec<(ResponderId, Option<Duration>)> {
+        let handles = self
+            .id_to_conn()
+            .into_iter()
+            .map(|(responder_id, mut conn)| (responder_id, thread::spawn(move || conn.ping())))
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|(responder_id, handle)| {
+                let latency = handle.join().ok().and_then(|result| result.ok());
+                (responder_id, latency)
+            })
+            .collect()
+    }
+
     /// Retrieve a map of URLs to the connection type.
     pub fn id_to_conn(&self) -> BTreeMap<ResponderId, SyncConnection<C>> {
         self.read().id_to_conn.clone()
@@ -88,4 +396,2087 @@ impl<C: Connection> ConnectionManager<C> {
     pub fn is_empty(&self) -> bool {
         self.read().id_to_conn.is_empty()
     }
+
+    /// Add a connection to this manager and every clone of it, replacing any
+    /// connection with the same responder ID.
+    ///
+    /// The connection shares the retry budget and concurrency limit given to
+    /// the manager, if any. Fails as `try_new` does if the connection's URI is
+    /// unusable.
+    pub fn add_conn(&self, conn: C) -> StdResult<ResponderId, ConnectionManagerError> {
+        let uri = conn.uri();
+        if !uri.has_supported_scheme() {
+            return Err(ConnectionManagerError::UnsupportedSchemes(
+                vec![uri.url().to_string()],
+                uri.supported_schemes()
+                    .iter()
+                    .map(|scheme| format!("{}://", scheme))
+                    .collect(),
+            ));
+        }
+        let responder_id = uri
+            .responder_id()
+            .map_err(|_| ConnectionManagerError::InvalidUris(vec![uri.to_string()]))?;
+
+        let mut inner = self.write();
+        let sync_conn = inner.sync_conn(conn);
+        inner.id_to_conn.insert(responder_id.clone(), sync_conn);
+        Ok(responder_id)
+    }
+
+    /// Remove a connection from this manager and every clone of it, returning
+    /// it if it was present.
+    ///
+    /// Clones of the connection already handed out remain usable.
+    pub fn remove_conn(&self, responder_id: &ResponderId) -> Option<SyncConnection<C>> {
+        let mut inner = self.write();
+        inner.propose_rate_limits.remove(responder_id);
+        inner.id_to_conn.remove(responder_id)
+    }
+
+    /// The number of connections whose URIs do not use TLS.
+    ///
+    /// Production configurations are expected to report zero.
+    pub fn insecure_connection_count(&self) -> usize {
+        self.read()
+            .id_to_conn
+            .values()
+            .filter(|conn| !conn.uri().use_tls())
+            .count()
+    }
+}
+
+impl<C: BlockchainConnection + 'static> ConnectionManager<C> {
+    /// Query every peer's block height in parallel, and return the highest
+    /// height which at least `quorum` peers have reached.
+    ///
+    /// A peer reporting height `h` is counted as agreeing with every height at
+    /// or below `h`, so a single peer reporting an inflated height cannot move
+    /// the result. Peers which fail to respond do not count towards the quorum.
+    pub fn consensus_block_height(&self, quorum: usize) -> Result<BlockIndex> {
+        match self.consensus_block_height_best_effort(quorum)? {
+            QuorumResult::Quorum(height) => Ok(height),
+            QuorumResult::BelowQuorum { responses, .. } => {
+                Err(Error::NoBlockHeightQuorum(quorum, responses))
+            }
+        }
+    }
+
+    /// As `consensus_block_height`, but if fewer than `quorum` peers respond,
+    /// return the highest height every responding peer has reached, flagged as
+    /// below quorum.
+    ///
+    /// Fails only if `quorum` is zero or no peer responds.
+    pub fn consensus_block_height_best_effort(
+        &self,
+        quorum: usize,
+    ) -> Result<QuorumResult<BlockIndex, BlockIndex>> {
+        let heights = self.query_all(|conn| conn.fetch_block_height());
+        if let Some(height) = highest_quorum_height(&heights, quorum) {
+            return Ok(QuorumResult::Quorum(height));
+        }
+
+        let responded = heights
+            .iter()
+            .filter(|(_, height)| height.is_some())
+            .count();
+        match highest_quorum_height(&heights, responded) {
+            Some(best_effort) if quorum > 0 => Ok(QuorumResult::BelowQuorum {
+                best_effort,
+                responses: heights,
+            }),
+            _ => Err(Error::NoBlockHeightQuorum(quorum, heights)),
+        }
+    }
+
+    /// Query every peer's block height in parallel, and return the highest
+    /// height reached by peers whose trust weights sum to at least
+    /// `threshold`.
+    ///
+    /// As with `consensus_block_height`, a peer reporting height `h` backs
+    /// every height at or below `h`. Peers missing from `trust`, or given a
+    /// negative or NaN weight, carry no weight.
+    pub fn trusted_block_height(
+        &self,
+        trust: &HashMap<ResponderId, f64>,
+        threshold: f64,
+    ) -> Result<BlockIndex> {
+        let heights = self.query_all(|conn| conn.fetch_block_height());
+        highest_trusted_height(&heights, trust, threshold)
+            .ok_or(Error::NoTrustedBlockHeight(threshold, heights))
+    }
+
+    /// Ask every peer for its block height in parallel, and report which
+    /// peers answered.
+    ///
+    /// Each request is metered and holds one of the peer's permits, so a peer
+    /// already at its concurrency limit is not asked and is reported as not
+    /// answering.
+    pub fn health_check(&self) -> HashMap<ResponderId, bool> {
+        let handles = self
+            .id_to_conn()
+            .into_iter()
+            .map(|(responder_id, conn)| {
+                let handle = thread::spawn(move || probe_health(&conn).is_ok());
+                (responder_id, handle)
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|(responder_id, handle)| (responder_id, handle.join().unwrap_or(false)))
+            .collect()
+    }
+
+    /// Iterate over the connections which answer a block height request, in
+    /// responder ID order, using the same probe as `health_check`.
+    ///
+    /// Each peer is only asked when the iterator reaches it, so a caller which
+    /// stops at the first healthy peer does not wait for the rest. The
+    /// responder IDs are read up front, but each connection is looked up as it
+    /// is reached, so the manager's lock is not held across the iteration.
+    /// Peers removed in the meantime are skipped, and peers added in the
+    /// meantime are not visited.
+    pub fn iter_healthy(&self) -> impl Iterator<Item = SyncConnection<C>> + '_ {
+        self.responder_ids()
+            .into_iter()
+            .filter_map(move |responder_id| self.conn(&responder_id))
+            .filter(|conn| probe_health(conn).is_ok())
+    }
+
+    /// Retrieve the blocks in `range`, serving those already cached and
+    /// fetching only the gaps between them.
+    ///
+    /// Each gap is fetched from the first peer able to provide it, in
+    /// responder ID order. The fetched blocks are cached only once they form
+    /// a chain and at least `quorum` peers agree on their IDs, so a single
+    /// peer cannot poison the cache. Blocks do not change once they are in
+    /// the ledger, so cached blocks never go stale.
+    ///
+    /// Fails with the last peer's error if no peer could provide a gap.
+    pub fn fetch_blocks_cached(
+        &self,
+        range: Range<BlockIndex>,
+        quorum: usize,
+    ) -> Result<Vec<Block>> {
+        let mut blocks = Vec::new();
+        let mut index = range.start;
+        while index < range.end {
+            let cached = self
+                .block_cache()
+                .as_mut()
+                .and_then(|cache| cache.get(&index).cloned());
+            if let Some(block) = cached {
+                blocks.push(block);
+                index += 1;
+                continue;
+            }
+
+            let gap_end = {
+                let cache = self.block_cache();
+                (index..range.end)
+                    .find(|index| cache.as_ref().map_or(false, |cache| cache.contains(index)))
+                    .unwrap_or(range.end)
+            };
+            let fetched = self.fetch_uncached_blocks(index..gap_end, quorum)?;
+            index += fetched.len() as BlockIndex;
+            blocks.extend(fetched);
+        }
+
+        validate_block_chain(range.start, &blocks)?;
+        Ok(blocks)
+    }
+
+    /// Fetch the blocks missing from a ledger holding the blocks in
+    /// `local_ranges`, as found by `find_ledger_gaps`.
+    ///
+    /// Returns each gap with its blocks, in block order. Each gap is fetched
+    /// as by `fetch_blocks_cached`, with the same `quorum`, and the first gap
+    /// no peer can provide fails the call.
+    pub fn fetch_ledger_gaps(
+        &self,
+        local_ranges: &[Range<BlockIndex>],
+        quorum: usize,
+    ) -> Result<Vec<(Range<BlockIndex>, Vec<Block>)>> {
+        find_ledger_gaps(local_ranges)
+            .into_iter()
+            .map(|gap| {
+                let blocks = self.fetch_blocks_cached(gap.clone(), quorum)?;
+                Ok((gap, blocks))
+            })
+            .collect()
+    }
+
+    /// Wait until a transaction spending every one of `key_images` has been
+    /// committed, and return the index of the block holding it.
+    ///
+    /// Each round, peers are asked in turn whether they have spent all of the
+    /// key images, and the first which has ends the wait. Each lookup carries
+    /// the overall deadline, so a peer which does not respond cannot hold up
+    /// the wait past `timeout`. Rounds are
+    /// separated by the poll interval, `DEFAULT_CONFIRMATION_POLL_INTERVAL`
+    /// unless set by `with_confirmation_poll_interval`.
+    ///
+    /// Fails with `Error::Timeout` if no peer confirms the transaction within
+    /// `timeout`, with `Error::UnsupportedByPeer` if no peer serves key image
+    /// lookups, and with `Error::Cancelled` if the manager's cancellation
+    /// token is cancelled while waiting.
+    pub fn await_tx_confirmation(
+        &self,
+        key_images: &[KeyImage],
+        timeout: Duration,
+    ) -> Result<BlockIndex> {
+        if key_images.is_empty() {
+            return Err(Error::Other("No key images to confirm".to_string()));
+        }
+
+        let deadline = Instant::now() + timeout;
+        let (poll_interval, cancellation) = {
+            let inner = self.read();
+            (inner.confirmation_poll_interval, inner.cancellation.clone())
+        };
+
+        loop {
+            let mut supported = false;
+            for conn in self.conns() {
+                if Instant::now() >= deadline {
+                    return Err(Error::Timeout(timeout));
+                }
+                let block_index = conn.metered("contains_key_image", |inner| {
+                    inner.set_deadline(Some(deadline));
+                    let result = (|| {
+                        let mut block_index = 0;
+                        for key_image in key_images {
+                            match inner.contains_key_image(key_image)? {
+                                Some(index) => block_index = block_index.max(index),
+                                None => return Ok(None),
+                            }
+                        }
+                        Ok(Some(block_index))
+                    })();
+                    inner.set_deadline(None);
+                    result
+                });
+                match block_index {
+                    Ok(Some(block_index)) => return Ok(block_index),
+                    Ok(None) => supported = true,
+                    Err(Error::UnsupportedByPeer(_)) => {}
+                    Err(err) => {
+                        supported = true;
+                        log::debug!(
+                            self.logger,
+                            "{}: Could not look up key images: {}",
+                            conn,
+                            err
+                        );
+                    }
+                }
+            }
+
+            if !supported {
+                return Err(Error::UnsupportedByPeer("contains_key_image".to_string()));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout(timeout));
+            }
+            let wait = poll_interval.min(deadline - now);
+            match &cancellation {
+                Some(token) => {
+                    if token.sleep(wait) {
+                        return Err(Error::Cancelled);
+                    }
+                }
+                None => thread::sleep(wait),
+            }
+        }
+    }
+
+    /// Fetch the blocks in `range` from a few peers at once, and return the
+    /// first response which holds them all.
+    ///
+    /// This trades bandwidth for latency. Up to three peers are raced,
+    /// preferring those whose last call succeeded, then those not yet
+    /// measured, then those with the lowest average latency. Once a response is
+    /// accepted, the race is cancelled: peers which have not yet sent their
+    /// request skip it, and requests already in flight are left to finish,
+    /// with their responses discarded.
+    ///
+    /// Each peer is asked once, without retries, under its concurrency limit
+    /// and with a deadline of `DEFAULT_FASTEST_FETCH_TIMEOUT` unless set by
+    /// `with_fastest_fetch_timeout`, so every thread exits once its request
+    /// completes or times out. The manager's cancellation token stops peers
+    /// which have not yet sent their request.
+    ///
+    /// A response is accepted only if it holds every block in `range`, in
+    /// order, forming a chain. If no peer gives one, fails with
+    /// `Error::AllPeersFailed` holding each raced peer's error.
+    pub fn fetch_blocks_fastest(&self, range: Range<BlockIndex>) -> Result<Vec<Block>> {
+        check_range(&range)?;
+        let timeout = self.read().fastest_fetch_timeout;
+
+        let mut conns = self.conns();
+        conns.sort_by_key(|conn| {
+            (
+                conn.last_call_succeeded() == Some(false),
+                latency_rank(conn),
+            )
+        });
+
+        let race = CancellationToken::new();
+        let (sender, receiver) = mpsc::channel();
+        for conn in conns.into_iter().take(FASTEST_FETCH_PEERS) {
+            let range = range.clone();
+            let race = race.clone();
+            let sender = sender.clone();
+            thread::spawn(move || {
+                // The race may have been decided before this thread started.
+                if race.is_cancelled() {
+                    return;
+                }
+                let result = if conn
+                    .cancellation()
+                    .map_or(false, CancellationToken::is_cancelled)
+                {
+                    Err(Error::Cancelled)
+                } else {
+                    match conn.try_acquire_permit() {
+                        Ok(_permit) => conn.metered("fetch_blocks", |inner| {
+                            inner.set_deadline(Some(Instant::now() + timeout));
+                            let result = inner.fetch_blocks(range.clone());
+                            inner.set_deadline(None);
+                            result.and_then(|blocks| {
+                                check_blocks_cover(&range, &blocks).map(|()| blocks)
+                            })
+                        }),
+                        Err(busy) => Err(busy.into()),
+                    }
+                };
+                // The receiver is gone once the race has been decided.
+                let _ = sender.send((conn.responder_id().clone(), result));
+            });
+        }
+        drop(sender);
+
+        // Ends once every peer has answered or skipped its request.
+        let mut failures = Vec::new();
+        for (responder_id, result) in receiver {
+            match result {
+                Ok(blocks) => {
+                    race.cancel();
+                    return Ok(blocks);
+                }
+                Err(err) => failures.push((responder_id, err)),
+            }
+        }
+        failures.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Err(Error::AllPeersFailed(failures))
+    }
+
+    /// Fetch a prefix of `range` from the first peer which has it and whose
+    /// blocks `quorum` peers vouch for, and cache the blocks.
+    fn fetch_uncached_blocks(&self, range: Range<BlockIndex>, quorum: usize) -> Result<Vec<Block>> {
+        let mut last_error = Error::NotFound;
+        for conn in self.conns() {
+            let verified = conn
+                .metered("fetch_available_blocks", |inner| {
+                    inner.fetch_available_blocks(range.clone())
+                })
+                .and_then(|blocks| {
+                    self.verify_fetched_blocks(&blocks, quorum)?;
+                    Ok(blocks)
+                });
+            match verified {
+                Ok(blocks) => {
+                    if let Some(cache) = self.block_cache().as_mut() {
+                        for block in &blocks {
+                            cache.put(block.index, block.clone());
+                        }
+                    }
+                    return Ok(blocks);
+                }
+                Err(err) => last_error = err,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Check that `blocks` is a non-empty chain whose IDs at least `quorum`
+    /// peers agree on.
+    fn verify_fetched_blocks(&self, blocks: &[Block], quorum: usize) -> Result<()> {
+        let start = blocks.first().ok_or(Error::NotFound)?.index;
+        validate_block_chain(start, blocks)?;
+
+        let block_ids = self.verify_block_ids(start..start + blocks.len() as BlockIndex, quorum)?;
+        match blocks
+            .iter()
+            .zip(block_ids.iter())
+            .find(|(block, block_id)| block.id != **block_id)
+        {
+            Some((block, _)) => Err(Error::ForkDetected(block.index)),
+            None => Ok(()),
+        }
+    }
+
+    /// Fetch the IDs of the blocks in `range` from every peer in parallel, and
+    /// return them if at least `quorum` peers reported the full range and no
+    /// two peers disagree.
+    ///
+    /// Any index at which peers report different IDs fails with
+    /// `Error::ForkDetected`, so callers learn of a fork before trying to
+    /// apply a block from one side of it.
+    pub fn verify_block_ids(
+        &self,
+        range: Range<BlockIndex>,
+        quorum: usize,
+    ) -> Result<Vec<BlockID>> {
+        match self.verify_block_ids_best_effort(range, quorum)? {
+            QuorumResult::Quorum(block_ids) => Ok(block_ids),
+            QuorumResult::BelowQuorum { responses, .. } => {
+                let reported = responses
+                    .iter()
+                    .filter(|(_, block_ids)| block_ids.is_some())
+                    .count();
+                Err(Error::NoBlockIdQuorum(quorum, reported))
+            }
+        }
+    }
+
+    /// As `verify_block_ids`, but if fewer than `quorum` peers report the full
+    /// range, return the IDs the reporting peers agree on, flagged as below
+    /// quorum.
+    ///
+    /// Disagreement still fails with `Error::ForkDetected`. Fails with
+    /// `Error::NoBlockIdQuorum` if `quorum` is zero or no peer reports the
+    /// full range.
+    pub fn verify_block_ids_best_effort(
+        &self,
+        range: Range<BlockIndex>,
+        quorum: usize,
+    ) -> Result<QuorumResult<Vec<BlockID>, Vec<BlockID>>> {
+        let range_start = range.start;
+        let expected_len = range.end.saturating_sub(range.start) as usize;
+        let responses = self
+            .query_all(move |conn| conn.fetch_block_ids(range.clone()))
+            .into_iter()
+            .map(|(responder_id, block_ids)| {
+                let block_ids = block_ids.filter(|block_ids| block_ids.len() == expected_len);
+                (responder_id, block_ids)
+            })
+            .collect::<Vec<_>>();
+        let reported = responses
+            .iter()
+            .filter_map(|(_, block_ids)| block_ids.clone())
+            .collect::<Vec<_>>();
+
+        if quorum > 0 && !reported.is_empty() && reported.len() < quorum {
+            let best_effort = agreed_block_ids(range_start, &reported, reported.len())?;
+            return Ok(QuorumResult::BelowQuorum {
+                best_effort,
+                responses,
+            });
+        }
+        agreed_block_ids(range_start, &reported, quorum).map(QuorumResult::Quorum)
+    }
+
+    /// Run `func` against every peer in parallel, collecting each peer's
+    /// result, or `None` if it failed.
+    fn query_all<T: Send + 'static>(
+        &self,
+        func: impl Fn(&mut C) -> Result<T> + Clone + Send + 'static,
+    ) -> Vec<(ResponderId, Option<T>)> {
+        let handles = self
+            .id_to_conn()
+            .into_iter()
+            .map(|(responder_id, conn)| {
+                let func = func.clone();
+                let handle = thread::spawn(move || func(&mut conn.write()));
+                (responder_id, handle)
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|(responder_id, handle)| {
+                let value = handle.join().ok().and_then(|result| result.ok());
+                (responder_id, value)
+            })
+            .collect()
+    }
+}
+
+impl<C: AttestedConnection + 'static> ConnectionManager<C>
+where
+    C::Error: 'static,
+{
+    /// Attest every connection in parallel, so that the first real call on
+    /// each does not pay for attestation.
+    ///
+    /// Connections which are already attested are left as they are. A peer
+    /// which fails to attest, or whose attestation panics, does not stop the
+    /// others, and each peer's outcome is returned so that callers can tell
+    /// which peers are ready.
+    pub fn warm_up(&self) -> HashMap<ResponderId, Result<()>> {
+        let handles = self
+            .id_to_conn()
+            .into_iter()
+            .map(|(responder_id, conn)| {
+                let handle = thread::spawn(move || {
+                    let mut conn = conn.write();
+                    if conn.is_attested() {
+                        return Ok(());
+                    }
+                    conn.attest()
+                        .map(|_verification_report| ())
+                        .map_err(Error::from)
+                });
+                (responder_id, handle)
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|(responder_id, handle)| {
+                let result = handle.join().unwrap_or_else(|_| {
+                    Err(Error::Other(format!(
+                        "attestation of {} panicked during warm up",
+                        responder_id
+                    )))
+                });
+                (responder_id, result)
+            })
+            .collect()
+    }
+
+    /// Describe the state of every connection, for attaching to bug reports.
+    ///
+    /// No peer is contacted. Only the manager's read lock is taken, and a
+    /// connection busy with a call is not waited for, so its attestation
+    /// status is reported as unknown.
+    pub fn diagnostics(&self) -> ManagerDiagnostics {
+        let inner = self.read();
+        let peers = inner
+            .id_to_conn
+            .iter()
+            .map(|(responder_id, conn)| PeerDiagnostics {
+                responder_id: responder_id.clone(),
+                uri: conn.uri().to_string(),
+                healthy: conn.last_call_succeeded(),
+                attested: conn.try_read().map(|conn| conn.is_attested()),
+                average_latency: conn.average_latency(),
+                last_error: conn.last_error(),
+            })
+            .collect();
+        ManagerDiagnostics { peers }
+    }
+}
+
+/// The state of every connection in a manager, from
+/// `ConnectionManager::diagnostics`.
+#[derive(Clone, Debug)]
+pub struct ManagerDiagnostics {
+    /// One entry per connection, in responder ID order.
+    pub peers: Vec<PeerDiagnostics>,
+}
+
+/// The state of one connection, from `ConnectionManager::diagnostics`.
+#[derive(Clone, Debug)]
+pub struct PeerDiagnostics {
+    /// The peer's responder ID.
+    pub responder_id: ResponderId,
+    /// The URI the connection was made to.
+    pub uri: String,
+    /// Whether the most recent call to the peer succeeded, or `None` if no
+    /// call has been made.
+    pub healthy: Option<bool>,
+    /// Whether the connection holds an attested session, or `None` if it was
+    /// busy with a call.
+    pub attested: Option<bool>,
+    /// The moving average of the time calls took, counting failed calls as
+    /// taking at least the latency tracker's failure penalty.
+    pub average_latency: Option<Duration>,
+    /// The most recent failed call, even if later calls succeeded.
+    pub last_error: Option<CallError>,
+}
+
+impl Display for ManagerDiagnostics {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        for peer in &self.peers {
+            writeln!(f, "{}", peer)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for PeerDiagnostics {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let unknown = |value: Option<bool>| match value {
+            Some(value) => value.to_string(),
+            None => "unknown".to_string(),
+        };
+        write!(
+            f,
+            "{} ({}): healthy: {}, attested: {}, average latency: {}",
+            self.responder_id,
+            self.uri,
+            unknown(self.healthy),
+            unknown(self.attested),
+            self.average_latency
+                .map_or_else(|| "unknown".to_string(), |latency| format!("{:?}", latency)),
+        )?;
+        if let Some(last_error) = self.last_error.as_ref() {
+            write!(
+                f,
+                ", last error: {} failed {:?} ago: {}",
+                last_error.call,
+                last_error.at.elapsed(),
+                last_error.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The answer to a query put to every peer, which may be backed by fewer
+/// peers than the caller asked for.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum QuorumResult<T, R> {
+    /// At least a quorum of peers backed the answer.
+    Quorum(T),
+    /// Fewer than a quorum of peers responded. `best_effort` is the answer
+    /// backed by every peer which did, and `responses` holds each peer's
+    /// response, or `None` if it failed.
+    BelowQuorum {
+        best_effort: T,
+        responses: Vec<(ResponderId, Option<R>)>,
+    },
+}
+
+impl<T, R> QuorumResult<T, R> {
+    /// Whether a quorum of peers backed the answer.
+    pub fn is_quorum(&self) -> bool {
+        matches!(self, QuorumResult::Quorum(_))
+    }
+
+    /// The answer, whether or not a quorum backed it.
+    pub fn into_value(self) -> T {
+        match self {
+            QuorumResult::Quorum(value) => value,
+            QuorumResult::BelowQuorum { best_effort, .. } => best_effort,
+        }
+    }
+}
+
+/// The outcome of proposing a transaction to a single peer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProposeTxOutcome {
+    /// The peer accepted the transaction when its ledger held this many
+    /// blocks.
+    Accepted(BlockIndex),
+    /// The peer rejected the submission because it already holds the
+    /// transaction.
+    AlreadyInMempool,
+}
+
+impl<C: UserTxConnection + 'static> ConnectionManager<C> {
+    /// Propose `tx` to every peer in parallel, retrying each with
+    /// `RetryPolicy::default()`, and return each peer's outcome.
+    ///
+    /// A peer which reports that it already holds the transaction yields
+    /// `ProposeTxOutcome::AlreadyInMempool` rather than an error, so that
+    /// resubmitting a transaction which has already propagated is not
+    /// mistaken for a failure.
+    ///
+    /// If `with_propose_rate_limit` was given, submissions to each peer are
+    /// spaced out, across calls, to respect it. A peer which rejects the
+    /// transaction for exceeding its own rate limit is submitted to again
+    /// after `with_rate_limited_retry_delay`, up to a fixed number of times,
+    /// rather than reported as failed.
+    pub fn propose_tx_all(&self, tx: &Tx) -> HashMap<ResponderId, RetryResult<ProposeTxOutcome>> {
+        let (peers, retry_delay, cancellation) = {
+            let mut inner = self.write();
+            let mut peers = Vec::new();
+            for (responder_id, conn) in inner.id_to_conn.clone() {
+                let rate_limit = inner.propose_interval.map(|interval| {
+                    inner
+                        .propose_rate_limits
+                        .entry(responder_id.clone())
+                        .or_insert_with(|| RateLimit::new(interval))
+                        .clone()
+                });
+                peers.push((responder_id, conn, rate_limit));
+            }
+            (
+                peers,
+                inner.rate_limited_retry_delay,
+                inner.cancellation.clone(),
+            )
+        };
+
+        let handles = peers
+            .into_iter()
+            .map(|(responder_id, conn, rate_limit)| {
+                let tx = tx.clone();
+                let cancellation = cancellation.clone();
+                let handle = thread::spawn(move || {
+                    propose_tx_rate_limited(
+                        &conn,
+                        &tx,
+                        rate_limit.as_ref(),
+                        retry_delay,
+                        cancellation.as_ref(),
+                    )
+                });
+                (responder_id, handle)
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|(responder_id, handle)| {
+                let result = handle.join().unwrap_or_else(|_| {
+                    Err(RetryError::Internal(format!(
+                        "propose_tx to {} panicked",
+                        responder_id
+                    )))
+                });
+                (responder_id, classify_propose_tx(result))
+            })
+            .collect()
+    }
+}
+
+/// Find the ranges of blocks missing from a ledger which holds the blocks in
+/// `local_ranges`.
+///
+/// The ranges may be given in any order, and may overlap or be empty. A ledger
+/// starts at block 0, so any blocks before the first range are missing too.
+/// Blocks after the last range are not reported, since how far the ledger
+/// should reach is up to the caller.
+pub fn find_ledger_gaps(local_ranges: &[Range<BlockIndex>]) -> Vec<Range<BlockIndex>> {
+    let mut ranges = local_ranges
+        .iter()
+        .filter(|range| !range.is_empty())
+        .cloned()
+        .collect::<Vec<_>>();
+    ranges.sort_unstable_by_key(|range| range.start);
+
+    let mut gaps = Vec::new();
+    let mut covered_to = 0;
+    for range in ranges {
+        if range.start > covered_to {
+            gaps.push(covered_to..range.start);
+        }
+        covered_to = covered_to.max(range.end);
+    }
+    gaps
+}
+
+/// Order connections by their average latency, putting those with no
+/// measurement yet first.
+fn latency_rank<C: Connection>(conn: &SyncConnection<C>) -> (bool, Duration) {
+    conn.average_latency()
+        .map_or((false, Duration::default()), |latency| (true, latency))
+}
+
+/// Ask a single peer for its block height, as a metered call holding one of
+/// its permits.
+fn probe_health<C: BlockchainConnection>(conn: &SyncConnection<C>) -> Result<BlockIndex> {
+    let _permit = conn.try_acquire_permit()?;
+    conn.metered("fetch_block_height", |inner| inner.fetch_block_height())
+}
+
+/// Propose `tx` to a single peer, waiting for a slot under `rate_limit` before
+/// each submission, and resubmitting after `retry_delay` if the peer rejects
+/// it for exceeding its own rate limit.
+///
+/// Every submission carries the same idempotency key, so a resubmission the
+/// peer already accepted is not applied twice.
+fn propose_tx_rate_limited<C: UserTxConnection>(
+    conn: &SyncConnection<C>,
+    tx: &Tx,
+    rate_limit: Option<&RateLimit>,
+    retry_delay: Duration,
+    cancellation: Option<&CancellationToken>,
+) -> RetryResult<RetrySuccess<ProposeTxResponse>> {
+    let idempotency_key = IdempotencyKey::random();
+    let mut rate_limited_retries = 0;
+    loop {
+        let result = conn.with_peer_context(
+            "propose_tx_with_idempotency_key",
+            conn._with_permit(|| {
+                _retry_with_stats(
+                    conn.retry_budget(),
+                    conn.cancellation(),
+                    RetryPolicy::default(),
+                    || {
+                        // Every attempt, retries included, takes its own slot
+                        // under the rate limit.
+                        if let Some(rate_limit) = rate_limit {
+                            rate_limit.wait();
+                        }
+                        match conn.metered("propose_tx_with_idempotency_key", |inner| {
+                            inner.propose_tx_with_idempotency_key(tx, &idempotency_key)
+                        }) {
+                            Ok(response) => OperationResult::Ok(response),
+                            // Rate limited rejections are resubmitted below,
+                            // after `retry_delay`, rather than straight away.
+                            Err(err) if err.should_retry() && !err.is_rate_limited() => {
+                                OperationResult::Retry(err)
+                            }
+                            Err(err) => OperationResult::Err(err),
+                        }
+                    },
+                )
+            }),
+        );
+        match &result {
+            Err(RetryError::Operation { error, .. })
+                if error.is_rate_limited() && rate_limited_retries < MAX_RATE_LIMITED_RETRIES =>
+            {
+                rate_limited_retries += 1;
+                let cancelled = match cancellation {
+                    Some(token) => token.sleep(retry_delay),
+                    None => {
+                        thread::sleep(retry_delay);
+                        false
+                    }
+                };
+                if cancelled {
+                    return result;
+                }
+            }
+            _ => return result,
+        }
+    }
+}
+
+/// Treat a peer's duplicate-submission error as a benign outcome.
+fn classify_propose_tx(
+    result: RetryResult<RetrySuccess<ProposeTxResponse>>,
+) -> RetryResult<ProposeTxOutcome> {
+    match result {
+        Ok(success) => Ok(ProposeTxOutcome::Accepted(success.value.block_index)),
+        Err(RetryError::Operation { error, .. }) if error.is_already_exists() => {
+            Ok(ProposeTxOutcome::AlreadyInMempool)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Check that every response agrees on the ID at each index, and that at
+/// least `quorum` responses were given.
+fn agreed_block_ids(
+    range_start: BlockIndex,
+    responses: &[Vec<BlockID>],
+    quorum: usize,
+) -> Result<Vec<BlockID>> {
+    if quorum == 0 || responses.len() < quorum {
+        return Err(Error::NoBlockIdQuorum(quorum, responses.len()));
+    }
+
+    let (first, rest) = responses.split_first().expect("responses is not empty");
+    for (offset, block_id) in first.iter().enumerate() {
+        if rest.iter().any(|other| &other[offset] != block_id) {
+            return Err(Error::ForkDetected(range_start + offset as BlockIndex));
+        }
+    }
+    Ok(first.clone())
+}
+
+/// Find the highest height which at least `quorum` of the reported heights
+/// have reached.
+fn highest_quorum_height(
+    heights: &[(ResponderId, Option<BlockIndex>)],
+    quorum: usize,
+) -> Option<BlockIndex> {
+    if quorum == 0 {
+        return None;
+    }
+
+    let mut reported = heights
+        .iter()
+        .filter_map(|(_, height)| *height)
+        .collect::<Vec<_>>();
+    reported.sort_unstable_by(|a, b| b.cmp(a));
+    reported.get(quorum - 1).copied()
+}
+
+/// Find the highest height which peers with a total trust of at least
+/// `threshold` have reached.
+fn highest_trusted_height(
+    heights: &[(ResponderId, Option<BlockIndex>)],
+    trust: &HashMap<ResponderId, f64>,
+    threshold: f64,
+) -> Option<BlockIndex> {
+    // As with a zero quorum, a threshold anything could meet is meaningless.
+    if threshold.is_nan() || threshold <= 0.0 {
+        return None;
+    }
+
+    let mut reported = heights
+        .iter()
+        .filter_map(|(responder_id, height)| {
+            let weight = trust.get(responder_id).copied().unwrap_or(0.0).max(0.0);
+            height.map(|height| (height, weight))
+        })
+        .collect::<Vec<_>>();
+    reported.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+
+    let mut total_weight = 0.0;
+    for (height, weight) in reported {
+        total_weight += weight;
+        if total_weight >= threshold {
+            return Some(height);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        credentials::HardcodedCredentialsProvider,
+        retry_policy::DEFAULT_RETRY_MAX_ATTEMPTS,
+        test_utils::{chain, test_client_uri, MockAttestedConnection},
+        thick::ThickClient,
+        traits::{BlockInfo, RetryableBlockchainConnection, BLOCK_ID_SEARCH_DEPTH},
+    };
+    use grpcio::{EnvBuilder, Error as GrpcError, RpcStatus, RpcStatusCode};
+    use mc_attest_core::Verifier;
+    use mc_common::logger::create_null_logger;
+    use mc_transaction_core::{tx::TxOutMembershipElement, BlockContents, BLOCK_VERSION};
+    use mc_util_uri::ConsensusClientUri;
+    use std::{
+        cmp::Ordering,
+        fmt::{Display as FmtDisplay, Formatter, Result as FmtResult},
+        hash::{Hash, Hasher},
+        str::FromStr,
+        time::{Duration, Instant},
+    };
+
+    /// A connection serving a fixed chain of blocks, which records each range
+    /// it is asked for.
+    #[derive(Clone)]
+    struct MockBlockchainConnection {
+        uri: ConsensusClientUri,
+        blocks: Vec<Block>,
+        requests: Vec<Range<BlockIndex>>,
+        delay: Duration,
+        deadline: Option<Instant>,
+        key_images: Option<Arc<Mutex<HashMap<KeyImage, BlockIndex>>>>,
+        submissions: Arc<Mutex<MockSubmissions>>,
+    }
+
+    /// The transactions submitted to a `MockBlockchainConnection`, which
+    /// rejects submissions closer together than `min_gap`, and the first
+    /// `forced_rejections` of them, as rate limited.
+    #[derive(Default)]
+    struct MockSubmissions {
+        accepted: Vec<Instant>,
+        rejected: usize,
+        min_gap: Duration,
+        forced_rejections: usize,
+    }
+
+    impl MockBlockchainConnection {
+        fn new(uri: &str, blocks: Vec<Block>) -> Self {
+            Self {
+                uri: ConsensusClientUri::from_str(uri).unwrap(),
+                blocks,
+                requests: Vec::new(),
+                delay: Duration::default(),
+                deadline: None,
+                key_images: None,
+                submissions: Default::default(),
+            }
+        }
+
+        fn with_rate_limit(self, min_gap: Duration, forced_rejections: usize) -> Self {
+            {
+                let mut submissions = self.submissions.lock().unwrap();
+                submissions.min_gap = min_gap;
+                submissions.forced_rejections = forced_rejections;
+            }
+            self
+        }
+
+        fn with_delay(mut self, delay: Duration) -> Self {
+            self.delay = delay;
+            self
+        }
+
+        fn with_key_images(
+            mut self,
+            key_images: Arc<Mutex<HashMap<KeyImage, BlockIndex>>>,
+        ) -> Self {
+            self.key_images = Some(key_images);
+            self
+        }
+
+        /// Wait out the response delay, failing if it would run past the
+        /// call's deadline.
+        fn respond(&self) -> Result<()> {
+            if let Some(deadline) = self.deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining < self.delay {
+                    thread::sleep(remaining);
+                    return Err(Error::Grpc(GrpcError::RpcFailure(RpcStatus::new(
+                        RpcStatusCode::DEADLINE_EXCEEDED,
+                        None,
+                    ))));
+                }
+            }
+            thread::sleep(self.delay);
+            Ok(())
+        }
+    }
+
+    impl FmtDisplay for MockBlockchainConnection {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "{}", self.uri)
+        }
+    }
+
+    impl Eq for MockBlockchainConnection {}
+
+    impl Hash for MockBlockchainConnection {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.uri.hash(state);
+        }
+    }
+
+    impl Ord for MockBlockchainConnection {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.uri.cmp(&other.uri)
+        }
+    }
+
+    impl PartialEq for MockBlockchainConnection {
+        fn eq(&self, other: &Self) -> bool {
+            self.uri == other.uri
+        }
+    }
+
+    impl PartialOrd for MockBlockchainConnection {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Connection for MockBlockchainConnection {
+        type Uri = ConsensusClientUri;
+
+        fn uri(&self) -> Self::Uri {
+            self.uri.clone()
+        }
+
+        fn ping(&mut self) -> Result<Duration> {
+            thread::sleep(self.delay);
+            Ok(self.delay)
+        }
+
+        fn set_deadline(&mut self, deadline: Option<Instant>) {
+            self.deadline = deadline;
+        }
+    }
+
+    impl BlockchainConnection for MockBlockchainConnection {
+        fn fetch_blocks(&mut self, range: Range<BlockIndex>) -> Result<Vec<Block>> {
+            self.requests.push(range.clone());
+            self.respond()?;
+            Ok(self
+                .blocks
+                .iter()
+                .filter(|block| range.contains(&block.index))
+                .cloned()
+                .collect())
+        }
+
+        fn fetch_block_ids(&mut self, range: Range<BlockIndex>) -> Result<Vec<BlockID>> {
+            Ok(self
+                .fetch_blocks(range)?
+                .into_iter()
+                .map(|block| block.id)
+                .collect())
+        }
+
+        fn fetch_block_height(&mut self) -> Result<BlockIndex> {
+            self.blocks
+                .last()
+                .map(|block| block.index)
+                .ok_or(Error::NotFound)
+        }
+
+        fn fetch_block_info(&mut self) -> Result<BlockInfo> {
+            Ok(BlockInfo {
+                block_index: self.fetch_block_height()?,
+                minimum_fee: 0,
+            })
+        }
+
+        fn contains_key_image(&mut self, key_image: &KeyImage) -> Result<Option<BlockIndex>> {
+            self.respond()?;
+            match &self.key_images {
+                Some(key_images) => Ok(key_images.lock().unwrap().get(key_image).cloned()),
+                None => Err(Error::UnsupportedByPeer("contains_key_image".to_string())),
+            }
+        }
+    }
+
+    impl UserTxConnection for MockBlockchainConnection {
+        fn propose_tx(&mut self, _tx: &Tx) -> Result<ProposeTxResponse> {
+            let mut submissions = self.submissions.lock().unwrap();
+            let now = Instant::now();
+            let too_soon = submissions
+                .accepted
+                .last()
+                .map_or(false, |last| now - *last < submissions.min_gap);
+            if too_soon || submissions.forced_rejections > 0 {
+                submissions.forced_rejections = submissions.forced_rejections.saturating_sub(1);
+                submissions.rejected += 1;
+                return Err(Error::Grpc(GrpcError::RpcFailure(RpcStatus::new(
+                    RpcStatusCode::RESOURCE_EXHAUSTED,
+                    None,
+                ))));
+            }
+            submissions.accepted.push(now);
+            Ok(ProposeTxResponse {
+                block_index: self.blocks.len() as BlockIndex,
+                tx_hash: Default::default(),
+            })
+        }
+    }
+
+    fn thick_client(uri: &str) -> ThickClient<HardcodedCredentialsProvider> {
+        ThickClient::new(
+            ConsensusClientUri::from_str(uri).unwrap(),
+            Verifier::default(),
+            Arc::new(EnvBuilder::new().build()),
+            HardcodedCredentialsProvider::default(),
+            create_null_logger(),
+        )
+        .unwrap()
+    }
+
+    fn heights(values: &[Option<BlockIndex>]) -> Vec<(ResponderId, Option<BlockIndex>)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, height)| {
+                let responder_id = ResponderId::from_str(&format!("node{}.test.com:443", i))
+                    .expect("Could not create responder id");
+                (responder_id, *height)
+            })
+            .collect()
+    }
+
+    #[test]
+    // The result should be the highest height reached by a quorum of peers.
+    fn highest_quorum_height_ignores_outliers() {
+        let heights = heights(&[Some(10), Some(12), Some(1_000_000), Some(11), None]);
+
+        assert_eq!(highest_quorum_height(&heights, 1), Some(1_000_000));
+        assert_eq!(highest_quorum_height(&heights, 2), Some(12));
+        assert_eq!(highest_quorum_height(&heights, 3), Some(11));
+        assert_eq!(highest_quorum_height(&heights, 4), Some(10));
+    }
+
+    #[test]
+    // A high-trust minority at the tip should outweigh a low-trust majority
+    // reporting a stale height.
+    fn highest_trusted_height_weighs_peers_by_trust() {
+        let heights = heights(&[Some(10), Some(10), Some(10), Some(20), Some(20), None]);
+        let trust = heights
+            .iter()
+            .enumerate()
+            .map(|(i, (responder_id, _))| (responder_id.clone(), if i < 3 { 1.0 } else { 5.0 }))
+            .collect::<HashMap<_, _>>();
+
+        // A simple count favours the stale majority.
+        assert_eq!(highest_quorum_height(&heights, 3), Some(10));
+
+        assert_eq!(highest_trusted_height(&heights, &trust, 6.0), Some(20));
+        assert_eq!(highest_trusted_height(&heights, &trust, 10.0), Some(20));
+        assert_eq!(highest_trusted_height(&heights, &trust, 13.0), Some(10));
+
+        // The silent peer's trust cannot be counted.
+        assert_eq!(highest_trusted_height(&heights, &trust, 14.0), None);
+        assert_eq!(highest_trusted_height(&heights, &trust, 0.0), None);
+        assert_eq!(
+            highest_trusted_height(&heights, &HashMap::default(), 1.0),
+            None
+        );
+    }
+
+    fn block_id(byte: u8) -> BlockID {
+        BlockID([byte; 32])
+    }
+
+    #[test]
+    // Matching responses from a quorum of peers should be accepted.
+    fn agreed_block_ids_accepts_matching_quorum() {
+        let ids = vec![block_id(1), block_id(2)];
+        let responses = vec![ids.clone(), ids.clone(), ids.clone()];
+
+        assert_eq!(agreed_block_ids(5, &responses, 3).unwrap(), ids);
+        match agreed_block_ids(5, &responses, 4) {
+            Err(Error::NoBlockIdQuorum(4, 3)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // A disagreement should be reported at the first index where it occurs.
+    fn agreed_block_ids_detects_fork() {
+        let responses = vec![
+            vec![block_id(1), block_id(2), block_id(3)],
+            vec![block_id(1), block_id(9), block_id(3)],
+        ];
+
+        match agreed_block_ids(5, &responses, 1) {
+            Err(Error::ForkDetected(6)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // Without enough responding peers there is no quorum height.
+    fn highest_quorum_height_requires_enough_peers() {
+        let heights = heights(&[Some(10), None, Some(12)]);
+
+        assert_eq!(highest_quorum_height(&heights, 3), None);
+        assert_eq!(highest_quorum_height(&heights, 0), None);
+        assert_eq!(highest_quorum_height(&[], 1), None);
+    }
+
+    #[test]
+    // A URI whose scheme only resembles a supported one should be rejected.
+    fn try_new_rejects_unsupported_schemes() {
+        // Consensus client URIs parse any scheme starting with "mc" as secure.
+        let conns = vec![
+            thick_client("mc://node1.test.com/"),
+            thick_client("mcp://node2.test.com/"),
+        ];
+
+        match ConnectionManager::try_new(conns, create_null_logger()) {
+            Err(ConnectionManagerError::UnsupportedSchemes(uris, schemes)) => {
+                assert_eq!(uris, vec!["mcp://node2.test.com/".to_string()]);
+                assert_eq!(schemes, vec!["mc://", "insecure-mc://"]);
+            }
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Unsupported scheme was accepted"),
+        }
+    }
+
+    #[test]
+    // Every URI which cannot name a responder should be reported, rather than
+    // only the first or a panic.
+    fn try_new_reports_invalid_uris() {
+        let conns = vec![
+            thick_client("mc://node1.test.com/"),
+            thick_client("mc://node2.test.com/?responder-id=node2"),
+            thick_client("mc://node3.test.com/?responder-id=node3"),
+        ];
+        let expected: Vec<String> = conns[1..]
+            .iter()
+            .map(|conn| conn.uri().to_string())
+            .collect();
+
+        match ConnectionManager::try_new(conns, create_null_logger()) {
+            Err(ConnectionManagerError::InvalidUris(uris)) => assert_eq!(uris, expected),
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Invalid responder IDs were accepted"),
+        }
+    }
+
+    #[test]
+    // Only connections without TLS should be counted as insecure.
+    fn insecure_connections_are_counted() {
+        let conns = vec![
+            thick_client("mc://node1.test.com/"),
+            thick_client("insecure-mc://node2.test.com/"),
+            thick_client("insecure-mc://node3.test.com/"),
+        ];
+
+        let manager = ConnectionManager::try_new(conns, create_null_logger())
+            .unwrap_or_else(|err| panic!("Could not create connection manager: {}", err));
+        assert_eq!(manager.insecure_connection_count(), 2);
+    }
+
+    #[test]
+    // Warming up should attest every peer once, reporting failures per peer.
+    fn warm_up_reports_each_peer() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockAttestedConnection::new(test_client_uri(1)),
+                MockAttestedConnection::new(
+                    ConsensusClientUri::from_str("mc://refused.test.com/").unwrap(),
+                ),
+            ],
+            create_null_logger(),
+        );
+
+        let results = manager.warm_up();
+        assert_eq!(results.len(), 2);
+        for (responder_id, result) in &results {
+            assert_eq!(
+                result.is_ok(),
+                responder_id.to_string() == "node1.test.com:443"
+            );
+        }
+
+        // Attested peers are not attested again.
+        manager.warm_up();
+        let attest_calls: Vec<_> = manager
+            .conns()
+            .iter()
+            .map(|conn| conn.read().attest_calls)
+            .collect();
+        assert_eq!(attest_calls, vec![1, 2]);
+    }
+
+    #[test]
+    // A peer whose attestation panics should be reported as failed, without
+    // stopping the others from warming up.
+    fn warm_up_reports_panicked_peer() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockAttestedConnection::new(test_client_uri(1)),
+                MockAttestedConnection::new(
+                    ConsensusClientUri::from_str("mc://panics.test.com/").unwrap(),
+                ),
+            ],
+            create_null_logger(),
+        );
+
+        let results = manager.warm_up();
+        assert_eq!(results.len(), 2);
+        for (responder_id, result) in &results {
+            match result {
+                Ok(()) => assert_eq!(responder_id.to_string(), "node1.test.com:443"),
+                Err(Error::Other(_)) => {
+                    assert_eq!(responder_id.to_string(), "panics.test.com:443")
+                }
+                Err(err) => panic!("Unexpected error: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    // A thread panicking while holding the manager's lock should not make the
+    // manager unusable.
+    fn manager_survives_poisoned_lock() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3)),
+            ],
+            create_null_logger(),
+        );
+
+        let poisoner = manager.clone();
+        let result = thread::spawn(move || {
+            let _guard = poisoner.write();
+            panic!("poisoning the lock");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(manager.inner.is_poisoned());
+
+        assert_eq!(manager.len(), 2);
+        let node2 = manager.responder_ids()[1].clone();
+        assert!(manager.remove_conn(&node2).is_some());
+        assert_eq!(manager.len(), 1);
+        assert_eq!(manager.fetch_blocks_cached(0..3, 1).unwrap(), chain(3));
+    }
+
+    #[test]
+    // Diagnostics should report each peer's last outcome without waiting on a
+    // connection which is busy with a call.
+    fn diagnostics_reports_each_peer() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockAttestedConnection::new(test_client_uri(1)),
+                MockAttestedConnection::new(
+                    ConsensusClientUri::from_str("mc://refused.test.com/").unwrap(),
+                ),
+            ],
+            create_null_logger(),
+        );
+        let diagnostics = manager.diagnostics();
+        assert_eq!(diagnostics.peers.len(), 2);
+        assert!(diagnostics.peers.iter().all(|peer| peer.healthy.is_none()));
+
+        for conn in manager.conns() {
+            let _ = conn.metered("attest", |inner| inner.attest());
+        }
+        let diagnostics = manager.diagnostics();
+        let node1 = &diagnostics.peers[0];
+        assert_eq!(node1.uri, "mc://node1.test.com:443/");
+        assert_eq!(node1.healthy, Some(true));
+        assert_eq!(node1.attested, Some(true));
+        assert!(node1.average_latency.is_some());
+        assert!(node1.last_error.is_none());
+
+        let refused = &diagnostics.peers[1];
+        assert_eq!(refused.healthy, Some(false));
+        assert_eq!(refused.attested, Some(false));
+        let last_error = refused.last_error.as_ref().unwrap();
+        assert_eq!(last_error.call, "attest");
+        assert_eq!(last_error.error, "Attestation was refused");
+        assert!(diagnostics
+            .to_string()
+            .contains("last error: attest failed"));
+
+        // A connection in use is skipped rather than waited for.
+        let conns = manager.conns();
+        let _busy = conns[0].write();
+        assert_eq!(manager.diagnostics().peers[0].attested, None);
+    }
+
+    #[test]
+    // Connections should be listed by responder ID, whatever order they were
+    // given or added in.
+    fn conns_are_sorted_by_responder_id() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node3.test.com/", Vec::new()),
+                MockBlockchainConnection::new("mc://node1.test.com/", Vec::new()),
+            ],
+            create_null_logger(),
+        );
+        manager
+            .add_conn(MockBlockchainConnection::new(
+                "mc://node2.test.com/",
+                Vec::new(),
+            ))
+            .unwrap();
+
+        let expected = vec![
+            "node1.test.com:443",
+            "node2.test.com:443",
+            "node3.test.com:443",
+        ];
+        let responder_ids: Vec<_> = manager
+            .responder_ids()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(responder_ids, expected);
+        let conn_ids: Vec<_> = manager
+            .conns()
+            .iter()
+            .map(|conn| conn.uri().responder_id().unwrap().to_string())
+            .collect();
+        assert_eq!(conn_ids, expected);
+    }
+
+    #[test]
+    // Healthy connections should be yielded in order, looking each one up only
+    // when it is reached.
+    fn iter_healthy_skips_unhealthy_peers() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", Vec::new()),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node3.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node4.test.com/", chain(3)),
+            ],
+            create_null_logger(),
+        );
+        let responder_id = |conn: SyncConnection<MockBlockchainConnection>| {
+            conn.uri().responder_id().unwrap().to_string()
+        };
+
+        let mut healthy = manager.iter_healthy();
+        assert_eq!(
+            healthy.next().map(responder_id),
+            Some("node2.test.com:443".to_string())
+        );
+
+        // A peer removed before the iterator reaches it is skipped.
+        manager.remove_conn(&ResponderId::from_str("node3.test.com:443").unwrap());
+        assert_eq!(
+            healthy.map(responder_id).collect::<Vec<_>>(),
+            vec!["node4.test.com:443".to_string()]
+        );
+    }
+
+    #[test]
+    // A peer with no permit free should not be probed, and should count as
+    // unhealthy.
+    fn health_probe_skips_busy_peers() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3)),
+            ],
+            create_null_logger(),
+        )
+        .with_concurrency_limit(1);
+        let busy_id = ResponderId::from_str("node1.test.com:443").unwrap();
+        let busy = manager.conn(&busy_id).unwrap();
+        let _permit = busy.try_acquire_permit().unwrap();
+
+        let healthy = manager
+            .iter_healthy()
+            .map(|conn| conn.uri().responder_id().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(healthy, vec!["node2.test.com:443".to_string()]);
+
+        let health = manager.health_check();
+        assert_eq!(health.get(&busy_id), Some(&false));
+        assert_eq!(
+            health.get(&ResponderId::from_str("node2.test.com:443").unwrap()),
+            Some(&true)
+        );
+    }
+
+    #[test]
+    // Too few responding peers should yield a flagged best-effort answer, while
+    // the strict variants still fail.
+    fn best_effort_queries_degrade_below_quorum() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(5)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(8)),
+                MockBlockchainConnection::new("mc://node3.test.com/", chain(10)),
+            ],
+            create_null_logger(),
+        );
+
+        assert_eq!(
+            manager.consensus_block_height_best_effort(2).unwrap(),
+            QuorumResult::Quorum(7)
+        );
+        match manager.consensus_block_height_best_effort(4).unwrap() {
+            QuorumResult::BelowQuorum {
+                best_effort,
+                responses,
+            } => {
+                assert_eq!(best_effort, 4);
+                assert_eq!(responses.len(), 3);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match manager.consensus_block_height(4) {
+            Err(Error::NoBlockHeightQuorum(4, _)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let block_ids: Vec<BlockID> = chain(4).into_iter().map(|block| block.id).collect();
+        let result = manager.verify_block_ids_best_effort(0..4, 4).unwrap();
+        assert!(!result.is_quorum());
+        assert_eq!(result.into_value(), block_ids);
+        match manager.verify_block_ids(0..4, 4) {
+            Err(Error::NoBlockIdQuorum(4, 3)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // With no responses at all there is no answer to give.
+        match manager.verify_block_ids_best_effort(20..24, 1) {
+            Err(Error::NoBlockIdQuorum(1, 0)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // Resuming from a known block should return everything after it, and a
+    // block the peer does not have should be reported as a fork.
+    fn fetch_blocks_since_resumes_after_known_block() {
+        let blocks = chain(5);
+        let mut conn = MockBlockchainConnection::new("mc://node1.test.com/", blocks.clone());
+
+        assert_eq!(conn.fetch_blocks_since(&blocks[2].id).unwrap(), blocks[3..]);
+        assert_eq!(conn.fetch_blocks_since(&blocks[4].id).unwrap(), Vec::new());
+
+        let other_chain = chain(6);
+        match conn.fetch_blocks_since(&other_chain[5].id) {
+            Err(Error::ForkFromKnownBlock(id)) if id == other_chain[5].id => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // Looking up a block by ID should only search the most recent blocks, and
+    // a known block beyond them should not be reported as a fork.
+    fn fetch_block_by_id_searches_recent_blocks() {
+        let blocks = chain(BLOCK_ID_SEARCH_DEPTH as usize + 2);
+        let mut conn = MockBlockchainConnection::new("mc://node1.test.com/", blocks.clone());
+
+        assert_eq!(conn.fetch_block_by_id(&blocks[2].id).unwrap(), blocks[2]);
+        match conn.fetch_block_by_id(&blocks[1].id) {
+            Err(Error::BlockIdSearchLimit(id, BLOCK_ID_SEARCH_DEPTH)) if id == blocks[1].id => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match conn.fetch_blocks_since(&blocks[1].id) {
+            Err(Error::BlockIdSearchLimit(..)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(conn.requests.iter().all(|range| range.start >= 2));
+    }
+
+    #[test]
+    // The root as of a block should be read from the block after it, and a
+    // block without a recorded root, or beyond the ledger, should be an error.
+    fn fetch_tx_out_merkle_root_reads_next_block() {
+        let mut blocks = chain(2);
+        let root_element = TxOutMembershipElement::new(Default::default(), [5u8; 32]);
+        let block = Block::new_with_parent(
+            BLOCK_VERSION,
+            blocks.last().unwrap(),
+            &root_element,
+            &BlockContents::default(),
+        );
+        blocks.push(block);
+        let mut conn = MockBlockchainConnection::new("mc://node1.test.com/", blocks);
+
+        assert_eq!(conn.fetch_tx_out_merkle_root(1).unwrap(), [5u8; 32]);
+        match conn.fetch_tx_out_merkle_root(0) {
+            Err(Error::UnsupportedByPeer(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match conn.fetch_tx_out_merkle_root(2) {
+            Err(Error::BeyondLedger(3)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // A large fetch should report progress after each chunk, ending with the
+    // last block of the range.
+    fn fetch_blocks_with_progress_reports_each_chunk() {
+        let blocks = chain(2_500);
+        let conn = SyncConnection::new(
+            MockBlockchainConnection::new("mc://node1.test.com/", blocks.clone()),
+            create_null_logger(),
+        );
+
+        let mut reported = Vec::new();
+        let result = conn
+            .fetch_blocks_with_progress(0..2_500, RetryPolicy::default(), |index| {
+                reported.push(index)
+            })
+            .unwrap();
+        assert_eq!(result.value, blocks);
+        assert_eq!(result.attempts, 3);
+        assert_eq!(reported, vec![999, 1_999, 2_499]);
+        assert_eq!(
+            conn.read().requests,
+            vec![0..1_000, 1_000..2_000, 2_000..2_500]
+        );
+
+        // Asking beyond the end of the ledger fails rather than stopping short,
+        // counting the attempts and time spent on the blocks received first.
+        let conn = SyncConnection::new(
+            MockBlockchainConnection::new("mc://node1.test.com/", blocks)
+                .with_delay(Duration::from_millis(10)),
+            create_null_logger(),
+        );
+        match conn.fetch_blocks_with_progress(2_000..3_000, RetryPolicy::default(), |_| {}) {
+            Err(RetryError::Operation {
+                error: Error::BeyondLedger(2_500),
+                total_delay,
+                tries: 2,
+            }) => assert!(total_delay >= Duration::from_millis(20)),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // Gaps should be found before and between the local ranges, whatever their
+    // order or overlap.
+    fn find_ledger_gaps_finds_missing_ranges() {
+        assert_eq!(find_ledger_gaps(&[]), Vec::<Range<BlockIndex>>::new());
+        assert_eq!(
+            find_ledger_gaps(&[0..100, 100..150, 120..200]),
+            Vec::<Range<BlockIndex>>::new()
+        );
+        assert_eq!(find_ledger_gaps(&[150..200, 0..100]), vec![100..150]);
+        assert_eq!(
+            find_ledger_gaps(&[300..400, 10..100, 50..120, 150..200, 160..160]),
+            vec![0..10, 120..150, 200..300]
+        );
+    }
+
+    #[test]
+    // Each gap should be fetched and returned with its blocks.
+    fn fetch_ledger_gaps_fills_missing_blocks() {
+        let blocks = chain(10);
+        let manager = ConnectionManager::new(
+            vec![MockBlockchainConnection::new(
+                "mc://node1.test.com/",
+                blocks.clone(),
+            )],
+            create_null_logger(),
+        );
+
+        let gaps = manager.fetch_ledger_gaps(&[0..3, 5..6, 8..10], 1).unwrap();
+        assert_eq!(
+            gaps,
+            vec![(3..5, blocks[3..5].to_vec()), (6..8, blocks[6..8].to_vec())]
+        );
+        assert!(manager.fetch_ledger_gaps(&[0..10], 1).unwrap().is_empty());
+    }
+
+    #[test]
+    // The first peer to return the whole range should win without waiting for
+    // slower peers, and peers which cannot serve it should not.
+    fn fetch_blocks_fastest_returns_first_complete_response() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(10))
+                    .with_delay(Duration::from_secs(2)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node3.test.com/", chain(10))
+                    .with_delay(Duration::from_millis(50)),
+            ],
+            create_null_logger(),
+        );
+
+        let start = Instant::now();
+        assert_eq!(manager.fetch_blocks_fastest(2..7).unwrap(), chain(10)[2..7]);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    // Unmeasured connections should be tried first, then the connection with
+    // the lowest measured latency preferred, counting failures against it.
+    fn fastest_connection_prefers_lowest_latency() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(3))
+                    .with_delay(Duration::from_millis(50)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node3.test.com/", chain(3))
+                    .with_delay(Duration::from_millis(10)),
+            ],
+            create_null_logger(),
+        );
+        let uri = |uri: &str| ConsensusClientUri::from_str(uri).unwrap();
+        assert_eq!(
+            manager.fastest_connection().unwrap().uri(),
+            uri("mc://node1.test.com/")
+        );
+
+        let conns = manager.conns();
+        for conn in &[&conns[0], &conns[2]] {
+            conn.metered("fetch_blocks", |inner| inner.fetch_blocks(0..1))
+                .unwrap();
+        }
+        assert!(conns[0].average_latency() > conns[2].average_latency());
+        assert_eq!(
+            manager.fastest_connection().unwrap().uri(),
+            uri("mc://node2.test.com/")
+        );
+
+        // A peer which fails quickly should not seem fast.
+        conns[1]
+            .metered("fetch_blocks", |_inner| -> Result<()> {
+                Err(Error::NotFound)
+            })
+            .unwrap_err();
+        assert!(conns[1].average_latency() > conns[0].average_latency());
+        assert_eq!(
+            manager.fastest_connection().unwrap().uri(),
+            uri("mc://node3.test.com/")
+        );
+    }
+
+    #[test]
+    // Pinging should report each peer's round trip and seed its latency
+    // average.
+    fn ping_all_seeds_latency() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(3))
+                    .with_delay(Duration::from_millis(50)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3))
+                    .with_delay(Duration::from_millis(10)),
+            ],
+            create_null_logger(),
+        );
+
+        let pings = manager.ping_all();
+        assert_eq!(pings.len(), 2);
+        assert!(pings[0].1.unwrap() >= Duration::from_millis(50));
+        assert!(pings[1].1.unwrap() >= Duration::from_millis(10));
+        assert_eq!(
+            manager.fastest_connection().unwrap().uri(),
+            ConsensusClientUri::from_str("mc://node2.test.com/").unwrap()
+        );
+    }
+
+    #[test]
+    // Peers should be polled until one has spent every key image, skipping
+    // those which cannot look them up.
+    fn await_tx_confirmation_polls_until_committed() {
+        let key_images = Arc::new(Mutex::new(HashMap::default()));
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3))
+                    .with_key_images(key_images.clone()),
+            ],
+            create_null_logger(),
+        )
+        .with_confirmation_poll_interval(Duration::from_millis(10));
+
+        let committer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut key_images = key_images.lock().unwrap();
+            key_images.insert(KeyImage::from(1), 7);
+            key_images.insert(KeyImage::from(2), 6);
+        });
+        let result = manager.await_tx_confirmation(
+            &[KeyImage::from(1), KeyImage::from(2)],
+            Duration::from_secs(10),
+        );
+        committer.join().unwrap();
+        assert_eq!(result.unwrap(), 7);
+
+        // A key image which is never spent times out.
+        match manager.await_tx_confirmation(&[KeyImage::from(3)], Duration::from_millis(50)) {
+            Err(Error::Timeout(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // Without a peer serving lookups, there is nothing to wait for.
+        let manager = ConnectionManager::new(
+            vec![MockBlockchainConnection::new(
+                "mc://node1.test.com/",
+                chain(3),
+            )],
+            create_null_logger(),
+        );
+        match manager.await_tx_confirmation(&[KeyImage::from(1)], Duration::from_secs(10)) {
+            Err(Error::UnsupportedByPeer(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // A peer which does not respond to a lookup should not hold up the wait
+    // past its timeout.
+    fn await_tx_confirmation_bounds_each_lookup() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(3))
+                    .with_key_images(Default::default())
+                    .with_delay(Duration::from_secs(5)),
+            ],
+            create_null_logger(),
+        )
+        .with_confirmation_poll_interval(Duration::from_millis(10));
+
+        let start = Instant::now();
+        match manager.await_tx_confirmation(&[KeyImage::from(1)], Duration::from_millis(100)) {
+            Err(Error::Timeout(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    // If no peer can serve the range, each peer's failure should be reported.
+    fn fetch_blocks_fastest_aggregates_failures() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", Vec::new()),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3)),
+            ],
+            create_null_logger(),
+        );
+
+        match manager.fetch_blocks_fastest(0..5) {
+            Err(Error::AllPeersFailed(failures)) => {
+                assert_eq!(failures.len(), 2);
+                assert!(failures
+                    .iter()
+                    .all(|(_, err)| matches!(err, Error::BeyondLedger(_))));
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // Only a few peers should be raced, preferring those whose last call
+    // succeeded.
+    fn fetch_blocks_fastest_races_a_few_healthy_peers() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node3.test.com/", chain(3)),
+                MockBlockchainConnection::new("mc://node4.test.com/", chain(10)),
+                MockBlockchainConnection::new("mc://node5.test.com/", chain(10)),
+            ],
+            create_null_logger(),
+        );
+
+        // With nothing known about the peers, the first three are raced, and
+        // none of them has the range.
+        match manager.fetch_blocks_fastest(0..5) {
+            Err(Error::AllPeersFailed(failures)) => {
+                assert_eq!(
+                    failures
+                        .iter()
+                        .map(|(responder_id, _)| responder_id.to_string())
+                        .collect::<Vec<_>>(),
+                    vec![
+                        "node1.test.com:443",
+                        "node2.test.com:443",
+                        "node3.test.com:443"
+                    ]
+                );
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // The peers which failed are now raced last.
+        assert_eq!(manager.fetch_blocks_fastest(0..5).unwrap(), chain(10)[0..5]);
+    }
+
+    #[test]
+    // A peer which does not respond should be given up on once the deadline
+    // passes.
+    fn fetch_blocks_fastest_applies_deadline() {
+        let manager = ConnectionManager::new(
+            vec![
+                MockBlockchainConnection::new("mc://node1.test.com/", chain(10))
+                    .with_delay(Duration::from_secs(5)),
+                MockBlockchainConnection::new("mc://node2.test.com/", chain(10))
+                    .with_delay(Duration::from_secs(5)),
+            ],
+            create_null_logger(),
+        )
+        .with_fastest_fetch_timeout(Duration::from_millis(100));
+
+        let start = Instant::now();
+        match manager.fetch_blocks_fastest(0..5) {
+            Err(Error::AllPeersFailed(failures)) => {
+                assert_eq!(failures.len(), 2);
+                assert!(failures.iter().all(|(_, err)| err.is_deadline_exceeded()));
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    // A range which ends before it starts should be rejected, not underflow.
+    fn fetch_blocks_fastest_rejects_inverted_ranges() {
+        let manager = ConnectionManager::new(
+            vec![MockBlockchainConnection::new(
+                "mc://node1.test.com/",
+                chain(10),
+            )],
+            create_null_logger(),
+        );
+
+        #[allow(clippy::reversed_empty_ranges)]
+        match manager.fetch_blocks_fastest(5..2) {
+            Err(Error::Other(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        #[allow(clippy::reversed_empty_ranges)]
+        match check_blocks_cover(&(5..2), &chain(10)) {
+            Err(Error::Other(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    fn rpc_failure(status: RpcStatusCode) -> RetryResult<RetrySuccess<ProposeTxResponse>> {
+        Err(RetryError::Operation {
+            error: Error::Grpc(GrpcError::RpcFailure(RpcStatus::new(status, None))),
+            total_delay: Duration::default(),
+            tries: 1,
+        })
+    }
+
+    #[test]
+    // Submissions to each peer should be spaced out to respect the rate limit,
+    // and a peer which rejects submissions as rate limited should be
+    // submitted to again rather than reported as failed.
+    fn propose_tx_all_respects_rate_limits() {
+        let conns = vec![
+            MockBlockchainConnection::new("mc://node1.test.com/", chain(3))
+                .with_rate_limit(Duration::from_millis(80), 0),
+            MockBlockchainConnection::new("mc://node2.test.com/", chain(3))
+                .with_rate_limit(Duration::from_millis(80), 0),
+        ];
+        let submissions = conns
+            .iter()
+            .map(|conn| conn.submissions.clone())
+            .collect::<Vec<_>>();
+        let manager = ConnectionManager::new(conns, create_null_logger())
+            .with_propose_rate_limit(Duration::from_millis(100));
+
+        for _ in 0..3 {
+            let outcomes = manager.propose_tx_all(&Tx::default());
+            assert_eq!(outcomes.len(), 2);
+            for outcome in outcomes.values() {
+                assert_eq!(outcome.as_ref().unwrap(), &ProposeTxOutcome::Accepted(3));
+            }
+        }
+        for submissions in &submissions {
+            let submissions = submissions.lock().unwrap();
+            assert_eq!(submissions.accepted.len(), 3);
+            assert_eq!(submissions.rejected, 0);
+        }
+
+        // More rejections than `RetryPolicy::default()` makes attempts. Each
+        // rejection should be followed by the rate limited retry delay, not
+        // resubmitted straight away.
+        let conn = MockBlockchainConnection::new("mc://node1.test.com/", chain(3))
+            .with_rate_limit(Duration::default(), DEFAULT_RETRY_MAX_ATTEMPTS + 1);
+        let submissions = conn.submissions.clone();
+        let manager = ConnectionManager::new(vec![conn], create_null_logger())
+            .with_rate_limited_retry_delay(Duration::from_millis(20));
+
+        let start = Instant::now();
+        let outcomes = manager.propose_tx_all(&Tx::default());
+        assert!(
+            start.elapsed() >= Duration::from_millis(20) * (DEFAULT_RETRY_MAX_ATTEMPTS + 1) as u32
+        );
+        assert_eq!(
+            outcomes.values().next().unwrap().as_ref().unwrap(),
+            &ProposeTxOutcome::Accepted(3)
+        );
+        assert_eq!(
+            submissions.lock().unwrap().rejected,
+            DEFAULT_RETRY_MAX_ATTEMPTS + 1
+        );
+    }
+
+    #[test]
+    // A duplicate submission should be benign, while other failures remain
+    // errors.
+    fn classify_propose_tx_treats_duplicates_as_benign() {
+        let accepted = Ok(RetrySuccess {
+            value: ProposeTxResponse {
+                block_index: 7,
+                tx_hash: Default::default(),
+            },
+            attempts: 1,
+            elapsed: Duration::default(),
+        });
+        assert_eq!(
+            classify_propose_tx(accepted).unwrap(),
+            ProposeTxOutcome::Accepted(7)
+        );
+
+        assert_eq!(
+            classify_propose_tx(rpc_failure(RpcStatusCode::ALREADY_EXISTS)).unwrap(),
+            ProposeTxOutcome::AlreadyInMempool
+        );
+
+        match classify_propose_tx(rpc_failure(RpcStatusCode::INVALID_ARGUMENT)) {
+            Err(RetryError::Operation { error, .. }) => assert!(!error.is_already_exists()),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
 }
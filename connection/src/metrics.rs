@@ -0,0 +1,34 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Hooks for observing the calls made over a connection.
+
+use std::time::Duration;
+
+/// A sink for connection-level metrics, such as a Prometheus registry.
+///
+/// `SyncConnection` reports each attempt of its (retried) methods, and
+/// `ThickClient` reports each gRPC call it makes along with every successful
+/// attestation. Each hook receives the connection's display name, and calls
+/// are named after the method or RPC they represent.
+///
+/// Every hook defaults to doing nothing, so implementors only need to provide
+/// the ones they are interested in.
+pub trait ConnectionMetrics: Send + Sync {
+    /// A call is about to be made.
+    fn call_attempted(&self, _connection: &str, _call: &str) {}
+
+    /// A call succeeded after `elapsed`.
+    fn call_succeeded(&self, _connection: &str, _call: &str, _elapsed: Duration) {}
+
+    /// A call failed after `elapsed`.
+    fn call_failed(&self, _connection: &str, _call: &str, _elapsed: Duration) {}
+
+    /// An attested session was established.
+    fn attested(&self, _connection: &str) {}
+}
+
+/// A metrics sink which discards everything it is given.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopConnectionMetrics;
+
+impl ConnectionMetrics for NoopConnectionMetrics {}
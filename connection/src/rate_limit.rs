@@ -0,0 +1,83 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A limit on how often requests are sent to a peer, so that a client
+//! submitting to many peers does not trip any one peer's own rate limit.
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Spaces out requests to a single peer, so that they start at least
+/// `interval` apart.
+///
+/// Each request reserves the next free slot, so callers sharing a limit are
+/// served in the order they arrive. Clones share the same slots.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    next_slot: Arc<Mutex<Option<Instant>>>,
+    interval: Duration,
+}
+
+impl RateLimit {
+    /// Create a limit allowing one request every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            next_slot: Arc::new(Mutex::new(None)),
+            interval,
+        }
+    }
+
+    /// The minimum time between the starts of two requests.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Reserve the next free slot, returning how long the caller must wait
+    /// before starting its request.
+    pub fn reserve(&self) -> Duration {
+        let now = Instant::now();
+        let mut next_slot = self.next_slot.lock().expect("RateLimit lock poisoned");
+        let slot = next_slot.map_or(now, |next_slot| next_slot.max(now));
+        *next_slot = Some(slot + self.interval);
+        slot - now
+    }
+
+    /// Reserve the next free slot, and sleep until it starts.
+    pub fn wait(&self) {
+        let delay = self.reserve();
+        if delay > Duration::default() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Each reservation should start one interval after the last, and clones
+    // should draw from the same slots.
+    fn reservations_are_spaced_by_interval() {
+        let limit = RateLimit::new(Duration::from_secs(10));
+        let clone = limit.clone();
+
+        assert_eq!(limit.reserve(), Duration::default());
+        let second = clone.reserve();
+        assert!(second > Duration::from_secs(9) && second <= Duration::from_secs(10));
+        let third = limit.reserve();
+        assert!(third > Duration::from_secs(19) && third <= Duration::from_secs(20));
+    }
+
+    #[test]
+    // A limit left idle for longer than its interval should not delay the
+    // next request.
+    fn idle_limit_does_not_delay() {
+        let limit = RateLimit::new(Duration::from_millis(1));
+        limit.wait();
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(limit.reserve(), Duration::default());
+    }
+}
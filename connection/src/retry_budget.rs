@@ -0,0 +1,112 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A retry budget shared between connections, limiting the total rate of
+//! retries so that an outage does not multiply the load on the network.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// A token bucket from which every retry of a retryable call takes a token.
+///
+/// First attempts are never limited. Once the bucket is empty, calls stop
+/// retrying and fail with their most recent error. Clones share the same
+/// bucket.
+#[derive(Clone)]
+pub struct RetryBudget {
+    bucket: Arc<Mutex<TokenBucket>>,
+    burst: u32,
+    refill_per_sec: f64,
+}
+
+impl RetryBudget {
+    /// Create a full budget holding at most `burst` tokens, which regains
+    /// `refill_per_sec` tokens each second.
+    pub fn new(burst: u32, refill_per_sec: f64) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(TokenBucket {
+                tokens: burst as f64,
+                last_refill: Instant::now(),
+            })),
+            burst,
+            refill_per_sec,
+        }
+    }
+
+    /// The maximum number of tokens the budget holds.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// The number of tokens regained each second.
+    pub fn refill_per_sec(&self) -> f64 {
+        self.refill_per_sec
+    }
+
+    /// Take a token for one retry, returning false if none are available.
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let mut bucket = self.bucket.lock().expect("RetryBudget lock poisoned");
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.refill_per_sec).min(self.burst as f64);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    // A budget should allow `burst` retries, then refuse until it refills.
+    fn budget_exhausts_and_refills() {
+        let budget = RetryBudget::new(3, 2.0);
+        let start = Instant::now();
+
+        assert!(budget.try_acquire_at(start));
+        assert!(budget.try_acquire_at(start));
+        assert!(budget.try_acquire_at(start));
+        assert!(!budget.try_acquire_at(start));
+
+        // Half a second regains one token at two tokens per second.
+        let later = start + Duration::from_millis(500);
+        assert!(budget.try_acquire_at(later));
+        assert!(!budget.try_acquire_at(later));
+
+        // Refilling never exceeds the burst size.
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(budget.try_acquire_at(much_later));
+        }
+        assert!(!budget.try_acquire_at(much_later));
+    }
+
+    #[test]
+    // Clones should draw from the same bucket.
+    fn clones_share_tokens() {
+        let budget = RetryBudget::new(1, 0.0);
+        let clone = budget.clone();
+
+        assert!(clone.try_acquire());
+        assert!(!budget.try_acquire());
+    }
+}
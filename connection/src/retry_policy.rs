@@ -0,0 +1,179 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Standard retry schedules for the retryable connection traits.
+
+use retry::delay::jitter;
+use std::{iter, time::Duration};
+
+/// Default number of attempts made by `RetryPolicy::default()`.
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: usize = 5;
+
+/// Default initial delay used by `RetryPolicy::default()`.
+pub const DEFAULT_RETRY_INITIAL_DELAY: Duration = Duration::from_millis(100);
+
+/// Default upper bound on a single exponential backoff delay.
+pub const DEFAULT_RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// How the delay between attempts changes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Schedule {
+    /// Wait the same amount of time before every retry.
+    Fixed(Duration),
+    /// Double the delay after every retry, up to the given maximum.
+    Exponential { initial: Duration, max: Duration },
+}
+
+/// A schedule of delays between attempts of a retryable call.
+///
+/// `RetryPolicy` implements `IntoIterator<Item = Duration>`, so it can be
+/// passed wherever the retryable traits take a `retry_iterator`:
+///
+/// ```ignore
+/// conn.fetch_blocks(range, RetryPolicy::exponential_backoff(delay, 5).with_jitter())
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    schedule: Schedule,
+    max_attempts: usize,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Make at most `max_attempts` attempts, waiting `delay` between each.
+    pub fn fixed(delay: Duration, max_attempts: usize) -> Self {
+        Self {
+            schedule: Schedule::Fixed(delay),
+            max_attempts,
+            jitter: false,
+        }
+    }
+
+    /// Make at most `max_attempts` attempts, waiting `initial_delay` before
+    /// the first retry and doubling the delay before each subsequent one, up
+    /// to `DEFAULT_RETRY_MAX_DELAY`.
+    pub fn exponential_backoff(initial_delay: Duration, max_attempts: usize) -> Self {
+        Self {
+            schedule: Schedule::Exponential {
+                initial: initial_delay,
+                max: DEFAULT_RETRY_MAX_DELAY,
+            },
+            max_attempts,
+            jitter: false,
+        }
+    }
+
+    /// Cap each exponential backoff delay at `max_delay`. Fixed schedules are
+    /// unaffected.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        if let Schedule::Exponential { max, .. } = &mut self.schedule {
+            *max = max_delay;
+        }
+        self
+    }
+
+    /// Scale each delay by a random factor in [0, 1), so that many clients
+    /// retrying at once spread out their attempts.
+    pub fn with_jitter(mut self) -> Self {
+        self.jitter = true;
+        self
+    }
+
+    /// The maximum number of attempts, including the first.
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::exponential_backoff(DEFAULT_RETRY_INITIAL_DELAY, DEFAULT_RETRY_MAX_ATTEMPTS)
+            .with_jitter()
+    }
+}
+
+impl IntoIterator for RetryPolicy {
+    type Item = Duration;
+    type IntoIter = Box<dyn Iterator<Item = Duration> + Send>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let delays: Box<dyn Iterator<Item = Duration> + Send> = match self.schedule {
+            Schedule::Fixed(delay) => Box::new(iter::repeat(delay)),
+            Schedule::Exponential { initial, max } => {
+                Box::new(iter::successors(Some(initial.min(max)), move |delay| {
+                    Some(delay.checked_mul(2).unwrap_or(max).min(max))
+                }))
+            }
+        };
+
+        // The `retry` crate does not consult the delay iterator before the first
+        // attempt, so `max_attempts` attempts need one fewer delay.
+        let delays = delays.take(self.max_attempts.saturating_sub(1));
+
+        if self.jitter {
+            Box::new(delays.map(jitter))
+        } else {
+            Box::new(delays)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // A fixed policy should repeat the same delay between each attempt.
+    fn fixed_policy_repeats_delay() {
+        let delays: Vec<_> = RetryPolicy::fixed(Duration::from_millis(50), 4)
+            .into_iter()
+            .collect();
+        assert_eq!(delays, vec![Duration::from_millis(50); 3]);
+    }
+
+    #[test]
+    // An exponential policy should double each delay, up to its maximum.
+    fn exponential_policy_doubles_delay() {
+        let delays: Vec<_> = RetryPolicy::exponential_backoff(Duration::from_millis(100), 6)
+            .with_max_delay(Duration::from_millis(1000))
+            .into_iter()
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400),
+                Duration::from_millis(800),
+                Duration::from_millis(1000),
+            ]
+        );
+    }
+
+    #[test]
+    // Jitter should never lengthen a delay, or change the number of attempts.
+    fn jitter_shortens_delays() {
+        let policy = RetryPolicy::fixed(Duration::from_millis(100), 10).with_jitter();
+        let delays: Vec<_> = policy.into_iter().collect();
+        assert_eq!(delays.len(), 9);
+        assert!(delays
+            .iter()
+            .all(|delay| *delay <= Duration::from_millis(100)));
+    }
+
+    #[test]
+    // A policy allowing a single attempt should never wait.
+    fn single_attempt_has_no_delays() {
+        assert_eq!(
+            RetryPolicy::fixed(Duration::from_secs(1), 1)
+                .into_iter()
+                .count(),
+            0
+        );
+        assert_eq!(
+            RetryPolicy::fixed(Duration::from_secs(1), 0)
+                .into_iter()
+                .count(),
+            0
+        );
+    }
+}
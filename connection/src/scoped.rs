@@ -0,0 +1,89 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A guard which holds an attested session open for its lifetime.
+
+use crate::traits::AttestedConnection;
+use mc_attest_core::VerificationReport;
+use std::{
+    ops::{Deref, DerefMut},
+    result::Result as StdResult,
+};
+
+/// Attests a connection on construction, and deattests it when dropped.
+///
+/// The guard dereferences to the underlying connection, so calls made through
+/// it use the session established by `new()`. If a call fails and the
+/// connection deattests itself, later calls will attest a new session as
+/// usual, and that session is torn down when the guard is dropped.
+pub struct ScopedAttestation<'a, C: AttestedConnection> {
+    conn: &'a mut C,
+    verification_report: VerificationReport,
+}
+
+impl<'a, C: AttestedConnection> ScopedAttestation<'a, C> {
+    /// Attest the given connection, replacing any existing session.
+    pub fn new(conn: &'a mut C) -> StdResult<Self, C::Error> {
+        let verification_report = conn.attest()?;
+        Ok(Self {
+            conn,
+            verification_report,
+        })
+    }
+
+    /// The verification report received when the session was established.
+    pub fn verification_report(&self) -> &VerificationReport {
+        &self.verification_report
+    }
+}
+
+impl<'a, C: AttestedConnection> Deref for ScopedAttestation<'a, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.conn
+    }
+}
+
+impl<'a, C: AttestedConnection> DerefMut for ScopedAttestation<'a, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.conn
+    }
+}
+
+impl<'a, C: AttestedConnection> Drop for ScopedAttestation<'a, C> {
+    fn drop(&mut self) {
+        self.conn.deattest();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{test_client_uri, MockAttestedConnection};
+    use mc_util_uri::ConsensusClientUri;
+    use std::str::FromStr;
+
+    #[test]
+    // The guard should attest a new session even if one is open, hold it for
+    // its lifetime, and tear it down when dropped.
+    fn scoped_attestation_holds_session_for_its_lifetime() {
+        let mut conn = MockAttestedConnection::new(test_client_uri(1));
+        conn.attest().unwrap();
+
+        {
+            let scoped = ScopedAttestation::new(&mut conn).unwrap();
+            assert!(scoped.is_attested());
+            assert_eq!(scoped.attest_calls, 2);
+            assert_eq!(scoped.verification_report(), &VerificationReport::default());
+        }
+        assert!(!conn.is_attested());
+        assert_eq!(conn.attest_calls, 2);
+
+        // A refused attestation produces no guard.
+        let mut refused = MockAttestedConnection::new(
+            ConsensusClientUri::from_str("mc://refused.test.com/").unwrap(),
+        );
+        assert!(ScopedAttestation::new(&mut refused).is_err());
+        assert!(!refused.is_attested());
+    }
+}
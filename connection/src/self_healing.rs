@@ -0,0 +1,245 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A connection manager which evicts peers that stay unreachable, and adds
+//! them back once they recover.
+
+use crate::{error::Result, manager::ConnectionManager, traits::BlockchainConnection};
+use mc_common::{
+    logger::{log, Logger},
+    HashMap, ResponderId,
+};
+use mc_util_uri::ConnectionUri;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Default time between health checks.
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default number of consecutive failed health checks after which a peer is
+/// evicted.
+pub const DEFAULT_MAX_HEALTH_CHECK_FAILURES: usize = 3;
+
+/// How often the health check thread checks whether it should stop.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A source of the peers a connection set should contain.
+pub trait PeerDiscovery: Send + Sync + 'static {
+    type Uri: ConnectionUri;
+
+    /// The URIs of every peer which should currently be connected.
+    fn peer_uris(&self) -> Vec<Self::Uri>;
+}
+
+/// Opens a connection to the peer at the given URI.
+pub type ConnectFn<C, U> = Arc<dyn Fn(&U) -> Result<C> + Send + Sync>;
+
+/// A `ConnectionManager` whose peers are health checked on a background
+/// thread.
+///
+/// A peer which fails `max_failures` consecutive health checks is removed
+/// from the manager. On each check, every peer named by the discovery source
+/// but missing from the manager is connected to, and added if it answers.
+/// This both adds newly discovered peers and restores evicted peers once they
+/// recover.
+///
+/// The thread is stopped when this is dropped.
+pub struct SelfHealingConnectionManager<C: BlockchainConnection + 'static> {
+    manager: ConnectionManager<C>,
+    join_handle: Option<thread::JoinHandle<()>>,
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl<C: BlockchainConnection + 'static> SelfHealingConnectionManager<C> {
+    /// Start health checking `manager`'s peers every `interval`, evicting
+    /// those which fail `max_failures` checks in a row.
+    pub fn start<D: PeerDiscovery<Uri = C::Uri>>(
+        manager: ConnectionManager<C>,
+        discovery: D,
+        connect: ConnectFn<C, C::Uri>,
+        interval: Duration,
+        max_failures: usize,
+        logger: Logger,
+    ) -> Self {
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let thread_stop_requested = stop_requested.clone();
+        let thread_manager = manager.clone();
+        let join_handle = Some(
+            thread::Builder::new()
+                .name("SelfHealingConnectionManager".into())
+                .spawn(move || {
+                    let mut failures = HashMap::default();
+                    let mut last_check = Instant::now();
+                    while !thread_stop_requested.load(Ordering::SeqCst) {
+                        if last_check.elapsed() >= interval {
+                            heal(
+                                &thread_manager,
+                                &discovery,
+                                &connect,
+                                &mut failures,
+                                max_failures,
+                                &logger,
+                            );
+                            last_check = Instant::now();
+                        }
+                        thread::sleep(STOP_POLL_INTERVAL.min(interval));
+                    }
+                })
+                .expect("Failed spawning SelfHealingConnectionManager thread"),
+        );
+
+        Self {
+            manager,
+            join_handle,
+            stop_requested,
+        }
+    }
+
+    /// The managed connections. Clones of the manager see peers as they are
+    /// evicted and restored.
+    pub fn manager(&self) -> &ConnectionManager<C> {
+        &self.manager
+    }
+
+    /// Stop health checking, leaving the connection set as it is.
+    pub fn stop(&mut self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.join_handle.take() {
+            thread
+                .join()
+                .expect("SelfHealingConnectionManager thread join failed");
+        }
+    }
+}
+
+impl<C: BlockchainConnection + 'static> Drop for SelfHealingConnectionManager<C> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Run one round of health checks: evict peers which have now failed
+/// `max_failures` checks in a row, then connect to any discovered peers which
+/// are missing and answer.
+fn heal<C: BlockchainConnection + 'static, D: PeerDiscovery<Uri = C::Uri>>(
+    manager: &ConnectionManager<C>,
+    discovery: &D,
+    connect: &ConnectFn<C, C::Uri>,
+    failures: &mut HashMap<ResponderId, usize>,
+    max_failures: usize,
+    logger: &Logger,
+) {
+    for (responder_id, healthy) in manager.health_check() {
+        if healthy {
+            failures.remove(&responder_id);
+            continue;
+        }
+
+        let count = failures.entry(responder_id.clone()).or_insert(0);
+        *count += 1;
+        if *count >= max_failures {
+            log::warn!(
+                logger,
+                "Evicting {} after {} failed health checks",
+                responder_id,
+                count
+            );
+            failures.remove(&responder_id);
+            manager.remove_conn(&responder_id);
+        }
+    }
+
+    for uri in discovery.peer_uris() {
+        let responder_id = match uri.responder_id() {
+            Ok(responder_id) => responder_id,
+            Err(err) => {
+                log::warn!(logger, "Ignoring discovered peer {}: {}", uri, err);
+                continue;
+            }
+        };
+        if manager.conn(&responder_id).is_some() {
+            continue;
+        }
+
+        let mut conn = match connect(&uri) {
+            Ok(conn) => conn,
+            Err(err) => {
+                log::debug!(logger, "Could not connect to {}: {}", uri, err);
+                continue;
+            }
+        };
+        if conn.fetch_block_height().is_err() {
+            continue;
+        }
+        match manager.add_conn(conn) {
+            Ok(_) => log::info!(logger, "Added peer {}", responder_id),
+            Err(err) => log::warn!(logger, "Could not add peer {}: {}", uri, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{chain, test_client_uri, InMemoryBlockchainConnection};
+    use mc_common::logger::create_null_logger;
+    use mc_util_uri::ConsensusClientUri;
+
+    struct StaticDiscovery(Vec<ConsensusClientUri>);
+
+    impl PeerDiscovery for StaticDiscovery {
+        type Uri = ConsensusClientUri;
+
+        fn peer_uris(&self) -> Vec<Self::Uri> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    // A peer should be evicted after failing enough health checks in a row,
+    // and restored once it answers again.
+    fn heal_evicts_and_restores_peers() {
+        let logger = create_null_logger();
+        let uris: Vec<ConsensusClientUri> = (1..=2).map(test_client_uri).collect();
+        let online: HashMap<ConsensusClientUri, Arc<AtomicBool>> = uris
+            .iter()
+            .map(|uri| (uri.clone(), Arc::new(AtomicBool::new(true))))
+            .collect();
+
+        let connect_online = online.clone();
+        let connect: ConnectFn<InMemoryBlockchainConnection, ConsensusClientUri> =
+            Arc::new(move |uri: &ConsensusClientUri| {
+                let mut conn = InMemoryBlockchainConnection::new(uri.clone(), chain(1));
+                conn.online = connect_online[uri].clone();
+                Ok(conn)
+            });
+        let manager = ConnectionManager::new(
+            uris.iter().map(|uri| connect(uri).unwrap()).collect(),
+            logger.clone(),
+        );
+        let discovery = StaticDiscovery(uris.clone());
+        let node2 = uris[1].responder_id().unwrap();
+        let mut failures = HashMap::default();
+
+        online[&uris[1]].store(false, Ordering::SeqCst);
+        heal(&manager, &discovery, &connect, &mut failures, 2, &logger);
+        assert!(manager.conn(&node2).is_some());
+        heal(&manager, &discovery, &connect, &mut failures, 2, &logger);
+        assert!(manager.conn(&node2).is_none());
+        assert_eq!(manager.len(), 1);
+
+        // The evicted peer is not restored while it is still unreachable.
+        heal(&manager, &discovery, &connect, &mut failures, 2, &logger);
+        assert_eq!(manager.len(), 1);
+
+        online[&uris[1]].store(true, Ordering::SeqCst);
+        heal(&manager, &discovery, &connect, &mut failures, 2, &logger);
+        assert!(manager.conn(&node2).is_some());
+        assert_eq!(manager.len(), 2);
+    }
+}
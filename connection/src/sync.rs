@@ -3,23 +3,52 @@
 //! A synchronous connection wrapper around an inner (thread-unsafe) connection
 
 use crate::{
-    error::RetryResult,
+    cancellation::{CancellationToken, Cancelled},
+    concurrency_limit::{ConcurrencyLimit, ConcurrencyPermit, ConnectionBusy},
+    error::{Error, Result, RetryError, RetryResult},
+    latency::LatencyTracker,
+    metrics::{ConnectionMetrics, NoopConnectionMetrics},
+    retry_budget::RetryBudget,
     traits::{
-        BlockInfo, BlockchainConnection, Connection, RetryableBlockchainConnection,
+        AsyncBlockchainConnection, AsyncUserTxConnection, BlockInfo, BlockchainConnection,
+        Connection, ConnectionFuture, FeeConnection, IdempotencyKey, ProposeTxResponse,
+        RetrySuccess, RetryableBlockchainConnection, RetryableFeeConnection,
         RetryableUserTxConnection, UserTxConnection,
     },
 };
-use mc_common::logger::Logger;
+use mc_common::{logger::Logger, ResponderId};
 use mc_transaction_core::{tx::Tx, Block, BlockID, BlockIndex};
+use mc_util_uri::ConnectionUri;
+use retry::OperationResult;
 use std::{
     cmp::Ordering,
     fmt::{Display, Formatter, Result as FmtResult},
     hash::{Hash, Hasher},
     ops::{Deref, Range},
-    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    time::Duration,
+    result::Result as StdResult,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    thread,
+    time::{Duration, Instant},
 };
 
+/// A failed call, as recorded by `SyncConnection::last_error`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CallError {
+    /// The name of the call.
+    pub call: &'static str,
+    /// The error the call failed with, as displayed.
+    pub error: String,
+    /// When the call failed.
+    pub at: Instant,
+}
+
+/// The outcomes of the calls made through `SyncConnection::metered`.
+#[derive(Default)]
+struct CallHistory {
+    last_succeeded: Option<bool>,
+    last_error: Option<CallError>,
+}
+
 /// A synchronous wrapper for a connection object.
 ///
 /// This object provides threadsafe access to the underlying connection.
@@ -27,18 +56,150 @@ pub struct SyncConnection<C: Connection> {
     inner: Arc<RwLock<C>>,
     cached_uri: C::Uri,
     cached_display: String,
+    cached_responder_id: ResponderId,
     logger: Logger,
+    metrics: Arc<dyn ConnectionMetrics>,
+    retry_budget: Option<RetryBudget>,
+    concurrency_limit: Option<ConcurrencyLimit>,
+    cancellation: Option<CancellationToken>,
+    latency: LatencyTracker,
+    call_history: Arc<Mutex<CallHistory>>,
 }
 
 impl<C: Connection> SyncConnection<C> {
     pub fn new(inner: C, logger: Logger) -> Self {
         let cached_uri = inner.uri();
         let cached_display = inner.to_string();
+        // Connections whose URI cannot name a responder are identified by
+        // their display instead.
+        let cached_responder_id = cached_uri
+            .responder_id()
+            .unwrap_or_else(|_| ResponderId(cached_display.clone()));
         Self {
             inner: Arc::new(RwLock::new(inner)),
             cached_uri,
             cached_display,
+            cached_responder_id,
             logger,
+            metrics: Arc::new(NoopConnectionMetrics),
+            retry_budget: None,
+            concurrency_limit: None,
+            cancellation: None,
+            latency: LatencyTracker::default(),
+            call_history: Arc::new(Mutex::new(CallHistory::default())),
+        }
+    }
+
+    /// Report each attempt of the retryable methods to the given metrics sink.
+    pub fn with_metrics(mut self, metrics: Arc<dyn ConnectionMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Take a token from the given budget for every retry made by the
+    /// retryable methods, giving up early once it is exhausted.
+    pub fn with_retry_budget(mut self, retry_budget: RetryBudget) -> Self {
+        self.retry_budget = Some(retry_budget);
+        self
+    }
+
+    /// The retry budget shared with other connections, if any.
+    pub fn retry_budget(&self) -> Option<&RetryBudget> {
+        self.retry_budget.as_ref()
+    }
+
+    /// Allow at most `concurrency_limit.max_in_flight()` retryable or timed
+    /// operations on this connection at once. Callers past the limit fail at
+    /// once with `Error::Busy`, rather than waiting for the connection.
+    ///
+    /// Clones made afterwards share the limit. By default there is none.
+    pub fn with_concurrency_limit(mut self, concurrency_limit: ConcurrencyLimit) -> Self {
+        self.concurrency_limit = Some(concurrency_limit);
+        self
+    }
+
+    /// The limit on concurrent operations, if any.
+    pub fn concurrency_limit(&self) -> Option<&ConcurrencyLimit> {
+        self.concurrency_limit.as_ref()
+    }
+
+    /// Stop the retryable methods before their next attempt once `token` is
+    /// cancelled, failing with `Error::Cancelled`. A call waiting between
+    /// attempts is woken at once.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// The token which cancels retries, if any.
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    /// Track the latency of calls with the given tracker, in place of the
+    /// default one. Clones share the tracker.
+    pub fn with_latency_tracker(mut self, latency: LatencyTracker) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// The moving average of the time calls made through `metered` took, or
+    /// `None` if none have been made yet.
+    ///
+    /// Only the call itself is timed, not the wait for the connection's lock.
+    /// Failed calls, timeouts included, count as taking at least the
+    /// tracker's failure penalty, so that a peer which refuses connections
+    /// quickly does not seem fast.
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.latency.average()
+    }
+
+    /// Whether the most recent call made through `metered` succeeded, or
+    /// `None` if none has been made.
+    pub fn last_call_succeeded(&self) -> Option<bool> {
+        self.call_history().last_succeeded
+    }
+
+    /// The most recent call made through `metered` which failed, if any,
+    /// even if later calls have succeeded.
+    pub fn last_error(&self) -> Option<CallError> {
+        self.call_history().last_error.clone()
+    }
+
+    // The history only holds plain values, so it is usable even if a thread
+    // panicked while holding it.
+    fn call_history(&self) -> MutexGuard<CallHistory> {
+        self.call_history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Take a permit for one operation, failing if the concurrency limit has
+    /// been reached. Returns `None` if there is no limit.
+    pub fn try_acquire_permit(&self) -> StdResult<Option<ConcurrencyPermit>, ConnectionBusy> {
+        match self.concurrency_limit.as_ref() {
+            Some(concurrency_limit) => concurrency_limit
+                .try_acquire()
+                .map(Some)
+                .ok_or(ConnectionBusy),
+            None => Ok(None),
+        }
+    }
+
+    /// Run a retryable operation while holding a permit, failing without any
+    /// attempts if none is available.
+    #[doc(hidden)]
+    pub fn _with_permit<T, E: From<ConnectionBusy>>(
+        &self,
+        func: impl FnOnce() -> StdResult<T, RetryError<E>>,
+    ) -> StdResult<T, RetryError<E>> {
+        match self.try_acquire_permit() {
+            Ok(_permit) => func(),
+            Err(busy) => Err(RetryError::Operation {
+                error: busy.into(),
+                total_delay: Duration::default(),
+                tries: 0,
+            }),
         }
     }
 
@@ -48,6 +209,12 @@ impl<C: Connection> SyncConnection<C> {
             .expect("Could not acquire read lock on SyncConnection")
     }
 
+    /// Lock the inner connection for reading, unless a call holds it or a
+    /// call panicked while holding it.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<C>> {
+        self.inner.try_read().ok()
+    }
+
     pub fn write(&self) -> RwLockWriteGuard<C> {
         self.inner
             .write()
@@ -57,6 +224,118 @@ impl<C: Connection> SyncConnection<C> {
     pub fn logger(&self) -> &Logger {
         &self.logger
     }
+
+    /// The responder ID which errors from this connection are attributed to.
+    pub fn responder_id(&self) -> &ResponderId {
+        &self.cached_responder_id
+    }
+
+    /// Attribute the error a retryable call finally failed with to this
+    /// connection and the named call, as `Error::Peer`.
+    ///
+    /// This is done once the retries are over, so the retry policy sees the
+    /// errors as the inner connection returned them.
+    pub(crate) fn with_peer_context<T>(
+        &self,
+        operation: &'static str,
+        result: RetryResult<T>,
+    ) -> RetryResult<T> {
+        result.map_err(|err| match err {
+            RetryError::Operation {
+                error,
+                total_delay,
+                tries,
+            } => RetryError::Operation {
+                error: error.with_peer(self.cached_responder_id.clone(), operation),
+                total_delay,
+                tries,
+            },
+            err => err,
+        })
+    }
+
+    /// Lock the inner connection and run a single attempt of `call`, reporting
+    /// it to the metrics sink, the call history and the latency average.
+    pub fn metered<T, E: Display>(
+        &self,
+        call: &'static str,
+        func: impl FnOnce(&mut C) -> StdResult<T, E>,
+    ) -> StdResult<T, E> {
+        self.metered_with_latency(call, func, |_value, elapsed| elapsed)
+    }
+
+    /// As `metered`, but a successful call is recorded in the latency average
+    /// as the time `latency` gives, from the call's value and the time the
+    /// call took.
+    fn metered_with_latency<T, E: Display>(
+        &self,
+        call: &'static str,
+        func: impl FnOnce(&mut C) -> StdResult<T, E>,
+        latency: impl FnOnce(&T, Duration) -> Duration,
+    ) -> StdResult<T, E> {
+        self.metrics.call_attempted(&self.cached_display, call);
+        let start = Instant::now();
+        let result = {
+            let mut inner = self.write();
+            let call_start = Instant::now();
+            let result = func(&mut inner);
+            match result.as_ref() {
+                Ok(value) => self.latency.record(latency(value, call_start.elapsed())),
+                Err(_) => self.latency.record_failure(call_start.elapsed()),
+            }
+            result
+        };
+        let mut call_history = self.call_history();
+        match result.as_ref() {
+            Ok(_) => {
+                self.metrics
+                    .call_succeeded(&self.cached_display, call, start.elapsed());
+                call_history.last_succeeded = Some(true);
+            }
+            Err(err) => {
+                self.metrics
+                    .call_failed(&self.cached_display, call, start.elapsed());
+                call_history.last_succeeded = Some(false);
+                call_history.last_error = Some(CallError {
+                    call,
+                    error: err.to_string(),
+                    at: Instant::now(),
+                });
+            }
+        }
+        drop(call_history);
+        result
+    }
+
+    /// Run `func` on the inner connection, failing with `Error::Timeout` if
+    /// the calls it makes do not complete within `timeout`.
+    ///
+    /// The deadline is passed to the inner connection and applied to each of
+    /// the gRPC calls `func` makes, so no thread is abandoned when it fires.
+    /// Connections which do not support deadlines ignore it.
+    ///
+    /// If the deadline fires during attestation, the attestation attempt fails
+    /// and the connection is left unattested, so the next call will attest
+    /// again from scratch.
+    pub fn with_timeout<T>(
+        &self,
+        timeout: Duration,
+        func: impl FnOnce(&mut C) -> Result<T>,
+    ) -> Result<T> {
+        let _permit = self.try_acquire_permit()?;
+        let mut inner = self.write();
+        inner.set_deadline(Some(Instant::now() + timeout));
+        let result = func(&mut inner);
+        inner.set_deadline(None);
+
+        // A single call which ran past its own deadline is still reported as
+        // such, so that it can be retried.
+        result.map_err(|err| match err {
+            Error::CallTimeout(_) => err,
+            err if err.is_deadline_exceeded() => Error::Timeout(timeout),
+            err => err,
+        })
+    }
 }
 
 impl<C: Connection> Clone for SyncConnection<C> {
@@ -65,7 +344,14 @@ impl<C: Connection> Clone for SyncConnection<C> {
             inner: self.inner.clone(),
             cached_uri: self.cached_uri.clone(),
             cached_display: self.cached_display.clone(),
+            cached_responder_id: self.cached_responder_id.clone(),
             logger: self.logger.clone(),
+            metrics: self.metrics.clone(),
+            retry_budget: self.retry_budget.clone(),
+            concurrency_limit: self.concurrency_limit.clone(),
+            cancellation: self.cancellation.clone(),
+            latency: self.latency.clone(),
+            call_history: self.call_history.clone(),
         }
     }
 }
@@ -76,6 +362,20 @@ impl<C: Connection> Connection for SyncConnection<C> {
     fn uri(&self) -> Self::Uri {
         self.cached_uri.clone()
     }
+
+    /// Ping the inner connection, recording the round trip it reports in the
+    /// latency average if it succeeds.
+    ///
+    /// The reported round trip is recorded rather than the time the call
+    /// took, since it leaves out any setup the ping needed first, such as
+    /// attesting.
+    fn ping(&mut self) -> Result<Duration> {
+        self.metered_with_latency(
+            "ping",
+            |inner| inner.ping(),
+            |round_trip, _elapsed| *round_trip,
+        )
+    }
 }
 
 impl<C: Connection> Display for SyncConnection<C> {
@@ -116,6 +416,82 @@ impl<C: Connection> PartialOrd for SyncConnection<C> {
     }
 }
 
+/// Run `operation` until it succeeds, fails permanently, or runs out of
+/// retries, waiting between attempts as `retry::retry` does, and recording the
+/// number of attempts made and the total time taken.
+///
+/// Each retry takes a token from `retry_budget`, if given. Once the budget is
+/// exhausted no further retries are made, and the last error is returned.
+///
+/// Once `cancellation`, if given, is cancelled, no further attempts are made
+/// and the call fails with `Cancelled`.
+#[doc(hidden)]
+pub fn _retry_with_stats<T, E: From<Cancelled>>(
+    retry_budget: Option<&RetryBudget>,
+    cancellation: Option<&CancellationToken>,
+    retry_iterator: impl IntoIterator<Item = Duration>,
+    mut operation: impl FnMut() -> OperationResult<T, E>,
+) -> StdResult<RetrySuccess<T>, RetryError<E>> {
+    let is_cancelled = || cancellation.map_or(false, CancellationToken::is_cancelled);
+    let mut delays = retry_iterator.into_iter();
+
+    let start = Instant::now();
+    let mut attempts = 0;
+    let mut total_delay = Duration::default();
+    loop {
+        if is_cancelled() {
+            return Err(RetryError::Operation {
+                error: Cancelled.into(),
+                total_delay,
+                tries: attempts,
+            });
+        }
+
+        attempts += 1;
+        let error = match operation() {
+            OperationResult::Ok(value) => {
+                return Ok(RetrySuccess {
+                    value,
+                    attempts,
+                    elapsed: start.elapsed(),
+                })
+            }
+            OperationResult::Retry(error) => error,
+            OperationResult::Err(error) => {
+                return Err(RetryError::Operation {
+                    error,
+                    total_delay,
+                    tries: attempts,
+                })
+            }
+        };
+
+        // A delay is only taken after a failed attempt, so this takes exactly
+        // one token per retry.
+        match delays
+            .next()
+            .filter(|_| retry_budget.map_or(true, RetryBudget::try_acquire))
+        {
+            Some(delay) => {
+                match cancellation {
+                    Some(cancellation) => {
+                        cancellation.sleep(delay);
+                    }
+                    None => thread::sleep(delay),
+                }
+                total_delay += delay;
+            }
+            None => {
+                return Err(RetryError::Operation {
+                    error,
+                    total_delay,
+                    tries: attempts,
+                })
+            }
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! _retry_wrapper {
     ($pred:expr) => {{
@@ -132,8 +508,14 @@ macro_rules! _retry_wrapper {
     }};
 }
 
-// Generic retry implementation, locks the inner object, calls the underlying
-// function and passes the given argument(s).
+// Generic retry implementation, locks the inner object of the given
+// SyncConnection, calls the underlying function and passes the given
+// argument(s). Each attempt is reported to the connection's metrics sink, each
+// retry draws on the connection's retry budget, and the successful value is
+// returned as a `RetrySuccess`. If the connection's concurrency limit has been
+// reached, this fails with `Error::Busy` without making any attempts, and once
+// the connection's cancellation token is cancelled, it fails with
+// `Error::Cancelled` before the next attempt.
 //
 // This will immediately stop on any non-gRPC error, however.
 //
@@ -141,35 +523,34 @@ macro_rules! _retry_wrapper {
 // as long as the request itself (not the entire retry interval).
 #[macro_export]
 macro_rules! impl_sync_connection_retry {
-    ($obj:expr, $logger:expr, $func:ident, $iter:expr) => {{
+    ($conn:expr, $func:ident, $iter:expr) => {{
         $crate::_trace_time!(
-            $logger,
+            $conn.logger(),
             "SyncConnection.{}({})",
             stringify!($func),
             stringify!($iter)
         );
-        $crate::_retry::retry($iter, || $crate::_retry_wrapper!($obj.$func()))
+        $conn._with_permit(|| {
+            $crate::_retry_with_stats($conn.retry_budget(), $conn.cancellation(), $iter, || {
+                $crate::_retry_wrapper!($conn.metered(stringify!($func), |inner| inner.$func()))
+            })
+        })
     }};
-    ($obj:expr, $logger:expr, $func:ident, $iter:expr, $arg1:expr) => {{
+    ($conn:expr, $func:ident, $iter:expr, $($arg:expr),+) => {{
         $crate::_trace_time!(
-            $logger,
+            $conn.logger(),
             "SyncConnection.{}({}, {})",
             stringify!($func),
-            stringify!($arg1),
+            stringify!($($arg),+),
             stringify!($iter)
         );
-        $crate::_retry::retry($iter, || $crate::_retry_wrapper!($obj.$func($arg1)))
-    }};
-    ($obj:expr, $logger:expr, $func:ident, $iter:expr, $arg1:expr, $arg2:expr) => {{
-        $crate::_trace_time!(
-            $logger,
-            "SyncConnection.{}({}, {}, {})",
-            stringify!($func),
-            stringify!($arg1),
-            stringify!($arg2),
-            stringify!($iter)
-        );
-        $crate::_retry::retry($iter, || $crate::_retry_wrapper!($obj.$func($arg1, $arg2)))
+        $conn._with_permit(|| {
+            $crate::_retry_with_stats($conn.retry_budget(), $conn.cancellation(), $iter, || {
+                $crate::_retry_wrapper!(
+                    $conn.metered(stringify!($func), |inner| inner.$func($($arg),+))
+                )
+            })
+        })
     }};
 }
 
@@ -178,13 +559,10 @@ impl<BC: BlockchainConnection> RetryableBlockchainConnection for SyncConnection<
         &self,
         range: Range<BlockIndex>,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<Vec<Block>> {
-        impl_sync_connection_retry!(
-            self.write(),
-            self.logger,
-            fetch_blocks,
-            retry_iterator,
-            range.clone()
+    ) -> RetryResult<RetrySuccess<Vec<Block>>> {
+        self.with_peer_context(
+            "fetch_blocks",
+            impl_sync_connection_retry!(self, fetch_blocks, retry_iterator, range.clone()),
         )
     }
 
@@ -192,42 +570,253 @@ impl<BC: BlockchainConnection> RetryableBlockchainConnection for SyncConnection<
         &self,
         range: Range<BlockIndex>,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<Vec<BlockID>> {
-        impl_sync_connection_retry!(
-            self.write(),
-            self.logger,
-            fetch_block_ids,
-            retry_iterator,
-            range.clone()
+    ) -> RetryResult<RetrySuccess<Vec<BlockID>>> {
+        self.with_peer_context(
+            "fetch_block_ids",
+            impl_sync_connection_retry!(self, fetch_block_ids, retry_iterator, range.clone()),
         )
     }
 
     fn fetch_block_height(
         &self,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<BlockIndex> {
-        impl_sync_connection_retry!(
-            self.write(),
-            self.logger,
-            fetch_block_height,
-            retry_iterator
+    ) -> RetryResult<RetrySuccess<BlockIndex>> {
+        self.with_peer_context(
+            "fetch_block_height",
+            impl_sync_connection_retry!(self, fetch_block_height, retry_iterator),
         )
     }
 
     fn fetch_block_info(
         &self,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<BlockInfo> {
-        impl_sync_connection_retry!(self.write(), self.logger, fetch_block_info, retry_iterator)
+    ) -> RetryResult<RetrySuccess<BlockInfo>> {
+        self.with_peer_context(
+            "fetch_block_info",
+            impl_sync_connection_retry!(self, fetch_block_info, retry_iterator),
+        )
+    }
+}
+
+// The lock is only held while each request is issued, not while it is awaited.
+impl<ABC: AsyncBlockchainConnection> AsyncBlockchainConnection for SyncConnection<ABC> {
+    fn fetch_blocks_async(&mut self, range: Range<BlockIndex>) -> ConnectionFuture<Vec<Block>> {
+        self.write().fetch_blocks_async(range)
+    }
+}
+
+impl<AUTC: AsyncUserTxConnection> AsyncUserTxConnection for SyncConnection<AUTC> {
+    fn propose_tx_async(&mut self, tx: &Tx) -> ConnectionFuture<ProposeTxResponse> {
+        self.write().propose_tx_async(tx)
+    }
+}
+
+impl<FC: FeeConnection> RetryableFeeConnection for SyncConnection<FC> {
+    fn fetch_minimum_fee(
+        &self,
+        retry_iterator: impl IntoIterator<Item = Duration>,
+    ) -> RetryResult<RetrySuccess<u64>> {
+        self.with_peer_context(
+            "fetch_minimum_fee",
+            impl_sync_connection_retry!(self, fetch_minimum_fee, retry_iterator),
+        )
     }
 }
 
 impl<UTC: UserTxConnection> RetryableUserTxConnection for SyncConnection<UTC> {
-    fn propose_tx(
+    fn propose_tx_with_idempotency_key(
         &self,
         tx: &Tx,
+        idempotency_key: &IdempotencyKey,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<BlockIndex> {
-        impl_sync_connection_retry!(self.write(), self.logger, propose_tx, retry_iterator, tx)
+    ) -> RetryResult<RetrySuccess<ProposeTxResponse>> {
+        self.with_peer_context(
+            "propose_tx_with_idempotency_key",
+            impl_sync_connection_retry!(
+                self,
+                propose_tx_with_idempotency_key,
+                retry_iterator,
+                tx,
+                idempotency_key
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{test_client_uri, MockUserTxConnection};
+    use mc_common::logger::create_null_logger;
+
+    fn sync_connection(failures: usize) -> SyncConnection<MockUserTxConnection> {
+        let mut conn = MockUserTxConnection::new(test_client_uri(1));
+        conn.failures = failures;
+        conn.round_trip = Some(Duration::from_secs(3));
+        SyncConnection::new(conn, create_null_logger())
+    }
+
+    /// Records each hook as `(hook, connection, call)`.
+    #[derive(Default)]
+    struct RecordingMetrics(Mutex<Vec<(&'static str, String, String)>>);
+
+    impl RecordingMetrics {
+        fn record(&self, hook: &'static str, connection: &str, call: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((hook, connection.to_string(), call.to_string()));
+        }
+    }
+
+    impl ConnectionMetrics for RecordingMetrics {
+        fn call_attempted(&self, connection: &str, call: &str) {
+            self.record("attempted", connection, call);
+        }
+
+        fn call_succeeded(&self, connection: &str, call: &str, _elapsed: Duration) {
+            self.record("succeeded", connection, call);
+        }
+
+        fn call_failed(&self, connection: &str, call: &str, _elapsed: Duration) {
+            self.record("failed", connection, call);
+        }
+    }
+
+    #[test]
+    // Every attempt should carry the caller's key, and a resubmission with the
+    // same key should carry it too.
+    fn retries_reuse_caller_idempotency_key() {
+        let conn = sync_connection(2);
+        let key = IdempotencyKey([7u8; 16]);
+        let tx = Tx::default();
+
+        let result = conn
+            .propose_tx_with_idempotency_key(&tx, &key, vec![Duration::default(); 3])
+            .unwrap();
+        assert_eq!(result.attempts, 3);
+        conn.propose_tx_with_idempotency_key(&tx, &key, Vec::new())
+            .unwrap();
+        assert_eq!(conn.read().idempotency_keys, vec![key; 4]);
+
+        // Without a key, the attempts of one call share a fresh key.
+        let conn = sync_connection(1);
+        conn.propose_tx(&tx, vec![Duration::default()]).unwrap();
+        let keys = conn.read().idempotency_keys.clone();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0], keys[1]);
+        assert_ne!(keys[0], key);
+    }
+
+    #[test]
+    // A call which succeeds after retrying should report how many attempts it
+    // took, and how long they took including the delays between them.
+    fn retried_calls_report_attempts_and_elapsed_time() {
+        let tx = Tx::default();
+        let delay = Duration::from_millis(20);
+
+        let result = sync_connection(2).propose_tx(&tx, vec![delay; 3]).unwrap();
+        assert_eq!(result.value, ProposeTxResponse::new(&tx, 1));
+        assert_eq!(result.attempts, 3);
+        assert!(result.elapsed >= delay * 2, "{:?}", result.elapsed);
+
+        let result = sync_connection(0).propose_tx(&tx, vec![delay; 3]).unwrap();
+        assert_eq!(result.attempts, 1);
+    }
+
+    #[test]
+    // A call which runs out of retries should name the peer and call it
+    // failed on, while keeping the original error as its source.
+    fn failed_calls_carry_peer_context() {
+        let conn = sync_connection(5);
+        let err = match conn.propose_tx(&Tx::default(), vec![Duration::default()]) {
+            Err(RetryError::Operation {
+                error, tries: 2, ..
+            }) => error,
+            other => panic!("Unexpected result: {:?}", other),
+        };
+
+        match &err {
+            Error::Peer {
+                responder_id,
+                operation: "propose_tx_with_idempotency_key",
+                ..
+            } => assert_eq!(responder_id, conn.responder_id()),
+            other => panic!("Unexpected error: {:?}", other),
+        }
+        assert_eq!(err.responder_id(), Some(conn.responder_id()));
+        assert!(err.should_retry());
+        assert!(matches!(err.without_context(), Error::Grpc(_)));
+        assert!(err.to_string().contains("node1.test.com"));
+
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), err.without_context().to_string());
+        assert!(source.source().is_some());
+    }
+
+    #[test]
+    // Each attempt of a retried call should be reported to the metrics sink,
+    // followed by whether it succeeded.
+    fn retried_calls_report_each_attempt() {
+        let metrics = Arc::new(RecordingMetrics::default());
+        let conn = sync_connection(2).with_metrics(metrics.clone());
+        conn.propose_tx(&Tx::default(), vec![Duration::default(); 2])
+            .unwrap();
+
+        let expected: Vec<_> = [
+            "attempted",
+            "failed",
+            "attempted",
+            "failed",
+            "attempted",
+            "succeeded",
+        ]
+        .iter()
+        .map(|hook| {
+            (
+                *hook,
+                conn.to_string(),
+                "propose_tx_with_idempotency_key".to_string(),
+            )
+        })
+        .collect();
+        assert_eq!(*metrics.0.lock().unwrap(), expected);
+    }
+
+    #[test]
+    // A call should see the deadline only while it runs, and should fail with
+    // a timeout once the deadline has passed.
+    fn with_timeout_applies_deadline() {
+        let conn = sync_connection(0);
+        let tx = Tx::default();
+
+        let result = conn.with_timeout(Duration::from_secs(60), |inner| {
+            assert!(inner.deadline.is_some());
+            inner.propose_tx(&tx)
+        });
+        assert!(result.is_ok());
+        assert_eq!(conn.read().deadline, None);
+
+        match conn.with_timeout(Duration::default(), |inner| inner.propose_tx(&tx)) {
+            Err(Error::Timeout(timeout)) => assert_eq!(timeout, Duration::default()),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(conn.read().deadline, None);
+
+        // Other errors are passed through.
+        let conn = sync_connection(1);
+        match conn.with_timeout(Duration::from_secs(60), |inner| inner.propose_tx(&tx)) {
+            Err(Error::Grpc(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // A ping should record the round trip the connection reports, not the
+    // time the call took.
+    fn ping_records_reported_round_trip() {
+        let mut conn = sync_connection(0);
+        assert_eq!(conn.ping().unwrap(), Duration::from_secs(3));
+        assert_eq!(conn.average_latency(), Some(Duration::from_secs(3)));
     }
 }
@@ -0,0 +1,92 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Attested Connection Mock
+
+use crate::traits::{AttestationError, AttestedConnection, Connection};
+use displaydoc::Display;
+use grpcio::Error as GrpcError;
+use mc_attest_core::VerificationReport;
+use mc_util_uri::{ConnectionUri, ConsensusClientUri};
+use std::{
+    fmt::{Display as FmtDisplay, Formatter, Result as FmtResult},
+    result::Result as StdResult,
+};
+
+/// A connection which counts its attestations. Attestation succeeds unless
+/// the peer's host starts with "refused", and panics if it starts with
+/// "panics".
+#[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MockAttestedConnection {
+    uri: ConsensusClientUri,
+
+    /// The number of times `attest` has been called
+    pub attest_calls: usize,
+
+    /// Whether the connection holds an attested session
+    pub attested: bool,
+}
+
+impl MockAttestedConnection {
+    pub fn new(uri: ConsensusClientUri) -> Self {
+        Self {
+            uri,
+            attest_calls: 0,
+            attested: false,
+        }
+    }
+}
+
+impl FmtDisplay for MockAttestedConnection {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.uri)
+    }
+}
+
+impl Connection for MockAttestedConnection {
+    type Uri = ConsensusClientUri;
+
+    fn uri(&self) -> Self::Uri {
+        self.uri.clone()
+    }
+}
+
+#[derive(Debug, Display)]
+pub enum MockAttestationError {
+    /// Attestation was refused
+    Refused,
+
+    /// The call failed
+    Failed,
+}
+
+impl AttestationError for MockAttestationError {}
+
+impl From<GrpcError> for MockAttestationError {
+    fn from(_src: GrpcError) -> Self {
+        MockAttestationError::Failed
+    }
+}
+
+impl AttestedConnection for MockAttestedConnection {
+    type Error = MockAttestationError;
+
+    fn is_attested(&self) -> bool {
+        self.attested
+    }
+
+    fn attest(&mut self) -> StdResult<VerificationReport, Self::Error> {
+        self.attest_calls += 1;
+        if self.uri.host().starts_with("panics") {
+            panic!("attesting {} panicked", self.uri);
+        }
+        if self.uri.host().starts_with("refused") {
+            return Err(MockAttestationError::Refused);
+        }
+        self.attested = true;
+        Ok(VerificationReport::default())
+    }
+
+    fn deattest(&mut self) {
+        self.attested = false;
+    }
+}
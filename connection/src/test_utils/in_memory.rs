@@ -0,0 +1,272 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A blockchain connection mock serving a fixed list of blocks, with
+//! injectable failures
+
+use crate::{
+    error::{Error as ConnectionError, Result as ConnectionResult},
+    traits::{BlockInfo, BlockchainConnection, Connection},
+};
+use grpcio::{Error as GrpcError, RpcStatus, RpcStatusCode};
+use mc_transaction_core::{constants::MINIMUM_FEE, Block, BlockID, BlockIndex};
+use mc_util_uri::{ConnectionUri, ConsensusClientUri};
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Formatter, Result as FmtResult},
+    hash::{Hash, Hasher},
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc,
+    },
+};
+
+/// A `BlockchainConnection` backed by an in-memory list of blocks, which does
+/// not need a ledger.
+///
+/// Wrap it in a `SyncConnection` to exercise the retryable form of the trait.
+/// Failures are reported as `UNAVAILABLE`, which the retryable form retries.
+#[derive(Clone)]
+pub struct InMemoryBlockchainConnection {
+    /// The destination uri
+    pub uri: ConsensusClientUri,
+
+    /// The blocks served, in order of index
+    pub blocks: Vec<Block>,
+
+    /// If set, every call whose (1-based) number is a multiple of this fails
+    pub fail_every: Option<u64>,
+
+    /// If set, `fetch_blocks` and `fetch_block_ids` return at most this many
+    /// blocks, as a node limiting its page size would
+    pub max_blocks_per_call: Option<usize>,
+
+    /// The number of calls made so far, including failed ones
+    pub calls: u64,
+
+    /// While false, every call fails as if the peer were unreachable. Clones
+    /// share it, so a test can take a peer down and bring it back.
+    pub online: Arc<AtomicBool>,
+}
+
+impl InMemoryBlockchainConnection {
+    pub fn new(uri: ConsensusClientUri, blocks: Vec<Block>) -> Self {
+        Self {
+            uri,
+            blocks,
+            fail_every: None,
+            max_blocks_per_call: None,
+            calls: 0,
+            online: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Count a call, failing it if it is due to fail.
+    fn begin_call(&mut self) -> ConnectionResult<()> {
+        self.calls += 1;
+        if !self.online.load(AtomicOrdering::SeqCst) {
+            return Err(ConnectionError::Grpc(GrpcError::RpcFailure(
+                RpcStatus::new(RpcStatusCode::UNAVAILABLE, Some("Peer offline".to_string())),
+            )));
+        }
+        match self.fail_every {
+            Some(fail_every) if fail_every > 0 && self.calls % fail_every == 0 => Err(
+                ConnectionError::Grpc(GrpcError::RpcFailure(RpcStatus::new(
+                    RpcStatusCode::UNAVAILABLE,
+                    Some("Injected failure".to_string()),
+                ))),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// The blocks in `range`, limited to `max_blocks_per_call`.
+    fn blocks_in(&self, range: Range<BlockIndex>) -> ConnectionResult<Vec<&Block>> {
+        let blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .filter(|block| range.contains(&block.index))
+            .take(self.max_blocks_per_call.unwrap_or(usize::MAX))
+            .collect();
+
+        if blocks.is_empty() {
+            return Err(ConnectionError::NotFound);
+        }
+        Ok(blocks)
+    }
+
+    fn last_block_index(&self) -> ConnectionResult<BlockIndex> {
+        self.blocks
+            .last()
+            .map(|block| block.index)
+            .ok_or(ConnectionError::NotFound)
+    }
+}
+
+impl Display for InMemoryBlockchainConnection {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.uri())
+    }
+}
+
+impl Eq for InMemoryBlockchainConnection {}
+
+impl Hash for InMemoryBlockchainConnection {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.uri.addr().hash(state);
+    }
+}
+
+impl Ord for InMemoryBlockchainConnection {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.uri.addr().cmp(&other.uri.addr())
+    }
+}
+
+impl PartialEq for InMemoryBlockchainConnection {
+    fn eq(&self, other: &Self) -> bool {
+        self.uri.addr() == other.uri.addr()
+    }
+}
+
+impl PartialOrd for InMemoryBlockchainConnection {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.uri.addr().partial_cmp(&other.uri.addr())
+    }
+}
+
+impl Connection for InMemoryBlockchainConnection {
+    type Uri = ConsensusClientUri;
+
+    fn uri(&self) -> Self::Uri {
+        self.uri.clone()
+    }
+}
+
+impl BlockchainConnection for InMemoryBlockchainConnection {
+    fn fetch_blocks(&mut self, range: Range<BlockIndex>) -> ConnectionResult<Vec<Block>> {
+        self.begin_call()?;
+        Ok(self.blocks_in(range)?.into_iter().cloned().collect())
+    }
+
+    fn fetch_block_ids(&mut self, range: Range<BlockIndex>) -> ConnectionResult<Vec<BlockID>> {
+        self.begin_call()?;
+        Ok(self
+            .blocks_in(range)?
+            .into_iter()
+            .map(|block| block.id.clone())
+            .collect())
+    }
+
+    fn fetch_block_height(&mut self) -> ConnectionResult<BlockIndex> {
+        self.begin_call()?;
+        self.last_block_index()
+    }
+
+    fn fetch_block_info(&mut self) -> ConnectionResult<BlockInfo> {
+        self.begin_call()?;
+        Ok(BlockInfo {
+            block_index: self.last_block_index()?,
+            minimum_fee: MINIMUM_FEE,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        manager::ConnectionManager,
+        retry_policy::RetryPolicy,
+        sync::SyncConnection,
+        test_utils::{chain, test_client_uri},
+        traits::RetryableBlockchainConnection,
+    };
+    use mc_common::logger::create_null_logger;
+    use std::time::Duration;
+
+    #[test]
+    // Every Nth call should fail, and retrying should get past it.
+    fn injected_failures_are_retried() {
+        let mut conn = InMemoryBlockchainConnection::new(test_client_uri(1), chain(10));
+        conn.fail_every = Some(2);
+
+        assert_eq!(conn.fetch_block_height().unwrap(), 9);
+
+        // The second call fails, and the retry succeeds.
+        let sync_conn = SyncConnection::new(conn, create_null_logger());
+        let success = sync_conn
+            .fetch_blocks(0..5, RetryPolicy::fixed(Duration::from_millis(0), 2))
+            .unwrap();
+        assert_eq!(success.value.len(), 5);
+        assert_eq!(success.attempts, 2);
+
+        match sync_conn.write().fetch_block_height() {
+            Err(ConnectionError::Grpc(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(sync_conn.read().calls, 4);
+    }
+
+    #[test]
+    // Responses should be cut short at the configured number of blocks.
+    fn partial_ranges_are_returned() {
+        let mut conn = InMemoryBlockchainConnection::new(test_client_uri(1), chain(10));
+        conn.max_blocks_per_call = Some(3);
+
+        let indices: Vec<_> = conn
+            .fetch_blocks(4..10)
+            .unwrap()
+            .iter()
+            .map(|block| block.index)
+            .collect();
+        assert_eq!(indices, vec![4, 5, 6]);
+        assert_eq!(conn.fetch_block_ids(8..20).unwrap().len(), 2);
+
+        match conn.fetch_blocks(10..12) {
+            Err(ConnectionError::NotFound) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // Blocks should be cached only once a quorum of peers vouch for them, and
+    // then served without asking the peers again.
+    fn fetch_blocks_cached_serves_verified_blocks() {
+        let blocks = chain(10);
+        let mut forged = blocks.clone();
+        forged[5].id = forged[4].id.clone();
+        let manager = ConnectionManager::new(
+            vec![
+                InMemoryBlockchainConnection::new(test_client_uri(1), blocks.clone()),
+                InMemoryBlockchainConnection::new(test_client_uri(2), forged),
+            ],
+            create_null_logger(),
+        );
+
+        // The peers disagree about block 5, but agree about 2..5.
+        assert!(manager.fetch_blocks_cached(2..8, 2).is_err());
+        assert_eq!(
+            manager.fetch_blocks_cached(2..5, 2).unwrap(),
+            blocks[2..5].to_vec()
+        );
+
+        // Giving a clone its own cache size leaves it with an empty cache of
+        // its own, while other clones keep sharing the original.
+        let resized = manager.clone().with_block_cache_size(10);
+        for conn in manager.conns() {
+            conn.write().fail_every = Some(1);
+        }
+        assert_eq!(
+            manager.clone().fetch_blocks_cached(2..5, 2).unwrap(),
+            blocks[2..5].to_vec()
+        );
+        assert!(resized.fetch_blocks_cached(2..5, 2).is_err());
+
+        // Nothing from the failed fetch was cached.
+        match manager.fetch_blocks_cached(5..6, 2) {
+            Err(ConnectionError::Grpc(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
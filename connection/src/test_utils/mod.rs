@@ -0,0 +1,38 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Connection mocks and test utilities, shared by this crate's tests and
+//! re-exported by `mc-connection-test-utils`
+
+mod attested;
+mod in_memory;
+mod user_tx;
+
+pub use self::{
+    attested::{MockAttestationError, MockAttestedConnection},
+    in_memory::InMemoryBlockchainConnection,
+    user_tx::MockUserTxConnection,
+};
+
+use mc_transaction_core::{Block, BlockContents, BLOCK_VERSION};
+use mc_util_uri::ConsensusClientUri;
+use std::str::FromStr;
+
+pub fn test_client_uri(node_id: u32) -> ConsensusClientUri {
+    ConsensusClientUri::from_str(&format!("mc://node{}.test.com/", node_id))
+        .expect("Could not construct client uri from string")
+}
+
+/// A chain of `len` empty blocks, starting with an origin block.
+pub fn chain(len: usize) -> Vec<Block> {
+    let mut blocks = vec![Block::new_origin_block(&[])];
+    while blocks.len() < len {
+        let block = Block::new_with_parent(
+            BLOCK_VERSION,
+            blocks.last().unwrap(),
+            &Default::default(),
+            &BlockContents::default(),
+        );
+        blocks.push(block);
+    }
+    blocks
+}
@@ -0,0 +1,190 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! User Transaction Connection Mock
+
+use crate::{
+    error::{Error as ConnectionError, Result as ConnectionResult},
+    traits::{Connection, IdempotencyKey, MempoolConnection, ProposeTxResponse, UserTxConnection},
+};
+use grpcio::{Error as GrpcError, RpcStatus, RpcStatusCode};
+use mc_transaction_core::{
+    ring_signature::Error as RingSignatureError,
+    tx::{Tx, TxHash},
+    validation::TransactionValidationError,
+};
+use mc_util_uri::{ConnectionUri, ConsensusClientUri};
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Formatter, Result as FmtResult},
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone)]
+pub struct MockUserTxConnection {
+    uri: ConsensusClientUri,
+    pub submitted_txs: Vec<Tx>,
+    pub pending_tx_hashes: Vec<TxHash>,
+    /// Transactions which `propose_tx` rejects as invalid.
+    pub rejected_tx_hashes: Vec<TxHash>,
+    /// The idempotency key of each submission, in order.
+    pub idempotency_keys: Vec<IdempotencyKey>,
+    /// The number of submissions which fail with a retriable error before any
+    /// succeeds.
+    pub failures: usize,
+    /// The round trip `ping` reports, or `None` if pings are unsupported.
+    pub round_trip: Option<Duration>,
+    /// The deadline submissions must complete by, if any.
+    pub deadline: Option<Instant>,
+}
+
+impl MockUserTxConnection {
+    pub fn new(uri: ConsensusClientUri) -> Self {
+        MockUserTxConnection {
+            uri,
+            submitted_txs: Vec::new(),
+            pending_tx_hashes: Vec::new(),
+            rejected_tx_hashes: Vec::new(),
+            idempotency_keys: Vec::new(),
+            failures: 0,
+            round_trip: None,
+            deadline: None,
+        }
+    }
+}
+
+impl Display for MockUserTxConnection {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.uri())
+    }
+}
+
+impl Eq for MockUserTxConnection {}
+
+impl Hash for MockUserTxConnection {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.uri.addr().hash(state);
+    }
+}
+
+impl Ord for MockUserTxConnection {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.uri.addr().cmp(&other.uri.addr())
+    }
+}
+
+impl PartialEq for MockUserTxConnection {
+    fn eq(&self, other: &MockUserTxConnection) -> bool {
+        self.uri.addr() == other.uri.addr()
+    }
+}
+
+impl PartialOrd for MockUserTxConnection {
+    fn partial_cmp(&self, other: &MockUserTxConnection) -> Option<Ordering> {
+        self.uri.addr().partial_cmp(&other.uri.addr())
+    }
+}
+
+impl Connection for MockUserTxConnection {
+    type Uri = ConsensusClientUri;
+
+    fn uri(&self) -> Self::Uri {
+        self.uri.clone()
+    }
+
+    fn ping(&mut self) -> ConnectionResult<Duration> {
+        self.round_trip
+            .ok_or_else(|| ConnectionError::UnsupportedByPeer("ping".to_string()))
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+}
+
+impl UserTxConnection for MockUserTxConnection {
+    fn propose_tx(&mut self, tx: &Tx) -> ConnectionResult<ProposeTxResponse> {
+        self.propose_tx_with_idempotency_key(tx, &IdempotencyKey::random())
+    }
+
+    fn propose_tx_with_idempotency_key(
+        &mut self,
+        tx: &Tx,
+        idempotency_key: &IdempotencyKey,
+    ) -> ConnectionResult<ProposeTxResponse> {
+        self.idempotency_keys.push(*idempotency_key);
+        if self
+            .deadline
+            .map_or(false, |deadline| Instant::now() >= deadline)
+        {
+            let status = RpcStatus::new(RpcStatusCode::DEADLINE_EXCEEDED, None);
+            return Err(ConnectionError::Grpc(GrpcError::RpcFailure(status)));
+        }
+        if self.idempotency_keys.len() <= self.failures {
+            let status = RpcStatus::new(RpcStatusCode::UNAVAILABLE, None);
+            return Err(ConnectionError::Grpc(GrpcError::RpcFailure(status)));
+        }
+        if self.rejected_tx_hashes.contains(&tx.tx_hash()) {
+            return Err(ConnectionError::TransactionValidation(
+                TransactionValidationError::InvalidTransactionSignature(
+                    RingSignatureError::InvalidSignature,
+                ),
+            ));
+        }
+        self.submitted_txs.push(tx.clone());
+        Ok(ProposeTxResponse::new(tx, 1))
+    }
+}
+
+impl MempoolConnection for MockUserTxConnection {
+    fn fetch_pending_tx_hashes(&mut self) -> ConnectionResult<Vec<TxHash>> {
+        Ok(self.pending_tx_hashes.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_client_uri;
+
+    #[test]
+    // All of the peer's pending transaction hashes should be delivered to the sink.
+    fn stream_pending_txs() {
+        let mut mock_peer = MockUserTxConnection::new(test_client_uri(123));
+        mock_peer.pending_tx_hashes = vec![TxHash([1u8; 32]), TxHash([2u8; 32]), TxHash([3u8; 32])];
+
+        let mut delivered = Vec::new();
+        mock_peer
+            .stream_pending_txs(|tx_hash| delivered.push(tx_hash))
+            .unwrap();
+
+        assert_eq!(delivered, mock_peer.pending_tx_hashes);
+    }
+
+    #[test]
+    // A rejected transaction should not prevent the rest of the batch from being
+    // submitted.
+    fn propose_tx_batch_reports_partial_failure() {
+        let mut mock_peer = MockUserTxConnection::new(test_client_uri(123));
+
+        let txs: Vec<Tx> = (1..=3)
+            .map(|fee| {
+                let mut tx = Tx::default();
+                tx.prefix.fee = fee;
+                tx
+            })
+            .collect();
+        mock_peer.rejected_tx_hashes = vec![txs[1].tx_hash()];
+
+        let results = mock_peer.propose_tx_batch(&txs).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().tx_hash, txs[0].tx_hash());
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().tx_hash, txs[2].tx_hash());
+
+        assert_eq!(
+            mock_peer.submitted_txs,
+            vec![txs[0].clone(), txs[2].clone()]
+        );
+    }
+}
@@ -4,17 +4,27 @@
 //! The attested client implementation.
 
 use crate::{
+    attested::Attested,
+    capabilities::PeerCapabilities,
+    compression::{decompress_blocks, ACCEPTED_BLOCK_COMPRESSION},
     credentials::{AuthenticationError, CredentialsProvider, CredentialsProviderError},
     error::{Error, Result},
+    metrics::{ConnectionMetrics, NoopConnectionMetrics},
+    retry_policy::{RetryPolicy, DEFAULT_RETRY_INITIAL_DELAY},
     traits::{
-        AttestationError, AttestedConnection, BlockInfo, BlockchainConnection, Connection,
-        UserTxConnection,
+        AsyncBlockchainConnection, AsyncUserTxConnection, AttestationError, AttestedConnection,
+        BlockInfo, BlockchainConnection, Connection, ConnectionFuture, FeeConnection,
+        IdempotencyKey, MempoolConnection, ProposeTxResponse, UserTxConnection,
     },
+    verify::{validate_block_chain, verify_block_signatures},
 };
 use aes_gcm::Aes256Gcm;
 use cookie::CookieJar;
 use displaydoc::Display;
-use grpcio::{CallOption, ChannelBuilder, Environment, Error as GrpcError, MetadataBuilder};
+use grpcio::{
+    CallOption, Channel, ChannelBuilder, ClientUnaryReceiver, ConnectivityState, Environment,
+    Error as GrpcError, Metadata, MetadataBuilder, RpcStatusCode,
+};
 use mc_attest_ake::{
     AuthResponseInput, ClientInitiate, Error as AkeError, Ready, Start, Transition,
 };
@@ -22,18 +32,28 @@ use mc_attest_api::{attest::Message, attest_grpc::AttestedApiClient};
 use mc_attest_core::{VerificationReport, Verifier};
 use mc_common::{
     logger::{log, o, Logger},
-    trace_time,
+    trace_time, ResponderId,
 };
 use mc_consensus_api::{
+    consensus_client::FeeEstimateRequest,
     consensus_client_grpc::ConsensusClientApiClient,
-    consensus_common::{BlocksRequest, ProposeTxResult},
+    consensus_common::{
+        BlocksRequest, BlocksResponse, CheckKeyImageRequest, CompressedBlocksRequest,
+        ProposeTxResponse as ProposeTxResponseProto, ProposeTxResult,
+    },
     consensus_common_grpc::BlockchainApiClient,
     empty::Empty,
+    ConversionError, ATTESTATION_VERSION_HEADER, IDEMPOTENCY_KEY_HEADER,
+    OLDEST_ATTESTATION_VERSION, SUPPORTED_ATTESTATION_VERSIONS,
 };
-use mc_crypto_keys::X25519;
+use mc_crypto_keys::{Ed25519Public, X25519};
 use mc_crypto_noise::CipherError;
 use mc_crypto_rand::McRng;
-use mc_transaction_core::{tx::Tx, Block, BlockID, BlockIndex};
+use mc_transaction_core::{
+    ring_signature::KeyImage,
+    tx::{Tx, TxHash, TxOutMembershipProof},
+    Block, BlockID, BlockIndex, BlockSignature,
+};
 use mc_util_grpc::{ConnectionUriGrpcioChannel, GrpcCookieStore};
 use mc_util_serial::encode;
 use mc_util_uri::{ConnectionUri, ConsensusClientUri as ClientUri, UriConversionError};
@@ -43,12 +63,29 @@ use std::{
     cmp::Ordering,
     convert::TryFrom,
     fmt::{Display, Formatter, Result as FmtResult},
+    future,
     hash::{Hash, Hasher},
     ops::Range,
     result::Result as StdResult,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+/// How long a fetched minimum fee is reused before asking the node again.
+const MINIMUM_FEE_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// How often to check whether a new channel has connected, when waiting for
+/// it to do so.
+const CONNECT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How many channels `reconnect` builds before giving up on a node which
+/// cannot be reached.
+const RECONNECT_MAX_ATTEMPTS: usize = 3;
+
 /// Attestation failures a thick client can generate
 #[derive(Debug, Display)]
 pub enum ThickClientAttestationError {
@@ -101,6 +138,37 @@ impl From<Box<dyn CredentialsProviderError + 'static>> for ThickClientAttestatio
     }
 }
 
+impl ThickClientAttestationError {
+    /// Whether the failure shows that the gRPC channel can no longer reach
+    /// the node, so that it should be rebuilt.
+    pub fn is_dead_channel(&self) -> bool {
+        match self {
+            Self::Grpc(grpc_error) => is_dead_channel(grpc_error),
+            _ => false,
+        }
+    }
+
+    /// Whether the node rejected attestation because it does not support the
+    /// protocol version offered.
+    pub fn is_version_mismatch(&self) -> bool {
+        match self {
+            Self::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::FAILED_PRECONDITION
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether a gRPC failure happened at the transport, rather than in the node.
+fn is_dead_channel(err: &GrpcError) -> bool {
+    match err {
+        GrpcError::RpcFailure(rpc_status) => rpc_status.status == RpcStatusCode::UNAVAILABLE,
+        GrpcError::RemoteStopped => true,
+        _ => false,
+    }
+}
+
 impl AuthenticationError for ThickClientAttestationError {
     fn is_unauthenticated(&self) -> bool {
         match self {
@@ -110,14 +178,88 @@ impl AuthenticationError for ThickClientAttestationError {
     }
 }
 
-impl AttestationError for ThickClientAttestationError {}
+impl AttestationError for ThickClientAttestationError {
+    fn is_retriable(&self) -> bool {
+        match self {
+            // The peer understood the request and rejected it outright.
+            Self::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status != RpcStatusCode::INVALID_ARGUMENT
+                    && rpc_status.status != RpcStatusCode::UNIMPLEMENTED
+                    && rpc_status.status != RpcStatusCode::ALREADY_EXISTS
+            }
+            // The peer's response could not be decoded.
+            Self::Grpc(GrpcError::Codec(_)) => false,
+            // Transport failures and timeouts may succeed on a later attempt.
+            Self::Grpc(_) => true,
+            // The peer's response was malformed or failed verification, or our
+            // own configuration is unusable, so trying again will not help.
+            Self::Ake(_)
+            | Self::Cipher(_)
+            | Self::InvalidResponderID(_, _)
+            | Self::UriConversionError(_)
+            | Self::CredentialsProvider(_) => false,
+        }
+    }
+
+    fn is_deadline_exceeded(&self) -> bool {
+        match self {
+            Self::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::DEADLINE_EXCEEDED
+            }
+            _ => false,
+        }
+    }
+
+    fn is_already_exists(&self) -> bool {
+        match self {
+            Self::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::ALREADY_EXISTS
+            }
+            _ => false,
+        }
+    }
+
+    fn is_rate_limited(&self) -> bool {
+        match self {
+            Self::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::RESOURCE_EXHAUSTED
+            }
+            _ => false,
+        }
+    }
+
+    fn is_unimplemented(&self) -> bool {
+        match self {
+            Self::Grpc(GrpcError::RpcFailure(rpc_status)) => {
+                rpc_status.status == RpcStatusCode::UNIMPLEMENTED
+            }
+            _ => false,
+        }
+    }
+}
 
 /// A connection from a client to a consensus enclave.
+///
+/// Two clients are equal, hash alike, and are ordered by, the responder ID of
+/// the node they connect to, as given by `ConnectionUri::responder_id`. Other
+/// state, such as attestation or credentials, is not compared, so clients for
+/// the same node are interchangeable as keys.
 pub struct ThickClient<CP: CredentialsProvider> {
     /// The destination's URI
     uri: ClientUri,
+    /// The destination's responder ID, which identifies this connection
+    responder_id: ResponderId,
     /// The logging instance
     logger: Logger,
+    /// The gRPC environment, used to rebuild the channel when it fails.
+    env: Arc<Environment>,
+    /// The channel the API clients share, kept to report its state.
+    channel: Channel,
+    /// Whether a call found the channel dead, so that the next attested call
+    /// should replace it.
+    channel_failed: bool,
+    /// The delays between attempts to reach the node in `reconnect`.
+    reconnect_policy: RetryPolicy,
     /// The gRPC API client we will use for blockchain detail retrieval.
     blockchain_api_client: BlockchainApiClient,
     /// The gRPC API client we will use for attestation and (eventually)
@@ -129,15 +271,42 @@ pub struct ThickClient<CP: CredentialsProvider> {
     verifier: Verifier,
     /// The AKE state machine object, if one is available.
     enclave_connection: Option<Ready<Aes256Gcm>>,
+    /// The attestation protocol versions to offer, newest first.
+    attestation_versions: Vec<u32>,
+    /// The attestation protocol version of the current attested session.
+    attested_version: Option<u32>,
+    /// The optional APIs the node serves, once asked during this attested
+    /// session.
+    peer_capabilities: Option<PeerCapabilities>,
     /// Generic interface for retreiving GRPC credentials.
     credentials_provider: CP,
     /// A hash map of metadata to set on outbound requests, filled by inbound
     /// `Set-Cookie` metadata
     cookies: CookieJar,
+    /// The node's block signing key, if signed blocks should be checked
+    /// against it.
+    pinned_signer: Option<Ed25519Public>,
+    /// The most recently fetched minimum fee, and when it was fetched.
+    cached_minimum_fee: Option<(u64, Instant)>,
+    /// The time by which calls must complete, if any.
+    deadline: Option<Instant>,
+    /// The longest any single gRPC call may take, if limited.
+    call_deadline: Option<Duration>,
+    /// Whether `fetch_blocks` asks for compressed blocks from nodes which
+    /// serve them.
+    block_compression: bool,
+    /// Where to report call timings and attestations.
+    metrics: Arc<dyn ConnectionMetrics>,
+    /// Set by a non-blocking call whose RPC failed, so that the next call
+    /// can reset the attested session and credentials.
+    async_call_failed: Arc<AtomicBool>,
 }
 
 impl<CP: CredentialsProvider> ThickClient<CP> {
     /// Create a new attested connection to the given consensus node.
+    ///
+    /// The channel connects lazily, so an unreachable node is only noticed
+    /// when the first call fails.
     pub fn new(
         uri: ClientUri,
         verifier: Verifier,
@@ -145,38 +314,252 @@ impl<CP: CredentialsProvider> ThickClient<CP> {
         credentials_provider: CP,
         logger: Logger,
     ) -> Result<Self> {
-        let logger = logger.new(o!("mc.cxn" => uri.to_string()));
+        Self::connect(uri, verifier, env, credentials_provider, None, logger)
+    }
+
+    /// Create a new attested connection to the given consensus node, failing
+    /// with `Error::ConnectFailed` if the channel does not connect within
+    /// `connect_timeout`.
+    ///
+    /// This bounds only the initial connection. Each call is still limited by
+    /// the call deadline, if one is set.
+    pub fn new_with_connect_timeout(
+        uri: ClientUri,
+        verifier: Verifier,
+        env: Arc<Environment>,
+        credentials_provider: CP,
+        connect_timeout: Duration,
+        logger: Logger,
+    ) -> Result<Self> {
+        Self::connect(
+            uri,
+            verifier,
+            env,
+            credentials_provider,
+            Some(connect_timeout),
+            logger,
+        )
+    }
 
-        let ch = ChannelBuilder::default_channel_builder(env).connect_to_uri(&uri, &logger);
+    fn connect(
+        uri: ClientUri,
+        verifier: Verifier,
+        env: Arc<Environment>,
+        credentials_provider: CP,
+        connect_timeout: Option<Duration>,
+        logger: Logger,
+    ) -> Result<Self> {
+        let logger = logger.new(o!("mc.cxn" => uri.to_string()));
+        let responder_id = uri
+            .responder_id()
+            .map_err(ThickClientAttestationError::from)?;
 
-        let attested_api_client = AttestedApiClient::new(ch.clone());
-        let blockchain_api_client = BlockchainApiClient::new(ch.clone());
-        let consensus_client_api_client = ConsensusClientApiClient::new(ch);
+        let ch = ChannelBuilder::default_channel_builder(env.clone()).connect_to_uri(&uri, &logger);
+        if let Some(connect_timeout) = connect_timeout {
+            wait_for_connected(&ch, &uri, connect_timeout)?;
+        }
+        let (attested_api_client, blockchain_api_client, consensus_client_api_client) =
+            api_clients(ch.clone());
 
         Ok(Self {
             uri,
+            responder_id,
             logger,
+            env,
+            channel: ch,
+            channel_failed: false,
+            reconnect_policy: RetryPolicy::exponential_backoff(
+                DEFAULT_RETRY_INITIAL_DELAY,
+                RECONNECT_MAX_ATTEMPTS,
+            )
+            .with_jitter(),
             blockchain_api_client,
             consensus_client_api_client,
             attested_api_client,
             verifier,
             enclave_connection: None,
+            attestation_versions: SUPPORTED_ATTESTATION_VERSIONS.to_vec(),
+            attested_version: None,
+            peer_capabilities: None,
             credentials_provider,
             cookies: CookieJar::default(),
+            pinned_signer: None,
+            cached_minimum_fee: None,
+            deadline: None,
+            call_deadline: None,
+            block_compression: false,
+            metrics: Arc::new(NoopConnectionMetrics),
+            async_call_failed: Arc::new(AtomicBool::new(false)),
         })
     }
 
+    /// Pin the consensus node's block signing key.
+    ///
+    /// Once set, `fetch_signed_blocks` rejects any block which was not signed
+    /// by this key.
+    pub fn set_pinned_signer(&mut self, pinned_signer: Option<Ed25519Public>) {
+        self.pinned_signer = pinned_signer;
+    }
+
+    /// Limit how long each gRPC call may take.
+    ///
+    /// A call which runs past this deadline fails with `Error::CallTimeout`,
+    /// which is retried. When a deadline is also set for a group of calls, the
+    /// earlier of the two applies.
+    pub fn set_call_deadline(&mut self, deadline: Duration) {
+        self.call_deadline = Some(deadline);
+    }
+
+    /// Ask nodes which serve `GetCompressedBlocks` for compressed blocks in
+    /// `fetch_blocks`, to save bandwidth when syncing. Off by default.
+    ///
+    /// Nodes which do not serve compressed blocks are asked for them
+    /// uncompressed, as if this were off.
+    pub fn set_block_compression(&mut self, enabled: bool) {
+        self.block_compression = enabled;
+    }
+
+    /// Offer the given attestation protocol versions, newest first, instead
+    /// of `SUPPORTED_ATTESTATION_VERSIONS`. An empty list restores the
+    /// default.
+    ///
+    /// `attest` offers each version in turn, moving to the next only if the
+    /// node rejects the last as unsupported, so a client keeps working while
+    /// the network is part way through an upgrade.
+    pub fn set_attestation_versions(&mut self, versions: Vec<u32>) {
+        self.attestation_versions = if versions.is_empty() {
+            SUPPORTED_ATTESTATION_VERSIONS.to_vec()
+        } else {
+            versions
+        };
+    }
+
+    /// The attestation protocol version negotiated with the node, or `None`
+    /// if the connection is not attested.
+    ///
+    /// Nodes echo the version they accepted. A node which predates version
+    /// negotiation ignores the offered version and does not echo it, so the
+    /// oldest version, which is the one it speaks, is reported for it.
+    pub fn attested_version(&self) -> Option<u32> {
+        self.attested_version
+    }
+
+    /// Report the timing of each gRPC call, and each attestation, to the given
+    /// metrics sink.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn ConnectionMetrics>) {
+        self.metrics = metrics;
+    }
+
+    /// The state of the gRPC channel to the node.
+    ///
+    /// This only reads the state: an idle channel is not asked to connect.
+    /// A channel which has never connected stays `GRPC_CHANNEL_IDLE` until a
+    /// call is made, while one which lost its connection reports
+    /// `GRPC_CHANNEL_TRANSIENT_FAILURE`.
+    pub fn channel_state(&self) -> ConnectivityState {
+        self.channel.check_connectivity_state(false)
+    }
+
+    /// Replace the gRPC channel to the node, and attest over the new one.
+    ///
+    /// The fresh channel drops the attested session and cookies tied to the
+    /// old one. While the new channel cannot reach the node, it is replaced
+    /// again after an exponential backoff, up to `RECONNECT_MAX_ATTEMPTS`
+    /// channels, or until the next wait would run past the deadline for the
+    /// current group of calls.
+    ///
+    /// An attested call which finds the channel dead reconnects and makes the
+    /// call once more before returning, except for `propose_tx`, which leaves
+    /// reconnecting to the next call.
+    pub fn reconnect(&mut self) -> StdResult<VerificationReport, ThickClientAttestationError> {
+        trace_time!(self.logger, "ThickClient::reconnect");
+
+        let mut delays = self.reconnect_policy.into_iter();
+        loop {
+            let err = match self.replace_channel() {
+                Err(err) if err.is_dead_channel() => err,
+                result => return result,
+            };
+            let delay = match delays.next() {
+                Some(delay) => delay,
+                None => return Err(err),
+            };
+            if self
+                .deadline
+                .map_or(false, |deadline| Instant::now() + delay >= deadline)
+            {
+                return Err(err);
+            }
+            log::debug!(
+                self.logger,
+                "Could not reach the node, reconnecting in {:?}: {}",
+                delay,
+                err
+            );
+            thread::sleep(delay);
+        }
+    }
+
+    /// Build a new gRPC channel to the node, and attest over it.
+    fn replace_channel(&mut self) -> StdResult<VerificationReport, ThickClientAttestationError> {
+        self.deattest();
+        self.channel = ChannelBuilder::default_channel_builder(self.env.clone())
+            .connect_to_uri(&self.uri, &self.logger);
+        let (attested_api_client, blockchain_api_client, consensus_client_api_client) =
+            api_clients(self.channel.clone());
+        self.attested_api_client = attested_api_client;
+        self.blockchain_api_client = blockchain_api_client;
+        self.consensus_client_api_client = consensus_client_api_client;
+        self.channel_failed = false;
+
+        self.attest()
+    }
+
+    /// Time a single gRPC call, reporting it to the metrics sink.
+    fn timed_rpc<T, E>(
+        &mut self,
+        rpc: &'static str,
+        func: impl FnOnce(&mut Self) -> StdResult<T, E>,
+    ) -> StdResult<T, E> {
+        let connection = self.uri.to_string();
+        self.metrics.call_attempted(&connection, rpc);
+        let start = Instant::now();
+        let result = func(self);
+        if result.is_ok() {
+            self.metrics
+                .call_succeeded(&connection, rpc, start.elapsed());
+        } else {
+            self.metrics.call_failed(&connection, rpc, start.elapsed());
+        }
+        result
+    }
+
     /// A wrapper for performing an authenticated call. This also takes care to
-    /// properly include cookie information in the request.
+    /// properly include cookie information and the idempotency key, if any, in
+    /// the request.
     fn authenticated_call<
         T,
         E: AuthenticationError + From<Box<dyn CredentialsProviderError + 'static>>,
     >(
         &mut self,
+        idempotency_key: Option<&IdempotencyKey>,
         func: impl FnOnce(&mut Self, CallOption) -> StdResult<T, E>,
     ) -> StdResult<T, E> {
         // Make the actual RPC call.
-        let call_option = self.call_option()?;
+        let call_option = self.call_option(idempotency_key, None)?;
+        self.call_with_credentials(call_option, func)
+    }
+
+    /// Make a call with the given options, clearing the credentials if the
+    /// node rejected them.
+    fn call_with_credentials<
+        T,
+        E: AuthenticationError + From<Box<dyn CredentialsProviderError + 'static>>,
+    >(
+        &mut self,
+        call_option: CallOption,
+        func: impl FnOnce(&mut Self, CallOption) -> StdResult<T, E>,
+    ) -> StdResult<T, E> {
         let result = func(self, call_option);
 
         // If the call failed due to authentication (credentials) error, reset creds so
@@ -192,15 +575,212 @@ impl<CP: CredentialsProvider> ThickClient<CP> {
     /// A convenience wrapper for performing authenticated+attested GRPC calls
     fn authenticated_attested_call<T>(
         &mut self,
+        rpc: &'static str,
+        mut func: impl FnMut(&mut Self, CallOption) -> StdResult<T, GrpcError>,
+    ) -> Result<T> {
+        self.reconnecting_call(|this| this.idempotent_attested_call(rpc, None, &mut func))
+    }
+
+    /// Make an attested call, and if it found the channel dead, make it once
+    /// more. The second attempt's attested call replaces the channel first, and
+    /// builds the call options anew, since cookies do not carry over.
+    ///
+    /// A call which started with a dead channel has already tried to replace
+    /// it, so it is not made again.
+    fn reconnecting_call<T, E>(
+        &mut self,
+        mut call: impl FnMut(&mut Self) -> StdResult<T, E>,
+    ) -> StdResult<T, E> {
+        let channel_failed = self.channel_failed;
+        match call(self) {
+            Err(_err) if !channel_failed && self.channel_failed => {
+                log::debug!(self.logger, "Channel to the node failed, reconnecting");
+                call(self)
+            }
+            result => result,
+        }
+    }
+
+    /// An authenticated+attested GRPC call which may carry an idempotency key
+    ///
+    /// This is not retried over a new channel, as `reconnecting_call` would,
+    /// because `propose_tx` sends a message encrypted for the current session.
+    fn idempotent_attested_call<T>(
+        &mut self,
+        rpc: &'static str,
+        idempotency_key: Option<&IdempotencyKey>,
         func: impl FnOnce(&mut Self, CallOption) -> StdResult<T, GrpcError>,
-    ) -> StdResult<T, ThickClientAttestationError> {
-        self.authenticated_call(|this, call_option| {
-            this.attested_call(|this| func(this, call_option))
+    ) -> Result<T> {
+        self.authenticated_call(idempotency_key, |this, call_option| {
+            this.attested_call(|this| this.timed_rpc(rpc, |this| func(this, call_option)))
+        })
+        .map_err(|err| self.call_error(err))
+    }
+
+    /// Convert the error from a gRPC call, reporting a call which ran past its
+    /// call deadline as a call timeout.
+    ///
+    /// A call cut short because the deadline for its group of calls passed is
+    /// not a call timeout, and is left for the group to report.
+    fn call_error(&self, err: ThickClientAttestationError) -> Error {
+        let group_expired = self
+            .deadline
+            .map_or(false, |deadline| Instant::now() >= deadline);
+        match self.call_deadline {
+            Some(call_deadline) if err.is_deadline_exceeded() && !group_expired => {
+                Error::CallTimeout(call_deadline)
+            }
+            _ => err.into(),
+        }
+    }
+
+    /// Whether the node has said that it does not serve the given API.
+    ///
+    /// This only consults capabilities already fetched during this session.
+    fn lacks_capability(&self, capability: PeerCapabilities) -> bool {
+        self.peer_capabilities
+            .map_or(false, |capabilities| !capabilities.contains(capability))
+    }
+
+    /// Fetch the requested blocks compressed, or return `None` if the node
+    /// does not serve compressed blocks.
+    ///
+    /// A node which predates `GetCapabilities` is assumed to serve them, so if
+    /// it answers `UNIMPLEMENTED`, compression is turned off for this client.
+    fn fetch_compressed_blocks(&mut self, request: &BlocksRequest) -> Result<Option<Vec<Block>>> {
+        let capabilities = self
+            .peer_capabilities()
+            .map_err(|err| self.call_error(err))?;
+        if !capabilities.contains(PeerCapabilities::COMPRESSED_BLOCKS) {
+            return Ok(None);
+        }
+
+        let mut compressed_request = CompressedBlocksRequest::new();
+        compressed_request.set_request(request.clone());
+        compressed_request.set_accepted(ACCEPTED_BLOCK_COMPRESSION.to_vec());
+
+        let result = self.reconnecting_call(|this| {
+            this.authenticated_call(None, |this, call_option| {
+                this.attested_call(|this| {
+                    this.timed_rpc("get_compressed_blocks", |this| {
+                        let (header, message, trailer) = this
+                            .blockchain_api_client
+                            .get_compressed_blocks_full(&compressed_request, call_option)?;
+
+                        // Update cookies from server-sent metadata
+                        if let Err(e) = this
+                            .cookies
+                            .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+                        {
+                            log::warn!(
+                                this.logger,
+                                "Could not update cookies from gRPC metadata: {}",
+                                e
+                            )
+                        }
+
+                        Ok(message)
+                    })
+                })
+            })
+        });
+
+        let response = match result {
+            Ok(response) => response,
+            Err(ThickClientAttestationError::Grpc(GrpcError::RpcFailure(rpc_status)))
+                if rpc_status.status == RpcStatusCode::UNIMPLEMENTED =>
+            {
+                log::debug!(
+                    self.logger,
+                    "Node does not serve compressed blocks, fetching them uncompressed"
+                );
+                self.block_compression = false;
+                return Ok(None);
+            }
+            Err(err) => return Err(self.call_error(err)),
+        };
+
+        let blocks_response = decompress_blocks(response.get_compression(), response.get_blocks())?;
+        blocks_from_proto(request.get_offset(), &blocks_response).map(Some)
+    }
+
+    /// Reset the attested session and credentials if a previous non-blocking
+    /// call failed, as a blocking call does when it fails.
+    fn recover_from_async_failure(&mut self) {
+        if self.async_call_failed.swap(false, AtomicOrdering::SeqCst) {
+            self.credentials_provider.clear();
+            self.deattest();
+        }
+    }
+
+    /// Issue an authenticated+attested GRPC call without waiting for its
+    /// response.
+    ///
+    /// Attestation, if needed, happens before the call is issued and blocks
+    /// the caller. The returned future does not hold the client, so it cannot
+    /// update cookies from the response. If the call fails, the attested
+    /// session and credentials are reset at the start of the next call.
+    fn async_attested_call<T: Send + 'static>(
+        &mut self,
+        rpc: &'static str,
+        idempotency_key: Option<&IdempotencyKey>,
+        func: impl FnOnce(&mut Self, CallOption) -> StdResult<ClientUnaryReceiver<T>, GrpcError>,
+    ) -> ConnectionFuture<T> {
+        self.recover_from_async_failure();
+
+        let connection = self.uri.to_string();
+        let issued = self.authenticated_call(
+            idempotency_key,
+            |this, call_option| -> StdResult<_, ThickClientAttestationError> {
+                if !this.is_attested() {
+                    let _verification_report = this.attest()?;
+                }
+                this.metrics.call_attempted(&connection, rpc);
+                let start = Instant::now();
+                Ok((func(this, call_option)?, start))
+            },
+        );
+
+        let (receiver, start) = match issued {
+            Ok(issued) => issued,
+            Err(err) => return ready_err(err),
+        };
+
+        let metrics = self.metrics.clone();
+        let async_call_failed = self.async_call_failed.clone();
+        Box::pin(async move {
+            match receiver.await {
+                Ok(message) => {
+                    metrics.call_succeeded(&connection, rpc, start.elapsed());
+                    Ok(message)
+                }
+                Err(err) => {
+                    metrics.call_failed(&connection, rpc, start.elapsed());
+                    if let GrpcError::RpcFailure(_) = &err {
+                        async_call_failed.store(true, AtomicOrdering::SeqCst);
+                    }
+                    Err(ThickClientAttestationError::from(err).into())
+                }
+            }
         })
     }
 
-    fn call_option(&self) -> StdResult<CallOption, Box<dyn CredentialsProviderError + 'static>> {
-        let retval = CallOption::default();
+    fn call_option(
+        &self,
+        idempotency_key: Option<&IdempotencyKey>,
+        attestation_version: Option<u32>,
+    ) -> StdResult<CallOption, Box<dyn CredentialsProviderError + 'static>> {
+        let mut retval = CallOption::default();
+        let remaining = self
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let timeout = match (remaining, self.call_deadline) {
+            (Some(remaining), Some(call_deadline)) => Some(remaining.min(call_deadline)),
+            (remaining, call_deadline) => remaining.or(call_deadline),
+        };
+        if let Some(timeout) = timeout {
+            retval = retval.timeout(timeout);
+        }
 
         // Create metadata from cookies and credentials
         let mut metadata_builder = self
@@ -220,44 +800,44 @@ impl<CP: CredentialsProvider> ThickClient<CP> {
             }
         }
 
-        Ok(retval.headers(metadata_builder.build()))
-    }
-}
-
-impl<CP: CredentialsProvider> Connection for ThickClient<CP> {
-    type Uri = ClientUri;
-
-    fn uri(&self) -> Self::Uri {
-        self.uri.clone()
-    }
-}
+        if let Some(idempotency_key) = idempotency_key {
+            metadata_builder
+                .add_bytes(IDEMPOTENCY_KEY_HEADER, idempotency_key.as_ref())
+                .expect("Error setting idempotency key header");
+        }
 
-impl<CP: CredentialsProvider> AttestedConnection for ThickClient<CP> {
-    type Error = ThickClientAttestationError;
+        if let Some(attestation_version) = attestation_version {
+            metadata_builder
+                .add_str(ATTESTATION_VERSION_HEADER, &attestation_version.to_string())
+                .expect("Error setting attestation version header");
+        }
 
-    fn is_attested(&self) -> bool {
-        self.enclave_connection.is_some()
+        Ok(retval.headers(metadata_builder.build()))
     }
 
-    fn attest(&mut self) -> StdResult<VerificationReport, Self::Error> {
-        trace_time!(self.logger, "ThickClient::attest");
-        // If we have an existing attestation, nuke it.
-        self.deattest();
-
+    /// Attest the node with the given attestation protocol version.
+    fn attest_with_version(
+        &mut self,
+        version: u32,
+    ) -> StdResult<VerificationReport, ThickClientAttestationError> {
         let mut csprng = McRng::default();
+        let call_option = self.call_option(None, Some(version))?;
 
-        let initiator = Start::new(self.uri.responder_id()?.to_string());
+        let initiator = Start::new(self.responder_id.to_string());
 
         let init_input = ClientInitiate::<X25519, Aes256Gcm, Sha512>::default();
         let (initiator, auth_request_output) = initiator.try_next(&mut csprng, init_input)?;
 
         // Do the gRPC Call
-        let (header, auth_response_msg, trailer) =
-            self.authenticated_call(|this, call_option| -> StdResult<_, Self::Error> {
-                Ok(this
-                    .attested_api_client
-                    .auth_full(&auth_request_output.into(), call_option)?)
-            })?;
+        let (header, auth_response_msg, trailer) = self.call_with_credentials(
+            call_option,
+            |this, call_option| -> StdResult<_, ThickClientAttestationError> {
+                Ok(this.timed_rpc("auth", |this| {
+                    this.attested_api_client
+                        .auth_full(&auth_request_output.into(), call_option)
+                })?)
+            },
+        )?;
 
         // Update cookies from server-sent metadata
         if let Err(e) = self
@@ -277,97 +857,285 @@ impl<CP: CredentialsProvider> AttestedConnection for ThickClient<CP> {
             initiator.try_next(&mut csprng, auth_response_event)?;
 
         self.enclave_connection = Some(initiator);
+        self.attested_version =
+            Some(echoed_attestation_version(header.as_ref()).unwrap_or(OLDEST_ATTESTATION_VERSION));
+        self.metrics.attested(&self.uri.to_string());
 
         Ok(verification_report)
     }
+}
 
-    fn deattest(&mut self) {
-        if self.is_attested() {
-            log::trace!(
-                self.logger,
-                "Tearing down existing attested connection and clearing cookies."
-            );
-            self.enclave_connection = None;
-            self.cookies = CookieJar::default();
+/// The attestation protocol version a node echoed in its `Auth` response
+/// headers, if any.
+fn echoed_attestation_version(header: Option<&Metadata>) -> Option<u32> {
+    header?
+        .iter()
+        .find(|(key, _value)| *key == ATTESTATION_VERSION_HEADER)
+        .and_then(|(_key, value)| std::str::from_utf8(value).ok()?.parse().ok())
+}
+
+/// Build the gRPC API clients over the given channel.
+fn api_clients(
+    ch: Channel,
+) -> (
+    AttestedApiClient,
+    BlockchainApiClient,
+    ConsensusClientApiClient,
+) {
+    (
+        AttestedApiClient::new(ch.clone()),
+        BlockchainApiClient::new(ch.clone()),
+        ConsensusClientApiClient::new(ch),
+    )
+}
+
+/// Wait for the channel to connect to the given node, failing with
+/// `Error::ConnectFailed` if it has not done so within `connect_timeout`.
+fn wait_for_connected(ch: &Channel, uri: &ClientUri, connect_timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + connect_timeout;
+    while ch.check_connectivity_state(true) != ConnectivityState::GRPC_CHANNEL_READY {
+        if Instant::now() >= deadline {
+            return Err(Error::ConnectFailed(uri.host()));
         }
+        thread::sleep(CONNECT_POLL_INTERVAL);
     }
+    Ok(())
 }
 
-impl<CP: CredentialsProvider> BlockchainConnection for ThickClient<CP> {
-    fn fetch_blocks(&mut self, range: Range<BlockIndex>) -> Result<Vec<Block>> {
-        trace_time!(self.logger, "ThickClient::get_blocks");
-
-        let mut request = BlocksRequest::new();
-        request.set_offset(range.start);
-        let limit = u32::try_from(range.end - range.start).or(Err(Error::RequestTooLarge))?;
-        request.set_limit(limit);
+impl<CP: CredentialsProvider> Connection for ThickClient<CP> {
+    type Uri = ClientUri;
 
-        self.authenticated_attested_call(|this, call_option| {
-            let (header, message, trailer) = this
-                .blockchain_api_client
-                .get_blocks_full(&request, call_option)?;
+    fn uri(&self) -> Self::Uri {
+        self.uri.clone()
+    }
 
-            // Update cookies from server-sent metadata
-            if let Err(e) = this
-                .cookies
-                .update_from_server_metadata(header.as_ref(), trailer.as_ref())
-            {
-                log::warn!(
-                    this.logger,
-                    "Could not update cookies from gRPC metadata: {}",
-                    e
-                )
-            }
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
 
-            Ok(message)
-        })?
-        .get_blocks()
-        .iter()
-        .map(|proto_block| Block::try_from(proto_block).map_err(Error::from))
-        .collect::<Result<Vec<Block>>>()
+    /// Ask for the last block's info, the cheapest call the node serves. The
+    /// connection is attested first if needed, so that the handshake is not
+    /// counted in the round trip.
+    fn ping(&mut self) -> Result<Duration> {
+        if !self.is_attested() {
+            let _verification_report = self.attest()?;
+        }
+        let start = Instant::now();
+        self.fetch_block_height()?;
+        Ok(start.elapsed())
     }
+}
 
-    fn fetch_block_ids(&mut self, range: Range<BlockIndex>) -> Result<Vec<BlockID>> {
-        trace_time!(self.logger, "ThickClient::get_block_ids");
+impl<CP: CredentialsProvider> AttestedConnection for ThickClient<CP> {
+    type Error = ThickClientAttestationError;
 
-        let mut request = BlocksRequest::new();
-        request.set_offset(range.start);
-        let limit = u32::try_from(range.end - range.start).or(Err(Error::RequestTooLarge))?;
-        request.set_limit(limit);
+    fn is_attested(&self) -> bool {
+        self.enclave_connection.is_some()
+    }
 
-        self.authenticated_attested_call(|this, call_option| {
-            let (header, message, trailer) = this
-                .blockchain_api_client
-                .get_blocks_full(&request, call_option)?;
+    /// Attest the node, offering each of the attestation protocol versions
+    /// given to `set_attestation_versions` in turn, newest first, until the
+    /// node accepts one.
+    fn attest(&mut self) -> StdResult<VerificationReport, Self::Error> {
+        trace_time!(self.logger, "ThickClient::attest");
+        // If we have an existing attestation, nuke it.
+        self.deattest();
 
-            // Update cookies from server-sent metadata
-            if let Err(e) = this
-                .cookies
-                .update_from_server_metadata(header.as_ref(), trailer.as_ref())
-            {
-                log::warn!(
-                    this.logger,
-                    "Could not update cookies from gRPC metadata: {}",
-                    e
-                )
+        let versions = self.attestation_versions.clone();
+        let (oldest, newer) = versions
+            .split_last()
+            .expect("attestation_versions is never empty");
+        for version in newer {
+            match self.attest_with_version(*version) {
+                Err(err) if err.is_version_mismatch() => log::info!(
+                    self.logger,
+                    "Node rejected attestation version {}, falling back: {}",
+                    version,
+                    err
+                ),
+                result => return result,
             }
-
-            Ok(message)
-        })?
-        .get_blocks()
-        .iter()
-        .map(|proto_block| BlockID::try_from(proto_block.get_id()).map_err(Error::from))
-        .collect::<Result<Vec<BlockID>>>()
+        }
+        self.attest_with_version(*oldest)
     }
 
-    fn fetch_block_height(&mut self) -> Result<BlockIndex> {
-        trace_time!(self.logger, "ThickClient::fetch_block_height");
-
-        Ok(self
-            .authenticated_attested_call(|this, call_option| {
+    fn attested_call<T>(
+        &mut self,
+        func: impl FnOnce(&mut Self) -> StdResult<T, GrpcError>,
+    ) -> StdResult<T, Self::Error> {
+        let attested = if self.channel_failed {
+            self.reconnect().map(|_verification_report| ())
+        } else if !self.is_attested() {
+            self.attest().map(|_verification_report| ())
+        } else {
+            Ok(())
+        };
+        if let Err(err) = attested {
+            self.channel_failed = err.is_dead_channel();
+            return Err(err);
+        }
+
+        let result = func(self);
+
+        match &result {
+            // The channel can no longer reach the node. It is replaced by the
+            // next attested call, which `reconnecting_call` makes at once for
+            // all but proposals.
+            Err(err) if is_dead_channel(err) => {
+                self.deattest();
+                self.channel_failed = true;
+            }
+            // The node does not serve this call, which says nothing about the
+            // session.
+            Err(GrpcError::RpcFailure(rpc_status))
+                if rpc_status.status == RpcStatusCode::UNIMPLEMENTED => {}
+            Err(GrpcError::RpcFailure(_rpc_status)) => self.deattest(),
+            _ => {}
+        }
+
+        Ok(result?)
+    }
+
+    fn peer_capabilities(&mut self) -> StdResult<PeerCapabilities, Self::Error> {
+        if let Some(peer_capabilities) = self.peer_capabilities {
+            return Ok(peer_capabilities);
+        }
+
+        if !self.is_attested() {
+            let _verification_report = self.attest()?;
+        }
+
+        // This is not made through `attested_call`, because a node which does
+        // not serve this API should not lose its attested session.
+        let result =
+            self.authenticated_call(None, |this, call_option| -> StdResult<_, Self::Error> {
+                Ok(this.timed_rpc("get_capabilities", |this| {
+                    this.blockchain_api_client
+                        .get_capabilities_full(&Empty::new(), call_option)
+                })?)
+            });
+
+        let peer_capabilities = match result {
+            Ok((header, message, trailer)) => {
+                // Update cookies from server-sent metadata
+                if let Err(e) = self
+                    .cookies
+                    .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+                {
+                    log::warn!(
+                        self.logger,
+                        "Could not update cookies from gRPC metadata: {}",
+                        e
+                    )
+                }
+
+                PeerCapabilities::from_bits_truncate(message.flags)
+            }
+            // Nodes which predate this API cannot say what they serve, so
+            // assume everything and fall back when a call fails.
+            Err(ThickClientAttestationError::Grpc(GrpcError::RpcFailure(rpc_status)))
+                if rpc_status.status == RpcStatusCode::UNIMPLEMENTED =>
+            {
+                PeerCapabilities::all()
+            }
+            Err(err) => return Err(err),
+        };
+
+        self.peer_capabilities = Some(peer_capabilities);
+        Ok(peer_capabilities)
+    }
+
+    fn deattest(&mut self) {
+        if self.is_attested() {
+            log::trace!(
+                self.logger,
+                "Tearing down existing attested connection and clearing cookies."
+            );
+            self.enclave_connection = None;
+            self.attested_version = None;
+            self.peer_capabilities = None;
+            self.cookies = CookieJar::default();
+        }
+    }
+}
+
+impl<'a, CP: CredentialsProvider> Attested<'a, ThickClient<CP>> {
+    /// Encrypt `plaintext` for the node's enclave, in a message bound to the
+    /// attested session.
+    pub fn encrypt_message(
+        &mut self,
+        plaintext: &[u8],
+    ) -> StdResult<Message, ThickClientAttestationError> {
+        let enclave_connection = self
+            .session_conn()?
+            .enclave_connection
+            .as_mut()
+            .expect("no enclave_connection even though attest succeeded");
+
+        let mut msg = Message::new();
+        msg.set_channel_id(Vec::from(enclave_connection.binding()));
+        msg.set_data(enclave_connection.encrypt(&[], plaintext)?);
+        Ok(msg)
+    }
+}
+
+impl<CP: CredentialsProvider> BlockchainConnection for ThickClient<CP> {
+    fn fetch_blocks(&mut self, range: Range<BlockIndex>) -> Result<Vec<Block>> {
+        trace_time!(self.logger, "ThickClient::get_blocks");
+
+        let request = match blocks_request(&range)? {
+            Some(request) => request,
+            None => return Ok(Vec::new()),
+        };
+
+        if self.block_compression {
+            if let Some(blocks) = self.fetch_compressed_blocks(&request)? {
+                return Ok(blocks);
+            }
+        }
+
+        let response = self.authenticated_attested_call("get_blocks", |this, call_option| {
+            let (header, message, trailer) = this
+                .blockchain_api_client
+                .get_blocks_full(&request, call_option)?;
+
+            // Update cookies from server-sent metadata
+            if let Err(e) = this
+                .cookies
+                .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+            {
+                log::warn!(
+                    this.logger,
+                    "Could not update cookies from gRPC metadata: {}",
+                    e
+                )
+            }
+
+            Ok(message)
+        })?;
+        blocks_from_proto(range.start, &response)
+    }
+
+    fn fetch_signed_blocks(
+        &mut self,
+        range: Range<BlockIndex>,
+    ) -> Result<Vec<(Block, Option<BlockSignature>)>> {
+        trace_time!(self.logger, "ThickClient::get_signed_blocks");
+
+        if self.lacks_capability(PeerCapabilities::SIGNED_BLOCKS) {
+            return Err(Error::UnsupportedByPeer("fetch_signed_blocks".to_string()));
+        }
+
+        let request = match blocks_request(&range)? {
+            Some(request) => request,
+            None => return Ok(Vec::new()),
+        };
+
+        let signed_blocks = self
+            .authenticated_attested_call("get_signed_blocks", |this, call_option| {
                 let (header, message, trailer) = this
                     .blockchain_api_client
-                    .get_last_block_info_full(&Empty::new(), call_option)?;
+                    .get_signed_blocks_full(&request, call_option)?;
 
                 // Update cookies from server-sent metadata
                 if let Err(e) = this
@@ -382,17 +1150,108 @@ impl<CP: CredentialsProvider> BlockchainConnection for ThickClient<CP> {
                 }
 
                 Ok(message)
+            })
+            .map_err(|err| match err {
+                // Nodes which predate this API do not implement it.
+                err if err.is_unimplemented() => {
+                    Error::UnsupportedByPeer("fetch_signed_blocks".to_string())
+                }
+                err => err,
             })?
-            .index)
+            .get_signed_blocks()
+            .iter()
+            .map(|signed_block| -> Result<(Block, Option<BlockSignature>)> {
+                let block = Block::try_from(signed_block.get_block())?;
+                let signature = if signed_block.has_signature() {
+                    Some(BlockSignature::try_from(signed_block.get_signature())?)
+                } else {
+                    None
+                };
+                Ok((block, signature))
+            })
+            .collect::<Result<Vec<(Block, Option<BlockSignature>)>>>()?;
+
+        if let Some(pinned_signer) = self.pinned_signer.as_ref() {
+            verify_block_signatures(&signed_blocks, pinned_signer)?;
+        }
+
+        Ok(signed_blocks)
     }
 
-    fn fetch_block_info(&mut self) -> Result<BlockInfo> {
-        trace_time!(self.logger, "ThickClient::fetch_block_height");
+    fn fetch_blocks_with_proofs(
+        &mut self,
+        range: Range<BlockIndex>,
+    ) -> Result<Vec<(Block, Vec<TxOutMembershipProof>)>> {
+        trace_time!(self.logger, "ThickClient::get_blocks_with_proofs");
+
+        if self.lacks_capability(PeerCapabilities::BLOCKS_WITH_PROOFS) {
+            return Err(Error::UnsupportedByPeer(
+                "fetch_blocks_with_proofs".to_string(),
+            ));
+        }
+
+        let request = match blocks_request(&range)? {
+            Some(request) => request,
+            None => return Ok(Vec::new()),
+        };
+
+        let response = self
+            .authenticated_attested_call("get_blocks_with_proofs", |this, call_option| {
+                let (header, message, trailer) = this
+                    .blockchain_api_client
+                    .get_blocks_with_proofs_full(&request, call_option)?;
+
+                // Update cookies from server-sent metadata
+                if let Err(e) = this
+                    .cookies
+                    .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+                {
+                    log::warn!(
+                        this.logger,
+                        "Could not update cookies from gRPC metadata: {}",
+                        e
+                    )
+                }
+
+                Ok(message)
+            })
+            .map_err(|err| match err {
+                // Nodes which predate this API do not implement it.
+                err if err.is_unimplemented() => {
+                    Error::UnsupportedByPeer("fetch_blocks_with_proofs".to_string())
+                }
+                err => err,
+            })?;
+
+        response
+            .get_blocks_with_proofs()
+            .iter()
+            .map(
+                |block_with_proofs| -> Result<(Block, Vec<TxOutMembershipProof>)> {
+                    let block = Block::try_from(block_with_proofs.get_block())?;
+                    let proofs = block_with_proofs
+                        .get_output_proofs()
+                        .iter()
+                        .map(TxOutMembershipProof::try_from)
+                        .collect::<StdResult<Vec<_>, ConversionError>>()?;
+                    Ok((block, proofs))
+                },
+            )
+            .collect()
+    }
+
+    fn fetch_block_ids(&mut self, range: Range<BlockIndex>) -> Result<Vec<BlockID>> {
+        trace_time!(self.logger, "ThickClient::get_block_ids");
+
+        let request = match blocks_request(&range)? {
+            Some(request) => request,
+            None => return Ok(Vec::new()),
+        };
 
-        let block_info = self.authenticated_attested_call(|this, call_option| {
+        self.authenticated_attested_call("get_blocks", |this, call_option| {
             let (header, message, trailer) = this
                 .blockchain_api_client
-                .get_last_block_info_full(&Empty::new(), call_option)?;
+                .get_blocks_full(&request, call_option)?;
 
             // Update cookies from server-sent metadata
             if let Err(e) = this
@@ -407,38 +1266,343 @@ impl<CP: CredentialsProvider> BlockchainConnection for ThickClient<CP> {
             }
 
             Ok(message)
-        })?;
+        })?
+        .get_blocks()
+        .iter()
+        .map(|proto_block| BlockID::try_from(proto_block.get_id()).map_err(Error::from))
+        .collect::<Result<Vec<BlockID>>>()
+    }
+
+    fn fetch_block_height(&mut self) -> Result<BlockIndex> {
+        trace_time!(self.logger, "ThickClient::fetch_block_height");
+
+        Ok(self
+            .authenticated_attested_call("get_last_block_info", |this, call_option| {
+                let (header, message, trailer) = this
+                    .blockchain_api_client
+                    .get_last_block_info_full(&Empty::new(), call_option)?;
+
+                // Update cookies from server-sent metadata
+                if let Err(e) = this
+                    .cookies
+                    .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+                {
+                    log::warn!(
+                        this.logger,
+                        "Could not update cookies from gRPC metadata: {}",
+                        e
+                    )
+                }
+
+                Ok(message)
+            })?
+            .index)
+    }
+
+    fn fetch_block_info(&mut self) -> Result<BlockInfo> {
+        trace_time!(self.logger, "ThickClient::fetch_block_height");
+
+        let block_info =
+            self.authenticated_attested_call("get_last_block_info", |this, call_option| {
+                let (header, message, trailer) = this
+                    .blockchain_api_client
+                    .get_last_block_info_full(&Empty::new(), call_option)?;
+
+                // Update cookies from server-sent metadata
+                if let Err(e) = this
+                    .cookies
+                    .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+                {
+                    log::warn!(
+                        this.logger,
+                        "Could not update cookies from gRPC metadata: {}",
+                        e
+                    )
+                }
+
+                Ok(message)
+            })?;
 
         Ok(block_info.into())
     }
+
+    fn contains_key_image(&mut self, key_image: &KeyImage) -> Result<Option<BlockIndex>> {
+        trace_time!(self.logger, "ThickClient::contains_key_image");
+
+        if self.lacks_capability(PeerCapabilities::KEY_IMAGES) {
+            return Err(Error::UnsupportedByPeer("contains_key_image".to_string()));
+        }
+
+        let mut request = CheckKeyImageRequest::new();
+        request.set_key_image(key_image.into());
+
+        let response = self
+            .authenticated_attested_call("check_key_image", |this, call_option| {
+                let (header, message, trailer) = this
+                    .blockchain_api_client
+                    .check_key_image_full(&request, call_option)?;
+
+                // Update cookies from server-sent metadata
+                if let Err(e) = this
+                    .cookies
+                    .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+                {
+                    log::warn!(
+                        this.logger,
+                        "Could not update cookies from gRPC metadata: {}",
+                        e
+                    )
+                }
+
+                Ok(message)
+            })
+            .map_err(|err| match err {
+                // Nodes which predate this API do not implement it.
+                err if err.is_unimplemented() => {
+                    Error::UnsupportedByPeer("contains_key_image".to_string())
+                }
+                err => err,
+            })?;
+
+        Ok(if response.get_spent() {
+            Some(response.get_block_index())
+        } else {
+            None
+        })
+    }
 }
 
 impl<CP: CredentialsProvider> UserTxConnection for ThickClient<CP> {
-    fn propose_tx(&mut self, tx: &Tx) -> Result<BlockIndex> {
+    fn propose_tx(&mut self, tx: &Tx) -> Result<ProposeTxResponse> {
+        self.propose_tx_with_idempotency_key(tx, &IdempotencyKey::random())
+    }
+
+    fn propose_tx_with_idempotency_key(
+        &mut self,
+        tx: &Tx,
+        idempotency_key: &IdempotencyKey,
+    ) -> Result<ProposeTxResponse> {
         trace_time!(self.logger, "ThickClient::propose_tx");
 
-        if !self.is_attested() {
-            let _verification_report = self.attest()?;
+        // Don't leave the plaintext serialization floating around
+        let tx_plaintext = SecretVec::new(encode(tx));
+        let msg = self
+            .attested()?
+            .encrypt_message(tx_plaintext.expose_secret().as_ref())?;
+
+        let resp = self.idempotent_attested_call(
+            "client_tx_propose",
+            Some(idempotency_key),
+            |this, call_option| {
+                let (header, message, trailer) = this
+                    .consensus_client_api_client
+                    .client_tx_propose_full(&msg, call_option)?;
+
+                // Update cookies from server-sent metadata
+                if let Err(e) = this
+                    .cookies
+                    .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+                {
+                    log::warn!(
+                        this.logger,
+                        "Could not update cookies from gRPC metadata: {}",
+                        e
+                    )
+                }
+
+                Ok(message)
+            },
+        )?;
+
+        if resp.get_result() == ProposeTxResult::Ok {
+            Ok(ProposeTxResponse::new(tx, resp.get_block_count()))
+        } else {
+            Err((&resp).into())
         }
+    }
 
-        let enclave_connection = self
-            .enclave_connection
-            .as_mut()
-            .expect("no enclave_connection even though attest succeeded");
+    fn propose_tx_batch(&mut self, txs: &[Tx]) -> Result<Vec<Result<ProposeTxResponse>>> {
+        trace_time!(self.logger, "ThickClient::propose_tx_batch");
 
-        let mut msg = Message::new();
-        msg.set_channel_id(Vec::from(enclave_connection.binding()));
+        // Attest once up front, so the whole batch goes over the same channel
+        // and an attestation failure is reported once rather than per-tx.
+        self.attested()?;
+
+        // The proposals are not pipelined. Each is encrypted with the next
+        // nonce of the session's cipher, which the node only decrypts in order,
+        // and concurrent gRPC calls may be handled in any order. A reordered
+        // proposal would fail to decrypt and end the session, so each waits for
+        // the previous one's response.
+        Ok(txs.iter().map(|tx| self.propose_tx(tx)).collect())
+    }
+}
+
+impl<CP: CredentialsProvider> AsyncBlockchainConnection for ThickClient<CP> {
+    fn fetch_blocks_async(&mut self, range: Range<BlockIndex>) -> ConnectionFuture<Vec<Block>> {
+        let request = match blocks_request(&range) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Box::pin(future::ready(Ok(Vec::new()))),
+            Err(err) => return ready_err(err),
+        };
+
+        let response = self.async_attested_call("get_blocks", None, |this, call_option| {
+            this.blockchain_api_client
+                .get_blocks_async_opt(&request, call_option)
+        });
+
+        Box::pin(blocks_from_response(range.start, response))
+    }
+}
+
+/// Build the request for the blocks in `range`, or `None` if the range is
+/// empty and there is nothing to ask for.
+fn blocks_request(range: &Range<BlockIndex>) -> Result<Option<BlocksRequest>> {
+    if range.end < range.start {
+        return Err(Error::InvalidRange(range.start, range.end));
+    }
+    if range.is_empty() {
+        return Ok(None);
+    }
+
+    let mut request = BlocksRequest::new();
+    request.set_offset(range.start);
+    let limit = u32::try_from(range.end - range.start).or(Err(Error::RequestTooLarge))?;
+    request.set_limit(limit);
+    Ok(Some(request))
+}
+
+/// Convert a `get_blocks` response, checking that the blocks form a chain
+/// starting at block `first_index`.
+fn blocks_from_proto(first_index: BlockIndex, response: &BlocksResponse) -> Result<Vec<Block>> {
+    let blocks = response
+        .get_blocks()
+        .iter()
+        .map(|proto_block| Block::try_from(proto_block).map_err(Error::from))
+        .collect::<Result<Vec<Block>>>()?;
+    validate_block_chain(first_index, &blocks)?;
+    Ok(blocks)
+}
+
+/// Convert the response to a non-blocking `get_blocks` call, checking that
+/// the blocks form a chain starting at block `first_index`.
+async fn blocks_from_response(
+    first_index: BlockIndex,
+    response: ConnectionFuture<BlocksResponse>,
+) -> Result<Vec<Block>> {
+    blocks_from_proto(first_index, &response.await?)
+}
+
+impl<CP: CredentialsProvider> AsyncUserTxConnection for ThickClient<CP> {
+    fn propose_tx_async(&mut self, tx: &Tx) -> ConnectionFuture<ProposeTxResponse> {
+        // Recover before encrypting, so the tx is not sent over a stale session.
+        self.recover_from_async_failure();
 
         // Don't leave the plaintext serialization floating around
         let tx_plaintext = SecretVec::new(encode(tx));
-        let tx_ciphertext =
-            enclave_connection.encrypt(&[], tx_plaintext.expose_secret().as_ref())?;
-        msg.set_data(tx_ciphertext);
+        let msg = match self.attested().and_then(|mut attested| {
+            attested.encrypt_message(tx_plaintext.expose_secret().as_ref())
+        }) {
+            Ok(msg) => msg,
+            Err(err) => return ready_err(err),
+        };
+
+        let response = self.async_attested_call(
+            "client_tx_propose",
+            Some(&IdempotencyKey::random()),
+            |this, call_option| {
+                this.consensus_client_api_client
+                    .client_tx_propose_async_opt(&msg, call_option)
+            },
+        );
+
+        Box::pin(propose_tx_from_response(tx.clone(), response))
+    }
+}
+
+/// Convert the response to a non-blocking `client_tx_propose` call.
+async fn propose_tx_from_response(
+    tx: Tx,
+    response: ConnectionFuture<ProposeTxResponseProto>,
+) -> Result<ProposeTxResponse> {
+    let resp = response.await?;
+    if resp.get_result() == ProposeTxResult::Ok {
+        Ok(ProposeTxResponse::new(&tx, resp.get_block_count()))
+    } else {
+        Err((&resp).into())
+    }
+}
 
-        let resp = self.authenticated_attested_call(|this, call_option| {
+/// A future which immediately fails with the given error.
+fn ready_err<T: Send + 'static>(err: impl Into<Error>) -> ConnectionFuture<T> {
+    Box::pin(future::ready(Err(err.into())))
+}
+
+impl<CP: CredentialsProvider> FeeConnection for ThickClient<CP> {
+    fn fetch_minimum_fee(&mut self) -> Result<u64> {
+        if let Some((minimum_fee, fetched_at)) = self.cached_minimum_fee {
+            if fetched_at.elapsed() < MINIMUM_FEE_CACHE_TTL {
+                return Ok(minimum_fee);
+            }
+        }
+
+        let minimum_fee = self.fetch_block_info()?.minimum_fee;
+        self.cached_minimum_fee = Some((minimum_fee, Instant::now()));
+        Ok(minimum_fee)
+    }
+
+    fn fetch_fee_estimate(&mut self, target_blocks: u64) -> Result<u64> {
+        trace_time!(self.logger, "ThickClient::fetch_fee_estimate");
+
+        let mut request = FeeEstimateRequest::new();
+        request.set_target_blocks(target_blocks);
+
+        let result = self.reconnecting_call(|this| {
+            this.authenticated_call(None, |this, call_option| {
+                this.attested_call(|this| {
+                    this.timed_rpc("get_fee_estimate", |this| {
+                        let (header, message, trailer) = this
+                            .consensus_client_api_client
+                            .get_fee_estimate_full(&request, call_option)?;
+
+                        // Update cookies from server-sent metadata
+                        if let Err(e) = this
+                            .cookies
+                            .update_from_server_metadata(header.as_ref(), trailer.as_ref())
+                        {
+                            log::warn!(
+                                this.logger,
+                                "Could not update cookies from gRPC metadata: {}",
+                                e
+                            )
+                        }
+
+                        Ok(message)
+                    })
+                })
+            })
+        });
+
+        match result {
+            Ok(response) => Ok(response.get_fee()),
+            // Nodes which predate fee estimates do not serve them.
+            Err(ThickClientAttestationError::Grpc(GrpcError::RpcFailure(rpc_status)))
+                if rpc_status.status == RpcStatusCode::UNIMPLEMENTED =>
+            {
+                Err(Error::UnsupportedByPeer("fetch_fee_estimate".to_string()))
+            }
+            Err(err) => Err(self.call_error(err)),
+        }
+    }
+}
+
+impl<CP: CredentialsProvider> MempoolConnection for ThickClient<CP> {
+    fn fetch_pending_tx_hashes(&mut self) -> Result<Vec<TxHash>> {
+        trace_time!(self.logger, "ThickClient::fetch_pending_tx_hashes");
+
+        self.authenticated_attested_call("get_pending_tx_hashes", |this, call_option| {
             let (header, message, trailer) = this
                 .consensus_client_api_client
-                .client_tx_propose_full(&msg, call_option)?;
+                .get_pending_tx_hashes_full(&Empty::new(), call_option)?;
 
             // Update cookies from server-sent metadata
             if let Err(e) = this
@@ -453,13 +1617,14 @@ impl<CP: CredentialsProvider> UserTxConnection for ThickClient<CP> {
             }
 
             Ok(message)
-        })?;
-
-        if resp.get_result() == ProposeTxResult::Ok {
-            Ok(resp.get_block_count())
-        } else {
-            Err(resp.get_result().into())
-        }
+        })?
+        .get_tx_hashes()
+        .iter()
+        .map(|tx_hash| {
+            TxHash::try_from(tx_hash.as_slice())
+                .or(Err(Error::from(ConversionError::ArrayCastError)))
+        })
+        .collect::<Result<Vec<TxHash>>>()
     }
 }
 
@@ -473,24 +1638,519 @@ impl<CP: CredentialsProvider> Eq for ThickClient<CP> {}
 
 impl<CP: CredentialsProvider> Hash for ThickClient<CP> {
     fn hash<H: Hasher>(&self, hasher: &mut H) {
-        self.uri.addr().hash(hasher);
+        self.responder_id.hash(hasher);
     }
 }
 
 impl<CP: CredentialsProvider> PartialEq for ThickClient<CP> {
     fn eq(&self, other: &Self) -> bool {
-        self.uri.addr() == other.uri.addr()
+        self.responder_id == other.responder_id
     }
 }
 
 impl<CP: CredentialsProvider> Ord for ThickClient<CP> {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.uri.addr().cmp(&other.uri.addr())
+        self.responder_id.cmp(&other.responder_id)
     }
 }
 
 impl<CP: CredentialsProvider> PartialOrd for ThickClient<CP> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.uri.addr().partial_cmp(&other.uri.addr())
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::HardcodedCredentialsProvider;
+    use futures::executor::block_on;
+    use grpcio::{EnvBuilder, RpcContext, RpcStatus, ServerBuilder, UnarySink};
+    use mc_attest_api::{
+        attest::AuthMessage,
+        attest_grpc::{create_attested_api, AttestedApi},
+    };
+    use mc_common::logger::create_null_logger;
+    use mc_util_grpc::send_result;
+    use std::{
+        collections::hash_map::DefaultHasher,
+        net::TcpListener,
+        str::FromStr,
+        sync::{mpsc, Mutex},
+    };
+
+    fn thick_client(uri: &str) -> ThickClient<HardcodedCredentialsProvider> {
+        ThickClient::new(
+            ClientUri::from_str(uri).unwrap(),
+            Verifier::default(),
+            Arc::new(EnvBuilder::new().build()),
+            HardcodedCredentialsProvider::default(),
+            create_null_logger(),
+        )
+        .unwrap()
+    }
+
+    fn hash_of(client: &ThickClient<HardcodedCredentialsProvider>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        client.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn rpc_failure(status: RpcStatusCode) -> ThickClientAttestationError {
+        GrpcError::RpcFailure(RpcStatus::new(status, None)).into()
+    }
+
+    /// An attestation service which rejects every version but one as
+    /// unsupported, and records the versions it is offered. It cannot complete
+    /// the handshake, so it denies the version it accepts.
+    #[derive(Clone)]
+    struct VersionedAttestedApi {
+        accepted_version: u32,
+        offered_versions: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    impl AttestedApi for VersionedAttestedApi {
+        fn auth(&mut self, ctx: RpcContext, _request: AuthMessage, sink: UnarySink<AuthMessage>) {
+            let offered_version = ctx
+                .request_headers()
+                .iter()
+                .find(|(key, _value)| *key == ATTESTATION_VERSION_HEADER)
+                .map(|(_key, value)| String::from_utf8_lossy(value).into_owned());
+            let status = if offered_version == Some(self.accepted_version.to_string()) {
+                RpcStatusCode::PERMISSION_DENIED
+            } else {
+                RpcStatusCode::FAILED_PRECONDITION
+            };
+            self.offered_versions.lock().unwrap().push(offered_version);
+            send_result(
+                ctx,
+                sink,
+                Err(RpcStatus::new(status, None)),
+                &create_null_logger(),
+            );
+        }
+    }
+
+    #[test]
+    // Transport failures and timeouts should be retried.
+    fn transport_errors_are_retriable() {
+        assert!(rpc_failure(RpcStatusCode::UNAVAILABLE).is_retriable());
+        assert!(rpc_failure(RpcStatusCode::DEADLINE_EXCEEDED).is_retriable());
+        assert!(rpc_failure(RpcStatusCode::UNAUTHENTICATED).is_retriable());
+        assert!(ThickClientAttestationError::from(GrpcError::RemoteStopped).is_retriable());
+    }
+
+    #[test]
+    // Requests the peer rejected outright should not be retried.
+    fn rejected_requests_are_fatal() {
+        assert!(!rpc_failure(RpcStatusCode::INVALID_ARGUMENT).is_retriable());
+        assert!(!rpc_failure(RpcStatusCode::UNIMPLEMENTED).is_retriable());
+        assert!(!rpc_failure(RpcStatusCode::ALREADY_EXISTS).is_retriable());
+    }
+
+    #[test]
+    // Malformed responses should not be retried.
+    fn malformed_responses_are_fatal() {
+        assert!(!ThickClientAttestationError::from(AkeError::ReportDeserialization).is_retriable());
+        assert!(!ThickClientAttestationError::from(CipherError::Aead).is_retriable());
+        assert!(!ThickClientAttestationError::from(UriConversionError::NoPubkey).is_retriable());
+    }
+
+    #[test]
+    // Only an expired deadline should be reported as a timeout.
+    fn deadline_exceeded_is_detected() {
+        assert!(rpc_failure(RpcStatusCode::DEADLINE_EXCEEDED).is_deadline_exceeded());
+        assert!(!rpc_failure(RpcStatusCode::UNAVAILABLE).is_deadline_exceeded());
+        assert!(Error::from(rpc_failure(RpcStatusCode::DEADLINE_EXCEEDED)).is_deadline_exceeded());
+        assert!(!Error::from(rpc_failure(RpcStatusCode::UNAVAILABLE)).is_deadline_exceeded());
+    }
+
+    #[test]
+    // A call running past its call deadline should fail with a retriable
+    // timeout, distinct from other transport failures.
+    fn call_deadline_exceeded_is_a_timeout() {
+        let mut client = thick_client("mc://node1.test.com/");
+        match client.call_error(rpc_failure(RpcStatusCode::DEADLINE_EXCEEDED)) {
+            Error::Attestation(_) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        client.set_call_deadline(Duration::from_millis(250));
+        let err = client.call_error(rpc_failure(RpcStatusCode::DEADLINE_EXCEEDED));
+        match err {
+            Error::CallTimeout(deadline) => assert_eq!(deadline, Duration::from_millis(250)),
+            ref other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(err.should_retry());
+
+        match client.call_error(rpc_failure(RpcStatusCode::UNAVAILABLE)) {
+            Error::Attestation(_) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // Once the deadline for a group of calls has passed, the failure is
+        // the group's, and is not reported as a call timeout.
+        client.set_deadline(Some(Instant::now()));
+        match client.call_error(rpc_failure(RpcStatusCode::DEADLINE_EXCEEDED)) {
+            Error::Attestation(_) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // Only a single call running past its deadline should be retried, not a
+    // whole operation running out of time.
+    fn only_call_timeouts_are_retried() {
+        assert!(Error::CallTimeout(Duration::from_secs(1)).should_retry());
+        assert!(!Error::Timeout(Duration::from_secs(1)).should_retry());
+        assert!(Error::CallTimeout(Duration::from_secs(1)).is_deadline_exceeded());
+        assert!(Error::Timeout(Duration::from_secs(1)).is_deadline_exceeded());
+    }
+
+    #[test]
+    // Only an ALREADY_EXISTS status should be reported as a duplicate.
+    fn already_exists_is_detected() {
+        assert!(rpc_failure(RpcStatusCode::ALREADY_EXISTS).is_already_exists());
+        assert!(!rpc_failure(RpcStatusCode::INVALID_ARGUMENT).is_already_exists());
+        assert!(Error::from(rpc_failure(RpcStatusCode::ALREADY_EXISTS)).is_already_exists());
+        assert!(!Error::from(rpc_failure(RpcStatusCode::UNAVAILABLE)).is_already_exists());
+    }
+
+    #[test]
+    // Only a RESOURCE_EXHAUSTED status should be reported as rate limiting.
+    fn rate_limiting_is_detected() {
+        assert!(rpc_failure(RpcStatusCode::RESOURCE_EXHAUSTED).is_rate_limited());
+        assert!(!rpc_failure(RpcStatusCode::UNAVAILABLE).is_rate_limited());
+        assert!(Error::from(rpc_failure(RpcStatusCode::RESOURCE_EXHAUSTED)).is_rate_limited());
+        assert!(!Error::from(rpc_failure(RpcStatusCode::ALREADY_EXISTS)).is_rate_limited());
+    }
+
+    #[test]
+    // Only an UNIMPLEMENTED status should be reported as an unserved call.
+    fn unimplemented_is_detected() {
+        assert!(rpc_failure(RpcStatusCode::UNIMPLEMENTED).is_unimplemented());
+        assert!(!rpc_failure(RpcStatusCode::UNAVAILABLE).is_unimplemented());
+        assert!(Error::from(rpc_failure(RpcStatusCode::UNIMPLEMENTED)).is_unimplemented());
+        assert!(!Error::from(rpc_failure(RpcStatusCode::INVALID_ARGUMENT)).is_unimplemented());
+    }
+
+    #[test]
+    // Only a FAILED_PRECONDITION status should make attestation fall back to
+    // an older version, and an empty list of versions should restore the
+    // supported ones.
+    fn attestation_version_fallback() {
+        assert!(rpc_failure(RpcStatusCode::FAILED_PRECONDITION).is_version_mismatch());
+        assert!(!rpc_failure(RpcStatusCode::PERMISSION_DENIED).is_version_mismatch());
+
+        let mut client = thick_client("mc://node1.test.com/");
+        assert_eq!(client.attestation_versions, SUPPORTED_ATTESTATION_VERSIONS);
+        assert_eq!(client.attested_version(), None);
+
+        client.set_attestation_versions(vec![2, 1]);
+        assert_eq!(client.attestation_versions, vec![2, 1]);
+        client.set_attestation_versions(Vec::new());
+        assert_eq!(client.attestation_versions, SUPPORTED_ATTESTATION_VERSIONS);
+    }
+
+    #[test]
+    // A node which rejects version 2 as unsupported should be offered version
+    // 1 next, and the error for version 1 should be returned.
+    fn attest_falls_back_to_older_version() {
+        let offered_versions = Arc::new(Mutex::new(Vec::new()));
+        let service = create_attested_api(VersionedAttestedApi {
+            accepted_version: 1,
+            offered_versions: offered_versions.clone(),
+        });
+        let mut server = ServerBuilder::new(Arc::new(EnvBuilder::new().build()))
+            .register_service(service)
+            .bind("127.0.0.1", 0)
+            .build()
+            .unwrap();
+        server.start();
+        let (_, port) = server.bind_addrs().next().unwrap();
+
+        let mut client = thick_client(&format!("insecure-mc://127.0.0.1:{}/", port));
+        client.set_attestation_versions(vec![2, 1]);
+
+        match client.attest() {
+            Err(ThickClientAttestationError::Grpc(GrpcError::RpcFailure(rpc_status)))
+                if rpc_status.status == RpcStatusCode::PERMISSION_DENIED => {}
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Attested to a node which cannot complete the handshake"),
+        }
+        assert_eq!(
+            *offered_versions.lock().unwrap(),
+            vec![Some("2".to_string()), Some("1".to_string())]
+        );
+        assert_eq!(client.attested_version(), None);
+    }
+
+    #[test]
+    // The version a node echoes should be read from its response headers, and
+    // a missing or malformed echo should be ignored.
+    fn echoed_attestation_version_is_read() {
+        let echo = |value: &str| {
+            let mut metadata_builder = MetadataBuilder::new();
+            metadata_builder
+                .add_str(ATTESTATION_VERSION_HEADER, value)
+                .unwrap();
+            metadata_builder.build()
+        };
+
+        assert_eq!(echoed_attestation_version(Some(&echo("2"))), Some(2));
+        assert_eq!(echoed_attestation_version(Some(&echo("two"))), None);
+        assert_eq!(
+            echoed_attestation_version(Some(&MetadataBuilder::new().build())),
+            None
+        );
+        assert_eq!(echoed_attestation_version(None), None);
+    }
+
+    #[test]
+    // Only transport failures should be taken to mean the channel is dead.
+    fn dead_channel_is_detected() {
+        assert!(rpc_failure(RpcStatusCode::UNAVAILABLE).is_dead_channel());
+        assert!(ThickClientAttestationError::from(GrpcError::RemoteStopped).is_dead_channel());
+        assert!(!rpc_failure(RpcStatusCode::UNAUTHENTICATED).is_dead_channel());
+        assert!(!rpc_failure(RpcStatusCode::DEADLINE_EXCEEDED).is_dead_channel());
+    }
+
+    #[test]
+    // A cached minimum fee should be served without reaching the node until it
+    // expires, after which the node is asked again.
+    fn cached_minimum_fee_expires() {
+        // No node is listening, so any call which reaches the network fails.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut client = thick_client(&format!("insecure-mc://127.0.0.1:{}/", port));
+
+        client.cached_minimum_fee = Some((42, Instant::now()));
+        assert_eq!(client.fetch_minimum_fee().unwrap(), 42);
+
+        client.cached_minimum_fee = Some((42, Instant::now() - MINIMUM_FEE_CACHE_TTL));
+        assert!(client.fetch_minimum_fee().is_err());
+    }
+
+    #[test]
+    // After failing to reach a node which was down, reconnecting should reach
+    // it once it is back up.
+    fn reconnect_recovers_from_channel_failure() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut client = thick_client(&format!("insecure-mc://127.0.0.1:{}/", port));
+
+        match client.attest() {
+            Err(err) if err.is_dead_channel() => {}
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Attested to a node which is not running"),
+        }
+
+        // The node comes back, though without an attestation service, so a
+        // call which reaches it is rejected as unimplemented.
+        let mut server = ServerBuilder::new(Arc::new(EnvBuilder::new().build()))
+            .bind("127.0.0.1", port)
+            .build()
+            .unwrap();
+        server.start();
+
+        match client.reconnect() {
+            Err(ThickClientAttestationError::Grpc(GrpcError::RpcFailure(rpc_status)))
+                if rpc_status.status == RpcStatusCode::UNIMPLEMENTED => {}
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Attested to a node without an attestation service"),
+        }
+    }
+
+    #[test]
+    // Reconnecting to a node which stays down should wait between attempts,
+    // and give up with the dead channel error once they run out.
+    fn reconnect_backs_off_while_node_is_down() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut client = thick_client(&format!("insecure-mc://127.0.0.1:{}/", port));
+        client.reconnect_policy = RetryPolicy::fixed(Duration::from_millis(20), 3);
+
+        let start = Instant::now();
+        match client.reconnect() {
+            Err(err) if err.is_dead_channel() => {}
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Attested to a node which is not running"),
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    // An attested call which finds the channel dead should reconnect within
+    // the same call, and reach the node once it is back up.
+    fn attested_call_reconnects_within_call() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut client = thick_client(&format!("insecure-mc://127.0.0.1:{}/", port));
+        client.reconnect_policy = RetryPolicy::fixed(Duration::from_millis(100), 10);
+
+        // The node comes back while the call is backing off, though without an
+        // attestation service, so a call which reaches it is rejected as
+        // unimplemented.
+        let (stop_node, node_stopped) = mpsc::channel::<()>();
+        let node = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut server = ServerBuilder::new(Arc::new(EnvBuilder::new().build()))
+                .bind("127.0.0.1", port)
+                .build()
+                .unwrap();
+            server.start();
+            let _ = node_stopped.recv();
+        });
+
+        match client.fetch_block_height() {
+            Err(Error::Attestation(_err)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(!client.channel_failed);
+
+        drop(stop_node);
+        node.join().unwrap();
+    }
+
+    #[test]
+    // Reading the channel state should not connect an idle channel, and should
+    // show once a call has connected it.
+    fn channel_state_reports_connectivity() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut client = thick_client(&format!("insecure-mc://127.0.0.1:{}/", port));
+        assert_eq!(client.channel_state(), ConnectivityState::GRPC_CHANNEL_IDLE);
+        assert_eq!(client.channel_state(), ConnectivityState::GRPC_CHANNEL_IDLE);
+
+        let mut server = ServerBuilder::new(Arc::new(EnvBuilder::new().build()))
+            .bind("127.0.0.1", port)
+            .build()
+            .unwrap();
+        server.start();
+
+        // The node has no attestation service, but the call still connects.
+        assert!(client.attest().is_err());
+        assert_eq!(
+            client.channel_state(),
+            ConnectivityState::GRPC_CHANNEL_READY
+        );
+    }
+
+    #[test]
+    // A node which is not running should fail the connect timeout with the
+    // node's host, rather than when the first call is made.
+    fn connect_timeout_fails_fast_on_dead_host() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let start = Instant::now();
+        let result = ThickClient::new_with_connect_timeout(
+            ClientUri::from_str(&format!("insecure-mc://127.0.0.1:{}/", port)).unwrap(),
+            Verifier::default(),
+            Arc::new(EnvBuilder::new().build()),
+            HardcodedCredentialsProvider::default(),
+            Duration::from_millis(200),
+            create_null_logger(),
+        );
+
+        match result {
+            Err(Error::ConnectFailed(host)) => assert_eq!(host, "127.0.0.1"),
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Connected to a node which is not running"),
+        }
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    // Connection errors should follow the attestation error's classification.
+    fn should_retry_follows_attestation_classification() {
+        assert!(Error::from(rpc_failure(RpcStatusCode::UNAVAILABLE)).should_retry());
+        assert!(!Error::from(rpc_failure(RpcStatusCode::INVALID_ARGUMENT)).should_retry());
+    }
+
+    #[test]
+    // An oversized range should fail without issuing a call.
+    fn fetch_blocks_async_rejects_oversized_range() {
+        let mut client = thick_client("mc://node1.test.com/");
+        match block_on(client.fetch_blocks_async(0..u64::MAX)) {
+            Err(Error::RequestTooLarge) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // An empty range should need no call, and an inverted one should be
+    // rejected before any call is made.
+    fn fetch_handles_empty_and_inverted_ranges() {
+        // The node does not exist, so any call made would fail.
+        let mut client = thick_client("mc://node1.test.com/");
+        assert_eq!(client.fetch_blocks(5..5).unwrap(), Vec::new());
+        assert_eq!(client.fetch_block_ids(5..5).unwrap(), Vec::new());
+        assert_eq!(
+            block_on(client.fetch_blocks_async(5..5)).unwrap(),
+            Vec::new()
+        );
+
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = 10..5;
+        match client.fetch_blocks(inverted.clone()) {
+            Err(Error::InvalidRange(10, 5)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match client.fetch_block_ids(inverted.clone()) {
+            Err(Error::InvalidRange(10, 5)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match block_on(client.fetch_blocks_async(inverted)) {
+            Err(Error::InvalidRange(10, 5)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // Clients for the same responder should be equal and hash alike.
+    fn same_responder_compares_equal() {
+        let first = thick_client("mc://node1.test.com/");
+        let second = thick_client("mc://node1.test.com/");
+        assert_eq!(first, second);
+        assert_eq!(first.cmp(&second), Ordering::Equal);
+        assert_eq!(hash_of(&first), hash_of(&second));
+
+        // Internal state is not compared.
+        let mut with_deadline = thick_client("mc://node1.test.com/");
+        with_deadline.set_deadline(Some(Instant::now()));
+        assert_eq!(first, with_deadline);
+
+        // A URI naming the same responder by parameter identifies the same node.
+        let via_proxy = thick_client("mc://proxy.test.com/?responder-id=node1.test.com:443");
+        assert_eq!(first, via_proxy);
+        assert_eq!(hash_of(&first), hash_of(&via_proxy));
+    }
+
+    #[test]
+    // Clients for different responders should be distinct and ordered by
+    // responder ID.
+    fn different_responders_compare_unequal() {
+        let first = thick_client("mc://node1.test.com/");
+        let second = thick_client("mc://node2.test.com/");
+        assert_ne!(first, second);
+        assert_eq!(first.cmp(&second), Ordering::Less);
     }
 }
@@ -2,31 +2,98 @@
 
 //! Traits which connection implementations can implement.
 
-use crate::error::{Result, RetryResult};
+use crate::{
+    attested::Attested,
+    capabilities::PeerCapabilities,
+    error::{Error, Result, RetryError, RetryResult},
+    verify::{validate_block_chain, verify_signed_blocks},
+};
 use grpcio::Error as GrpcError;
 use mc_attest_core::VerificationReport;
 use mc_consensus_api::consensus_common::LastBlockInfoResponse;
-use mc_transaction_core::{tx::Tx, Block, BlockID, BlockIndex};
+use mc_crypto_keys::Ed25519Public;
+use mc_crypto_rand::{McRng, RngCore};
+use mc_transaction_core::{
+    ring_signature::KeyImage,
+    tx::{Tx, TxHash, TxOutMembershipElement, TxOutMembershipProof},
+    Block, BlockID, BlockIndex, BlockSignature,
+};
 use mc_util_serial::prost::alloc::fmt::Formatter;
 use mc_util_uri::ConnectionUri;
 use std::{
     fmt::{Debug, Display, Result as FmtResult},
+    future::Future,
     hash::Hash,
     ops::Range,
+    pin::Pin,
     result::Result as StdResult,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+/// How many block IDs `fetch_block_by_id` asks for at a time while searching
+/// for a block.
+const BLOCK_ID_SEARCH_BATCH_SIZE: u64 = 1_000;
+
+/// How many of the most recent blocks `fetch_block_by_id` searches before
+/// giving up.
+pub(crate) const BLOCK_ID_SEARCH_DEPTH: u64 = 10_000;
+
+/// How many blocks `fetch_blocks_with_progress` asks for at a time.
+const PROGRESS_CHUNK_SIZE: u64 = 1_000;
+
 /// A base connection trait, applicable to all connections.
 pub trait Connection: Display + Eq + Hash + Ord + PartialEq + PartialOrd + Send + Sync {
     type Uri: ConnectionUri;
 
     fn uri(&self) -> Self::Uri;
+
+    /// Set a deadline which subsequent calls must complete by, or clear it.
+    ///
+    /// The default implementation ignores the deadline.
+    fn set_deadline(&mut self, _deadline: Option<Instant>) {}
+
+    /// Make the cheapest round trip the peer allows, returning how long it
+    /// took.
+    ///
+    /// Connections which cannot ping their peer return
+    /// `Error::UnsupportedByPeer`.
+    fn ping(&mut self) -> Result<Duration> {
+        Err(Error::UnsupportedByPeer("ping".to_string()))
+    }
 }
 
-/// A marker trait used to encapsulate connection-impl-specific attestation
-/// errors.
-pub trait AttestationError: Debug + Display + Send + Sync {}
+/// A trait used to encapsulate connection-impl-specific attestation errors.
+pub trait AttestationError: Debug + Display + Send + Sync {
+    /// Policy decision, whether a call which failed with this error should be
+    /// retried.
+    ///
+    /// The default implementation retries every attestation failure.
+    fn is_retriable(&self) -> bool {
+        true
+    }
+
+    /// Whether the call failed because its deadline passed.
+    fn is_deadline_exceeded(&self) -> bool {
+        false
+    }
+
+    /// Whether the peer rejected the request because it already holds what
+    /// was submitted.
+    fn is_already_exists(&self) -> bool {
+        false
+    }
+
+    /// Whether the peer rejected the request because the client has made too
+    /// many.
+    fn is_rate_limited(&self) -> bool {
+        false
+    }
+
+    /// Whether the peer does not implement the call.
+    fn is_unimplemented(&self) -> bool {
+        false
+    }
+}
 
 pub trait AttestedConnection: Connection {
     type Error: AttestationError + From<GrpcError>;
@@ -35,8 +102,29 @@ pub trait AttestedConnection: Connection {
 
     fn attest(&mut self) -> StdResult<VerificationReport, Self::Error>;
 
+    /// Attest the connection unless it is already attested, returning a
+    /// handle through which attested-only calls can be made.
+    fn attested(&mut self) -> StdResult<Attested<'_, Self>, Self::Error>
+    where
+        Self: Sized,
+    {
+        Attested::new(self)
+    }
+
+    /// Tear down the attested session, if any.
+    ///
+    /// `ScopedAttestation` can be used to tie a session to a scope.
     fn deattest(&mut self);
 
+    /// The optional APIs the peer serves.
+    ///
+    /// Implementations should ask the peer at most once per attested
+    /// session. The default assumes every optional API is served, so callers
+    /// only learn otherwise when a call fails.
+    fn peer_capabilities(&mut self) -> StdResult<PeerCapabilities, Self::Error> {
+        Ok(PeerCapabilities::all())
+    }
+
     fn attested_call<T>(
         &mut self,
         func: impl FnOnce(&mut Self) -> StdResult<T, GrpcError>,
@@ -87,30 +175,414 @@ impl From<LastBlockInfoResponse> for BlockInfo {
 /// consensus node.
 pub trait BlockchainConnection: Connection {
     /// Retrieve the block metadata from the blockchain service.
+    ///
+    /// An empty range yields no blocks without contacting the node, and a
+    /// range which ends before it starts fails with `Error::InvalidRange`.
     fn fetch_blocks(&mut self, range: Range<BlockIndex>) -> Result<Vec<Block>>;
 
+    /// Retrieve as many of the blocks in `range` as the node has.
+    ///
+    /// The result is contiguous from the start of the range: the first block
+    /// has index `range.start`, and each block follows the one before it, so
+    /// the length tells how far the node's ledger reaches. It may be shorter
+    /// than the node's ledger allows if the node limits how many blocks it
+    /// returns at once.
+    ///
+    /// Fails with `Error::BeyondLedger` only if the node does not have the
+    /// block at `range.start`.
+    fn fetch_available_blocks(&mut self, range: Range<BlockIndex>) -> Result<Vec<Block>> {
+        if range.end < range.start {
+            return Err(Error::InvalidRange(range.start, range.end));
+        }
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut blocks = match self.fetch_blocks(range.clone()) {
+            Err(Error::NotFound) => Vec::new(),
+            result => result?,
+        };
+
+        // Discard anything after the first gap, or outside the range.
+        let contiguous = blocks
+            .iter()
+            .zip(range.clone())
+            .take_while(|(block, index)| block.index == *index)
+            .count();
+        blocks.truncate(contiguous);
+
+        if blocks.is_empty() {
+            return Err(Error::BeyondLedger(range.start));
+        }
+        validate_block_chain(range.start, &blocks)?;
+        Ok(blocks)
+    }
+
     /// Retrieve the BlockIDs (hashes) of the given blocks from the blockchain
     /// service.
+    ///
+    /// Empty and inverted ranges are handled as by `fetch_blocks`.
     fn fetch_block_ids(&mut self, range: Range<BlockIndex>) -> Result<Vec<BlockID>>;
 
     /// Retrieve the consensus node's current block height
     fn fetch_block_height(&mut self) -> Result<BlockIndex>;
 
+    /// Retrieve the last block in the consensus node's ledger.
+    ///
+    /// By default this asks for the block height and then fetches that
+    /// block, which takes two round trips. Connections able to fetch the
+    /// tip in a single call should override it.
+    fn fetch_latest_block(&mut self) -> Result<Block> {
+        let index = self.fetch_block_height()?;
+        let end = index.checked_add(1).ok_or(Error::RequestTooLarge)?;
+        self.fetch_blocks(index..end)?
+            .into_iter()
+            .find(|block| block.index == index)
+            .ok_or(Error::NotFound)
+    }
+
     /// Retrieve the consensus node's current block height and fee
     fn fetch_block_info(&mut self) -> Result<BlockInfo>;
+
+    /// Retrieve the block with the given ID, failing with `Error::NotFound`
+    /// if it is not in the node's ledger.
+    ///
+    /// By default this searches the block IDs backwards from the tip, since
+    /// the blocks a client asks about are usually recent. Only the last
+    /// 10,000 blocks are searched, so that an unknown ID does not scan the
+    /// whole chain. An ID not found among them fails with
+    /// `Error::BlockIdSearchLimit` unless they reach back to the origin block.
+    /// Connections whose node can look up a block by its ID should override
+    /// it.
+    fn fetch_block_by_id(&mut self, id: &BlockID) -> Result<Block> {
+        let mut end = self
+            .fetch_block_height()?
+            .checked_add(1)
+            .ok_or(Error::RequestTooLarge)?;
+        let limit = end.saturating_sub(BLOCK_ID_SEARCH_DEPTH);
+        while end > limit {
+            let start = end.saturating_sub(BLOCK_ID_SEARCH_BATCH_SIZE).max(limit);
+            let ids = self.fetch_block_ids(start..end)?;
+            if let Some(offset) = ids.iter().position(|block_id| block_id == id) {
+                let index = start + offset as u64;
+                return self
+                    .fetch_blocks(index..index + 1)?
+                    .into_iter()
+                    .find(|block| block.id == *id)
+                    .ok_or(Error::NotFound);
+            }
+            end = start;
+        }
+        if limit > 0 {
+            return Err(Error::BlockIdSearchLimit(id.clone(), BLOCK_ID_SEARCH_DEPTH));
+        }
+        Err(Error::NotFound)
+    }
+
+    /// Retrieve every block after the one with ID `last_known`, up to the
+    /// node's tip.
+    ///
+    /// Fails with `Error::ForkFromKnownBlock` if `last_known` is not on the
+    /// node's chain, in which case the caller should roll back to an earlier
+    /// block and resume from there. A `last_known` too far behind the tip for
+    /// `fetch_block_by_id` to find fails with `Error::BlockIdSearchLimit`
+    /// instead, since it may well be on the chain.
+    fn fetch_blocks_since(&mut self, last_known: &BlockID) -> Result<Vec<Block>> {
+        let known_block = match self.fetch_block_by_id(last_known) {
+            Err(Error::NotFound) => return Err(Error::ForkFromKnownBlock(last_known.clone())),
+            result => result?,
+        };
+        let start = known_block.index + 1;
+        let end = self
+            .fetch_block_height()?
+            .checked_add(1)
+            .ok_or(Error::RequestTooLarge)?;
+        if end <= start {
+            return Ok(Vec::new());
+        }
+
+        let blocks = self.fetch_blocks(start..end)?;
+        if let Some(first) = blocks.first() {
+            if first.parent_id != *last_known {
+                return Err(Error::ForkFromKnownBlock(last_known.clone()));
+            }
+        }
+        validate_block_chain(start, &blocks)?;
+        Ok(blocks)
+    }
+
+    /// Retrieve blocks along with their signatures, if the node has them.
+    ///
+    /// Connections which cannot provide signatures return
+    /// `Error::UnsupportedByPeer`.
+    fn fetch_signed_blocks(
+        &mut self,
+        _range: Range<BlockIndex>,
+    ) -> Result<Vec<(Block, Option<BlockSignature>)>> {
+        Err(Error::UnsupportedByPeer("fetch_signed_blocks".to_string()))
+    }
+
+    /// Retrieve blocks, checking that each was signed by one of
+    /// `trusted_signers`.
+    ///
+    /// This fails on the first block without a valid signature by a trusted
+    /// signer, so a peer cannot pass off blocks it made up. The origin block
+    /// is never signed, and is accepted as is.
+    ///
+    /// The blocks must also be exactly those in `range`, in order, forming a
+    /// chain, so a peer cannot substitute other validly signed blocks or
+    /// leave some out. A response which stops short of the end of `range`
+    /// fails with `Error::BeyondLedger`.
+    fn fetch_verified_blocks(
+        &mut self,
+        range: Range<BlockIndex>,
+        trusted_signers: &[Ed25519Public],
+    ) -> Result<Vec<Block>> {
+        let signed_blocks = self.fetch_signed_blocks(range.clone())?;
+        verify_signed_blocks(&range, signed_blocks, trusted_signers)
+    }
+
+    /// Retrieve blocks along with a membership proof for each of their
+    /// outputs, in output order.
+    ///
+    /// Connections which cannot provide proofs return
+    /// `Error::UnsupportedByPeer`.
+    fn fetch_blocks_with_proofs(
+        &mut self,
+        _range: Range<BlockIndex>,
+    ) -> Result<Vec<(Block, Vec<TxOutMembershipProof>)>> {
+        Err(Error::UnsupportedByPeer(
+            "fetch_blocks_with_proofs".to_string(),
+        ))
+    }
+
+    /// Retrieve the root of the TxOut Merkle tree once the outputs of every
+    /// block up to and including `block_index` have been added.
+    ///
+    /// Each block records the root its transactions' membership proofs were
+    /// checked against, which covers the outputs of every earlier block, so
+    /// by default this is read from the block after `block_index`. The root
+    /// is part of that block's ID, so a client which has verified the block
+    /// ID can trust it.
+    ///
+    /// Fails with `Error::BeyondLedger` if the block after `block_index` has
+    /// not been committed yet, and with `Error::UnsupportedByPeer` if the node
+    /// recorded no root in it.
+    fn fetch_tx_out_merkle_root(&mut self, block_index: BlockIndex) -> Result<[u8; 32]> {
+        let next = block_index.checked_add(1).ok_or(Error::RequestTooLarge)?;
+        let end = next.checked_add(1).ok_or(Error::RequestTooLarge)?;
+        let block = self
+            .fetch_blocks(next..end)?
+            .into_iter()
+            .find(|block| block.index == next)
+            .ok_or(Error::BeyondLedger(next))?;
+        if block.root_element == TxOutMembershipElement::default() {
+            return Err(Error::UnsupportedByPeer(
+                "fetch_tx_out_merkle_root".to_string(),
+            ));
+        }
+        Ok(block.root_element.hash.0)
+    }
+
+    /// Retrieve the time at which the given block was committed, in seconds
+    /// since the Unix epoch.
+    ///
+    /// By default this asks for the timestamps of a single-block range.
+    fn fetch_block_timestamp(&mut self, index: BlockIndex) -> Result<u64> {
+        let end = index.checked_add(1).ok_or(Error::RequestTooLarge)?;
+        self.fetch_block_timestamps(index..end)?
+            .into_iter()
+            .next()
+            .ok_or(Error::NotFound)
+    }
+
+    /// Retrieve the commit times of the blocks in `range`, in block order, in
+    /// seconds since the Unix epoch.
+    ///
+    /// Blocks do not record when they were committed, so this cannot be
+    /// derived from their contents. Connections whose node does not serve
+    /// timestamps return `Error::UnsupportedByPeer`.
+    fn fetch_block_timestamps(&mut self, _range: Range<BlockIndex>) -> Result<Vec<u64>> {
+        Err(Error::UnsupportedByPeer(
+            "fetch_block_timestamps".to_string(),
+        ))
+    }
+
+    /// Look up the block in which `key_image` was spent, or `None` if it has
+    /// not been spent in the peer's ledger.
+    ///
+    /// Blocks do not include their key images, so this cannot be derived from
+    /// them. Connections whose node does not serve key image lookups return
+    /// `Error::UnsupportedByPeer`.
+    fn contains_key_image(&mut self, _key_image: &KeyImage) -> Result<Option<BlockIndex>> {
+        Err(Error::UnsupportedByPeer("contains_key_image".to_string()))
+    }
+}
+
+/// A connection trait providing the fee a node currently requires.
+pub trait FeeConnection: Connection {
+    /// Retrieve the minimum fee the node will accept for a transaction.
+    fn fetch_minimum_fee(&mut self) -> Result<u64>;
+
+    /// Retrieve the fee the node estimates a transaction needs to pay to be
+    /// included within `target_blocks` blocks, based on the blocks it recently
+    /// externalized. Connections whose node does not estimate fees return
+    /// `Error::UnsupportedByPeer`, and callers may fall back to the minimum
+    /// fee.
+    fn fetch_fee_estimate(&mut self, _target_blocks: u64) -> Result<u64> {
+        Err(Error::UnsupportedByPeer("fetch_fee_estimate".to_string()))
+    }
+}
+
+/// A client-chosen key identifying one logical transaction submission.
+///
+/// Retries of a submission reuse its key, so that the node can recognize them
+/// and return the original result instead of rejecting the transaction as a
+/// duplicate.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct IdempotencyKey(pub [u8; 16]);
+
+impl IdempotencyKey {
+    /// Create a new, random key.
+    pub fn random() -> Self {
+        let mut key = [0u8; 16];
+        McRng::default().fill_bytes(&mut key);
+        Self(key)
+    }
+}
+
+impl AsRef<[u8]> for IdempotencyKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A node's acknowledgement of a proposed transaction.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ProposeTxResponse {
+    /// The number of blocks in the ledger at the time the call was received.
+    pub block_index: BlockIndex,
+    /// The hash of the accepted transaction.
+    pub tx_hash: TxHash,
+}
+
+impl ProposeTxResponse {
+    /// Create a response for the given transaction. The hash is computed
+    /// locally, rather than trusting the node to report it.
+    pub fn new(tx: &Tx, block_index: BlockIndex) -> Self {
+        Self {
+            block_index,
+            tx_hash: tx.tx_hash(),
+        }
+    }
+}
+
+impl Display for ProposeTxResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Tx {} at block {}", self.tx_hash, self.block_index)
+    }
 }
 
 /// A trait which supports supporting the submission of transactions to a node
 pub trait UserTxConnection: Connection {
+    /// Propose a transaction over the encrypted channel.
+    /// Returns the number of blocks in the ledger at the time the call was
+    /// received, along with the hash of the accepted transaction.
+    fn propose_tx(&mut self, tx: &Tx) -> Result<ProposeTxResponse>;
+
+    /// Propose a transaction, identifying the submission with the given
+    /// idempotency key. Resubmitting with the same key returns the original
+    /// result.
+    ///
+    /// The default implementation ignores the key.
+    fn propose_tx_with_idempotency_key(
+        &mut self,
+        tx: &Tx,
+        _idempotency_key: &IdempotencyKey,
+    ) -> Result<ProposeTxResponse> {
+        self.propose_tx(tx)
+    }
+
     /// Propose a transaction over the encrypted channel.
     /// Returns the number of blocks in the ledger at the time the call was
     /// received.
-    fn propose_tx(&mut self, tx: &Tx) -> Result<BlockIndex>;
+    #[deprecated(note = "use propose_tx, which also returns the accepted tx hash")]
+    fn propose_tx_block_index(&mut self, tx: &Tx) -> Result<BlockIndex> {
+        self.propose_tx(tx).map(|response| response.block_index)
+    }
+
+    /// Propose a batch of transactions.
+    ///
+    /// Returns one result per transaction, in the order given, so a rejected
+    /// transaction does not prevent the rest of the batch from being
+    /// submitted. An outer error indicates the batch could not be submitted
+    /// at all.
+    ///
+    /// The default implementation submits the transactions one at a time.
+    fn propose_tx_batch(&mut self, txs: &[Tx]) -> Result<Vec<Result<ProposeTxResponse>>> {
+        Ok(txs.iter().map(|tx| self.propose_tx(tx)).collect())
+    }
+}
+
+/// A future resolving to the result of a non-blocking connection call.
+pub type ConnectionFuture<T> = Pin<Box<dyn Future<Output = Result<T>> + Send>>;
+
+/// A non-blocking counterpart to `BlockchainConnection`.
+///
+/// The request is issued when the method is called, and the returned future
+/// resolves once the node responds, so waiting on many peers does not need a
+/// thread per peer. The future does not borrow the connection.
+pub trait AsyncBlockchainConnection: Connection {
+    /// Retrieve the block metadata from the blockchain service, as
+    /// `BlockchainConnection::fetch_blocks` does.
+    fn fetch_blocks_async(&mut self, range: Range<BlockIndex>) -> ConnectionFuture<Vec<Block>>;
+}
+
+/// A non-blocking counterpart to `UserTxConnection`.
+///
+/// See `AsyncBlockchainConnection` for how requests are issued.
+pub trait AsyncUserTxConnection: Connection {
+    /// Propose a transaction over the encrypted channel, as
+    /// `UserTxConnection::propose_tx` does.
+    fn propose_tx_async(&mut self, tx: &Tx) -> ConnectionFuture<ProposeTxResponse>;
+}
+
+/// A connection trait providing visibility into a node's pending transactions.
+pub trait MempoolConnection: Connection {
+    /// Retrieve the hashes of the transactions the node is currently holding
+    /// for inclusion in a future block.
+    fn fetch_pending_tx_hashes(&mut self) -> Result<Vec<TxHash>>;
+
+    /// Deliver each of the node's pending transaction hashes to the given sink.
+    fn stream_pending_txs(&mut self, mut sink: impl FnMut(TxHash)) -> Result<()> {
+        for tx_hash in self.fetch_pending_tx_hashes()? {
+            sink(tx_hash);
+        }
+        Ok(())
+    }
 }
 
 // Retryable connections: these traits exist to allow SyncConnection to extend
 // itself when its inner connection API supports additional APIs.
 
+/// The successful result of a retryable call, along with how it was obtained.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetrySuccess<T> {
+    /// The value returned by the successful attempt.
+    pub value: T,
+    /// The number of attempts made, including the successful one.
+    pub attempts: u32,
+    /// The time taken by all attempts, including delays between them.
+    pub elapsed: Duration,
+}
+
+impl<T> RetrySuccess<T> {
+    /// Discard the retry information, returning only the value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
 /// A connection trait providing retryable blockchain data APIs
 pub trait RetryableBlockchainConnection {
     /// Retrieve the block metadata from the blockchain service.
@@ -118,7 +590,67 @@ pub trait RetryableBlockchainConnection {
         &self,
         range: Range<BlockIndex>,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<Vec<Block>>;
+    ) -> RetryResult<RetrySuccess<Vec<Block>>>;
+
+    /// Retrieve the blocks in `range`, calling `progress` with the index of
+    /// the last block received each time more blocks arrive.
+    ///
+    /// The range is fetched in chunks, each retried on its own copy of
+    /// `retry_iterator`. A chunk the peer answers only in part is continued
+    /// from the last block received. If a chunk fails, the blocks already
+    /// received are discarded and its error returned. The reported attempts
+    /// and elapsed time cover every chunk, including in the error, whose
+    /// total delay is the time since the first chunk was requested.
+    fn fetch_blocks_with_progress(
+        &self,
+        range: Range<BlockIndex>,
+        retry_iterator: impl IntoIterator<Item = Duration> + Clone,
+        mut progress: impl FnMut(BlockIndex),
+    ) -> RetryResult<RetrySuccess<Vec<Block>>> {
+        if range.is_empty() {
+            return self.fetch_blocks(range, retry_iterator);
+        }
+
+        let start = Instant::now();
+        let mut blocks = Vec::new();
+        let mut attempts = 0;
+        let mut next = range.start;
+        while next < range.end {
+            let end = next.saturating_add(PROGRESS_CHUNK_SIZE).min(range.end);
+            let chunk = match self.fetch_blocks(next..end, retry_iterator.clone()) {
+                Ok(chunk) => chunk,
+                Err(RetryError::Operation { error, tries, .. }) => {
+                    return Err(RetryError::Operation {
+                        error,
+                        total_delay: start.elapsed(),
+                        tries: attempts + tries,
+                    })
+                }
+                Err(err) => return Err(err),
+            };
+            attempts += chunk.attempts;
+
+            let last_index = match chunk.value.last() {
+                Some(block) if (next..end).contains(&block.index) => block.index,
+                _ => {
+                    return Err(RetryError::Operation {
+                        error: Error::BeyondLedger(next),
+                        total_delay: start.elapsed(),
+                        tries: attempts,
+                    })
+                }
+            };
+            blocks.extend(chunk.value);
+            progress(last_index);
+            next = last_index + 1;
+        }
+
+        Ok(RetrySuccess {
+            value: blocks,
+            attempts,
+            elapsed: start.elapsed(),
+        })
+    }
 
     /// Retrieve the BlockIDs (hashes) of the given blocks from the blockchain
     /// service.
@@ -126,29 +658,59 @@ pub trait RetryableBlockchainConnection {
         &self,
         range: Range<BlockIndex>,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<Vec<BlockID>>;
+    ) -> RetryResult<RetrySuccess<Vec<BlockID>>>;
 
     /// Retrieve the highest block index published
     fn fetch_block_height(
         &self,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<BlockIndex>;
+    ) -> RetryResult<RetrySuccess<BlockIndex>>;
 
     /// Retrieve the highest block index published
     fn fetch_block_info(
         &self,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<BlockInfo>;
+    ) -> RetryResult<RetrySuccess<BlockInfo>>;
+}
+
+/// A connection trait providing retryable fee APIs
+pub trait RetryableFeeConnection {
+    /// Retrieve the minimum fee the node will accept for a transaction.
+    fn fetch_minimum_fee(
+        &self,
+        retry_iterator: impl IntoIterator<Item = Duration>,
+    ) -> RetryResult<RetrySuccess<u64>>;
 }
 
 /// A trait which supports re-trying transaction submission
 pub trait RetryableUserTxConnection {
     /// Propose a transaction over the encrypted channel.
     /// Returns the number of blocks in the ledger at the time the call was
-    /// received.
+    /// received, along with the hash of the accepted transaction.
+    ///
+    /// Every attempt carries the same idempotency key, so a retry of a
+    /// submission which the node already accepted returns the original
+    /// result.
     fn propose_tx(
         &self,
         tx: &Tx,
         retry_iterator: impl IntoIterator<Item = Duration>,
-    ) -> RetryResult<BlockIndex>;
+    ) -> RetryResult<RetrySuccess<ProposeTxResponse>> {
+        self.propose_tx_with_idempotency_key(tx, &IdempotencyKey::random(), retry_iterator)
+    }
+
+    /// Propose a transaction as `propose_tx` does, but with a key chosen by
+    /// the caller.
+    ///
+    /// Every attempt carries `idempotency_key`. A caller which resubmits the
+    /// same transaction itself, such as after this call times out, should
+    /// pass the same key again, so that the node returns the result of
+    /// whichever submission it saw first. Nodes which do not support
+    /// idempotency keys ignore them.
+    fn propose_tx_with_idempotency_key(
+        &self,
+        tx: &Tx,
+        idempotency_key: &IdempotencyKey,
+        retry_iterator: impl IntoIterator<Item = Duration>,
+    ) -> RetryResult<RetrySuccess<ProposeTxResponse>>;
 }
@@ -0,0 +1,364 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Verification of data returned by a consensus node.
+
+use crate::error::{ChainValidationError, Error, Result};
+use mc_crypto_keys::Ed25519Public;
+use mc_transaction_core::{Block, BlockIndex, BlockSignature};
+use std::{ops::Range, result::Result as StdResult, slice};
+
+/// Check that the blocks form a chain starting at block `first_index`.
+///
+/// The first block must have index `first_index`, and every later block the
+/// index one above its predecessor and a parent ID equal to its predecessor's
+/// ID, so a peer cannot splice blocks from another chain or height into a
+/// range. Each block's ID is also recomputed from its contents, so a block
+/// cannot take a place in the chain its contents do not match. The first
+/// problem found is returned.
+pub fn validate_block_chain(
+    first_index: BlockIndex,
+    blocks: &[Block],
+) -> StdResult<(), ChainValidationError> {
+    let mut previous: Option<&Block> = None;
+    for block in blocks {
+        match previous {
+            None if block.index != first_index => {
+                return Err(ChainValidationError::UnexpectedFirstIndex {
+                    expected: first_index,
+                    found: block.index,
+                });
+            }
+            None => {}
+            Some(previous) => {
+                let expected = previous.index + 1;
+                if block.index != expected {
+                    return Err(ChainValidationError::NonConsecutiveIndex {
+                        previous: previous.index,
+                        expected,
+                        found: block.index,
+                    });
+                }
+
+                if block.parent_id != previous.id {
+                    return Err(ChainValidationError::ParentMismatch(block.index));
+                }
+            }
+        }
+
+        if !block.is_block_id_valid() {
+            return Err(ChainValidationError::InvalidBlockId(block.index));
+        }
+        previous = Some(block);
+    }
+    Ok(())
+}
+
+/// Check that `range` does not end before it starts.
+pub(crate) fn check_range(range: &Range<BlockIndex>) -> Result<()> {
+    if range.end < range.start {
+        return Err(Error::Other(format!(
+            "Invalid block range {}..{}",
+            range.start, range.end
+        )));
+    }
+    Ok(())
+}
+
+/// Check that `blocks` holds every block in `range`, in order, forming a
+/// chain.
+///
+/// A response which stops short of the end of `range` fails with
+/// `Error::BeyondLedger`.
+pub(crate) fn check_blocks_cover(range: &Range<BlockIndex>, blocks: &[Block]) -> Result<()> {
+    check_range(range)?;
+    if let Some((block, index)) = blocks
+        .iter()
+        .zip(range.clone())
+        .find(|(block, index)| block.index != *index)
+    {
+        return Err(Error::Other(format!(
+            "Expected block {}, found block {}",
+            index, block.index
+        )));
+    }
+
+    let end = range.start + blocks.len() as BlockIndex;
+    if end < range.end {
+        return Err(Error::BeyondLedger(end));
+    }
+    if end > range.end {
+        return Err(Error::Other(format!(
+            "Expected {} blocks, found {}",
+            range.end - range.start,
+            blocks.len()
+        )));
+    }
+
+    validate_block_chain(range.start, blocks)?;
+    Ok(())
+}
+
+/// Check that `signed_blocks` holds every block in `range`, forming a chain,
+/// and that each was signed by one of `trusted_signers`, returning the
+/// blocks.
+pub(crate) fn verify_signed_blocks(
+    range: &Range<BlockIndex>,
+    signed_blocks: Vec<(Block, Option<BlockSignature>)>,
+    trusted_signers: &[Ed25519Public],
+) -> Result<Vec<Block>> {
+    verify_trusted_block_signatures(&signed_blocks, trusted_signers)?;
+    let blocks = signed_blocks
+        .into_iter()
+        .map(|(block, _)| block)
+        .collect::<Vec<_>>();
+    check_blocks_cover(range, &blocks)?;
+    Ok(blocks)
+}
+
+/// Check that every block was signed by the given signing key.
+///
+/// The origin block is never signed, so it is accepted without a signature.
+/// Any other block must carry a valid signature made by `pinned_signer`.
+pub fn verify_block_signatures(
+    signed_blocks: &[(Block, Option<BlockSignature>)],
+    pinned_signer: &Ed25519Public,
+) -> Result<()> {
+    verify_trusted_block_signatures(signed_blocks, slice::from_ref(pinned_signer))
+}
+
+/// Check that every block was signed by one of the given signing keys.
+///
+/// As with `verify_block_signatures`, the origin block is accepted without a
+/// signature. The first block which fails is reported.
+pub fn verify_trusted_block_signatures(
+    signed_blocks: &[(Block, Option<BlockSignature>)],
+    trusted_signers: &[Ed25519Public],
+) -> Result<()> {
+    for (block, signature) in signed_blocks {
+        let signature = match signature {
+            Some(signature) => signature,
+            None if block.index == 0 => continue,
+            None => return Err(Error::MissingBlockSignature(block.index)),
+        };
+
+        if !trusted_signers.contains(signature.signer()) {
+            return Err(Error::UnexpectedSigner(block.index));
+        }
+
+        signature
+            .verify(block)
+            .map_err(|_| Error::InvalidBlockSignature(block.index))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::chain;
+    use mc_crypto_keys::Ed25519Pair;
+    use mc_transaction_core::{BlockContents, BLOCK_VERSION};
+    use mc_util_from_random::FromRandom;
+    use rand::SeedableRng;
+    use rand_hc::Hc128Rng;
+
+    fn signed_chain(keypair: &Ed25519Pair) -> Vec<(Block, Option<BlockSignature>)> {
+        let origin = Block::new_origin_block(&[]);
+        let block = Block::new_with_parent(
+            BLOCK_VERSION,
+            &origin,
+            &Default::default(),
+            &BlockContents::default(),
+        );
+        let signature = BlockSignature::from_block_and_keypair(&block, keypair).unwrap();
+        vec![(origin, None), (block, Some(signature))]
+    }
+
+    fn signed_chain_of(keypair: &Ed25519Pair, len: usize) -> Vec<(Block, Option<BlockSignature>)> {
+        chain(len)
+            .into_iter()
+            .map(|block| {
+                let signature = match block.index {
+                    0 => None,
+                    _ => Some(BlockSignature::from_block_and_keypair(&block, keypair).unwrap()),
+                };
+                (block, signature)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accepts_linked_chain() {
+        assert_eq!(validate_block_chain(0, &chain(4)), Ok(()));
+        assert_eq!(validate_block_chain(1, &chain(4)[1..]), Ok(()));
+        assert_eq!(validate_block_chain(0, &[]), Ok(()));
+    }
+
+    #[test]
+    fn rejects_chain_starting_elsewhere() {
+        assert_eq!(
+            validate_block_chain(2, &chain(4)[1..]),
+            Err(ChainValidationError::UnexpectedFirstIndex {
+                expected: 2,
+                found: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_block_id_not_matching_contents() {
+        // The last block keeps its ID, and so its link to its parent, but its
+        // contents no longer hash to that ID.
+        let mut blocks = chain(4);
+        blocks[3].cumulative_txo_count += 1;
+        assert_eq!(
+            validate_block_chain(0, &blocks),
+            Err(ChainValidationError::InvalidBlockId(3))
+        );
+    }
+
+    #[test]
+    fn rejects_gaps_and_foreign_blocks() {
+        let mut blocks = chain(4);
+        blocks.remove(2);
+        assert_eq!(
+            validate_block_chain(0, &blocks),
+            Err(ChainValidationError::NonConsecutiveIndex {
+                previous: 1,
+                expected: 2,
+                found: 3,
+            })
+        );
+
+        // A block with the right index, but built on a different parent.
+        let mut blocks = chain(4);
+        blocks[2].parent_id = blocks[0].id.clone();
+        assert_eq!(
+            validate_block_chain(0, &blocks),
+            Err(ChainValidationError::ParentMismatch(2))
+        );
+    }
+
+    #[test]
+    fn accepts_blocks_signed_by_pinned_key() {
+        let mut rng = Hc128Rng::from_seed([1u8; 32]);
+        let keypair = Ed25519Pair::from_random(&mut rng);
+
+        let signed_blocks = signed_chain(&keypair);
+        assert!(verify_block_signatures(&signed_blocks, &keypair.public_key()).is_ok());
+    }
+
+    #[test]
+    fn rejects_blocks_signed_by_other_key() {
+        let mut rng = Hc128Rng::from_seed([1u8; 32]);
+        let keypair = Ed25519Pair::from_random(&mut rng);
+        let pinned = Ed25519Pair::from_random(&mut rng);
+
+        let signed_blocks = signed_chain(&keypair);
+        match verify_block_signatures(&signed_blocks, &pinned.public_key()) {
+            Err(Error::UnexpectedSigner(1)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accepts_blocks_signed_by_any_trusted_key() {
+        let mut rng = Hc128Rng::from_seed([1u8; 32]);
+        let keypair = Ed25519Pair::from_random(&mut rng);
+        let other = Ed25519Pair::from_random(&mut rng);
+
+        let signed_blocks = signed_chain(&keypair);
+        let trusted = [other.public_key(), keypair.public_key()];
+        assert!(verify_trusted_block_signatures(&signed_blocks, &trusted).is_ok());
+
+        match verify_trusted_block_signatures(&signed_blocks, &trusted[..1]) {
+            Err(Error::UnexpectedSigner(1)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match verify_trusted_block_signatures(&signed_blocks, &[]) {
+            Err(Error::UnexpectedSigner(1)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_missing_and_forged_signatures() {
+        let mut rng = Hc128Rng::from_seed([1u8; 32]);
+        let keypair = Ed25519Pair::from_random(&mut rng);
+
+        let mut signed_blocks = signed_chain(&keypair);
+        signed_blocks[1].1 = None;
+        match verify_block_signatures(&signed_blocks, &keypair.public_key()) {
+            Err(Error::MissingBlockSignature(1)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // A signature by the pinned key, but over a different block.
+        let mut signed_blocks = signed_chain(&keypair);
+        let origin_signature =
+            BlockSignature::from_block_and_keypair(&signed_blocks[0].0, &keypair).unwrap();
+        signed_blocks[1].1 = Some(origin_signature);
+        match verify_block_signatures(&signed_blocks, &keypair.public_key()) {
+            Err(Error::InvalidBlockSignature(1)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verified_blocks_must_match_requested_range() {
+        let mut rng = Hc128Rng::from_seed([1u8; 32]);
+        let keypair = Ed25519Pair::from_random(&mut rng);
+        let trusted = [keypair.public_key()];
+
+        let signed_blocks = signed_chain_of(&keypair, 4);
+        assert_eq!(
+            verify_signed_blocks(&(0..4), signed_blocks.clone(), &trusted).unwrap(),
+            chain(4)
+        );
+
+        // Validly signed blocks, but not the ones asked for.
+        match verify_signed_blocks(&(1..5), signed_blocks, &trusted) {
+            Err(Error::Other(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verified_blocks_must_cover_requested_range() {
+        let mut rng = Hc128Rng::from_seed([1u8; 32]);
+        let keypair = Ed25519Pair::from_random(&mut rng);
+        let trusted = [keypair.public_key()];
+
+        // A peer which leaves out the end of the range.
+        let mut signed_blocks = signed_chain_of(&keypair, 4);
+        signed_blocks.truncate(2);
+        match verify_signed_blocks(&(0..4), signed_blocks, &trusted) {
+            Err(Error::BeyondLedger(2)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verified_blocks_must_form_a_chain() {
+        let mut rng = Hc128Rng::from_seed([1u8; 32]);
+        let keypair = Ed25519Pair::from_random(&mut rng);
+        let trusted = [keypair.public_key()];
+
+        // Each block is signed by the trusted key, but block 2 was built on
+        // the origin block rather than block 1.
+        let mut signed_blocks = signed_chain_of(&keypair, 4);
+        let mut foreign = Block::new_with_parent(
+            BLOCK_VERSION,
+            &signed_blocks[0].0,
+            &Default::default(),
+            &BlockContents::default(),
+        );
+        foreign.index = 2;
+        let signature = BlockSignature::from_block_and_keypair(&foreign, &keypair).unwrap();
+        signed_blocks[2] = (foreign, Some(signature));
+        match verify_signed_blocks(&(0..4), signed_blocks, &trusted) {
+            Err(Error::ChainValidation(ChainValidationError::ParentMismatch(2))) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
@@ -3,8 +3,8 @@
 //! Connection mock and test utilities
 
 use mc_connection::{
-    BlockInfo, BlockchainConnection, Connection, Error as ConnectionError,
-    Result as ConnectionResult, UserTxConnection,
+    BlockInfo, BlockchainConnection, Connection, Error as ConnectionError, FeeConnection,
+    ProposeTxResponse, Result as ConnectionResult, UserTxConnection,
 };
 use mc_ledger_db::Ledger;
 use mc_transaction_core::{constants::MINIMUM_FEE, tx::Tx, Block, BlockID, BlockIndex};
@@ -122,9 +122,18 @@ impl<L: Ledger + Sync> BlockchainConnection for MockBlockchainConnection<L> {
 }
 
 impl<L: Ledger + Sync> UserTxConnection for MockBlockchainConnection<L> {
-    fn propose_tx(&mut self, tx: &Tx) -> ConnectionResult<BlockIndex> {
+    fn propose_tx(&mut self, tx: &Tx) -> ConnectionResult<ProposeTxResponse> {
         self.proposed_txs.push(tx.clone());
-        Ok(self.ledger.num_blocks().unwrap())
+        Ok(ProposeTxResponse::new(
+            tx,
+            self.ledger.num_blocks().unwrap(),
+        ))
+    }
+}
+
+impl<L: Ledger + Sync> FeeConnection for MockBlockchainConnection<L> {
+    fn fetch_minimum_fee(&mut self) -> ConnectionResult<u64> {
+        Ok(MINIMUM_FEE)
     }
 }
 
@@ -168,4 +177,51 @@ mod tests {
             assert_eq!(blocks.len(), 5)
         }
     }
+
+    #[test]
+    // Fetching available blocks should return the prefix of the range the peer
+    // has, and only fail if the peer has none of it.
+    fn fetch_available_blocks() {
+        let mock_ledger = get_mock_ledger(25);
+        let mut mock_peer = MockBlockchainConnection::new(test_client_uri(123), mock_ledger, 0);
+
+        let blocks = mock_peer.fetch_available_blocks(20..1000).unwrap();
+        let indices: Vec<_> = blocks.iter().map(|block| block.index).collect();
+        assert_eq!(indices, vec![20, 21, 22, 23, 24]);
+
+        assert_eq!(mock_peer.fetch_available_blocks(0..10).unwrap().len(), 10);
+        assert!(mock_peer.fetch_available_blocks(5..5).unwrap().is_empty());
+
+        match mock_peer.fetch_available_blocks(25..30) {
+            Err(ConnectionError::BeyondLedger(25)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    // The latest block should be the last block in the peer's ledger.
+    fn fetch_latest_block() {
+        let mock_ledger = get_mock_ledger(25);
+        let expected = mock_ledger.get_block(24).unwrap();
+        let mut mock_peer = MockBlockchainConnection::new(test_client_uri(123), mock_ledger, 0);
+
+        assert_eq!(mock_peer.fetch_latest_block().unwrap(), expected);
+    }
+
+    #[test]
+    // A peer which does not serve timestamps should say so, rather than
+    // returning a made-up time.
+    fn fetch_block_timestamp_unsupported() {
+        let mock_ledger = get_mock_ledger(25);
+        let mut mock_peer = MockBlockchainConnection::new(test_client_uri(123), mock_ledger, 0);
+
+        match mock_peer.fetch_block_timestamp(3) {
+            Err(ConnectionError::UnsupportedByPeer(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match mock_peer.fetch_block_timestamps(0..10) {
+            Err(ConnectionError::UnsupportedByPeer(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
 }
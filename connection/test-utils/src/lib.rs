@@ -2,15 +2,10 @@
 
 //! Connection mock and test utilities
 
-use mc_util_uri::ConsensusClientUri;
-use std::str::FromStr;
-
 mod blockchain;
-mod user_tx;
-
-pub fn test_client_uri(node_id: u32) -> ConsensusClientUri {
-    ConsensusClientUri::from_str(&format!("mc://node{}.test.com/", node_id))
-        .expect("Could not construct client uri from string")
-}
 
-pub use self::{blockchain::MockBlockchainConnection, user_tx::MockUserTxConnection};
+pub use self::blockchain::MockBlockchainConnection;
+pub use mc_connection::test_utils::{
+    chain, test_client_uri, InMemoryBlockchainConnection, MockAttestationError,
+    MockAttestedConnection, MockUserTxConnection,
+};
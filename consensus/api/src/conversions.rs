@@ -8,9 +8,9 @@
 //! such as `mc_consensus_api::consensus_common::ProposeTxResult` and
 //! `mc_transaction_core::validation::TransactionValidationError`.
 
-use crate::consensus_common::ProposeTxResult;
+use crate::consensus_common::{ProposeTxResponse, ProposeTxResult};
 use mc_transaction_core::{ring_signature, validation::TransactionValidationError};
-use std::convert::{From, TryInto};
+use std::convert::{From, TryFrom, TryInto};
 
 /// Convert TransactionValidationError --> ProposeTxResult.
 impl From<TransactionValidationError> for ProposeTxResult {
@@ -56,12 +56,18 @@ impl From<TransactionValidationError> for ProposeTxResult {
             }
             TransactionValidationError::InvalidLedgerContext => Self::InvalidLedgerContext,
             TransactionValidationError::Ledger(_) => Self::Ledger,
+            TransactionValidationError::MembershipProofIndexOutOfBounds { .. } => {
+                Self::MembershipProofIndexOutOfBounds
+            }
             TransactionValidationError::MembershipProofValidationError => {
                 Self::MembershipProofValidationError
             }
             TransactionValidationError::TxFeeError => Self::TxFeeError,
             TransactionValidationError::KeyError => Self::KeyError,
             TransactionValidationError::UnsortedInputs => Self::UnsortedInputs,
+            TransactionValidationError::UnsupportedTransactionVersion { .. } => {
+                Self::UnsupportedTransactionVersion
+            }
         }
     }
 }
@@ -117,15 +123,80 @@ impl TryInto<TransactionValidationError> for ProposeTxResult {
             }
             Self::InvalidLedgerContext => Ok(TransactionValidationError::InvalidLedgerContext),
             Self::Ledger => Ok(TransactionValidationError::Ledger(String::default())),
+            Self::MembershipProofIndexOutOfBounds => Err(
+                "MembershipProofIndexOutOfBounds does not carry the indices needed for a TransactionValidationError",
+            ),
             Self::MembershipProofValidationError => {
                 Ok(TransactionValidationError::MembershipProofValidationError)
             }
             Self::TxFeeError => Ok(TransactionValidationError::TxFeeError),
             Self::KeyError => Ok(TransactionValidationError::KeyError),
             Self::UnsortedInputs => Ok(TransactionValidationError::UnsortedInputs),
+            Self::UnsupportedTransactionVersion => Err(
+                "UnsupportedTransactionVersion does not carry the versions needed for a TransactionValidationError",
+            ),
+        }
+    }
+}
+
+/// Convert TransactionValidationError --> ProposeTxResponse, carrying the
+/// details of errors which have them.
+impl From<TransactionValidationError> for ProposeTxResponse {
+    fn from(src: TransactionValidationError) -> Self {
+        let mut response = ProposeTxResponse::new();
+        if let TransactionValidationError::MembershipProofIndexOutOfBounds {
+            requested,
+            ledger_size,
+        } = src
+        {
+            response.set_requested_index(requested);
+            response.set_ledger_size(ledger_size);
+        }
+        response.set_result(src.into());
+        response
+    }
+}
+
+/// Convert ProposeTxResponse --> TransactionValidationError, using the
+/// details the response carries.
+impl TryFrom<&ProposeTxResponse> for TransactionValidationError {
+    type Error = &'static str;
+
+    fn try_from(src: &ProposeTxResponse) -> Result<Self, Self::Error> {
+        match src.get_result() {
+            ProposeTxResult::MembershipProofIndexOutOfBounds => Ok(
+                TransactionValidationError::MembershipProofIndexOutOfBounds {
+                    requested: src.get_requested_index(),
+                    ledger_size: src.get_ledger_size(),
+                },
+            ),
+            result => result.try_into(),
         }
     }
 }
 
 #[cfg(test)]
-mod conversion_tests {}
+mod conversion_tests {
+    use super::*;
+
+    #[test]
+    // The indices of an out of bounds membership proof should survive a round
+    // trip through a ProposeTxResponse.
+    fn membership_proof_index_out_of_bounds_round_trip() {
+        let err = TransactionValidationError::MembershipProofIndexOutOfBounds {
+            requested: 10002,
+            ledger_size: 100,
+        };
+        let response = ProposeTxResponse::from(err.clone());
+        assert_eq!(
+            response.get_result(),
+            ProposeTxResult::MembershipProofIndexOutOfBounds
+        );
+        assert_eq!(TransactionValidationError::try_from(&response), Ok(err));
+
+        // The result code alone does not carry the indices.
+        let result: Result<TransactionValidationError, _> =
+            ProposeTxResult::MembershipProofIndexOutOfBounds.try_into();
+        assert!(result.is_err());
+    }
+}
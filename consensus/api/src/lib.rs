@@ -22,3 +22,20 @@ pub mod conversions;
 
 pub use autogenerated_code::*;
 pub use mc_api::ConversionError;
+
+/// The request metadata key carrying a client-chosen idempotency key for
+/// `ClientTxPropose`. Retries of a submission carry the same key.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "mc-idempotency-key-bin";
+
+/// The request metadata key carrying the attestation protocol version a client
+/// offers in `Auth`, as a decimal string. Clients which omit it are taken to
+/// use the oldest version. Nodes echo the version they accepted under the same
+/// key in the `Auth` response headers.
+pub const ATTESTATION_VERSION_HEADER: &str = "mc-attestation-version";
+
+/// The attestation protocol version spoken by clients and nodes which predate
+/// version negotiation.
+pub const OLDEST_ATTESTATION_VERSION: u32 = 1;
+
+/// The attestation protocol versions this release supports, newest first.
+pub const SUPPORTED_ATTESTATION_VERSIONS: &[u32] = &[1];
@@ -123,6 +123,9 @@ impl From<&Tx> for WellFormedTxContext {
 /// Defines a sort order for transactions in a block.
 /// Transactions are sorted by fee (high to low), then by transaction hash and
 /// any other fields.
+///
+/// This is a total order, so every node sorts the same set of transactions
+/// identically, regardless of the order in which it received them.
 impl Ord for WellFormedTxContext {
     fn cmp(&self, other: &Self) -> Ordering {
         if self.fee != other.fee {
@@ -186,6 +189,8 @@ pub struct TxContext {
     pub highest_indices: Vec<u64>,
     pub key_images: Vec<KeyImage>,
     pub output_public_keys: Vec<CompressedRistrettoPublic>,
+    /// The version of the transaction format the transaction was built for.
+    pub tx_version: u32,
 }
 
 pub type SealedBlockSigningKey = Vec<u8>;
@@ -322,6 +322,7 @@ impl ConsensusEnclave for SgxConsensusEnclave {
             highest_indices,
             key_images,
             output_public_keys,
+            tx_version: tx.prefix.version(),
         })
     }
 
@@ -354,6 +355,7 @@ impl ConsensusEnclave for SgxConsensusEnclave {
                     highest_indices,
                     key_images,
                     output_public_keys,
+                    tx_version: tx.prefix.version(),
                 })
             })
             .collect()
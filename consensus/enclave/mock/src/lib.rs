@@ -73,6 +73,7 @@ impl ConsensusServiceMockEnclave {
             highest_indices,
             key_images,
             output_public_keys,
+            tx_version: tx.prefix.version(),
         }
     }
 }
@@ -97,6 +97,31 @@ impl<V: Value, ValidationError: Clone + Display + 'static> Node<V, ValidationErr
         }
     }
 
+    /// Sets `scp_timebase`, including for the current slot.
+    ///
+    /// The current slot is recreated, so this should be called before the
+    /// node proposes values or handles messages.
+    pub fn with_scp_timebase(mut self, scp_timebase: Duration) -> Self {
+        self.scp_timebase = scp_timebase;
+        self.current_slot = Box::new(self.new_slot(self.current_slot.get_index()));
+        self
+    }
+
+    // Create a slot using this node's configuration.
+    fn new_slot(&self, slot_index: SlotIndex) -> Slot<V, ValidationError> {
+        let mut slot = Slot::new(
+            self.ID.clone(),
+            self.Q.clone(),
+            slot_index,
+            self.validity_fn.clone(),
+            self.combine_fn.clone(),
+            self.logger.clone(),
+        );
+        slot.base_round_interval = self.scp_timebase;
+        slot.base_ballot_interval = self.scp_timebase;
+        slot
+    }
+
     // Record the values externalized by the current slot and advance the current
     // slot.
     fn externalize(&mut self, payload: &ExternalizePayload<V>) -> Result<(), String> {
@@ -116,14 +141,7 @@ impl<V: Value, ValidationError: Clone + Display + 'static> Node<V, ValidationErr
             }
         }
 
-        let next_slot = Box::new(Slot::new(
-            self.ID.clone(),
-            self.Q.clone(),
-            slot_index + 1,
-            self.validity_fn.clone(),
-            self.combine_fn.clone(),
-            self.logger.clone(),
-        ));
+        let next_slot = Box::new(self.new_slot(slot_index + 1));
 
         // Advance to the next slot.
         let externalized_slot = std::mem::replace(&mut self.current_slot, next_slot);
@@ -303,14 +321,7 @@ impl<V: Value, ValidationError: Clone + Display + 'static> ScpNode<V> for Node<V
         // The slot index should only increase.
         debug_assert!(slot_index > self.current_slot_index());
 
-        self.current_slot = Box::new(Slot::new(
-            self.ID.clone(),
-            self.Q.clone(),
-            slot_index,
-            self.validity_fn.clone(),
-            self.combine_fn.clone(),
-            self.logger.clone(),
-        ));
+        self.current_slot = Box::new(self.new_slot(slot_index));
 
         self.externalized_slots.clear();
     }
@@ -732,6 +743,30 @@ mod tests {
         assert_eq!(node.externalized_slots.len(), 0);
     }
 
+    #[test_with_logger]
+    // Slots created after `with_scp_timebase`, e.g. on externalizing or on a
+    // reset, should use the node's timebase.
+    fn test_scp_timebase_reaches_new_slots(logger: Logger) {
+        let scp_timebase = Duration::from_millis(1);
+        let mut node = get_node(0, logger).with_scp_timebase(scp_timebase);
+
+        let next_slot = node.new_slot(1);
+        assert_eq!(next_slot.base_round_interval, scp_timebase);
+        assert_eq!(next_slot.base_ballot_interval, scp_timebase);
+
+        // The reset slot's nomination round should time out long before the
+        // default timebase of one second would allow.
+        node.reset_slot_index(5);
+        node.propose_values(btreeset!["a"]).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        node.process_timeouts();
+
+        let slot_state: serde_json::Value =
+            serde_json::from_str(&node.current_slot.get_debug_snapshot()).unwrap();
+        assert_eq!(slot_state["slot_index"], 5);
+        assert!(slot_state["nominate_round"].as_u64().unwrap() > 1);
+    }
+
     #[test_with_logger]
     /// Steps through a sequence of messages that allow a two-node network to
     /// reach consensus.
@@ -187,6 +187,9 @@ pub struct SlotMetrics {
 
     /// The highest ballot counter.
     pub bN: u32,
+
+    /// Whether a nomination or ballot timer is set.
+    pub has_pending_timeouts: bool,
 }
 
 impl<V: Value, ValidationError: Display> ScpSlot<V> for Slot<V, ValidationError> {
@@ -199,6 +202,8 @@ impl<V: Value, ValidationError: Display> ScpSlot<V> for Slot<V, ValidationError>
             num_confirmed_nominated: self.Z.len(),
             cur_nomination_round: self.nominate_round,
             bN: self.B.N,
+            has_pending_timeouts: self.next_nominate_round_at.is_some()
+                || self.next_ballot_at.is_some(),
         }
     }
 
@@ -8,7 +8,7 @@
 
 use crate::mock_network;
 use mc_common::NodeID;
-use mc_consensus_scp::{test_utils, QuorumSet};
+use mc_consensus_scp::test_utils;
 use std::collections::HashSet;
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -29,11 +29,11 @@ pub fn dense_mesh(
             .map(|other_node_index| test_utils::test_node_id(other_node_index as u32))
             .collect::<Vec<NodeID>>();
 
-        nodes.push(mock_network::NodeConfig::new(
+        nodes.push(mock_network::NodeConfig::with_threshold(
             format!("m{}", node_index),
             test_utils::test_node_id(node_index as u32),
-            peers_vector.iter().cloned().collect::<HashSet<NodeID>>(),
-            QuorumSet::new_with_node_ids(k as u32, peers_vector),
+            peers_vector.into_iter().collect::<HashSet<NodeID>>(),
+            k as u32,
         ));
     }
 
@@ -10,11 +10,15 @@ use mc_consensus_scp::{
     core_types::{CombineFn, SlotIndex, ValidityFn},
     msg::Msg,
     node::{Node, ScpNode},
-    quorum_set::QuorumSet,
+    quorum_set::{QuorumSet, QuorumSetMember},
+    slot::{Phase, SlotMetrics},
     test_utils,
 };
+use mc_crypto_digestible::{Digestible, MerlinTranscript};
+use mc_util_test_helper::{RngCore, RngType, SeedableRng};
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
     sync::{Arc, Mutex},
     thread,
     thread::JoinHandle,
@@ -28,6 +32,236 @@ pub mod metamesh_topology;
 // Test values are random strings of this length.
 const CHARACTERS_PER_VALUE: usize = 10;
 
+/// How often a restarted node checks its peers for blocks it has missed.
+const CATCH_UP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Quorum intersection is checked exhaustively, so it is only attempted for
+/// networks of at most this many nodes.
+pub const MAX_QUORUM_INTERSECTION_NODES: usize = 20;
+
+/// The seed simulations use unless `TestOptions::seed` is changed. This is the
+/// seed of `mc_util_test_helper::get_seeded_rng`.
+pub const DEFAULT_SEED: [u8; 32] = [7u8; 32];
+
+/// Identifies a block in a simulated node's ledger.
+pub type BlockID = [u8; 32];
+
+/// Returns how long a node spends validating the given value.
+pub type ValidationLatencyFn = Arc<dyn Fn(&String) -> Duration + Sync + Send>;
+
+/// Computes the BlockID a node derives for a block, given the node, the parent
+/// block's ID, and the block's values.
+pub type BlockIdFn = Arc<dyn Fn(&NodeID, &BlockID, &[String]) -> BlockID + Sync + Send>;
+
+// The data committed to by a BlockID.
+#[derive(Digestible)]
+struct BlockIdContents {
+    parent_id: BlockID,
+    values: Vec<String>,
+}
+
+/// The canonical BlockID for a block with the given parent and values.
+pub fn compute_block_id(parent_id: &BlockID, values: &[String]) -> BlockID {
+    BlockIdContents {
+        parent_id: *parent_id,
+        values: values.to_vec(),
+    }
+    .digest32::<MerlinTranscript>(b"mock-network-block-id")
+}
+
+/// A simulated node's record of the blocks it has externalized.
+pub trait SimulatedLedger: Send {
+    /// Appends a newly externalized block.
+    fn push_block(&mut self, values: Vec<String>);
+
+    /// The externalized blocks, in order.
+    fn blocks(&self) -> &[Vec<String>];
+
+    /// The number of values in the ledger, counting each externalization.
+    fn ledger_size(&self) -> usize {
+        self.blocks().iter().map(Vec::len).sum()
+    }
+
+    /// A digest committing to the ledger up to and including the given block,
+    /// if this ledger computes one.
+    fn block_digest(&self, _block_index: usize) -> Option<BlockID> {
+        None
+    }
+
+    /// Whether this ledger agrees with `other` at the given block. Digests are
+    /// compared when both ledgers compute them, and values otherwise.
+    fn block_matches(&self, other: &dyn SimulatedLedger, block_index: usize) -> bool {
+        match (
+            self.block_digest(block_index),
+            other.block_digest(block_index),
+        ) {
+            (Some(digest), Some(other_digest)) => digest == other_digest,
+            _ => self.blocks().get(block_index) == other.blocks().get(block_index),
+        }
+    }
+}
+
+/// Creates an empty ledger for each simulated node.
+pub type LedgerFn = Arc<dyn Fn() -> Box<dyn SimulatedLedger> + Sync + Send>;
+
+/// A ledger which compares blocks by their values.
+#[derive(Clone, Debug, Default)]
+pub struct VecLedger {
+    blocks: Vec<Vec<String>>,
+}
+
+impl SimulatedLedger for VecLedger {
+    fn push_block(&mut self, values: Vec<String>) {
+        self.blocks.push(values);
+    }
+
+    fn blocks(&self) -> &[Vec<String>] {
+        &self.blocks
+    }
+}
+
+/// A ledger which chains a digest through its blocks, so that two ledgers
+/// agree at a block only if they externalized the same values in the same
+/// order at every block up to it.
+#[derive(Clone, Debug, Default)]
+pub struct HashingLedger {
+    blocks: Vec<Vec<String>>,
+    digests: Vec<BlockID>,
+}
+
+impl SimulatedLedger for HashingLedger {
+    fn push_block(&mut self, values: Vec<String>) {
+        let parent_digest = self.digests.last().cloned().unwrap_or_default();
+        self.digests.push(compute_block_id(&parent_digest, &values));
+        self.blocks.push(values);
+    }
+
+    fn blocks(&self) -> &[Vec<String>] {
+        &self.blocks
+    }
+
+    fn block_digest(&self, block_index: usize) -> Option<BlockID> {
+        self.digests.get(block_index).cloned()
+    }
+}
+
+/// A copy of a simulated node's externalized state, from which a replacement
+/// node can be started as if restarting from persisted state.
+///
+/// SCP's in-memory slot state is not included: a restored node begins at the
+/// slot following its last block, as a restarted validator would.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NodeSnapshot {
+    /// The externalized blocks, in order.
+    pub blocks: Vec<Vec<String>>,
+
+    /// The BlockID the node derived for each block.
+    pub block_ids: Vec<BlockID>,
+}
+
+/// How nodes treat a submitted value that is already in their own ledger.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Values are checked only by `validity_fn`, so a value submitted late to
+    /// some node may be externalized again in a later slot.
+    AllowDuplicates,
+
+    /// In addition to `validity_fn`, each node treats values already in its
+    /// own ledger as invalid, so no value is externalized twice.
+    RejectDuplicates,
+}
+
+/// A message one node sent to another during a simulation.
+#[derive(Clone)]
+pub struct SentMsg {
+    /// Time since the trace began.
+    pub at: Duration,
+
+    /// The name of the sending node.
+    pub from: String,
+
+    /// The name of the receiving node.
+    pub to: String,
+
+    /// The message.
+    pub msg: Arc<Msg<String>>,
+}
+
+impl fmt::Display for SentMsg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:>10.3?}] {} -> {}: {}",
+            self.at, self.from, self.to, self.msg
+        )
+    }
+}
+
+/// Records every message sent during a simulation. Clones share the same
+/// record.
+///
+/// Messages are recorded when they are queued for their recipient, not when
+/// the recipient handles them. Each node receives its messages in the recorded
+/// order, but nodes run on their own threads, so how their handling
+/// interleaves is not recorded.
+#[derive(Clone)]
+pub struct MessageTrace {
+    start: Instant,
+    sent_msgs: Arc<Mutex<Vec<SentMsg>>>,
+}
+
+impl Default for MessageTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageTrace {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            sent_msgs: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The messages recorded so far, in the order they were sent.
+    pub fn sent_msgs(&self) -> Vec<SentMsg> {
+        self.sent_msgs
+            .lock()
+            .expect("lock failed on message trace")
+            .clone()
+    }
+
+    fn record(&self, from: &str, to: &str, msg: &Arc<Msg<String>>) {
+        let sent_msg = SentMsg {
+            at: self.start.elapsed(),
+            from: from.to_string(),
+            to: to.to_string(),
+            msg: msg.clone(),
+        };
+        self.sent_msgs
+            .lock()
+            .expect("lock failed on message trace")
+            .push(sent_msg);
+    }
+}
+
+/// A schedule for crashing and restarting nodes during a simulation.
+///
+/// While values are being submitted, a node chosen with the seeded RNG is
+/// stopped every `interval`, and restarted `downtime` later with the ledger it
+/// had when it stopped. A restarted node copies the blocks it missed from its
+/// peers, as a validator would sync them after a crash, and then rejoins
+/// consensus. Any node still stopped when submission ends is restarted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NodeChurn {
+    /// Time from one node restarting until the next is stopped.
+    pub interval: Duration,
+
+    /// How long each stopped node stays down.
+    pub downtime: Duration,
+}
+
 // Controls test parameters
 #[derive(Clone)]
 pub struct TestOptions {
@@ -59,11 +293,70 @@ pub struct TestOptions {
     /// SCP suggests one second, but threads can run much faster.
     pub scp_timebase: Duration,
 
+    /// Each node's scp_timebase is lengthened by an offset drawn uniformly
+    /// from [0, clock_skew] using the seeded RNG, simulating nodes whose
+    /// clocks run at different rates. Timeouts are multiples of the timebase,
+    /// so a node stuck for `r` rounds waits up to `r * clock_skew` longer than
+    /// an unskewed node; allowed_test_time should leave room for this.
+    pub clock_skew: Duration,
+
     /// The values validity function to use (typically trivial)
     pub validity_fn: ValidityFn<String, test_utils::TransactionValidationError>,
 
+    /// Each call to a node's validity function first sleeps for the duration
+    /// this returns for the value, simulating CPU-bound validation (typically
+    /// zero). The node's thread is blocked meanwhile, so the cost delays every
+    /// message queued behind it, and a slot takes at least as long as its
+    /// slowest node spends validating the slot's values. Timeouts are not
+    /// lengthened to match, so a cost approaching scp_timebase causes extra
+    /// rounds.
+    pub validation_latency_fn: ValidationLatencyFn,
+
     /// The values combine function to use (typically trivial)
     pub combine_fn: CombineFn<String, test_utils::TransactionValidationError>,
+
+    /// Whether nodes also reject values already in their ledger, on top of
+    /// `validity_fn`
+    pub duplicate_policy: DuplicatePolicy,
+
+    /// Once every node has externalized every value, submit the first
+    /// `resubmitted_values` of them again, so that each is externalized a
+    /// second time (typically zero). Requires
+    /// `DuplicatePolicy::AllowDuplicates`.
+    pub resubmitted_values: usize,
+
+    /// The function each node uses to derive BlockIDs (typically
+    /// `compute_block_id`)
+    pub block_id_fn: BlockIdFn,
+
+    /// Creates the ledger each node records externalized blocks in (typically
+    /// a `VecLedger`)
+    pub ledger_fn: LedgerFn,
+
+    /// On shutdown, nodes finish the slot in progress and process queued
+    /// messages until their queues and timers are empty (true), or exit as
+    /// soon as they see the stop (false). Draining nodes do not begin new
+    /// slots. When draining, nodes are stopped before their ledgers are
+    /// compared.
+    pub drain_on_stop: bool,
+
+    /// Reject networks whose quorums do not all intersect before running them
+    pub check_quorum_intersection: bool,
+
+    /// Periodically restart random nodes while values are submitted. Values
+    /// sent to a stopped node are lost, so this requires
+    /// `submit_in_parallel`, and the network must make progress with any
+    /// one node down.
+    pub node_churn: Option<NodeChurn>,
+
+    /// Seeds the RNG that generates the submitted values, clock skews, and
+    /// node churn, so that a failed run can be re-run with
+    /// `rerun_with_trace`.
+    pub seed: [u8; 32],
+
+    /// Records every message sent, if set (typically only when re-running
+    /// with `rerun_with_trace`).
+    pub message_trace: Option<MessageTrace>,
 }
 
 impl TestOptions {
@@ -76,8 +369,430 @@ impl TestOptions {
             allowed_test_time: Duration::from_secs(300),
             log_flush_delay: Duration::from_millis(50),
             scp_timebase: Duration::from_millis(1000),
+            clock_skew: Duration::from_millis(0),
             validity_fn: Arc::new(test_utils::trivial_validity_fn::<String>),
+            validation_latency_fn: Arc::new(|_value| Duration::from_millis(0)),
             combine_fn: Arc::new(test_utils::get_bounded_combine_fn::<String>(100)),
+            duplicate_policy: DuplicatePolicy::AllowDuplicates,
+            resubmitted_values: 0,
+            block_id_fn: Arc::new(|_node_id, parent_id, values| {
+                compute_block_id(parent_id, values)
+            }),
+            ledger_fn: Arc::new(|| Box::new(VecLedger::default())),
+            drain_on_stop: false,
+            check_quorum_intersection: true,
+            node_churn: None,
+            seed: DEFAULT_SEED,
+            message_trace: None,
+        }
+    }
+
+    /// Checks that the options can be simulated together.
+    pub fn validate(&self) -> Result<(), TestOptionsError> {
+        // Values pushed to a stopped node are lost, so every value must also
+        // go to the nodes that are up.
+        if self.node_churn.is_some() && !self.submit_in_parallel {
+            return Err(TestOptionsError::ChurnWithSequentialSubmission);
+        }
+        if self.submissions_per_sec == 0 {
+            return Err(TestOptionsError::ZeroSubmissionRate);
+        }
+        if self.max_slot_proposed_values == 0 {
+            return Err(TestOptionsError::ZeroProposedValues);
+        }
+        // Nodes rejecting duplicates would never externalize the resubmitted
+        // values, so the run could not finish.
+        if self.resubmitted_values > 0 && self.duplicate_policy == DuplicatePolicy::RejectDuplicates
+        {
+            return Err(TestOptionsError::ResubmissionWithRejectDuplicates);
+        }
+        Ok(())
+    }
+}
+
+/// The reason a set of `TestOptions` cannot be simulated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TestOptionsError {
+    /// `node_churn` is set, but values are submitted to one node at a time.
+    ChurnWithSequentialSubmission,
+
+    /// `submissions_per_sec` is zero, so no value would be submitted.
+    ZeroSubmissionRate,
+
+    /// `max_slot_proposed_values` is zero, so no value would be proposed.
+    ZeroProposedValues,
+
+    /// `resubmitted_values` is set, but nodes reject duplicate values.
+    ResubmissionWithRejectDuplicates,
+}
+
+impl fmt::Display for TestOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ChurnWithSequentialSubmission => {
+                write!(f, "node_churn requires submit_in_parallel")
+            }
+            Self::ZeroSubmissionRate => write!(f, "submissions_per_sec must be positive"),
+            Self::ZeroProposedValues => write!(f, "max_slot_proposed_values must be positive"),
+            Self::ResubmissionWithRejectDuplicates => {
+                write!(
+                    f,
+                    "resubmitted_values requires DuplicatePolicy::AllowDuplicates"
+                )
+            }
+        }
+    }
+}
+
+/// Builds `TestOptions`, starting from the defaults of `TestOptions::new`, and
+/// rejects combinations that cannot be simulated when built.
+#[derive(Clone)]
+pub struct TestOptionsBuilder {
+    options: TestOptions,
+}
+
+impl TestOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            options: TestOptions::new(),
+        }
+    }
+
+    pub fn with_submit_in_parallel(mut self, submit_in_parallel: bool) -> Self {
+        self.options.submit_in_parallel = submit_in_parallel;
+        self
+    }
+
+    pub fn with_values_to_submit(mut self, values_to_submit: usize) -> Self {
+        self.options.values_to_submit = values_to_submit;
+        self
+    }
+
+    pub fn with_submissions_per_sec(mut self, submissions_per_sec: u64) -> Self {
+        self.options.submissions_per_sec = submissions_per_sec;
+        self
+    }
+
+    pub fn with_max_slot_proposed_values(mut self, max_slot_proposed_values: usize) -> Self {
+        self.options.max_slot_proposed_values = max_slot_proposed_values;
+        self
+    }
+
+    pub fn with_allowed_test_time(mut self, allowed_test_time: Duration) -> Self {
+        self.options.allowed_test_time = allowed_test_time;
+        self
+    }
+
+    pub fn with_log_flush_delay(mut self, log_flush_delay: Duration) -> Self {
+        self.options.log_flush_delay = log_flush_delay;
+        self
+    }
+
+    pub fn with_scp_timebase(mut self, scp_timebase: Duration) -> Self {
+        self.options.scp_timebase = scp_timebase;
+        self
+    }
+
+    pub fn with_clock_skew(mut self, clock_skew: Duration) -> Self {
+        self.options.clock_skew = clock_skew;
+        self
+    }
+
+    pub fn with_validity_fn(
+        mut self,
+        validity_fn: ValidityFn<String, test_utils::TransactionValidationError>,
+    ) -> Self {
+        self.options.validity_fn = validity_fn;
+        self
+    }
+
+    pub fn with_validation_latency_fn(
+        mut self,
+        validation_latency_fn: ValidationLatencyFn,
+    ) -> Self {
+        self.options.validation_latency_fn = validation_latency_fn;
+        self
+    }
+
+    pub fn with_combine_fn(
+        mut self,
+        combine_fn: CombineFn<String, test_utils::TransactionValidationError>,
+    ) -> Self {
+        self.options.combine_fn = combine_fn;
+        self
+    }
+
+    pub fn with_duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> Self {
+        self.options.duplicate_policy = duplicate_policy;
+        self
+    }
+
+    pub fn with_resubmitted_values(mut self, resubmitted_values: usize) -> Self {
+        self.options.resubmitted_values = resubmitted_values;
+        self
+    }
+
+    pub fn with_block_id_fn(mut self, block_id_fn: BlockIdFn) -> Self {
+        self.options.block_id_fn = block_id_fn;
+        self
+    }
+
+    pub fn with_ledger_fn(mut self, ledger_fn: LedgerFn) -> Self {
+        self.options.ledger_fn = ledger_fn;
+        self
+    }
+
+    pub fn with_drain_on_stop(mut self, drain_on_stop: bool) -> Self {
+        self.options.drain_on_stop = drain_on_stop;
+        self
+    }
+
+    pub fn with_check_quorum_intersection(mut self, check_quorum_intersection: bool) -> Self {
+        self.options.check_quorum_intersection = check_quorum_intersection;
+        self
+    }
+
+    pub fn with_node_churn(mut self, node_churn: Option<NodeChurn>) -> Self {
+        self.options.node_churn = node_churn;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: [u8; 32]) -> Self {
+        self.options.seed = seed;
+        self
+    }
+
+    pub fn with_message_trace(mut self, message_trace: Option<MessageTrace>) -> Self {
+        self.options.message_trace = message_trace;
+        self
+    }
+
+    /// The options, if they can be simulated together.
+    pub fn build(self) -> Result<TestOptions, TestOptionsError> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+/// Summary statistics from a simulation run.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TestReport {
+    /// The number of distinct values submitted to the network.
+    pub values_submitted: usize,
+
+    /// The number of values in the externalized ledger, counting each
+    /// occurrence of a value.
+    pub values_externalized: usize,
+
+    /// Externalizations of a value beyond its first. Each one is wasted block
+    /// capacity.
+    pub duplicate_externalizations: usize,
+
+    /// The number of distinct values that were externalized more than once.
+    pub values_externalized_more_than_once: usize,
+
+    /// The number of times a node was restarted by `TestOptions::node_churn`.
+    pub node_restarts: usize,
+}
+
+impl TestReport {
+    /// Adds the externalization statistics for `ledger` to the report.
+    pub fn record_externalized(&mut self, ledger: &[Vec<String>]) {
+        let mut occurrences: HashMap<&String, usize> = HashMap::default();
+        for value in ledger.iter().flatten() {
+            *occurrences.entry(value).or_insert(0) += 1;
+        }
+
+        self.values_externalized = occurrences.values().sum();
+        self.duplicate_externalizations = self.values_externalized - occurrences.len();
+        self.values_externalized_more_than_once =
+            occurrences.values().filter(|count| **count > 1).count();
+    }
+}
+
+/// The outcome of a successful simulation run.
+#[derive(Clone, Debug)]
+pub struct TestResult {
+    /// The name of the simulated network.
+    pub name: String,
+
+    /// Time from the start of value submission until every node externalized
+    /// every value.
+    pub elapsed: Duration,
+
+    /// The number of values submitted.
+    pub values: usize,
+
+    /// Average throughput, in values per second.
+    pub tps: f64,
+
+    /// The number of values in each node's ledger, keyed by node name.
+    pub per_node_ledger_sizes: BTreeMap<String, usize>,
+
+    /// Externalization statistics for the agreed-upon ledger.
+    pub report: TestReport,
+}
+
+/// The reason a simulation run failed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TestFailure {
+    /// A node did not externalize every value within the allowed test time.
+    Timeout {
+        node: String,
+        externalized: usize,
+        allowed_test_time: Duration,
+    },
+
+    /// A node externalized a set of values other than those submitted.
+    WrongValues {
+        node: String,
+        missing: BTreeSet<String>,
+        unexpected: BTreeSet<String>,
+    },
+
+    /// A node's ledger differs from the first node's ledger, in block count,
+    /// block contents, or BlockID, starting at `block_index`.
+    LedgerMismatch { node: String, block_index: usize },
+
+    /// The network's quorum sets cannot be expected to reach agreement.
+    InvalidTopology(QuorumError),
+
+    /// The test options cannot be simulated together.
+    InvalidOptions(TestOptionsError),
+}
+
+impl fmt::Display for TestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Timeout {
+                node,
+                externalized,
+                allowed_test_time,
+            } => write!(
+                f,
+                "node {} externalized only {} values within {} sec",
+                node,
+                externalized,
+                allowed_test_time.as_secs()
+            ),
+            Self::WrongValues {
+                node,
+                missing,
+                unexpected,
+            } => write!(
+                f,
+                "node {} externalized wrong values! missing: {:?}, unexpected: {:?}",
+                node, missing, unexpected
+            ),
+            Self::LedgerMismatch { node, block_index } => write!(
+                f,
+                "ledger at node {} differs from the first node's at block {}",
+                node, block_index
+            ),
+            Self::InvalidTopology(err) => write!(f, "invalid topology: {}", err),
+            Self::InvalidOptions(err) => write!(f, "invalid test options: {}", err),
+        }
+    }
+}
+
+/// The first point at which a node's ledger diverges from the first node's.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConsistencyError {
+    /// `node` holds different values at `block_index` than the first node.
+    /// A block is `None` when that node's ledger ends before it.
+    BlockMismatch {
+        node: String,
+        block_index: usize,
+        expected: Option<Vec<String>>,
+        found: Option<Vec<String>>,
+    },
+
+    /// `node` holds the same values as the first node, but derived a different
+    /// BlockID at `block_index`.
+    BlockIdMismatch {
+        node: String,
+        block_index: usize,
+        expected: Option<BlockID>,
+        found: Option<BlockID>,
+    },
+}
+
+impl ConsistencyError {
+    /// The name of the node whose ledger diverges.
+    pub fn node(&self) -> &str {
+        match self {
+            Self::BlockMismatch { node, .. } | Self::BlockIdMismatch { node, .. } => node,
+        }
+    }
+
+    /// The index of the first block at which the ledgers diverge.
+    pub fn block_index(&self) -> usize {
+        match self {
+            Self::BlockMismatch { block_index, .. } | Self::BlockIdMismatch { block_index, .. } => {
+                *block_index
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BlockMismatch {
+                node,
+                block_index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "ledger at node {} differs from the first node's at block {}: expected {:?}, found {:?}",
+                node, block_index, expected, found
+            ),
+            Self::BlockIdMismatch {
+                node,
+                block_index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "node {} derived a different block ID at block {}: expected {:?}, found {:?}",
+                node, block_index, expected, found
+            ),
+        }
+    }
+}
+
+/// The reason a network's quorum sets fail the quorum intersection check.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum QuorumError {
+    /// A node's quorum set is malformed.
+    InvalidQuorumSet(String),
+
+    /// No set of nodes satisfies all of its members' quorum sets, so the
+    /// network can never externalize a value.
+    NoQuorum,
+
+    /// These two quorums share no node, so they can externalize conflicting
+    /// values.
+    DisjointQuorums(BTreeSet<String>, BTreeSet<String>),
+
+    /// The network has more than `MAX_QUORUM_INTERSECTION_NODES` nodes.
+    TooManyNodes(usize),
+}
+
+impl fmt::Display for QuorumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidQuorumSet(node) => write!(f, "node {} has an invalid quorum set", node),
+            Self::NoQuorum => write!(f, "no set of nodes forms a quorum"),
+            Self::DisjointQuorums(first, second) => {
+                write!(f, "quorums {:?} and {:?} do not intersect", first, second)
+            }
+            Self::TooManyNodes(num_nodes) => write!(
+                f,
+                "cannot check quorum intersection for {} nodes (max {})",
+                num_nodes, MAX_QUORUM_INTERSECTION_NODES
+            ),
         }
     }
 }
@@ -94,7 +809,8 @@ pub struct NodeConfig {
     /// The nodes to which this node broadcasts
     peers: HashSet<NodeID>,
 
-    /// This node's quorum set
+    /// This node's quorum set, which may contain nested quorum sets and need
+    /// not match `peers`
     quorum_set: QuorumSet,
 }
 
@@ -107,6 +823,20 @@ impl NodeConfig {
             quorum_set,
         }
     }
+
+    /// A node which broadcasts to `peers` and requires `threshold` of them to
+    /// agree.
+    pub fn with_threshold(
+        name: String,
+        id: NodeID,
+        peers: HashSet<NodeID>,
+        threshold: u32,
+    ) -> Self {
+        let mut peer_ids: Vec<NodeID> = peers.iter().cloned().collect();
+        peer_ids.sort();
+        let quorum_set = QuorumSet::new_with_node_ids(threshold, peer_ids);
+        Self::new(name, id, peers, quorum_set)
+    }
 }
 
 // Describes a network of nodes for simulation
@@ -120,13 +850,105 @@ impl NetworkConfig {
     pub fn new(name: String, nodes: Vec<NodeConfig>) -> Self {
         Self { name, nodes }
     }
+
+    /// Checks that every pair of quorums in the network shares at least one
+    /// node.
+    ///
+    /// Every subset of nodes is considered, so this is only practical for
+    /// small networks. Nodes that appear in quorum sets but not in the network
+    /// are treated as never voting.
+    pub fn check_quorum_intersection(&self) -> Result<(), QuorumError> {
+        let num_nodes = self.nodes.len();
+        if num_nodes > MAX_QUORUM_INTERSECTION_NODES {
+            return Err(QuorumError::TooManyNodes(num_nodes));
+        }
+
+        if let Some(node) = self.nodes.iter().find(|n| !n.quorum_set.is_valid()) {
+            return Err(QuorumError::InvalidQuorumSet(node.name.clone()));
+        }
+
+        let all_nodes: u32 = ((1u64 << num_nodes) - 1) as u32;
+        let mut found_quorum = false;
+
+        for candidate in 1..=all_nodes {
+            if !self.is_quorum(candidate) {
+                continue;
+            }
+            found_quorum = true;
+
+            let disjoint = self.largest_quorum_within(all_nodes & !candidate);
+            if disjoint != 0 {
+                return Err(QuorumError::DisjointQuorums(
+                    self.node_names(candidate),
+                    self.node_names(disjoint),
+                ));
+            }
+        }
+
+        if found_quorum {
+            Ok(())
+        } else {
+            Err(QuorumError::NoQuorum)
+        }
+    }
+
+    // Whether every node in `nodes` (a bitmask of indices into self.nodes) has
+    // its quorum set satisfied by `nodes`.
+    fn is_quorum(&self, nodes: u32) -> bool {
+        nodes != 0
+            && (0..self.nodes.len())
+                .filter(|index| nodes & (1 << index) != 0)
+                .all(|index| self.is_satisfied_by(&self.nodes[index].quorum_set, nodes))
+    }
+
+    // The largest quorum contained in `nodes`, found by repeatedly removing
+    // nodes whose quorum sets are not satisfied. Returns 0 if there is none.
+    fn largest_quorum_within(&self, mut nodes: u32) -> u32 {
+        loop {
+            let unsatisfied = (0..self.nodes.len())
+                .filter(|index| nodes & (1 << index) != 0)
+                .find(|index| !self.is_satisfied_by(&self.nodes[*index].quorum_set, nodes));
+            match unsatisfied {
+                Some(index) => nodes &= !(1 << index),
+                None => return nodes,
+            }
+        }
+    }
+
+    fn is_satisfied_by(&self, quorum_set: &QuorumSet, nodes: u32) -> bool {
+        let satisfied_members = quorum_set
+            .members
+            .iter()
+            .filter(|member| match member {
+                QuorumSetMember::Node(node_id) => self
+                    .nodes
+                    .iter()
+                    .position(|n| n.id == *node_id)
+                    .map_or(false, |index| nodes & (1 << index) != 0),
+                QuorumSetMember::InnerSet(inner_set) => self.is_satisfied_by(inner_set, nodes),
+            })
+            .count();
+        satisfied_members >= quorum_set.threshold as usize
+    }
+
+    fn node_names(&self, nodes: u32) -> BTreeSet<String> {
+        (0..self.nodes.len())
+            .filter(|index| nodes & (1 << index) != 0)
+            .map(|index| self.nodes[index].name.clone())
+            .collect()
+    }
 }
 
 pub struct SCPNetwork {
+    node_ids: Vec<NodeID>,
     handle_map: HashMap<NodeID, JoinHandle<()>>,
     names_map: HashMap<NodeID, String>,
+    configs_map: HashMap<NodeID, NodeConfig>,
+    clock_skews_map: HashMap<NodeID, Duration>,
     nodes_map: Arc<Mutex<HashMap<NodeID, SCPNode>>>,
     shared_data_map: HashMap<NodeID, Arc<Mutex<SCPNodeSharedData>>>,
+    drain_on_stop: bool,
+    message_trace: Option<MessageTrace>,
     logger: Logger,
 }
 
@@ -134,48 +956,176 @@ impl SCPNetwork {
     // creates a new network simulation
     pub fn new(network_config: &NetworkConfig, test_options: &TestOptions, logger: Logger) -> Self {
         let mut scp_network = SCPNetwork {
+            node_ids: network_config.nodes.iter().map(|n| n.id.clone()).collect(),
             handle_map: HashMap::default(),
             names_map: HashMap::default(),
+            configs_map: HashMap::default(),
+            clock_skews_map: HashMap::default(),
             nodes_map: Arc::new(Mutex::new(HashMap::default())),
             shared_data_map: HashMap::default(),
+            drain_on_stop: test_options.drain_on_stop,
+            message_trace: test_options.message_trace.clone(),
             logger: logger.clone(),
         };
 
+        let mut rng = RngType::from_seed(test_options.seed);
+
         for node_config in network_config.nodes.iter() {
             assert!(!node_config.peers.contains(&node_config.id));
 
-            let nodes_map_clone = Arc::clone(&scp_network.nodes_map);
-            let peers_clone = node_config.peers.clone();
-
-            let (node, join_handle) = SCPNode::new(
-                node_config.clone(),
-                test_options,
-                Arc::new(move |logger, msg| {
-                    SCPNetwork::broadcast_msg(logger, &nodes_map_clone, &peers_clone, msg)
-                }),
-                0, // first slot index
-                logger.clone(),
-            );
-            scp_network
-                .handle_map
-                .insert(node_config.id.clone(), join_handle);
+            let skew_nanos = test_options.clock_skew.as_nanos() as u64;
+            let clock_skew = Duration::from_nanos(rng.next_u64() % (skew_nanos + 1));
+
             scp_network
                 .names_map
                 .insert(node_config.id.clone(), node_config.name.clone());
             scp_network
-                .shared_data_map
-                .insert(node_config.id.clone(), node.shared_data.clone());
+                .configs_map
+                .insert(node_config.id.clone(), node_config.clone());
             scp_network
-                .nodes_map
-                .lock()
-                .expect("lock failed on nodes_map inserting node")
-                .insert(node_config.id.clone(), node);
+                .clock_skews_map
+                .insert(node_config.id.clone(), clock_skew);
+
+            let shared_data = Arc::new(Mutex::new(SCPNodeSharedData::new((test_options
+                .ledger_fn)(
+            ))));
+            scp_network.start_node(&node_config.id, test_options, shared_data, Vec::new());
         }
 
         scp_network
     }
 
-    fn stop_all(&mut self) {
+    // starts a thread for the node, recording externalized blocks in
+    // `shared_data`. The node begins at the slot following the last block
+    // already there, and catches up from `catch_up_peers` if they are ahead.
+    fn start_node(
+        &mut self,
+        node_id: &NodeID,
+        test_options: &TestOptions,
+        shared_data: Arc<Mutex<SCPNodeSharedData>>,
+        catch_up_peers: Vec<Arc<Mutex<SCPNodeSharedData>>>,
+    ) {
+        let node_config = self.configs_map[node_id].clone();
+        let current_slot_index = shared_data
+            .lock()
+            .expect("lock failed on shared_data starting node")
+            .ledger
+            .blocks()
+            .len() as SlotIndex;
+
+        let (node, join_handle) = SCPNode::new(
+            node_config,
+            test_options,
+            self.broadcast_fn(node_id),
+            self.clock_skews_map[node_id],
+            current_slot_index,
+            shared_data,
+            catch_up_peers,
+            self.logger.clone(),
+        );
+        self.insert_node(node_id, node, join_handle);
+    }
+
+    // returns the function a node uses to send messages to its peers.
+    fn broadcast_fn(&self, node_id: &NodeID) -> Arc<dyn Fn(Logger, Msg<String>) + Sync + Send> {
+        let nodes_map_clone = Arc::clone(&self.nodes_map);
+        let peers_clone = self.configs_map[node_id].peers.clone();
+        let tracer = self.message_trace.clone().map(|message_trace| {
+            (
+                message_trace,
+                self.names_map[node_id].clone(),
+                self.names_map.clone(),
+            )
+        });
+        Arc::new(move |logger, msg| {
+            SCPNetwork::broadcast_msg(logger, &nodes_map_clone, &peers_clone, &tracer, msg)
+        })
+    }
+
+    // records a newly started node, replacing any previous node with its ID.
+    fn insert_node(&mut self, node_id: &NodeID, node: SCPNode, join_handle: JoinHandle<()>) {
+        self.handle_map.insert(node_id.clone(), join_handle);
+        self.shared_data_map
+            .insert(node_id.clone(), node.shared_data.clone());
+        self.nodes_map
+            .lock()
+            .expect("lock failed on nodes_map inserting node")
+            .insert(node_id.clone(), node);
+    }
+
+    // stops one node and waits for its thread to exit, as if it had crashed.
+    // Its ledger is kept for when it restarts.
+    fn stop_node(&mut self, node_id: &NodeID) {
+        self.nodes_map
+            .lock()
+            .expect("lock failed on nodes_map in stop_node")
+            .get(node_id)
+            .expect("could not find node_id in nodes_map")
+            .send_stop();
+
+        if let Some(join_handle) = self.handle_map.remove(node_id) {
+            join_handle.join().expect("SCPNode join failed");
+        }
+    }
+
+    // restarts a stopped node with the ledger it had when it stopped. It
+    // copies the blocks it missed from its peers before rejoining consensus.
+    fn restart_node(&mut self, node_id: &NodeID, test_options: &TestOptions) {
+        let shared_data = self.shared_data_map[node_id].clone();
+        let catch_up_peers = self.peer_shared_data(node_id);
+        self.start_node(node_id, test_options, shared_data, catch_up_peers);
+    }
+
+    // returns the shared data of the given node's peers.
+    fn peer_shared_data(&self, node_id: &NodeID) -> Vec<Arc<Mutex<SCPNodeSharedData>>> {
+        self.configs_map[node_id]
+            .peers
+            .iter()
+            .map(|peer_id| self.shared_data_map[peer_id].clone())
+            .collect()
+    }
+
+    /// Copies the given node's externalized state.
+    pub fn snapshot_node(&self, node_id: &NodeID) -> NodeSnapshot {
+        self.nodes_map
+            .lock()
+            .expect("lock failed on nodes_map in snapshot_node")
+            .get(node_id)
+            .expect("could not find node_id in nodes_map")
+            .snapshot()
+    }
+
+    /// Replaces the given node with one started from `snapshot`, discarding
+    /// anything the node externalized since. The replacement copies the
+    /// blocks it is missing from its peers before rejoining consensus.
+    ///
+    /// The node's shared data is reset in place, so peers catching up from
+    /// it see the replacement's ledger.
+    pub fn restore_node(
+        &mut self,
+        node_id: &NodeID,
+        test_options: &TestOptions,
+        snapshot: &NodeSnapshot,
+    ) {
+        self.stop_node(node_id);
+
+        let (node, join_handle) = SCPNode::from_snapshot(
+            self.configs_map[node_id].clone(),
+            test_options,
+            self.broadcast_fn(node_id),
+            self.clock_skews_map[node_id],
+            snapshot,
+            self.shared_data_map[node_id].clone(),
+            self.peer_shared_data(node_id),
+            self.logger.clone(),
+        );
+        self.insert_node(node_id, node, join_handle);
+    }
+
+    /// Stops all nodes and waits for their threads to exit, draining them
+    /// first if `drain_on_stop` is set. Nodes that have already been stopped
+    /// are skipped.
+    pub fn stop_all(&mut self) {
         let mut nodes_map = self
             .nodes_map
             .lock()
@@ -184,26 +1134,30 @@ impl SCPNetwork {
         for (node_id, node) in nodes_map.iter_mut() {
             log::trace!(
                 self.logger,
-                "sending stop to {}",
+                "sending {} to {}",
+                if self.drain_on_stop { "drain" } else { "stop" },
                 self.names_map
                     .get(node_id)
                     .expect("could not find node_id in nodes_map"),
             );
-            node.send_stop();
+            if self.drain_on_stop {
+                node.send_drain_then_stop();
+            } else {
+                node.send_stop();
+            }
             node_ids.push(node_id.clone());
         }
         drop(nodes_map);
 
         for node_id in node_ids {
-            self.handle_map
-                .remove(&node_id)
-                .expect("thread handle is missing")
-                .join()
-                .expect("SCPNode join failed");
+            if let Some(join_handle) = self.handle_map.remove(&node_id) {
+                join_handle.join().expect("SCPNode join failed");
+            }
         }
     }
 
-    fn push_value(&self, node_id: &NodeID, value: &str) {
+    /// Submits a value to the given node.
+    pub fn push_value(&self, node_id: &NodeID, value: &str) {
         self.nodes_map
             .lock()
             .expect("lock failed on nodes_map pushing value")
@@ -212,6 +1166,20 @@ impl SCPNetwork {
             .send_value(value);
     }
 
+    /// The phase the given node has reached in the given slot, or `None` if
+    /// it has not begun the slot.
+    ///
+    /// This only reads what the node last published, so it does not wait on
+    /// the node's thread.
+    pub fn current_phase(&self, node_id: &NodeID, slot_index: SlotIndex) -> Option<Phase> {
+        self.shared_data_map
+            .get(node_id)
+            .expect("could not find node_id in shared_data_map")
+            .lock()
+            .expect("lock failed on shared_data getting phase")
+            .current_phase(slot_index)
+    }
+
     fn get_ledger(&self, node_id: &NodeID) -> Vec<Vec<String>> {
         self.shared_data_map
             .get(node_id)
@@ -219,6 +1187,97 @@ impl SCPNetwork {
             .lock()
             .expect("lock failed on shared_data getting ledger")
             .ledger
+            .blocks()
+            .to_vec()
+    }
+
+    // Returns the index of the first block at which the two nodes' ledgers
+    // disagree, if any. A ledger which is a strict prefix of the other
+    // disagrees at the block following its last.
+    fn first_mismatched_block(&self, node_id: &NodeID, other_node_id: &NodeID) -> Option<usize> {
+        let shared_data = self
+            .shared_data_map
+            .get(node_id)
+            .expect("could not find node_id in shared_data_map")
+            .lock()
+            .expect("lock failed on shared_data comparing ledgers");
+        let other_shared_data = self
+            .shared_data_map
+            .get(other_node_id)
+            .expect("could not find node_id in shared_data_map")
+            .lock()
+            .expect("lock failed on shared_data comparing ledgers");
+
+        let ledger = shared_data.ledger.as_ref();
+        let other_ledger = other_shared_data.ledger.as_ref();
+        let num_blocks = ledger.blocks().len();
+        let other_num_blocks = other_ledger.blocks().len();
+
+        (0..num_blocks.min(other_num_blocks))
+            .find(|block_index| !ledger.block_matches(other_ledger, *block_index))
+            .or_else(|| {
+                if num_blocks != other_num_blocks {
+                    Some(num_blocks.min(other_num_blocks))
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Checks that every node's ledger matches the first node's, block by
+    /// block, and that every node derived the same BlockIDs.
+    ///
+    /// Returns the first divergence found. Nodes may still be externalizing,
+    /// so a node whose ledger is merely shorter is reported as diverging.
+    pub fn assert_ledgers_consistent(&self) -> Result<(), ConsistencyError> {
+        let first_node_id = match self.node_ids.first() {
+            Some(node_id) => node_id,
+            None => return Ok(()),
+        };
+
+        for node_id in self.node_ids.iter().skip(1) {
+            if let Some(block_index) = self.first_mismatched_block(first_node_id, node_id) {
+                return Err(ConsistencyError::BlockMismatch {
+                    node: self.names_map[node_id].clone(),
+                    block_index,
+                    expected: self.get_ledger(first_node_id).get(block_index).cloned(),
+                    found: self.get_ledger(node_id).get(block_index).cloned(),
+                });
+            }
+        }
+
+        let node_block_ids: Vec<Vec<BlockID>> = self
+            .node_ids
+            .iter()
+            .map(|node_id| self.get_block_ids(node_id))
+            .collect();
+        if let Some(block_index) = first_divergent_block_id(&node_block_ids) {
+            let expected = node_block_ids[0].get(block_index).cloned();
+            let (node_id, found) = self
+                .node_ids
+                .iter()
+                .zip(node_block_ids.iter())
+                .map(|(node_id, block_ids)| (node_id, block_ids.get(block_index).cloned()))
+                .find(|(_, found)| *found != expected)
+                .expect("a node must have diverged");
+            return Err(ConsistencyError::BlockIdMismatch {
+                node: self.names_map[node_id].clone(),
+                block_index,
+                expected,
+                found,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn get_block_ids(&self, node_id: &NodeID) -> Vec<BlockID> {
+        self.shared_data_map
+            .get(node_id)
+            .expect("could not find node_id in shared_data_map")
+            .lock()
+            .expect("lock failed on shared_data getting block ids")
+            .block_ids
             .clone()
     }
 
@@ -235,6 +1294,7 @@ impl SCPNetwork {
         logger: Logger,
         nodes_map: &Arc<Mutex<HashMap<NodeID, SCPNode>>>,
         peers: &HashSet<NodeID>,
+        tracer: &Option<(MessageTrace, String, HashMap<NodeID, String>)>,
         msg: Msg<String>,
     ) {
         let mut nodes_map = nodes_map
@@ -246,6 +1306,9 @@ impl SCPNetwork {
         let amsg = Arc::new(msg);
 
         for peer_id in peers {
+            if let Some((message_trace, sender_name, names_map)) = tracer {
+                message_trace.record(sender_name, &names_map[peer_id], &amsg);
+            }
             nodes_map
                 .get_mut(&peer_id)
                 .expect("failed to get peer from nodes_map")
@@ -264,20 +1327,104 @@ enum SCPNodeTaskMessage {
     Value(String),
     Msg(Arc<Msg<String>>),
     StopTrigger,
+    DrainTrigger,
 }
 
 // Node data shared between threads
-#[derive(Clone)]
 struct SCPNodeSharedData {
-    pub ledger: Vec<Vec<String>>,
+    pub ledger: Box<dyn SimulatedLedger>,
+
+    /// The BlockID this node derived for each block in its ledger.
+    pub block_ids: Vec<BlockID>,
+
+    /// Every value in this node's ledger.
+    pub externalized_values: HashSet<String>,
+
+    /// The slot the node is working on, and its phase in that slot.
+    pub slot_phase: Option<(SlotIndex, Phase)>,
 }
 
 impl SCPNodeSharedData {
+    pub fn new(ledger: Box<dyn SimulatedLedger>) -> Self {
+        Self {
+            ledger,
+            block_ids: Vec::new(),
+            externalized_values: HashSet::new(),
+            slot_phase: None,
+        }
+    }
+
+    /// Rebuilds a node's data from a snapshot, replaying its blocks into
+    /// `ledger`.
+    pub fn from_snapshot(mut ledger: Box<dyn SimulatedLedger>, snapshot: &NodeSnapshot) -> Self {
+        let mut externalized_values = HashSet::new();
+        for block in &snapshot.blocks {
+            externalized_values.extend(block.iter().cloned());
+            ledger.push_block(block.clone());
+        }
+        Self {
+            ledger,
+            block_ids: snapshot.block_ids.clone(),
+            externalized_values,
+            slot_phase: None,
+        }
+    }
+
     pub fn ledger_size(&self) -> usize {
-        self.ledger.iter().fold(0, |acc, block| acc + block.len())
+        self.ledger.ledger_size()
+    }
+
+    /// The phase this node has reached in the given slot. Slots in the ledger,
+    /// or before the one the node is working on, are externalized.
+    pub fn current_phase(&self, slot_index: SlotIndex) -> Option<Phase> {
+        match self.slot_phase {
+            Some((current, phase)) if current == slot_index => Some(phase),
+            Some((current, _)) if current > slot_index => Some(Phase::Externalize),
+            _ if (slot_index as usize) < self.ledger_size() => Some(Phase::Externalize),
+            _ => None,
+        }
+    }
+
+    /// Copies this node's ledger and BlockIDs.
+    pub fn snapshot(&self) -> NodeSnapshot {
+        NodeSnapshot {
+            blocks: self.ledger.blocks().to_vec(),
+            block_ids: self.block_ids.clone(),
+        }
+    }
+
+    /// Appends a block to this node's ledger, deriving its BlockID.
+    fn append_block(&mut self, node_id: &NodeID, block_id_fn: &BlockIdFn, values: Vec<String>) {
+        let parent_id = self.block_ids.last().cloned().unwrap_or_default();
+        let block_id = (block_id_fn)(node_id, &parent_id, &values);
+        self.block_ids.push(block_id);
+
+        self.externalized_values.extend(values.iter().cloned());
+        self.ledger.push_block(values);
     }
 }
 
+// Returns the blocks beyond the first `num_blocks` in the longest of the
+// peers' ledgers.
+fn blocks_missing_from(
+    num_blocks: usize,
+    peers: &[Arc<Mutex<SCPNodeSharedData>>],
+) -> Vec<Vec<String>> {
+    peers
+        .iter()
+        .map(|peer| {
+            peer.lock()
+                .expect("lock failed on peer shared_data catching up")
+                .ledger
+                .blocks()
+                .get(num_blocks..)
+                .map(|blocks| blocks.to_vec())
+                .unwrap_or_default()
+        })
+        .max_by_key(|blocks| blocks.len())
+        .unwrap_or_default()
+}
+
 // A simulated validator node
 struct SCPNode {
     sender: crossbeam_channel::Sender<SCPNodeTaskMessage>,
@@ -289,30 +1436,76 @@ impl SCPNode {
         node_config: NodeConfig,
         test_options: &TestOptions,
         broadcast_msg_fn: Arc<dyn Fn(Logger, Msg<String>) + Sync + Send>,
+        clock_skew: Duration,
         current_slot_index: SlotIndex,
+        shared_data: Arc<Mutex<SCPNodeSharedData>>,
+        catch_up_peers: Vec<Arc<Mutex<SCPNodeSharedData>>>,
         logger: Logger,
     ) -> (Self, JoinHandle<()>) {
         let (sender, receiver) = crossbeam_channel::unbounded();
 
         let scp_node = Self {
             sender,
-            shared_data: Arc::new(Mutex::new(SCPNodeSharedData { ledger: Vec::new() })),
+            shared_data,
         };
 
+        let validation_latency_fn = test_options.validation_latency_fn.clone();
+        let timed_validity_fn = test_options.validity_fn.clone();
+        let timed_validity_fn: ValidityFn<String, test_utils::TransactionValidationError> =
+            Arc::new(move |value: &String| {
+                let latency = validation_latency_fn(value);
+                if latency > Duration::from_millis(0) {
+                    thread::sleep(latency);
+                }
+                timed_validity_fn(value)
+            });
+
+        let validity_fn: ValidityFn<String, test_utils::TransactionValidationError> =
+            match test_options.duplicate_policy {
+                DuplicatePolicy::AllowDuplicates => timed_validity_fn,
+                DuplicatePolicy::RejectDuplicates => {
+                    let validity_fn = timed_validity_fn;
+                    let shared_data = Arc::clone(&scp_node.shared_data);
+                    Arc::new(move |value: &String| {
+                        let already_externalized = shared_data
+                            .lock()
+                            .expect("shared_data lock failed")
+                            .externalized_values
+                            .contains(value);
+                        if already_externalized {
+                            return Err(test_utils::TransactionValidationError);
+                        }
+                        validity_fn(value)
+                    })
+                }
+            };
+
         let mut thread_local_node = Node::new(
             node_config.id.clone(),
             node_config.quorum_set.clone(),
-            test_options.validity_fn.clone(),
+            validity_fn,
             test_options.combine_fn.clone(),
             current_slot_index,
             logger.clone(),
+        )
+        .with_scp_timebase(test_options.scp_timebase + clock_skew);
+
+        let mut published_phase = (
+            current_slot_index,
+            thread_local_node.get_current_slot_metrics().phase,
         );
-        thread_local_node.scp_timebase = test_options.scp_timebase;
+        scp_node
+            .shared_data
+            .lock()
+            .expect("lock failed on shared_data publishing phase")
+            .slot_phase = Some(published_phase);
 
         let thread_shared_data = Arc::clone(&scp_node.shared_data);
         let max_slot_proposed_values: usize = test_options.max_slot_proposed_values;
+        let block_id_fn = test_options.block_id_fn.clone();
 
-        let mut current_slot: usize = 0;
+        let mut current_slot = current_slot_index as usize;
+        let mut last_catch_up = Instant::now();
         let mut total_broadcasts: u32 = 0;
 
         let join_handle = {
@@ -322,37 +1515,97 @@ impl SCPNode {
                     // All values that have not yet been externalized.
                     let mut pending_values: Vec<String> = Vec::default();
 
+                    // Once draining, the node exits when its queue is empty
+                    // and its current slot is idle.
+                    let mut draining = false;
+
                     'main_loop: loop {
+                        let mut queue_empty = false;
+
                         // Compare to byzantine_ledger::tick()
                         // there pending values are proposed before incoming msg is handled
                         let mut incoming_msg_option: Option<Arc<Msg<String>>> = None;
 
                         // Collect one incoming message using a non-blocking channel read
                         match receiver.try_recv() {
-                            Ok(scp_msg) => match scp_msg {
-                                // Collect values submitted from the client
-                                SCPNodeTaskMessage::Value(value) => {
-                                    pending_values.push(value.clone());
+                            Ok(scp_msg) => {
+                                match scp_msg {
+                                    // Collect values submitted from the client
+                                    SCPNodeTaskMessage::Value(value) => {
+                                        pending_values.push(value.clone());
+                                    }
+
+                                    // Process an incoming SCP message
+                                    SCPNodeTaskMessage::Msg(msg) => {
+                                        incoming_msg_option = Some(msg);
+                                    }
+
+                                    // Stop the thread
+                                    SCPNodeTaskMessage::StopTrigger => {
+                                        break 'main_loop;
+                                    }
+
+                                    // Stop the thread once queued messages are processed
+                                    SCPNodeTaskMessage::DrainTrigger => {
+                                        draining = true;
+                                    }
                                 }
-
-                                // Process an incoming SCP message
-                                SCPNodeTaskMessage::Msg(msg) => {
-                                    incoming_msg_option = Some(msg);
-                                }
-
-                                // Stop the thread
-                                SCPNodeTaskMessage::StopTrigger => {
-                                    break 'main_loop;
-                                }
-                            },
+                            }
                             Err(_) => {
+                                queue_empty = true;
                                 // Yield to other threads when we don't get a new message
                                 std::thread::yield_now();
                             }
                         };
 
-                        // Propose pending values submitted to our node
-                        if !pending_values.is_empty() {
+                        // A restarted node copies the blocks it missed from its
+                        // peers, since messages for later slots are ignored.
+                        let behind = incoming_msg_option
+                            .as_ref()
+                            .map_or(false, |msg| msg.slot_index > current_slot as SlotIndex);
+                        if !catch_up_peers.is_empty()
+                            && (behind || last_catch_up.elapsed() >= CATCH_UP_POLL_INTERVAL)
+                        {
+                            last_catch_up = Instant::now();
+                            let missed_blocks = blocks_missing_from(current_slot, &catch_up_peers);
+                            if !missed_blocks.is_empty() {
+                                let mut locked_shared_data = thread_shared_data
+                                    .lock()
+                                    .expect("thread_shared_data lock failed");
+                                for block in missed_blocks {
+                                    let block_values: HashSet<&String> = block.iter().collect();
+                                    pending_values.retain(|v| !block_values.contains(v));
+                                    locked_shared_data.append_block(
+                                        &node_config.id,
+                                        &block_id_fn,
+                                        block,
+                                    );
+                                    current_slot += 1;
+                                }
+                                drop(locked_shared_data);
+
+                                // The node may already have moved past a slot it
+                                // externalized but has not yet recorded.
+                                if current_slot as SlotIndex
+                                    > thread_local_node.current_slot_index()
+                                {
+                                    thread_local_node.reset_slot_index(current_slot as SlotIndex);
+                                }
+                                log::trace!(
+                                    logger,
+                                    "(  ledger ) node {} caught up to slot {}",
+                                    node_config.name,
+                                    current_slot,
+                                );
+                            }
+                        }
+
+                        // Propose pending values submitted to our node. A draining
+                        // node only adds them to a slot already in progress.
+                        if !pending_values.is_empty()
+                            && (!draining
+                                || slot_in_progress(&thread_local_node.get_current_slot_metrics()))
+                        {
                             let values_to_propose: BTreeSet<String> = pending_values
                                 .iter()
                                 .take(max_slot_proposed_values)
@@ -403,8 +1656,11 @@ impl SCPNode {
                             let mut locked_shared_data = thread_shared_data
                                 .lock()
                                 .expect("thread_shared_data lock failed");
-
-                            locked_shared_data.ledger.push(new_block);
+                            locked_shared_data.append_block(
+                                &node_config.id,
+                                &block_id_fn,
+                                new_block,
+                            );
 
                             let ledger_size = locked_shared_data.ledger_size();
 
@@ -422,6 +1678,27 @@ impl SCPNode {
 
                             current_slot += 1;
                         }
+
+                        // Publish the node's phase for tests, taking the lock
+                        // only when it has changed.
+                        let phase = (
+                            thread_local_node.current_slot_index(),
+                            thread_local_node.get_current_slot_metrics().phase,
+                        );
+                        if phase != published_phase {
+                            thread_shared_data
+                                .lock()
+                                .expect("thread_shared_data lock failed")
+                                .slot_phase = Some(phase);
+                            published_phase = phase;
+                        }
+
+                        if draining
+                            && queue_empty
+                            && !slot_in_progress(&thread_local_node.get_current_slot_metrics())
+                        {
+                            break 'main_loop;
+                        }
                     }
                     log::info!(
                         logger,
@@ -437,6 +1714,44 @@ impl SCPNode {
         (scp_node, join_handle)
     }
 
+    /// Starts a node from `snapshot`, replacing the contents of
+    /// `shared_data` with a fresh ledger from `test_options.ledger_fn`
+    /// holding the snapshot's blocks. The node begins at the slot following
+    /// the snapshot's last block.
+    fn from_snapshot(
+        node_config: NodeConfig,
+        test_options: &TestOptions,
+        broadcast_msg_fn: Arc<dyn Fn(Logger, Msg<String>) + Sync + Send>,
+        clock_skew: Duration,
+        snapshot: &NodeSnapshot,
+        shared_data: Arc<Mutex<SCPNodeSharedData>>,
+        catch_up_peers: Vec<Arc<Mutex<SCPNodeSharedData>>>,
+        logger: Logger,
+    ) -> (Self, JoinHandle<()>) {
+        *shared_data
+            .lock()
+            .expect("lock failed on shared_data restoring snapshot") =
+            SCPNodeSharedData::from_snapshot((test_options.ledger_fn)(), snapshot);
+        Self::new(
+            node_config,
+            test_options,
+            broadcast_msg_fn,
+            clock_skew,
+            snapshot.blocks.len() as SlotIndex,
+            shared_data,
+            catch_up_peers,
+            logger,
+        )
+    }
+
+    /// Copies this node's externalized state.
+    pub fn snapshot(&self) -> NodeSnapshot {
+        self.shared_data
+            .lock()
+            .expect("lock failed on shared_data taking snapshot")
+            .snapshot()
+    }
+
     /// Push value to this node's consensus task.
     pub fn send_value(&self, value: &str) {
         match self
@@ -477,20 +1792,149 @@ impl SCPNode {
             },
         }
     }
+
+    /// Stop this node's consensus task once its queue is empty and its
+    /// current slot is idle.
+    pub fn send_drain_then_stop(&self) {
+        match self.sender.try_send(SCPNodeTaskMessage::DrainTrigger) {
+            Ok(_) => {}
+            Err(err) => match err {
+                crossbeam_channel::TrySendError::Disconnected(_) => {}
+                _ => {
+                    panic!("send_drain_then_stop failed: {:?}", err);
+                }
+            },
+        }
+    }
+}
+
+// Whether a node has begun a slot it has not yet externalized: it has a timer
+// set, or it is balloting (when no timer need be set).
+fn slot_in_progress(metrics: &SlotMetrics) -> bool {
+    metrics.has_pending_timeouts || matches!(metrics.phase, Phase::Prepare | Phase::Commit)
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 /// Test Helpers
 ///////////////////////////////////////////////////////////////////////////////
 
+/// Stops and restarts nodes as scheduled by `TestOptions::node_churn`.
+struct NodeChurner {
+    node_churn: NodeChurn,
+    rng: RngType,
+
+    /// When the next node is stopped, if none is stopped.
+    next_stop: Instant,
+
+    /// The stopped node, and when it is restarted.
+    stopped: Option<(NodeID, Instant)>,
+
+    /// The number of restarts so far.
+    restarts: usize,
+}
+
+impl NodeChurner {
+    fn new(node_churn: NodeChurn, seed: [u8; 32]) -> Self {
+        Self {
+            node_churn,
+            rng: RngType::from_seed(seed),
+            next_stop: Instant::now() + node_churn.interval,
+            stopped: None,
+            restarts: 0,
+        }
+    }
+
+    // stops or restarts a node, if one is due.
+    fn tick(
+        &mut self,
+        simulation: &mut SCPNetwork,
+        node_ids: &[NodeID],
+        test_options: &TestOptions,
+    ) {
+        match self.stopped.take() {
+            Some((node_id, restart_at)) if Instant::now() < restart_at => {
+                self.stopped = Some((node_id, restart_at));
+            }
+            Some((node_id, _)) => {
+                self.restart(simulation, &node_id, test_options);
+                self.next_stop = Instant::now() + self.node_churn.interval;
+            }
+            None if Instant::now() >= self.next_stop => {
+                let index = (self.rng.next_u64() % node_ids.len() as u64) as usize;
+                let node_id = node_ids[index].clone();
+                log::info!(
+                    simulation.logger,
+                    "( testing ) stopping node {}",
+                    simulation.names_map[&node_id],
+                );
+                simulation.stop_node(&node_id);
+                self.stopped = Some((node_id, Instant::now() + self.node_churn.downtime));
+            }
+            None => {}
+        }
+    }
+
+    // restarts the stopped node, if any.
+    fn finish(&mut self, simulation: &mut SCPNetwork, test_options: &TestOptions) {
+        if let Some((node_id, _)) = self.stopped.take() {
+            self.restart(simulation, &node_id, test_options);
+        }
+    }
+
+    fn restart(
+        &mut self,
+        simulation: &mut SCPNetwork,
+        node_id: &NodeID,
+        test_options: &TestOptions,
+    ) {
+        log::info!(
+            simulation.logger,
+            "( testing ) restarting node {}",
+            simulation.names_map[node_id],
+        );
+        simulation.restart_node(node_id, test_options);
+        self.restarts += 1;
+    }
+}
+
 /// Support skipping slow tests based on environment variables
 pub fn skip_slow_tests() -> bool {
     std::env::var("SKIP_SLOW_TESTS") == Ok("1".to_string())
 }
 
+/// Returns the index of the first block for which nodes derived different
+/// BlockIDs, if any.
+pub fn first_divergent_block_id(node_block_ids: &[Vec<BlockID>]) -> Option<usize> {
+    let num_blocks = node_block_ids.iter().map(Vec::len).max().unwrap_or(0);
+    (0..num_blocks).find(|block_index| {
+        let first = node_block_ids[0].get(*block_index);
+        node_block_ids
+            .iter()
+            .any(|block_ids| block_ids.get(*block_index) != first)
+    })
+}
+
 /// Injects values to a network and waits for completion
-pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions, logger: Logger) {
-    let simulation = SCPNetwork::new(network_config, test_options, logger.clone());
+pub fn build_and_test(
+    network_config: &NetworkConfig,
+    test_options: &TestOptions,
+    logger: Logger,
+) -> Result<TestResult, TestFailure> {
+    // Fail fast rather than waiting for a topology that can't agree to time out.
+    if test_options.check_quorum_intersection {
+        network_config
+            .check_quorum_intersection()
+            .map_err(TestFailure::InvalidTopology)?;
+    }
+
+    test_options
+        .validate()
+        .map_err(TestFailure::InvalidOptions)?;
+
+    let mut simulation = SCPNetwork::new(network_config, test_options, logger.clone());
+    let mut churner = test_options
+        .node_churn
+        .map(|node_churn| NodeChurner::new(node_churn, test_options.seed));
 
     if test_options.submit_in_parallel {
         log::info!(
@@ -508,9 +1952,19 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
         );
     }
 
+    log::info!(
+        logger,
+        "( testing ) seed {}",
+        test_options
+            .seed
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    );
+
     let start = Instant::now();
 
-    let mut rng = mc_util_test_helper::get_seeded_rng();
+    let mut rng = RngType::from_seed(test_options.seed);
     let mut values = Vec::<String>::with_capacity(test_options.values_to_submit);
     for _i in 0..test_options.values_to_submit {
         let value = mc_util_test_helper::random_str(&mut rng, CHARACTERS_PER_VALUE);
@@ -557,6 +2011,10 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
             last_log = Instant::now();
         }
 
+        if let Some(churner) = churner.as_mut() {
+            churner.tick(&mut simulation, &node_ids, test_options);
+        }
+
         let elapsed_duration = Instant::now().duration_since(start);
         let target_duration = Duration::from_micros(1_000_000 / test_options.submissions_per_sec);
         if let Some(extra_delay) = target_duration.checked_sub(elapsed_duration) {
@@ -564,6 +2022,11 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
         }
     }
 
+    // Every node must be running to externalize the remaining values.
+    if let Some(churner) = churner.as_mut() {
+        churner.finish(&mut simulation, test_options);
+    }
+
     // report end of value push
     log::info!(
         simulation.logger,
@@ -578,6 +2041,8 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
     for node_id in node_ids.iter() {
         let mut last_log = Instant::now();
         loop {
+            let num_externalized_values = simulation.get_ledger_size(&node_id);
+
             if Instant::now() > deadline {
                 log::error!(
                     simulation.logger,
@@ -588,11 +2053,13 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
                         .get(node_id)
                         .expect("could not find node_id"),
                 );
-                // panic
-                panic!("test failed due to timeout");
+                return Err(TestFailure::Timeout {
+                    node: simulation.names_map[node_id].clone(),
+                    externalized: num_externalized_values,
+                    allowed_test_time: test_options.allowed_test_time,
+                });
             }
 
-            let num_externalized_values = simulation.get_ledger_size(&node_id);
             if num_externalized_values >= test_options.values_to_submit {
                 // if the validity_fn does not enforce unique values, we can end up
                 // with values that appear in multiple slots. This is not a problem
@@ -611,7 +2078,8 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
                 if num_externalized_values > test_options.values_to_submit {
                     log::warn!(
                         simulation.logger,
-                        "( testing ) externalized extra values at node {}",
+                        "( testing ) externalized {} extra values at node {}",
+                        num_externalized_values - test_options.values_to_submit,
                         simulation
                             .names_map
                             .get(node_id)
@@ -649,12 +2117,12 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
         let values_hashset = values.iter().cloned().collect::<HashSet<String>>();
 
         if values_hashset != externalized_values_hashset {
-            let missing_values: HashSet<String> = values_hashset
+            let missing_values: BTreeSet<String> = values_hashset
                 .difference(&externalized_values_hashset)
                 .cloned()
                 .collect();
 
-            let unexpected_values: HashSet<String> = externalized_values_hashset
+            let unexpected_values: BTreeSet<String> = externalized_values_hashset
                 .difference(&values_hashset)
                 .cloned()
                 .collect();
@@ -669,38 +2137,78 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
                 missing_values,
                 unexpected_values,
             );
-            // panic
-            panic!("test failed due to wrong values being externalized");
+            return Err(TestFailure::WrongValues {
+                node: simulation.names_map[node_id].clone(),
+                missing: missing_values,
+                unexpected: unexpected_values,
+            });
         }
     }
 
-    // Check that all of the externalized ledgers match block-by-block
-    let first_node_ledger = simulation.get_ledger(&node_ids[0]);
-    for node_id in node_ids.iter().skip(1) {
-        let other_node_ledger = simulation.get_ledger(&node_id);
-
-        if first_node_ledger.len() != other_node_ledger.len() {
-            log::error!(
-                simulation.logger,
-                "first_node_ledger.len() != other_node_ledger.len() in run_test()"
-            );
-            // panic
-            panic!("test failed due to ledgers having different block count");
+    // Submit some values again now that every node has externalized them, and
+    // wait for each node to externalize them a second time.
+    let resubmitted = &values[..test_options.resubmitted_values.min(values.len())];
+    for value in resubmitted {
+        for node_id in node_ids.iter() {
+            simulation.push_value(node_id, value);
         }
-
-        for block_index in 0..first_node_ledger.len() {
-            if first_node_ledger.get(block_index) != other_node_ledger.get(block_index) {
-                log::error!(
-                    simulation.logger,
-                    "first_node_ledger block differs from other_node_ledger block at block {}",
-                    block_index,
-                );
-                //panic
-                panic!("test failed due to ledgers having different block content");
+    }
+    for node_id in node_ids.iter() {
+        loop {
+            let ledger = simulation.get_ledger(node_id);
+            let externalized_twice = resubmitted
+                .iter()
+                .all(|value| ledger.iter().flatten().filter(|v| *v == value).count() >= 2);
+            if externalized_twice {
+                break;
+            }
+            if Instant::now() > deadline {
+                return Err(TestFailure::Timeout {
+                    node: simulation.names_map[node_id].clone(),
+                    externalized: ledger.iter().map(Vec::len).sum(),
+                    allowed_test_time: test_options.allowed_test_time,
+                });
             }
+            std::thread::sleep(Duration::from_millis(10));
         }
     }
 
+    // Let every node finish the slots already in its pipeline before comparing
+    // ledgers.
+    if test_options.drain_on_stop {
+        simulation.stop_all();
+    }
+
+    // Check that all of the externalized ledgers match block-by-block, and
+    // that all nodes derived the same BlockID for every block
+    simulation.assert_ledgers_consistent().map_err(|err| {
+        log::error!(simulation.logger, "( testing ) {}", err);
+        TestFailure::LedgerMismatch {
+            node: err.node().to_string(),
+            block_index: err.block_index(),
+        }
+    })?;
+
+    // All ledgers match, so any one of them describes the network.
+    let first_node_ledger = simulation.get_ledger(&node_ids[0]);
+    let mut report = TestReport {
+        values_submitted: values.len(),
+        node_restarts: churner.map_or(0, |churner| churner.restarts),
+        ..Default::default()
+    };
+    report.record_externalized(&first_node_ledger);
+
+    let per_node_ledger_sizes: BTreeMap<String, usize> = node_ids
+        .iter()
+        .map(|node_id| {
+            (
+                simulation.names_map[node_id].clone(),
+                simulation.get_ledger_size(node_id),
+            )
+        })
+        .collect();
+    let elapsed = start.elapsed();
+
     // drop the simulation here so that MESSAGES log statements appear before
     // results
     drop(simulation);
@@ -710,7 +2218,7 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
         logger,
         "test results: {},{},{},{},{},{}",
         network_config.name,
-        start.elapsed().as_millis(),
+        elapsed.as_millis(),
         values.len(),
         test_options.submissions_per_sec,
         test_options.max_slot_proposed_values,
@@ -718,14 +2226,80 @@ pub fn build_and_test(network_config: &NetworkConfig, test_options: &TestOptions
     );
 
     // human readable throughput
+    let tps = values.len() as f64 / elapsed.as_secs_f64();
+    log::info!(
+        logger,
+        "test completed for {}: {:?} (avg {:.0} tx/s)",
+        network_config.name,
+        elapsed,
+        tps,
+    );
+
     log::info!(
         logger,
-        "test completed for {}: {:?} (avg {} tx/s)",
+        "test report for {}: {} duplicate externalizations of {} values",
         network_config.name,
-        start.elapsed(),
-        (1_000_000 * values.len() as u128) / start.elapsed().as_micros(),
+        report.duplicate_externalizations,
+        report.values_externalized_more_than_once,
     );
 
     // allow log to flush
     std::thread::sleep(test_options.log_flush_delay);
+
+    Ok(TestResult {
+        name: network_config.name.clone(),
+        elapsed,
+        values: values.len(),
+        tps,
+        per_node_ledger_sizes,
+        report,
+    })
+}
+
+/// Re-runs `build_and_test` with the given seed, recording every message
+/// sent, and logs the messages sent leading up to the failure if it fails
+/// again. Returns the result along with those messages.
+///
+/// This is a best-effort trace, not a replay. The seed reproduces the
+/// submitted values, clock skews and node churn of the original run, but nodes
+/// still run on their own threads, so the order in which they handle messages
+/// can differ between runs, and a failure may not recur. The trace shows what
+/// was sent in this run. For a ledger mismatch, only messages for slots up to
+/// the divergent block are kept.
+pub fn rerun_with_trace(
+    seed: [u8; 32],
+    network_config: &NetworkConfig,
+    test_options: &TestOptions,
+    logger: Logger,
+) -> (Result<TestResult, TestFailure>, Vec<SentMsg>) {
+    let message_trace = MessageTrace::new();
+    let mut test_options = test_options.clone();
+    test_options.seed = seed;
+    test_options.message_trace = Some(message_trace.clone());
+
+    let result = build_and_test(network_config, &test_options, logger.clone());
+
+    let last_slot = match &result {
+        Err(TestFailure::LedgerMismatch { block_index, .. }) => *block_index as SlotIndex,
+        _ => SlotIndex::MAX,
+    };
+    let sent_msgs: Vec<SentMsg> = message_trace
+        .sent_msgs()
+        .into_iter()
+        .filter(|sent_msg| sent_msg.msg.slot_index <= last_slot)
+        .collect();
+
+    if let Err(failure) = &result {
+        log::info!(
+            logger,
+            "( trace ) {} messages sent leading to failure: {}",
+            sent_msgs.len(),
+            failure
+        );
+        for (step, sent_msg) in sent_msgs.iter().enumerate() {
+            log::info!(logger, "( trace ) #{} {}", step, sent_msg);
+        }
+    }
+
+    (result, sent_msgs)
 }
@@ -3,7 +3,12 @@
 mod mock_network;
 
 use mc_common::logger::{test_with_logger, Logger};
+use mc_consensus_scp::{slot::Phase, test_utils};
 use serial_test_derive::serial;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 /// Performs a consensus test for a mesh network of (n) nodes.
 fn mesh_test_helper(
@@ -20,7 +25,8 @@ fn mesh_test_helper(
     let mut test_options = mock_network::TestOptions::new();
     test_options.values_to_submit = 10000;
     let network_config = mock_network::mesh_topology::dense_mesh(n, k);
-    mock_network::build_and_test(&network_config, &test_options, logger.clone());
+    mock_network::build_and_test(&network_config, &test_options, logger.clone())
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure));
 }
 
 #[test_with_logger]
@@ -64,3 +70,540 @@ fn mesh_5k3(logger: Logger) {
 fn mesh_5k4(logger: Logger) {
     mesh_test_helper(5, 4, logger);
 }
+
+#[test_with_logger]
+#[serial]
+// A node which derives BlockIDs differently should be detected, even though
+// every node externalizes the same values.
+fn mesh_3k2_detects_block_id_divergence(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 100;
+
+    // Node 0 mixes an extra byte into every BlockID it derives.
+    let perturbed_node_id = test_utils::test_node_id(0);
+    test_options.block_id_fn = Arc::new(move |node_id, parent_id, values| {
+        let mut block_id = mock_network::compute_block_id(parent_id, values);
+        if *node_id == perturbed_node_id {
+            block_id[0] ^= 1;
+        }
+        block_id
+    });
+
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    match mock_network::build_and_test(&network_config, &test_options, logger) {
+        Err(mock_network::TestFailure::LedgerMismatch { block_index, .. }) => {
+            assert_eq!(block_index, 0)
+        }
+        other => panic!("expected a ledger mismatch, got {:?}", other),
+    }
+}
+
+#[test_with_logger]
+#[serial]
+// A run should report what each node externalized, and a run whose values are
+// never externalized should fail with a timeout rather than panic.
+fn build_and_test_reports_results_and_failures(logger: Logger) {
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 100;
+
+    let result = mock_network::build_and_test(&network_config, &test_options, logger.clone())
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure));
+    assert_eq!(result.name, network_config.name);
+    assert_eq!(result.values, 100);
+    assert!(result.tps > 0.0);
+    assert_eq!(result.per_node_ledger_sizes.len(), 3);
+    assert!(result
+        .per_node_ledger_sizes
+        .values()
+        .all(|size| *size >= 100));
+
+    // Nodes which reject every value externalize nothing.
+    test_options.validity_fn =
+        Arc::new(|_value: &String| Err(test_utils::TransactionValidationError));
+    test_options.allowed_test_time = Duration::from_secs(1);
+    match mock_network::build_and_test(&network_config, &test_options, logger) {
+        Err(mock_network::TestFailure::Timeout {
+            externalized,
+            allowed_test_time,
+            ..
+        }) => {
+            assert_eq!(externalized, 0);
+            assert_eq!(allowed_test_time, Duration::from_secs(1));
+        }
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
+
+#[test]
+// Every externalization of a value beyond its first should be counted as a
+// duplicate.
+fn test_report_counts_duplicate_externalizations() {
+    let ledger: Vec<Vec<String>> = vec![
+        vec!["a".to_string(), "b".to_string()],
+        vec!["a".to_string(), "c".to_string()],
+        vec!["a".to_string(), "b".to_string(), "d".to_string()],
+    ];
+
+    let mut report = mock_network::TestReport::default();
+    report.record_externalized(&ledger);
+
+    assert_eq!(report.values_externalized, 7);
+    // "a" twice more, "b" once more.
+    assert_eq!(report.duplicate_externalizations, 3);
+    assert_eq!(report.values_externalized_more_than_once, 2);
+}
+
+#[test]
+// The builder should start from the default options, and refuse combinations
+// that cannot be simulated.
+fn test_options_builder_rejects_invalid_combinations() {
+    let defaults = mock_network::TestOptions::new();
+    let built = mock_network::TestOptionsBuilder::new().build().unwrap();
+    assert_eq!(built.submit_in_parallel, defaults.submit_in_parallel);
+    assert_eq!(built.values_to_submit, defaults.values_to_submit);
+    assert_eq!(built.node_churn, defaults.node_churn);
+
+    let churn = mock_network::NodeChurn {
+        interval: Duration::from_millis(100),
+        downtime: Duration::from_millis(100),
+    };
+    let result = mock_network::TestOptionsBuilder::new()
+        .with_node_churn(Some(churn))
+        .with_submit_in_parallel(false)
+        .build();
+    assert_eq!(
+        result.err(),
+        Some(mock_network::TestOptionsError::ChurnWithSequentialSubmission)
+    );
+
+    let result = mock_network::TestOptionsBuilder::new()
+        .with_submissions_per_sec(0)
+        .build();
+    assert_eq!(
+        result.err(),
+        Some(mock_network::TestOptionsError::ZeroSubmissionRate)
+    );
+
+    let result = mock_network::TestOptionsBuilder::new()
+        .with_max_slot_proposed_values(0)
+        .build();
+    assert_eq!(
+        result.err(),
+        Some(mock_network::TestOptionsError::ZeroProposedValues)
+    );
+
+    let result = mock_network::TestOptionsBuilder::new()
+        .with_resubmitted_values(1)
+        .with_duplicate_policy(mock_network::DuplicatePolicy::RejectDuplicates)
+        .build();
+    assert_eq!(
+        result.err(),
+        Some(mock_network::TestOptionsError::ResubmissionWithRejectDuplicates)
+    );
+
+    // Churn is fine when values go to every node.
+    let built = mock_network::TestOptionsBuilder::new()
+        .with_node_churn(Some(churn))
+        .build()
+        .unwrap();
+    assert_eq!(built.node_churn, Some(churn));
+}
+
+#[test_with_logger]
+#[serial]
+// A network whose nodes have externalized nothing has consistent ledgers, but
+// a node which derives its own BlockIDs for the same values is detected.
+fn new_network_ledgers_are_consistent(logger: Logger) {
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let simulation = mock_network::SCPNetwork::new(
+        &network_config,
+        &mock_network::TestOptions::new(),
+        logger.clone(),
+    );
+    assert_eq!(simulation.assert_ledgers_consistent(), Ok(()));
+    drop(simulation);
+
+    // Node 0 mixes an extra byte into every BlockID it derives.
+    let mut test_options = mock_network::TestOptions::new();
+    let perturbed_node_id = test_utils::test_node_id(0);
+    test_options.block_id_fn = Arc::new(move |node_id, parent_id, values| {
+        let mut block_id = mock_network::compute_block_id(parent_id, values);
+        if *node_id == perturbed_node_id {
+            block_id[0] ^= 1;
+        }
+        block_id
+    });
+    let node_ids: Vec<_> = network_config.nodes.iter().map(|n| n.id.clone()).collect();
+    let simulation = mock_network::SCPNetwork::new(&network_config, &test_options, logger);
+    for node_id in &node_ids {
+        simulation.push_value(node_id, "value");
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while node_ids
+        .iter()
+        .any(|node_id| simulation.snapshot_node(node_id).blocks.is_empty())
+    {
+        assert!(Instant::now() < deadline, "value was not externalized");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    match simulation.assert_ledgers_consistent() {
+        Err(mock_network::ConsistencyError::BlockIdMismatch { block_index, .. }) => {
+            assert_eq!(block_index, 0)
+        }
+        other => panic!("expected a BlockID mismatch, got {:?}", other),
+    }
+}
+
+#[test_with_logger]
+#[serial]
+// A node restored from an old snapshot should keep the snapshot's blocks and
+// catch up on the rest from its peers.
+fn restored_node_catches_up_from_snapshot(logger: Logger) {
+    let test_options = mock_network::TestOptions::new();
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let node_ids: Vec<_> = network_config.nodes.iter().map(|n| n.id.clone()).collect();
+    let mut simulation = mock_network::SCPNetwork::new(&network_config, &test_options, logger);
+
+    let mut snapshot = mock_network::NodeSnapshot::default();
+    for i in 0..3 {
+        let parent_id = snapshot.block_ids.last().cloned().unwrap_or_default();
+        let values = vec![format!("value{}", i)];
+        snapshot
+            .block_ids
+            .push(mock_network::compute_block_id(&parent_id, &values));
+        snapshot.blocks.push(values);
+    }
+    let mut old_snapshot = snapshot.clone();
+    old_snapshot.blocks.truncate(1);
+    old_snapshot.block_ids.truncate(1);
+
+    for node_id in &node_ids[1..] {
+        simulation.restore_node(node_id, &test_options, &snapshot);
+    }
+    simulation.restore_node(&node_ids[0], &test_options, &old_snapshot);
+    assert_eq!(simulation.snapshot_node(&node_ids[1]), snapshot);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while simulation.snapshot_node(&node_ids[0]) != snapshot {
+        assert!(Instant::now() < deadline, "restored node did not catch up");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(simulation.assert_ledgers_consistent(), Ok(()));
+}
+
+#[test_with_logger]
+#[serial]
+// Each node's phase should be visible while it runs, from the start of a slot
+// through to externalizing it.
+fn nodes_report_current_phase(logger: Logger) {
+    let test_options = mock_network::TestOptions::new();
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let node_ids: Vec<_> = network_config.nodes.iter().map(|n| n.id.clone()).collect();
+    let simulation = mock_network::SCPNetwork::new(&network_config, &test_options, logger);
+
+    for node_id in &node_ids {
+        assert_eq!(
+            simulation.current_phase(node_id, 0),
+            Some(Phase::NominatePrepare)
+        );
+        assert_eq!(simulation.current_phase(node_id, 1), None);
+        simulation.push_value(node_id, "value");
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    for node_id in &node_ids {
+        while simulation.current_phase(node_id, 0) != Some(Phase::Externalize) {
+            assert!(Instant::now() < deadline, "node did not externalize slot 0");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            simulation.current_phase(node_id, 1),
+            Some(Phase::NominatePrepare)
+        );
+    }
+}
+
+#[test_with_logger]
+#[serial]
+// A seeded run should record each message a node sends to each of its peers,
+// and re-running the seed with a trace should return the messages sent.
+fn rerun_with_trace_records_sent_msgs(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 100;
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+
+    let message_trace = mock_network::MessageTrace::new();
+    let traced_options = mock_network::TestOptionsBuilder::new()
+        .with_values_to_submit(100)
+        .with_seed([3u8; 32])
+        .with_message_trace(Some(message_trace.clone()))
+        .build()
+        .unwrap();
+    mock_network::build_and_test(&network_config, &traced_options, logger.clone())
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure));
+
+    let sent_msgs = message_trace.sent_msgs();
+    assert!(!sent_msgs.is_empty());
+    for sent_msg in &sent_msgs {
+        assert_ne!(sent_msg.from, sent_msg.to);
+    }
+    assert!(sent_msgs.windows(2).all(|pair| pair[0].at <= pair[1].at));
+
+    let (result, rerun_msgs) =
+        mock_network::rerun_with_trace([3u8; 32], &network_config, &test_options, logger);
+    result.unwrap_or_else(|failure| panic!("rerun failed: {}", failure));
+    assert!(!rerun_msgs.is_empty());
+}
+
+#[test_with_logger]
+#[serial]
+// Re-running a seed which fails should return the failure, along with the
+// messages sent before it.
+fn rerun_with_trace_returns_failure(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 1000;
+    test_options.allowed_test_time = Duration::from_millis(1);
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+
+    let (result, sent_msgs) =
+        mock_network::rerun_with_trace([3u8; 32], &network_config, &test_options, logger);
+    match result {
+        Err(mock_network::TestFailure::Timeout { .. }) => {}
+        Err(failure) => panic!("Unexpected failure: {}", failure),
+        Ok(_) => panic!("Every value was externalized within 1ms"),
+    }
+    assert!(!sent_msgs.is_empty());
+}
+
+#[test_with_logger]
+#[serial]
+// With a validity function that does not enforce unique values, values
+// submitted again after being externalized should be externalized again, and
+// the report should account for every externalized value beyond those
+// submitted.
+fn mesh_3k2_reports_duplicates(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 1000;
+    test_options.duplicate_policy = mock_network::DuplicatePolicy::AllowDuplicates;
+    test_options.resubmitted_values = 10;
+
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let report = mock_network::build_and_test(&network_config, &test_options, logger)
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure))
+        .report;
+
+    assert_eq!(report.values_submitted, test_options.values_to_submit);
+    assert_eq!(
+        report.duplicate_externalizations,
+        report.values_externalized - report.values_submitted
+    );
+    assert!(report.duplicate_externalizations >= test_options.resubmitted_values);
+    assert!(report.values_externalized_more_than_once >= test_options.resubmitted_values);
+    assert!(report.values_externalized_more_than_once <= report.duplicate_externalizations);
+}
+
+#[test_with_logger]
+#[serial]
+// Nodes rejecting values already in their ledger should never externalize a
+// value twice, and should still agree on the ledger.
+fn mesh_3k2_rejects_duplicates(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 1000;
+    test_options.duplicate_policy = mock_network::DuplicatePolicy::RejectDuplicates;
+
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let report = mock_network::build_and_test(&network_config, &test_options, logger)
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure))
+        .report;
+
+    assert_eq!(report.values_submitted, test_options.values_to_submit);
+    assert_eq!(report.values_externalized, test_options.values_to_submit);
+    assert_eq!(report.duplicate_externalizations, 0);
+}
+
+#[test_with_logger]
+#[serial]
+// Draining nodes on shutdown should still produce matching ledgers.
+fn mesh_3k2_drain_on_stop(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 1000;
+    test_options.drain_on_stop = true;
+
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let report = mock_network::build_and_test(&network_config, &test_options, logger)
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure))
+        .report;
+    assert_eq!(report.values_submitted, test_options.values_to_submit);
+}
+
+#[test_with_logger]
+#[serial]
+// Nodes which crash and restart mid-test should catch up from their peers, and
+// every ledger should still match.
+fn mesh_4k2_node_churn(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 1000;
+    test_options.submissions_per_sec = 2000;
+    test_options.node_churn = Some(mock_network::NodeChurn {
+        interval: Duration::from_millis(100),
+        downtime: Duration::from_millis(50),
+    });
+
+    // Each node needs two of its three peers, so any one node can be down.
+    let network_config = mock_network::mesh_topology::dense_mesh(4, 2);
+    let report = mock_network::build_and_test(&network_config, &test_options, logger)
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure))
+        .report;
+
+    assert!(report.node_restarts > 0);
+    assert_eq!(report.values_submitted, test_options.values_to_submit);
+}
+
+#[test_with_logger]
+#[serial]
+// Every node validates every value on its own thread, so a per-value
+// validation cost should bound how quickly values can be externalized.
+fn mesh_3k2_validation_latency(logger: Logger) {
+    let latency = Duration::from_millis(2);
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 100;
+    test_options.validation_latency_fn = Arc::new(move |_value| latency);
+
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let result = mock_network::build_and_test(&network_config, &test_options, logger)
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure));
+
+    assert!(result.elapsed >= latency * test_options.values_to_submit as u32);
+}
+
+#[test_with_logger]
+#[serial]
+// Two groups of nodes that only trust each other form disjoint quorums, and
+// should be rejected before the simulation starts.
+fn disjoint_topology_is_rejected(logger: Logger) {
+    let group = |indices: [u32; 2]| {
+        indices
+            .iter()
+            .map(|&index| {
+                let peer_index = if index == indices[0] {
+                    indices[1]
+                } else {
+                    indices[0]
+                };
+                mock_network::NodeConfig::with_threshold(
+                    format!("d{}", index),
+                    test_utils::test_node_id(index),
+                    vec![test_utils::test_node_id(peer_index)]
+                        .into_iter()
+                        .collect(),
+                    1,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut nodes = group([0, 1]);
+    nodes.extend(group([2, 3]));
+    let network_config = mock_network::NetworkConfig::new("disjoint".to_string(), nodes);
+
+    match network_config.check_quorum_intersection() {
+        Err(mock_network::QuorumError::DisjointQuorums(first, second)) => {
+            assert!(first.is_disjoint(&second));
+        }
+        other => panic!("expected disjoint quorums, got {:?}", other),
+    }
+
+    let start = Instant::now();
+    match mock_network::build_and_test(&network_config, &mock_network::TestOptions::new(), logger) {
+        Err(mock_network::TestFailure::InvalidTopology(_)) => {}
+        other => panic!("expected an invalid topology, got {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // A dense mesh has the intersection property.
+    assert_eq!(
+        mock_network::mesh_topology::dense_mesh(5, 3).check_quorum_intersection(),
+        Ok(())
+    );
+}
+
+#[test_with_logger]
+#[serial]
+// Nodes with skewed timebases should still agree.
+fn mesh_3k2_clock_skew(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 1000;
+    test_options.scp_timebase = Duration::from_millis(100);
+    test_options.clock_skew = Duration::from_millis(50);
+
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    mock_network::build_and_test(&network_config, &test_options, logger)
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure));
+}
+
+#[test]
+// Hashing ledgers holding the same values in a different order should disagree
+// from the reordered block onwards.
+fn hashing_ledger_detects_reordered_values() {
+    use mock_network::{HashingLedger, SimulatedLedger};
+
+    let block = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+
+    let mut ledger = HashingLedger::default();
+    let mut other_ledger = HashingLedger::default();
+    ledger.push_block(block(&["a", "b"]));
+    other_ledger.push_block(block(&["a", "b"]));
+    ledger.push_block(block(&["c", "d"]));
+    other_ledger.push_block(block(&["d", "c"]));
+    ledger.push_block(block(&["e"]));
+    other_ledger.push_block(block(&["e"]));
+
+    assert!(ledger.block_matches(&other_ledger, 0));
+    assert!(!ledger.block_matches(&other_ledger, 1));
+    // The digest chains, so later identical blocks still disagree.
+    assert!(!ledger.block_matches(&other_ledger, 2));
+    assert_eq!(ledger.ledger_size(), other_ledger.ledger_size());
+}
+
+#[test_with_logger]
+#[serial]
+// Nodes recording blocks in hashing ledgers should still agree.
+fn mesh_3k2_hashing_ledger(logger: Logger) {
+    let mut test_options = mock_network::TestOptions::new();
+    test_options.values_to_submit = 1000;
+    test_options.ledger_fn = Arc::new(|| Box::new(mock_network::HashingLedger::default()));
+
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    mock_network::build_and_test(&network_config, &test_options, logger)
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure));
+}
+
+#[test_with_logger]
+#[serial]
+// Nodes stopped in the middle of a slot should finish it before exiting when
+// draining.
+fn drain_on_stop_finishes_slot_in_progress(logger: Logger) {
+    let test_options = mock_network::TestOptionsBuilder::new()
+        .with_drain_on_stop(true)
+        .build()
+        .unwrap();
+    let network_config = mock_network::mesh_topology::dense_mesh(3, 2);
+    let node_ids: Vec<_> = network_config.nodes.iter().map(|n| n.id.clone()).collect();
+    let mut simulation = mock_network::SCPNetwork::new(&network_config, &test_options, logger);
+
+    for node_id in &node_ids {
+        simulation.push_value(node_id, "value");
+    }
+    // Each node has the value queued ahead of the drain, so it is mid-slot
+    // when it sees the drain.
+    simulation.stop_all();
+
+    for node_id in &node_ids {
+        assert_eq!(
+            simulation.snapshot_node(node_id).blocks,
+            vec![vec!["value".to_string()]]
+        );
+    }
+    assert_eq!(simulation.assert_ledgers_consistent(), Ok(()));
+}
@@ -27,8 +27,14 @@ fn metamesh_test_helper(
     test_options.values_to_submit = 1;
     test_options.scp_timebase = Duration::from_millis(100);
 
+    // With k_m = 1, a node's quorum set does not require its own organization,
+    // so disjoint sets of organizations can each form a quorum. Honest nodes
+    // still agree, so these networks are simulated anyway.
+    test_options.check_quorum_intersection = k_m > 1;
+
     let network_config = mock_network::metamesh_topology::metamesh(n, k_n, m, k_m);
-    mock_network::build_and_test(&network_config, &test_options, logger.clone());
+    mock_network::build_and_test(&network_config, &test_options, logger.clone())
+        .unwrap_or_else(|failure| panic!("test failed: {}", failure));
 }
 
 #[test_with_logger]
@@ -2,15 +2,20 @@
 
 //! Serves node-to-node attested gRPC requests.
 
-use grpcio::{RpcContext, UnarySink};
+use grpcio::{MetadataBuilder, RpcContext, UnarySink};
 use mc_attest_api::{attest::AuthMessage, attest_grpc::AttestedApi};
 use mc_attest_enclave_api::{ClientSession, PeerSession, Session};
 use mc_common::{
     logger::{log, Logger},
     HashSet,
 };
+use mc_consensus_api::{
+    ATTESTATION_VERSION_HEADER, OLDEST_ATTESTATION_VERSION, SUPPORTED_ATTESTATION_VERSIONS,
+};
 use mc_consensus_enclave::ConsensusEnclave;
-use mc_util_grpc::{rpc_logger, rpc_permissions_error, send_result, Authenticator};
+use mc_util_grpc::{
+    rpc_logger, rpc_permissions_error, rpc_precondition_error, send_result, Authenticator,
+};
 use mc_util_metrics::SVC_COUNTERS;
 use std::sync::{Arc, Mutex};
 
@@ -37,6 +42,25 @@ impl<S: Session> AttestedApiService<S> {
     }
 }
 
+/// The attestation protocol version to use with the client, or the version it
+/// offered if this node does not support it. Clients which offer none use the
+/// oldest version, which is always supported.
+fn attestation_version(ctx: &RpcContext) -> Result<u32, String> {
+    let value = match ctx
+        .request_headers()
+        .iter()
+        .find(|(key, _value)| *key == ATTESTATION_VERSION_HEADER)
+    {
+        Some((_key, value)) => value,
+        None => return Ok(OLDEST_ATTESTATION_VERSION),
+    };
+    let version = String::from_utf8_lossy(value).into_owned();
+    match version.parse::<u32>() {
+        Ok(version) if SUPPORTED_ATTESTATION_VERSIONS.contains(&version) => Ok(version),
+        _ => Err(version),
+    }
+}
+
 impl AttestedApi for AttestedApiService<PeerSession> {
     fn auth(&mut self, ctx: RpcContext, request: AuthMessage, sink: UnarySink<AuthMessage>) {
         let _timer = SVC_COUNTERS.req(&ctx);
@@ -81,13 +105,27 @@ impl AttestedApi for AttestedApiService<PeerSession> {
 }
 
 impl AttestedApi for AttestedApiService<ClientSession> {
-    fn auth(&mut self, ctx: RpcContext, request: AuthMessage, sink: UnarySink<AuthMessage>) {
+    fn auth(&mut self, ctx: RpcContext, request: AuthMessage, mut sink: UnarySink<AuthMessage>) {
         let _timer = SVC_COUNTERS.req(&ctx);
         mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
             if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
                 return send_result(ctx, sink, err.into(), &logger);
             }
 
+            // The client falls back to an older version when told this one is
+            // unsupported.
+            let version = match attestation_version(&ctx) {
+                Ok(version) => version,
+                Err(version) => {
+                    let err = rpc_precondition_error(
+                        "client_auth",
+                        format!("Unsupported attestation version {}", version),
+                        &logger,
+                    );
+                    return send_result(ctx, sink, Err(err), &logger);
+                }
+            };
+
             // TODO: Use the prost message directly, once available
             match self.enclave.client_accept(request.into()) {
                 Ok((response, session_id)) => {
@@ -97,6 +135,15 @@ impl AttestedApi for AttestedApiService<ClientSession> {
                             .expect("Thread crashed while inserting client sesssion ID")
                             .insert(session_id);
                     }
+
+                    // Tell the client which version was accepted, so that it can
+                    // tell this node apart from one which ignores the offer.
+                    let mut headers = MetadataBuilder::new();
+                    headers
+                        .add_str(ATTESTATION_VERSION_HEADER, &version.to_string())
+                        .expect("Error setting attestation version header");
+                    sink.set_headers(headers.build());
+
                     send_result(ctx, sink, Ok(response.into()), &logger);
                 }
                 Err(client_error) => {
@@ -193,12 +240,13 @@ mod peer_tests {
 mod client_tests {
     use super::*;
     use grpcio::{
-        ChannelBuilder, Environment, Error as GrpcError, RpcStatusCode, Server, ServerBuilder,
+        CallOption, ChannelBuilder, Environment, Error as GrpcError, MetadataBuilder,
+        RpcStatusCode, Server, ServerBuilder,
     };
     use mc_attest_api::attest_grpc::{self, AttestedApiClient};
     use mc_common::{logger::test_with_logger, time::SystemTimeProvider};
     use mc_consensus_enclave_mock::MockConsensusEnclave;
-    use mc_util_grpc::TokenAuthenticator;
+    use mc_util_grpc::{AnonymousAuthenticator, TokenAuthenticator};
     use std::{
         sync::atomic::{AtomicUsize, Ordering::SeqCst},
         time::Duration,
@@ -255,4 +303,74 @@ mod client_tests {
             }
         }
     }
+
+    #[test_with_logger]
+    // `auth` should turn away a client offering an attestation version this
+    // node does not support, so that the client can fall back.
+    fn test_client_auth_unsupported_version(logger: Logger) {
+        let enclave = Arc::new(MockConsensusEnclave::new());
+        let attested_api_service = AttestedApiService::<ClientSession>::new(
+            enclave,
+            Arc::new(AnonymousAuthenticator::default()),
+            logger,
+        );
+
+        let (client, _server) = get_client_server(attested_api_service);
+
+        let mut metadata_builder = MetadataBuilder::new();
+        metadata_builder
+            .add_str(ATTESTATION_VERSION_HEADER, "999")
+            .unwrap();
+        let call_option = CallOption::default().headers(metadata_builder.build());
+
+        match client.auth_opt(&AuthMessage::default(), call_option) {
+            Err(GrpcError::RpcFailure(rpc_status)) => {
+                assert_eq!(rpc_status.status, RpcStatusCode::FAILED_PRECONDITION);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_with_logger]
+    // `auth` should echo the attestation version it accepted, including the
+    // oldest version for a client which offers none.
+    fn test_client_auth_echoes_version(logger: Logger) {
+        let mut enclave = MockConsensusEnclave::new();
+        enclave
+            .expect_client_accept()
+            .times(2)
+            .returning(|_request| Ok((vec![1u8].into(), vec![2u8].into())));
+        let attested_api_service = AttestedApiService::<ClientSession>::new(
+            Arc::new(enclave),
+            Arc::new(AnonymousAuthenticator::default()),
+            logger,
+        );
+
+        let (client, _server) = get_client_server(attested_api_service);
+
+        let echoed_version = |call_option: CallOption| -> Option<Vec<u8>> {
+            let (header, _response, _trailer) = client
+                .auth_full(&AuthMessage::default(), call_option)
+                .expect("auth failed");
+            header?
+                .iter()
+                .find(|(key, _value)| *key == ATTESTATION_VERSION_HEADER)
+                .map(|(_key, value)| value.to_vec())
+        };
+
+        let newest = SUPPORTED_ATTESTATION_VERSIONS[0];
+        let mut metadata_builder = MetadataBuilder::new();
+        metadata_builder
+            .add_str(ATTESTATION_VERSION_HEADER, &newest.to_string())
+            .unwrap();
+        assert_eq!(
+            echoed_version(CallOption::default().headers(metadata_builder.build())),
+            Some(newest.to_string().into_bytes())
+        );
+
+        assert_eq!(
+            echoed_version(CallOption::default()),
+            Some(OLDEST_ATTESTATION_VERSION.to_string().into_bytes())
+        );
+    }
 }
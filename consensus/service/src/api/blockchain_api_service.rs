@@ -4,18 +4,28 @@
 
 use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
 use mc_common::logger::{log, Logger};
+use mc_connection::{compress_blocks, negotiate_block_compression, PeerCapabilities};
 use mc_consensus_api::{
     blockchain,
-    consensus_common::{BlocksRequest, BlocksResponse, LastBlockInfoResponse},
+    consensus_common::{
+        BlockWithProofs, BlocksRequest, BlocksResponse, BlocksWithProofsResponse,
+        CapabilitiesResponse, CheckKeyImageRequest, CheckKeyImageResponse, CompressedBlocksRequest,
+        CompressedBlocksResponse, LastBlockInfoResponse, SignedBlock, SignedBlocksResponse,
+    },
     consensus_common_grpc::BlockchainApi,
     empty::Empty,
+    external,
 };
 use mc_ledger_db::Ledger;
-use mc_transaction_core::constants::MINIMUM_FEE;
+use mc_transaction_core::{constants::MINIMUM_FEE, ring_signature::KeyImage};
 use mc_util_grpc::{rpc_logger, send_result, Authenticator};
 use mc_util_metrics::{self, SVC_COUNTERS};
 use protobuf::RepeatedField;
-use std::{cmp, convert::From, sync::Arc};
+use std::{
+    cmp,
+    convert::{From, TryFrom},
+    sync::Arc,
+};
 
 #[derive(Clone)]
 pub struct BlockchainApiService<L: Ledger + Clone> {
@@ -68,6 +78,20 @@ impl<L: Ledger + Clone> BlockchainApiService<L> {
         Ok(resp)
     }
 
+    /// Returns the optional APIs this node serves.
+    fn get_capabilities_helper(&self) -> CapabilitiesResponse {
+        let capabilities = PeerCapabilities::SIGNED_BLOCKS
+            | PeerCapabilities::BLOCKS_WITH_PROOFS
+            | PeerCapabilities::MINIMUM_FEE
+            | PeerCapabilities::PENDING_TX_HASHES
+            | PeerCapabilities::COMPRESSED_BLOCKS
+            | PeerCapabilities::KEY_IMAGES;
+
+        let mut resp = CapabilitiesResponse::new();
+        resp.set_flags(capabilities.bits());
+        resp
+    }
+
     /// Returns blocks in the range [offset, offset + limit).
     ///
     /// If `limit` exceeds `max_page_size`, then only [offset, offset +
@@ -110,6 +134,150 @@ impl<L: Ledger + Clone> BlockchainApiService<L> {
         response.set_blocks(RepeatedField::from_vec(blocks));
         Ok(response)
     }
+
+    /// Returns blocks in the range [offset, offset + limit).
+    ///
+    /// The range is truncated in the same manner as `get_blocks_helper`, but
+    /// any ledger error other than reaching the end of the ledger is returned.
+    fn get_block_range(
+        &self,
+        offset: u64,
+        limit: u32,
+    ) -> Result<BlocksResponse, mc_ledger_db::Error> {
+        let end_index = offset + cmp::min(limit, self.max_page_size as u32) as u64;
+
+        let mut blocks: Vec<blockchain::Block> = vec![];
+        for block_index in offset..end_index {
+            match self.ledger.get_block(block_index) {
+                Ok(block) => blocks.push(blockchain::Block::from(&block)),
+                Err(mc_ledger_db::Error::NotFound) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut response = BlocksResponse::new();
+        response.set_blocks(RepeatedField::from_vec(blocks));
+        Ok(response)
+    }
+
+    /// Returns blocks in the range [offset, offset + limit), along with their
+    /// signatures when available.
+    ///
+    /// The range is truncated in the same manner as `get_blocks_helper`.
+    fn get_signed_blocks_helper(
+        &mut self,
+        offset: u64,
+        limit: u32,
+    ) -> Result<SignedBlocksResponse, mc_ledger_db::Error> {
+        let blocks = self.get_block_range(offset, limit)?;
+
+        let signed_blocks = blocks
+            .get_blocks()
+            .iter()
+            .map(|block| {
+                let mut signed_block = SignedBlock::new();
+                match self.ledger.get_block_signature(block.get_index()) {
+                    Ok(signature) => {
+                        signed_block.set_signature(blockchain::BlockSignature::from(&signature))
+                    }
+                    Err(mc_ledger_db::Error::NotFound) => {}
+                    Err(err) => return Err(err),
+                }
+                signed_block.set_block(block.clone());
+                Ok(signed_block)
+            })
+            .collect::<Result<Vec<SignedBlock>, mc_ledger_db::Error>>()?;
+
+        let mut response = SignedBlocksResponse::new();
+        response.set_signed_blocks(RepeatedField::from_vec(signed_blocks));
+        Ok(response)
+    }
+
+    /// Returns blocks in the range [offset, offset + limit), compressed with
+    /// the first algorithm the client accepts.
+    ///
+    /// The range is truncated in the same manner as `get_blocks_helper`.
+    fn get_compressed_blocks_helper(
+        &mut self,
+        request: &CompressedBlocksRequest,
+    ) -> Result<CompressedBlocksResponse, mc_connection::Error> {
+        let blocks = self
+            .get_block_range(request.get_request().offset, request.get_request().limit)
+            .map_err(|err| mc_connection::Error::Other(format!("Ledger: {}", err)))?;
+        let compression = negotiate_block_compression(request.get_accepted());
+
+        let mut response = CompressedBlocksResponse::new();
+        response.set_compression(compression);
+        response.set_blocks(compress_blocks(&blocks, compression)?);
+        Ok(response)
+    }
+
+    /// Returns blocks in the range [offset, offset + limit), along with a
+    /// membership proof for each of their outputs.
+    ///
+    /// The range is truncated in the same manner as `get_blocks_helper`.
+    /// Proofs are computed against the current state of the ledger.
+    fn get_blocks_with_proofs_helper(
+        &mut self,
+        offset: u64,
+        limit: u32,
+    ) -> Result<BlocksWithProofsResponse, mc_ledger_db::Error> {
+        let blocks = self.get_block_range(offset, limit)?;
+
+        let blocks_with_proofs = blocks
+            .get_blocks()
+            .iter()
+            .map(|block| {
+                let num_outputs = self
+                    .ledger
+                    .get_block_contents(block.get_index())?
+                    .outputs
+                    .len() as u64;
+                let first_output_index = block.get_cumulative_txo_count() - num_outputs;
+                let indexes: Vec<u64> =
+                    (first_output_index..block.get_cumulative_txo_count()).collect();
+                let proofs = self.ledger.get_tx_out_proof_of_memberships(&indexes)?;
+
+                let mut block_with_proofs = BlockWithProofs::new();
+                block_with_proofs.set_block(block.clone());
+                block_with_proofs.set_output_proofs(RepeatedField::from_vec(
+                    proofs
+                        .iter()
+                        .map(external::TxOutMembershipProof::from)
+                        .collect(),
+                ));
+                Ok(block_with_proofs)
+            })
+            .collect::<Result<Vec<BlockWithProofs>, mc_ledger_db::Error>>()?;
+
+        let mut response = BlocksWithProofsResponse::new();
+        response.set_blocks_with_proofs(RepeatedField::from_vec(blocks_with_proofs));
+        Ok(response)
+    }
+
+    /// Looks up the block in which a key image was spent, if any.
+    fn check_key_image_helper(
+        &self,
+        request: &CheckKeyImageRequest,
+    ) -> Result<CheckKeyImageResponse, RpcStatus> {
+        let key_image = KeyImage::try_from(request.get_key_image()).map_err(|_| {
+            RpcStatus::new(
+                RpcStatusCode::INVALID_ARGUMENT,
+                Some("Invalid key image".to_string()),
+            )
+        })?;
+        let block_index = self
+            .ledger
+            .check_key_image(&key_image)
+            .map_err(|_| RpcStatus::new(RpcStatusCode::INTERNAL, None))?;
+
+        let mut response = CheckKeyImageResponse::new();
+        if let Some(block_index) = block_index {
+            response.set_spent(true);
+            response.set_block_index(block_index);
+        }
+        Ok(response)
+    }
 }
 
 impl<L: Ledger + Clone> BlockchainApi for BlockchainApiService<L> {
@@ -134,6 +302,24 @@ impl<L: Ledger + Clone> BlockchainApi for BlockchainApiService<L> {
         });
     }
 
+    /// Gets the optional APIs this node serves.
+    fn get_capabilities(
+        &mut self,
+        ctx: RpcContext,
+        _request: Empty,
+        sink: UnarySink<CapabilitiesResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+                return send_result(ctx, sink, err.into(), &logger);
+            }
+
+            send_result(ctx, sink, Ok(self.get_capabilities_helper()), &logger);
+        });
+    }
+
     /// Gets a range [offset, offset+limit) of Blocks.
     fn get_blocks(
         &mut self,
@@ -161,6 +347,109 @@ impl<L: Ledger + Clone> BlockchainApi for BlockchainApiService<L> {
             send_result(ctx, sink, resp, &logger);
         });
     }
+
+    /// Gets a range [offset, offset+limit) of Blocks, with their signatures.
+    fn get_signed_blocks(
+        &mut self,
+        ctx: RpcContext,
+        request: BlocksRequest,
+        sink: UnarySink<SignedBlocksResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+                return send_result(ctx, sink, err.into(), &logger);
+            }
+
+            log::trace!(
+                logger,
+                "Received signed BlocksRequest for offset {} and limit {})",
+                request.offset,
+                request.limit
+            );
+
+            let resp = self
+                .get_signed_blocks_helper(request.offset, request.limit)
+                .map_err(|_| RpcStatus::new(RpcStatusCode::INTERNAL, None));
+            send_result(ctx, sink, resp, &logger);
+        });
+    }
+
+    /// Gets a range [offset, offset+limit) of Blocks, with membership proofs
+    /// for their outputs.
+    fn get_blocks_with_proofs(
+        &mut self,
+        ctx: RpcContext,
+        request: BlocksRequest,
+        sink: UnarySink<BlocksWithProofsResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+                return send_result(ctx, sink, err.into(), &logger);
+            }
+
+            log::trace!(
+                logger,
+                "Received BlocksRequest with proofs for offset {} and limit {})",
+                request.offset,
+                request.limit
+            );
+
+            let resp = self
+                .get_blocks_with_proofs_helper(request.offset, request.limit)
+                .map_err(|_| RpcStatus::new(RpcStatusCode::INTERNAL, None));
+            send_result(ctx, sink, resp, &logger);
+        });
+    }
+
+    /// Gets a range [offset, offset+limit) of Blocks, compressed.
+    fn get_compressed_blocks(
+        &mut self,
+        ctx: RpcContext,
+        request: CompressedBlocksRequest,
+        sink: UnarySink<CompressedBlocksResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+                return send_result(ctx, sink, err.into(), &logger);
+            }
+
+            log::trace!(
+                logger,
+                "Received compressed BlocksRequest for offset {} and limit {})",
+                request.get_request().offset,
+                request.get_request().limit
+            );
+
+            let resp = self
+                .get_compressed_blocks_helper(&request)
+                .map_err(|_| RpcStatus::new(RpcStatusCode::INTERNAL, None));
+            send_result(ctx, sink, resp, &logger);
+        });
+    }
+
+    /// Looks up the block in which a key image was spent.
+    fn check_key_image(
+        &mut self,
+        ctx: RpcContext,
+        request: CheckKeyImageRequest,
+        sink: UnarySink<CheckKeyImageResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+                return send_result(ctx, sink, err.into(), &logger);
+            }
+
+            send_result(ctx, sink, self.check_key_image_helper(&request), &logger);
+        });
+    }
 }
 
 #[cfg(test)]
@@ -168,11 +457,21 @@ mod tests {
     use super::*;
     use grpcio::{ChannelBuilder, Environment, Error as GrpcError, Server, ServerBuilder};
     use mc_common::{logger::test_with_logger, time::SystemTimeProvider};
-    use mc_consensus_api::consensus_common_grpc::{self, BlockchainApiClient};
+    use mc_connection::decompress_blocks;
+    use mc_consensus_api::{
+        consensus_common::BlockCompression,
+        consensus_common_grpc::{self, BlockchainApiClient},
+    };
+    use mc_ledger_db::test_utils::get_mock_ledger;
+    use mc_transaction_core::{
+        membership_proofs::{compute_implied_merkle_root, is_membership_proof_valid},
+        tx::TxOutMembershipProof,
+    };
     use mc_transaction_core_test_utils::{create_ledger, initialize_ledger, AccountKey};
     use mc_util_grpc::{AnonymousAuthenticator, TokenAuthenticator};
     use rand::{rngs::StdRng, SeedableRng};
     use std::{
+        convert::TryFrom,
         sync::atomic::{AtomicUsize, Ordering::SeqCst},
         time::Duration,
     };
@@ -254,6 +553,187 @@ mod tests {
         }
     }
 
+    #[test_with_logger]
+    // `check_key_image` should report the block a key image was spent in, and
+    // that an unknown key image has not been spent.
+    fn test_check_key_image(logger: Logger) {
+        let mut ledger_db = create_ledger();
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let account_key = AccountKey::random(&mut rng);
+        let _ = initialize_ledger(&mut ledger_db, 3, &account_key, &mut rng);
+        let spent = ledger_db.get_key_images_by_block(2).unwrap().remove(0);
+
+        let blockchain_api_service =
+            BlockchainApiService::new(ledger_db, authenticator, logger, None);
+        let (client, _server) = get_client_server(blockchain_api_service);
+
+        let mut request = CheckKeyImageRequest::new();
+        request.set_key_image((&spent).into());
+        let response = client.check_key_image(&request).unwrap();
+        assert!(response.get_spent());
+        assert_eq!(response.get_block_index(), 2);
+
+        request.set_key_image((&KeyImage::from(7)).into());
+        let response = client.check_key_image(&request).unwrap();
+        assert!(!response.get_spent());
+    }
+
+    #[test_with_logger]
+    // `get_capabilities` should declare every optional API the node serves.
+    fn test_get_capabilities(logger: Logger) {
+        let ledger_db = create_ledger();
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let blockchain_api_service =
+            BlockchainApiService::new(ledger_db, authenticator, logger, None);
+
+        let (client, _server) = get_client_server(blockchain_api_service);
+
+        let response = client.get_capabilities(&Empty::default()).unwrap();
+        assert_eq!(
+            PeerCapabilities::from_bits(response.flags),
+            Some(PeerCapabilities::all())
+        );
+    }
+
+    #[test_with_logger]
+    // `get_signed_blocks` should report a ledger error to the client, rather
+    // than returning a truncated range of blocks.
+    fn test_get_signed_blocks_reports_ledger_errors(logger: Logger) {
+        let ledger = get_mock_ledger(5);
+        ledger.lock().get_block_error = Some(mc_ledger_db::Error::Deserialization);
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let blockchain_api_service = BlockchainApiService::new(ledger, authenticator, logger, None);
+
+        let (client, _server) = get_client_server(blockchain_api_service);
+
+        let mut request = BlocksRequest::new();
+        request.set_offset(0);
+        request.set_limit(5);
+        match client.get_signed_blocks(&request) {
+            Err(GrpcError::RpcFailure(rpc_status)) => {
+                assert_eq!(rpc_status.status, RpcStatusCode::INTERNAL);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_with_logger]
+    // `get_compressed_blocks` should return the same blocks as `get_blocks`,
+    // compressed as the client prefers.
+    fn test_get_compressed_blocks(logger: Logger) {
+        let mut ledger_db = create_ledger();
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let account_key = AccountKey::random(&mut rng);
+        initialize_ledger(&mut ledger_db, 10, &account_key, &mut rng);
+
+        let mut blockchain_api_service =
+            BlockchainApiService::new(ledger_db, authenticator, logger, None);
+        let expected = blockchain_api_service.get_blocks_helper(2, 5).unwrap();
+
+        for accepted in &[
+            vec![BlockCompression::Zstd, BlockCompression::Gzip],
+            vec![BlockCompression::Gzip],
+            vec![],
+        ] {
+            let mut blocks_request = BlocksRequest::new();
+            blocks_request.set_offset(2);
+            blocks_request.set_limit(5);
+            let mut request = CompressedBlocksRequest::new();
+            request.set_request(blocks_request);
+            request.set_accepted(accepted.clone());
+
+            let response = blockchain_api_service
+                .get_compressed_blocks_helper(&request)
+                .unwrap();
+            assert_eq!(
+                response.get_compression(),
+                accepted
+                    .first()
+                    .copied()
+                    .unwrap_or(BlockCompression::Uncompressed)
+            );
+            let blocks =
+                decompress_blocks(response.get_compression(), response.get_blocks()).unwrap();
+            assert_eq!(blocks, expected);
+        }
+    }
+
+    #[test_with_logger]
+    // `get_blocks_with_proofs` should return a valid proof for every output.
+    fn test_get_blocks_with_proofs(logger: Logger) {
+        let mut ledger_db = create_ledger();
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let account_key = AccountKey::random(&mut rng);
+        initialize_ledger(&mut ledger_db, 10, &account_key, &mut rng);
+
+        // Every proof is computed against the same ledger, so should imply the same
+        // root as a proof of the first output.
+        let root_element = compute_implied_merkle_root(
+            &ledger_db.get_tx_out_proof_of_memberships(&[0]).unwrap()[0],
+        )
+        .unwrap();
+
+        let mut blockchain_api_service =
+            BlockchainApiService::new(ledger_db.clone(), authenticator, logger, None);
+
+        let response = blockchain_api_service
+            .get_blocks_with_proofs_helper(0, 10)
+            .unwrap();
+        assert_eq!(response.blocks_with_proofs.len(), 10);
+
+        for block_with_proofs in response.blocks_with_proofs.iter() {
+            let block_index = block_with_proofs.get_block().get_index();
+            let outputs = ledger_db.get_block_contents(block_index).unwrap().outputs;
+            assert_eq!(block_with_proofs.output_proofs.len(), outputs.len());
+
+            for (tx_out, proof) in outputs.iter().zip(block_with_proofs.output_proofs.iter()) {
+                let proof = TxOutMembershipProof::try_from(proof).unwrap();
+                assert!(
+                    is_membership_proof_valid(tx_out, &proof, root_element.hash.as_ref()).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test_with_logger]
+    // `get_compressed_blocks` should report a ledger error, rather than
+    // returning a truncated range of blocks.
+    fn test_get_compressed_blocks_reports_ledger_errors(logger: Logger) {
+        let ledger = get_mock_ledger(5);
+        ledger.lock().get_block_error = Some(mc_ledger_db::Error::Deserialization);
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let mut blockchain_api_service =
+            BlockchainApiService::new(ledger, authenticator, logger, None);
+
+        let mut blocks_request = BlocksRequest::new();
+        blocks_request.set_limit(5);
+        let mut request = CompressedBlocksRequest::new();
+        request.set_request(blocks_request);
+        match blockchain_api_service.get_compressed_blocks_helper(&request) {
+            Err(mc_connection::Error::Other(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_with_logger]
+    // `get_blocks_with_proofs` should report a ledger error, rather than
+    // returning a truncated range of blocks.
+    fn test_get_blocks_with_proofs_reports_ledger_errors(logger: Logger) {
+        let ledger = get_mock_ledger(5);
+        ledger.lock().get_block_error = Some(mc_ledger_db::Error::Deserialization);
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let mut blockchain_api_service =
+            BlockchainApiService::new(ledger, authenticator, logger, None);
+
+        assert_eq!(
+            blockchain_api_service.get_blocks_with_proofs_helper(0, 5),
+            Err(mc_ledger_db::Error::Deserialization)
+        );
+    }
+
     #[test_with_logger]
     // `get_blocks` should returns the correct range of blocks.
     fn test_get_blocks_response_range(logger: Logger) {
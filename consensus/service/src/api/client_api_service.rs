@@ -6,35 +6,54 @@ use crate::{
     api::grpc_error::ConsensusGrpcError,
     consensus_service::ProposeTxCallback,
     counters,
+    fee_estimator::FeeEstimator,
     tx_manager::{TxManager, TxManagerError},
 };
-use grpcio::{RpcContext, RpcStatus, UnarySink};
+use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
 use mc_attest_api::attest::Message;
-use mc_common::logger::Logger;
+use mc_common::{logger::Logger, LruCache};
 use mc_consensus_api::{
+    consensus_client::{FeeEstimateRequest, FeeEstimateResponse, PendingTxHashesResponse},
     consensus_client_grpc::ConsensusClientApi,
     consensus_common::{ProposeTxResponse, ProposeTxResult},
+    empty::Empty,
+    IDEMPOTENCY_KEY_HEADER,
 };
 use mc_consensus_enclave::ConsensusEnclave;
 use mc_ledger_db::Ledger;
+use mc_transaction_core::tx::TxHash;
 use mc_util_grpc::{rpc_logger, send_result, Authenticator};
 use mc_util_metrics::{self, SVC_COUNTERS};
-use std::sync::Arc;
+use protobuf::RepeatedField;
+use std::sync::{Arc, Mutex};
 
 /// Maximum number of pending values for consensus service before rejecting
 /// add_transaction requests.
 const PENDING_LIMIT: i64 = 500;
 
+/// Maximum number of idempotency keys whose responses are remembered.
+const IDEMPOTENT_RESPONSES_CACHE_SIZE: usize = 10_000;
+
 #[derive(Clone)]
 pub struct ClientApiService {
     enclave: Arc<dyn ConsensusEnclave + Send + Sync>,
     tx_manager: Arc<dyn TxManager + Send + Sync>,
     ledger: Arc<dyn Ledger + Send + Sync>,
+    /// Estimates the fee needed for inclusion from recently externalized
+    /// blocks.
+    fee_estimator: Arc<Mutex<FeeEstimator>>,
     /// Passes proposed transactions to the consensus service.
     propose_tx_callback: ProposeTxCallback,
     /// Returns true if this node is able to process proposed transactions.
     is_serving_fn: Arc<(dyn Fn() -> bool + Sync + Send)>,
+    /// Returns the hashes of the transactions this node has yet to get into a
+    /// block.
+    pending_tx_hashes_fn: Arc<(dyn Fn() -> Vec<TxHash> + Sync + Send)>,
     authenticator: Arc<dyn Authenticator + Send + Sync>,
+    /// The hash of each accepted transaction and the response sent for it,
+    /// keyed by the client's idempotency key, so that a retried submission
+    /// gets the original response.
+    idempotent_responses: Arc<Mutex<LruCache<Vec<u8>, (TxHash, ProposeTxResponse)>>>,
     logger: Logger,
 }
 
@@ -44,7 +63,9 @@ impl ClientApiService {
         scp_client_value_sender: ProposeTxCallback,
         ledger: Arc<dyn Ledger + Send + Sync>,
         tx_manager: Arc<dyn TxManager + Send + Sync>,
+        fee_estimator: Arc<Mutex<FeeEstimator>>,
         is_serving_fn: Arc<(dyn Fn() -> bool + Sync + Send)>,
+        pending_tx_hashes_fn: Arc<(dyn Fn() -> Vec<TxHash> + Sync + Send)>,
         authenticator: Arc<dyn Authenticator + Send + Sync>,
         logger: Logger,
     ) -> Self {
@@ -52,22 +73,62 @@ impl ClientApiService {
             enclave,
             tx_manager,
             ledger,
+            fee_estimator,
             propose_tx_callback: scp_client_value_sender,
             is_serving_fn,
+            pending_tx_hashes_fn,
             authenticator,
+            idempotent_responses: Arc::new(Mutex::new(LruCache::new(
+                IDEMPOTENT_RESPONSES_CACHE_SIZE,
+            ))),
             logger,
         }
     }
 
+    /// The hash of the transaction previously accepted with this idempotency
+    /// key, and the response sent for it, if any.
+    fn idempotent_response(&self, idempotency_key: &[u8]) -> Option<(TxHash, ProposeTxResponse)> {
+        self.idempotent_responses
+            .lock()
+            .expect("Lock poisoned")
+            .get(&idempotency_key.to_vec())
+            .cloned()
+    }
+
+    /// Handles a submission carrying an idempotency key which was already used
+    /// to submit the transaction `tx_hash`.
+    ///
+    /// The message is still decrypted, both to keep the enclave in sync with
+    /// the client's channel and to check that it holds the same transaction.
+    /// A different transaction reusing the key is rejected, rather than being
+    /// dropped while the client is told it was accepted.
+    fn handle_retried_tx(
+        &mut self,
+        msg: Message,
+        tx_hash: TxHash,
+        response: ProposeTxResponse,
+    ) -> Result<ProposeTxResponse, RpcStatus> {
+        match self.enclave.client_tx_propose(msg.into()) {
+            Ok(tx_context) if tx_context.tx_hash == tx_hash => Ok(response),
+            Ok(_) => Err(RpcStatus::new(
+                RpcStatusCode::INVALID_ARGUMENT,
+                Some("Idempotency key was already used for a different transaction".into()),
+            )),
+            Err(e) => ConsensusGrpcError::from(e).into(),
+        }
+    }
+
     /// Handles a client's proposed transaction.
     ///
     /// # Arguments
     /// `msg` - An encrypted message from a client to the enclave.
     /// `logger` - Logger
+    ///
+    /// Returns the hash of the accepted transaction, along with the response.
     fn handle_proposed_tx(
         &mut self,
         msg: Message,
-    ) -> Result<ProposeTxResponse, ConsensusGrpcError> {
+    ) -> Result<(TxHash, ProposeTxResponse), ConsensusGrpcError> {
         counters::ADD_TX_INITIATED.inc();
         let tx_context = self.enclave.client_tx_propose(msg.into())?;
         let mut response = ProposeTxResponse::new();
@@ -76,8 +137,7 @@ impl ClientApiService {
         let tx_hash = self.tx_manager.insert(tx_context).map_err(|err| {
             if let TxManagerError::TransactionValidation(cause) = &err {
                 counters::TX_VALIDATION_ERROR_COUNTER.inc(&format!("{:?}", cause));
-                let result = ProposeTxResult::from(cause.clone());
-                response.set_result(result);
+                response = ProposeTxResponse::from(cause.clone());
             }
             err
         })?;
@@ -90,7 +150,7 @@ impl ClientApiService {
         // The transaction can be considered by the network.
         (*self.propose_tx_callback)(tx_hash, None, None);
         counters::ADD_TX.inc();
-        Ok(response)
+        Ok((tx_hash, response))
     }
 }
 
@@ -107,6 +167,25 @@ impl ConsensusClientApi for ClientApiService {
             return send_result(ctx, sink, err.into(), &self.logger);
         }
 
+        let idempotency_key: Option<Vec<u8>> = ctx
+            .request_headers()
+            .iter()
+            .find(|(key, _value)| *key == IDEMPOTENCY_KEY_HEADER)
+            .map(|(_key, value)| value.to_vec());
+
+        if let Some((tx_hash, response)) = idempotency_key
+            .as_ref()
+            .and_then(|key| self.idempotent_response(key))
+        {
+            // This is a retry of a submission we already accepted.
+            let result = self.handle_retried_tx(msg, tx_hash, response);
+            return mc_common::logger::scoped_global_logger(
+                &rpc_logger(&ctx, &self.logger),
+                |logger| send_result(ctx, sink, result, &logger),
+            );
+        }
+
+        let mut accepted_tx_hash = None;
         let mut result: Result<ProposeTxResponse, RpcStatus> =
             if counters::CUR_NUM_PENDING_VALUES.get() >= PENDING_LIMIT {
                 // This node is over capacity, and is not accepting proposed transaction.
@@ -124,6 +203,10 @@ impl ConsensusClientApi for ClientApiService {
                 }
             } else {
                 self.handle_proposed_tx(msg)
+                    .map(|(tx_hash, response)| {
+                        accepted_tx_hash = Some(tx_hash);
+                        response
+                    })
                     .or_else(ConsensusGrpcError::into)
             };
 
@@ -133,10 +216,72 @@ impl ConsensusClientApi for ClientApiService {
             Ok(response)
         });
 
+        if let (Some(key), Some(tx_hash), Ok(response)) =
+            (idempotency_key, accepted_tx_hash, result.as_ref())
+        {
+            if response.get_result() == ProposeTxResult::Ok {
+                self.idempotent_responses
+                    .lock()
+                    .expect("Lock poisoned")
+                    .put(key, (tx_hash, response.clone()));
+            }
+        }
+
         mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
             send_result(ctx, sink, result, &logger)
         });
     }
+
+    fn get_pending_tx_hashes(
+        &mut self,
+        ctx: RpcContext,
+        _request: Empty,
+        sink: UnarySink<PendingTxHashesResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+            return send_result(ctx, sink, err.into(), &self.logger);
+        }
+
+        let tx_hashes: Vec<Vec<u8>> = (self.pending_tx_hashes_fn)()
+            .iter()
+            .map(|tx_hash| tx_hash.to_vec())
+            .collect();
+
+        let mut response = PendingTxHashesResponse::new();
+        response.set_tx_hashes(RepeatedField::from_vec(tx_hashes));
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            send_result(ctx, sink, Ok(response), &logger)
+        });
+    }
+
+    fn get_fee_estimate(
+        &mut self,
+        ctx: RpcContext,
+        request: FeeEstimateRequest,
+        sink: UnarySink<FeeEstimateResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+            return send_result(ctx, sink, err.into(), &self.logger);
+        }
+
+        let fee = self
+            .fee_estimator
+            .lock()
+            .expect("Lock poisoned")
+            .estimate_fee(request.get_target_blocks());
+
+        let mut response = FeeEstimateResponse::new();
+        response.set_fee(fee);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            send_result(ctx, sink, Ok(response), &logger)
+        });
+    }
 }
 
 #[cfg(test)]
@@ -144,10 +289,14 @@ mod client_api_tests {
     use crate::{
         api::client_api_service::{ClientApiService, PENDING_LIMIT},
         counters,
+        fee_estimator::{
+            FeeEstimator, DEFAULT_FEE_ESTIMATE_CONFIDENCE, DEFAULT_FEE_ESTIMATOR_DECAY,
+        },
         tx_manager::{MockTxManager, TxManagerError},
     };
     use grpcio::{
-        ChannelBuilder, Environment, Error as GrpcError, RpcStatusCode, Server, ServerBuilder,
+        CallOption, ChannelBuilder, Environment, Error as GrpcError, MetadataBuilder,
+        RpcStatusCode, Server, ServerBuilder,
     };
     use mc_attest_api::attest::Message;
     use mc_common::{
@@ -156,18 +305,31 @@ mod client_api_tests {
         NodeID, ResponderId,
     };
     use mc_consensus_api::{
-        consensus_client_grpc, consensus_client_grpc::ConsensusClientApiClient,
-        consensus_common::ProposeTxResult,
+        consensus_client::FeeEstimateRequest, consensus_client_grpc,
+        consensus_client_grpc::ConsensusClientApiClient, consensus_common::ProposeTxResult,
+        empty::Empty, IDEMPOTENCY_KEY_HEADER,
     };
-    use mc_consensus_enclave::TxContext;
+    use mc_consensus_enclave::{TxContext, WellFormedTxContext};
     use mc_consensus_enclave_mock::MockConsensusEnclave;
     use mc_ledger_db::MockLedger;
     use mc_transaction_core::{
-        ring_signature::KeyImage, tx::TxHash, validation::TransactionValidationError,
+        constants::MINIMUM_FEE, ring_signature::KeyImage, tx::TxHash,
+        validation::TransactionValidationError,
     };
     use mc_util_grpc::{AnonymousAuthenticator, TokenAuthenticator};
     use serial_test_derive::serial;
-    use std::{sync::Arc, time::Duration};
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    fn get_fee_estimator() -> Arc<Mutex<FeeEstimator>> {
+        Arc::new(Mutex::new(FeeEstimator::new(
+            MINIMUM_FEE,
+            DEFAULT_FEE_ESTIMATOR_DECAY,
+            DEFAULT_FEE_ESTIMATE_CONFIDENCE,
+        )))
+    }
 
     /// Starts the service on localhost and connects a client to it.
     fn get_client_server(instance: ClientApiService) -> (ConsensusClientApiClient, Server) {
@@ -231,7 +393,9 @@ mod client_api_tests {
             scp_client_value_sender,
             Arc::new(ledger),
             Arc::new(tx_manager),
+            get_fee_estimator(),
             is_serving_fn,
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
             Arc::new(authenticator),
             logger,
         );
@@ -299,7 +463,9 @@ mod client_api_tests {
             scp_client_value_sender,
             Arc::new(ledger),
             Arc::new(tx_manager),
+            get_fee_estimator(),
             is_serving_fn,
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
             Arc::new(authenticator),
             logger,
         );
@@ -361,7 +527,9 @@ mod client_api_tests {
             scp_client_value_sender,
             Arc::new(ledger),
             Arc::new(tx_manager),
+            get_fee_estimator(),
             is_serving_fn,
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
             Arc::new(authenticator),
             logger,
         );
@@ -405,7 +573,9 @@ mod client_api_tests {
             scp_client_value_sender,
             Arc::new(MockLedger::new()),
             Arc::new(MockTxManager::new()),
+            get_fee_estimator(),
             is_serving_fn,
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
             Arc::new(authenticator),
             logger,
         );
@@ -451,7 +621,9 @@ mod client_api_tests {
             scp_client_value_sender,
             Arc::new(MockLedger::new()),
             Arc::new(MockTxManager::new()),
+            get_fee_estimator(),
             is_serving_fn,
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
             Arc::new(authenticator),
             logger,
         );
@@ -501,7 +673,9 @@ mod client_api_tests {
             scp_client_value_sender,
             Arc::new(MockLedger::new()),
             Arc::new(MockTxManager::new()),
+            get_fee_estimator(),
             is_serving_fn,
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
             Arc::new(authenticator),
             logger,
         );
@@ -522,4 +696,261 @@ mod client_api_tests {
             }
         };
     }
+
+    #[test_with_logger]
+    #[serial(counters)]
+    // A retried submission carrying the same idempotency key should get the
+    // original response, rather than being re-validated and rejected as a
+    // duplicate.
+    fn test_client_tx_propose_idempotent_retry(logger: Logger) {
+        let mut consensus_enclave = MockConsensusEnclave::new();
+        {
+            let mut tx_context = TxContext::default();
+            tx_context.key_images = vec![KeyImage::default(), KeyImage::default()];
+
+            // Both submissions are decrypted, so that the retry can be checked
+            // against the original.
+            consensus_enclave
+                .expect_client_tx_propose()
+                .times(2)
+                .return_const(Ok(tx_context));
+        }
+
+        let scp_client_value_sender = Arc::new(
+            |_tx_hash: TxHash, _node_id: Option<&NodeID>, _responder_id: Option<&ResponderId>| {},
+        );
+
+        let num_blocks = 5;
+        let mut ledger = MockLedger::new();
+        ledger
+            .expect_num_blocks()
+            .times(1)
+            .return_const(Ok(num_blocks));
+
+        // Only the first submission reaches the tx manager. Re-validating the retry
+        // would report its key images as spent.
+        let mut tx_manager = MockTxManager::new();
+        tx_manager
+            .expect_insert()
+            .times(1)
+            .return_const(Ok(TxHash::default()));
+        tx_manager.expect_validate().times(1).return_const(Ok(()));
+
+        let is_serving_fn = Arc::new(|| -> bool { true });
+
+        let authenticator = AnonymousAuthenticator::default();
+
+        let instance = ClientApiService::new(
+            Arc::new(consensus_enclave),
+            scp_client_value_sender,
+            Arc::new(ledger),
+            Arc::new(tx_manager),
+            get_fee_estimator(),
+            is_serving_fn,
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
+            Arc::new(authenticator),
+            logger,
+        );
+
+        // gRPC client and server.
+        let (client, _server) = get_client_server(instance);
+
+        let call_option = || {
+            let mut metadata_builder = MetadataBuilder::new();
+            metadata_builder
+                .add_bytes(IDEMPOTENCY_KEY_HEADER, &[7u8; 16])
+                .unwrap();
+            CallOption::default().headers(metadata_builder.build())
+        };
+
+        let message = Message::default();
+        for _attempt in 0..2 {
+            match client.client_tx_propose_opt(&message, call_option()) {
+                Ok(propose_tx_response) => {
+                    assert_eq!(propose_tx_response.get_result(), ProposeTxResult::Ok);
+                    assert_eq!(propose_tx_response.get_block_count(), num_blocks);
+                }
+                Err(e) => panic!("Unexpected error: {:?}", e),
+            }
+        }
+    }
+
+    #[test_with_logger]
+    #[serial(counters)]
+    // A different transaction submitted with an idempotency key which was
+    // already used should be rejected, rather than reported as accepted.
+    fn test_client_tx_propose_rejects_reused_idempotency_key(logger: Logger) {
+        let mut consensus_enclave = MockConsensusEnclave::new();
+        {
+            let first = TxContext::default();
+            let mut second = TxContext::default();
+            second.tx_hash = TxHash([1u8; 32]);
+
+            let mut contexts = vec![first, second].into_iter();
+            consensus_enclave
+                .expect_client_tx_propose()
+                .times(2)
+                .returning(move |_msg| Ok(contexts.next().unwrap()));
+        }
+
+        let scp_client_value_sender = Arc::new(
+            |_tx_hash: TxHash, _node_id: Option<&NodeID>, _responder_id: Option<&ResponderId>| {},
+        );
+
+        let mut ledger = MockLedger::new();
+        ledger.expect_num_blocks().times(1).return_const(Ok(5));
+
+        // Only the first transaction is accepted.
+        let mut tx_manager = MockTxManager::new();
+        tx_manager
+            .expect_insert()
+            .times(1)
+            .return_const(Ok(TxHash::default()));
+        tx_manager.expect_validate().times(1).return_const(Ok(()));
+
+        let instance = ClientApiService::new(
+            Arc::new(consensus_enclave),
+            scp_client_value_sender,
+            Arc::new(ledger),
+            Arc::new(tx_manager),
+            get_fee_estimator(),
+            Arc::new(|| -> bool { true }),
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
+            Arc::new(AnonymousAuthenticator::default()),
+            logger,
+        );
+
+        // gRPC client and server.
+        let (client, _server) = get_client_server(instance);
+
+        let call_option = || {
+            let mut metadata_builder = MetadataBuilder::new();
+            metadata_builder
+                .add_bytes(IDEMPOTENCY_KEY_HEADER, &[7u8; 16])
+                .unwrap();
+            CallOption::default().headers(metadata_builder.build())
+        };
+
+        let message = Message::default();
+        match client.client_tx_propose_opt(&message, call_option()) {
+            Ok(propose_tx_response) => {
+                assert_eq!(propose_tx_response.get_result(), ProposeTxResult::Ok)
+            }
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+        match client.client_tx_propose_opt(&message, call_option()) {
+            Err(GrpcError::RpcFailure(rpc_status)) => {
+                assert_eq!(rpc_status.status, RpcStatusCode::INVALID_ARGUMENT)
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_with_logger]
+    // The fee estimate should reflect the blocks recorded in the estimator.
+    fn test_get_fee_estimate(logger: Logger) {
+        let fee_estimator = get_fee_estimator();
+        let instance = ClientApiService::new(
+            Arc::new(MockConsensusEnclave::new()),
+            Arc::new(
+                |_tx_hash: TxHash,
+                 _node_id: Option<&NodeID>,
+                 _responder_id: Option<&ResponderId>| {},
+            ),
+            Arc::new(MockLedger::new()),
+            Arc::new(MockTxManager::new()),
+            fee_estimator.clone(),
+            Arc::new(|| -> bool { true }),
+            Arc::new(|| -> Vec<TxHash> { vec![] }),
+            Arc::new(AnonymousAuthenticator::default()),
+            logger,
+        );
+
+        // gRPC client and server.
+        let (client, _server) = get_client_server(instance);
+        let mut request = FeeEstimateRequest::new();
+        request.set_target_blocks(1);
+
+        // Without history, the estimate is the minimum fee.
+        match client.get_fee_estimate(&request) {
+            Ok(response) => assert_eq!(response.get_fee(), MINIMUM_FEE),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        // Blocks which only include transactions paying ten times the minimum
+        // fee should raise the estimate.
+        for id in 0..20u8 {
+            let candidates: Vec<_> = [(1u8, MINIMUM_FEE * 10), (2u8, MINIMUM_FEE)]
+                .iter()
+                .map(|(offset, fee)| {
+                    Arc::new(WellFormedTxContext::new(
+                        *fee,
+                        TxHash([id.wrapping_mul(2).wrapping_add(*offset); 32]),
+                        100,
+                        vec![],
+                        vec![],
+                        vec![],
+                    ))
+                })
+                .collect();
+            let included = vec![candidates[0].tx_hash().clone()];
+            fee_estimator
+                .lock()
+                .unwrap()
+                .record_block(&candidates, &included);
+        }
+
+        match client.get_fee_estimate(&request) {
+            Ok(response) => {
+                assert!(response.get_fee() > MINIMUM_FEE, "{}", response.get_fee());
+                assert!(
+                    response.get_fee() <= MINIMUM_FEE * 10,
+                    "{}",
+                    response.get_fee()
+                );
+            }
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test_with_logger]
+    // Should return the hashes of the pending values, and only those.
+    fn test_get_pending_tx_hashes(logger: Logger) {
+        let pending_tx_hashes: Vec<TxHash> = (0..3).map(|i| TxHash([i as u8; 32])).collect();
+
+        // The tx manager's cache is not consulted.
+        let tx_manager = MockTxManager::new();
+
+        let instance = ClientApiService::new(
+            Arc::new(MockConsensusEnclave::new()),
+            Arc::new(
+                |_tx_hash: TxHash,
+                 _node_id: Option<&NodeID>,
+                 _responder_id: Option<&ResponderId>| {},
+            ),
+            Arc::new(MockLedger::new()),
+            Arc::new(tx_manager),
+            get_fee_estimator(),
+            Arc::new(|| -> bool { true }),
+            {
+                let pending_tx_hashes = pending_tx_hashes.clone();
+                Arc::new(move || pending_tx_hashes.clone())
+            },
+            Arc::new(AnonymousAuthenticator::default()),
+            logger,
+        );
+
+        // gRPC client and server.
+        let (client, _server) = get_client_server(instance);
+        match client.get_pending_tx_hashes(&Empty::new()) {
+            Ok(response) => {
+                let tx_hashes: Vec<Vec<u8>> = pending_tx_hashes
+                    .iter()
+                    .map(|tx_hash| tx_hash.to_vec())
+                    .collect();
+                assert_eq!(response.get_tx_hashes(), &tx_hashes[..]);
+            }
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
 }
@@ -4,7 +4,7 @@ use crate::tx_manager::TxManagerError;
 use displaydoc::Display;
 use grpcio::{RpcStatus, RpcStatusCode};
 use mc_common::logger::global_log;
-use mc_consensus_api::consensus_common::{ProposeTxResponse, ProposeTxResult};
+use mc_consensus_api::consensus_common::ProposeTxResponse;
 use mc_consensus_enclave::Error as EnclaveError;
 use mc_ledger_db::Error as LedgerError;
 use mc_transaction_core::validation::TransactionValidationError;
@@ -118,11 +118,7 @@ impl From<ConsensusGrpcError> for RpcStatus {
 impl From<ConsensusGrpcError> for Result<ProposeTxResponse, RpcStatus> {
     fn from(src: ConsensusGrpcError) -> Result<ProposeTxResponse, RpcStatus> {
         match src {
-            ConsensusGrpcError::TransactionValidation(err) => {
-                let mut resp = ProposeTxResponse::new();
-                resp.set_result(ProposeTxResult::from(err));
-                Ok(resp)
-            }
+            ConsensusGrpcError::TransactionValidation(err) => Ok(ProposeTxResponse::from(err)),
             _ => Err(RpcStatus::from(src)),
         }
     }
@@ -147,11 +147,15 @@ impl PeerApiService {
             PeerServiceError::InternalError
         })?;
 
-        // Handle each transaction.
-        for tx_context in tx_contexts {
-            let tx_hash = tx_context.tx_hash;
-
-            match self.tx_manager.insert(tx_context) {
+        // Handle each transaction. The batch is checked against a single view
+        // of the ledger.
+        let tx_hashes: Vec<TxHash> = tx_contexts
+            .iter()
+            .map(|tx_context| tx_context.tx_hash)
+            .collect();
+        let results = self.tx_manager.insert_batch(tx_contexts);
+        for (tx_hash, result) in tx_hashes.into_iter().zip(results) {
+            match result {
                 Ok(tx_hash) => {
                     // Submit for consideration in next SCP slot.
                     (*self.scp_client_value_sender)(
@@ -90,7 +90,9 @@ fn main() -> Result<(), ConsensusServiceError> {
 
     let tx_manager = TxManagerImpl::new(
         enclave.clone(),
-        DefaultTxManagerUntrustedInterfaces::new(local_ledger.clone()),
+        DefaultTxManagerUntrustedInterfaces::new(local_ledger.clone())
+            .with_logger(logger.clone())
+            .with_min_combine_fee(config.network().min_combine_fee),
         logger.clone(),
     );
 
@@ -13,6 +13,7 @@ mod worker;
 use crate::{
     byzantine_ledger::{task_message::TaskMessage, worker::ByzantineLedgerWorker},
     counters,
+    fee_estimator::FeeEstimator,
     tx_manager::TxManager,
 };
 use mc_common::{logger::Logger, NodeID, ResponderId};
@@ -60,6 +61,9 @@ pub struct ByzantineLedger {
 
     // Highest consensus message issued by this node. (Set by the worker)
     highest_issued_msg: Arc<Mutex<Option<ConsensusMsg>>>,
+
+    // Values this node has yet to get into a block. (Set by the worker)
+    pending_tx_hashes: Arc<Mutex<Vec<TxHash>>>,
 }
 
 impl ByzantineLedger {
@@ -72,6 +76,7 @@ impl ByzantineLedger {
     /// * `ledger` - The local node's ledger.
     /// * `tx_manager` - TxManager
     /// * `broadcaster` - Broadcaster
+    /// * `fee_estimator` - Records each block this node externalizes.
     /// * `msg_signer_key` - Signs consensus messages issued by this node.
     /// * `tx_source_urls` - Source URLs for fetching block contents.
     /// * `scp_debug_dir` - If Some, debugging info will be written in this
@@ -88,6 +93,7 @@ impl ByzantineLedger {
         ledger: L,
         tx_manager: Arc<TXM>,
         broadcaster: Arc<Mutex<dyn Broadcast>>,
+        fee_estimator: Arc<Mutex<FeeEstimator>>,
         msg_signer_key: Arc<Ed25519Pair>,
         tx_source_urls: Vec<String>,
         scp_debug_dir: Option<PathBuf>,
@@ -124,6 +130,7 @@ impl ByzantineLedger {
         let is_behind = Arc::new(AtomicBool::new(false));
         let highest_peer_block = Arc::new(AtomicU64::new(0));
         let highest_issued_msg = Arc::new(Mutex::new(Option::<ConsensusMsg>::None));
+        let pending_tx_hashes = Arc::new(Mutex::new(Vec::<TxHash>::new()));
 
         // Start worker thread
         let worker_handle = {
@@ -146,6 +153,8 @@ impl ByzantineLedger {
                 is_behind.clone(),
                 highest_peer_block.clone(),
                 highest_issued_msg.clone(),
+                pending_tx_hashes.clone(),
+                fee_estimator,
                 logger,
             );
 
@@ -168,6 +177,7 @@ impl ByzantineLedger {
             is_behind,
             highest_peer_block,
             highest_issued_msg,
+            pending_tx_hashes,
         }
     }
 
@@ -213,6 +223,14 @@ impl ByzantineLedger {
             .clone()
     }
 
+    /// Get the hashes of the values this node has yet to get into a block.
+    pub fn pending_tx_hashes(&self) -> Vec<TxHash> {
+        self.pending_tx_hashes
+            .lock()
+            .expect("mutex poisoned")
+            .clone()
+    }
+
     /// Get the highest block agreed upon by peers.
     pub fn highest_peer_block(&self) -> u64 {
         self.highest_peer_block.load(Ordering::SeqCst)
@@ -229,6 +247,7 @@ impl Drop for ByzantineLedger {
 mod tests {
     use super::*;
     use crate::{
+        fee_estimator::{DEFAULT_FEE_ESTIMATE_CONFIDENCE, DEFAULT_FEE_ESTIMATOR_DECAY},
         tx_manager::{MockTxManager, TxManagerImpl},
         validators::DefaultTxManagerUntrustedInterfaces,
     };
@@ -375,6 +394,11 @@ mod tests {
             ledger.clone(),
             tx_manager.clone(),
             broadcaster,
+            Arc::new(Mutex::new(FeeEstimator::new(
+                1,
+                DEFAULT_FEE_ESTIMATOR_DECAY,
+                DEFAULT_FEE_ESTIMATE_CONFIDENCE,
+            ))),
             msg_signer_key.clone(),
             Vec::new(),
             None,
@@ -448,6 +472,12 @@ mod tests {
             logger.clone(),
         ));
 
+        let fee_estimator = Arc::new(Mutex::new(FeeEstimator::new(
+            1,
+            DEFAULT_FEE_ESTIMATOR_DECAY,
+            DEFAULT_FEE_ESTIMATE_CONFIDENCE,
+        )));
+
         let byzantine_ledger = ByzantineLedger::new(
             local_node_id.clone(),
             local_quorum_set.clone(),
@@ -455,6 +485,7 @@ mod tests {
             ledger.clone(),
             tx_manager.clone(),
             broadcaster,
+            fee_estimator.clone(),
             local_signer_key.clone(),
             Vec::new(),
             None,
@@ -695,6 +726,10 @@ mod tests {
 
         let signature_verification_result = signature.verify(&block);
         assert!(signature_verification_result.is_ok());
+
+        // The block included every transaction waiting for it, so the fee
+        // estimator should now recommend their fee rather than its minimum.
+        assert!(fee_estimator.lock().unwrap().estimate_fee(1) > 1);
     }
 
     #[test]
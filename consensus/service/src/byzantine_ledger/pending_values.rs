@@ -5,7 +5,7 @@
 use crate::tx_manager::TxManager;
 use mc_transaction_core::tx::TxHash;
 use std::{
-    collections::{hash_map::Entry::Vacant, HashMap},
+    collections::{hash_map::Entry::Vacant, HashMap, HashSet},
     sync::Arc,
     time::Instant,
 };
@@ -107,10 +107,21 @@ impl<TXM: TxManager> PendingValues<TXM> {
         assert_eq!(self.pending_values_map.len(), self.pending_values.len());
     }
 
-    /// Clear any pending values that are no longer valid.
+    /// Clear any pending values that are no longer valid. The values are
+    /// validated as one batch, against a single view of the ledger.
     pub fn clear_invalid_values(&mut self) {
-        let tx_manager = self.tx_manager.clone();
-        self.retain(|tx_hash| tx_manager.validate(tx_hash).is_ok());
+        if self.pending_values.is_empty() {
+            return;
+        }
+
+        let invalid: HashSet<TxHash> = self
+            .pending_values
+            .iter()
+            .zip(self.tx_manager.validate_batch(&self.pending_values))
+            .filter(|(_tx_hash, result)| result.is_err())
+            .map(|(tx_hash, _result)| *tx_hash)
+            .collect();
+        self.retain(|tx_hash| !invalid.contains(tx_hash));
     }
 }
 
@@ -201,22 +212,22 @@ mod tests {
         // A few test values.
         let values = vec![TxHash([1u8; 32]), TxHash([2u8; 32]), TxHash([3u8; 32])];
 
-        // `validate` should be called one for each pending value.
-        tx_manager
-            .expect_validate()
-            .with(eq(values[0].clone()))
-            .return_const(Ok(()));
-        // This transaction has expired.
+        // `validate_batch` should be called once, for all pending values. The
+        // second transaction has expired.
+        let expected = values.clone();
         tx_manager
-            .expect_validate()
-            .with(eq(values[1].clone()))
-            .return_const(Err(TxManagerError::TransactionValidation(
-                TransactionValidationError::TombstoneBlockExceeded,
-            )));
-        tx_manager
-            .expect_validate()
-            .with(eq(values[2].clone()))
-            .return_const(Ok(()));
+            .expect_validate_batch()
+            .withf(move |tx_hashes| tx_hashes == &expected[..])
+            .times(1)
+            .returning(|_| {
+                vec![
+                    Ok(()),
+                    Err(TxManagerError::TransactionValidation(
+                        TransactionValidationError::TombstoneBlockExceeded,
+                    )),
+                    Ok(()),
+                ]
+            });
 
         // Create new PendingValues and forcefully shove the pending values into it in
         // order to skip the validation call done by `push()`.
@@ -6,6 +6,7 @@ use crate::{
         task_message::TaskMessage, IS_BEHIND_GRACE_PERIOD, MAX_PENDING_VALUES_TO_NOMINATE,
     },
     counters,
+    fee_estimator::FeeEstimator,
     tx_manager::TxManager,
 };
 use mc_common::{
@@ -29,7 +30,7 @@ use mc_util_metered_channel::Receiver;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::{
     cmp::min,
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeSet, HashMap, HashSet},
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, Mutex,
@@ -74,6 +75,12 @@ pub struct ByzantineLedgerWorker<
     // Highest consensus message issued by this node.
     highest_issued_msg: Arc<Mutex<Option<ConsensusMsg>>>,
 
+    // The worker sets this to the values it has pending.
+    pending_tx_hashes: Arc<Mutex<Vec<TxHash>>>,
+
+    // Learns from each externalized block which fees get transactions included.
+    fee_estimator: Arc<Mutex<FeeEstimator>>,
+
     // Network state, used to track if we've fallen behind.
     network_state: SCPNetworkState,
 
@@ -118,6 +125,8 @@ impl<
     ///   network agrees on.
     /// * `highest_issued_msg` - Worker sets to highest consensus message issued
     ///   by this node.
+    /// * `pending_tx_hashes` - Worker sets to the values it has pending.
+    /// * `fee_estimator` - Worker records each externalized block in it.
     /// * `logger` - Logger instance.
     pub fn new(
         scp_node: Box<dyn ScpNode<TxHash>>,
@@ -131,6 +140,8 @@ impl<
         is_behind: Arc<AtomicBool>,
         highest_peer_block: Arc<AtomicU64>,
         highest_issued_msg: Arc<Mutex<Option<ConsensusMsg>>>,
+        pending_tx_hashes: Arc<Mutex<Vec<TxHash>>>,
+        fee_estimator: Arc<Mutex<FeeEstimator>>,
         logger: Logger,
     ) -> Self {
         let current_slot_index = ledger.num_blocks().unwrap();
@@ -144,6 +155,8 @@ impl<
             is_behind,
             highest_peer_block,
             highest_issued_msg,
+            pending_tx_hashes,
+            fee_estimator,
             ledger,
             tx_manager: tx_manager.clone(),
             broadcaster,
@@ -287,6 +300,10 @@ impl<
         // Update metrics.
         self.update_current_slot_metrics();
 
+        // Publish the values that are still pending.
+        *self.pending_tx_hashes.lock().expect("mutex poisoned") =
+            self.pending_values.iter().cloned().collect();
+
         true
     }
 
@@ -342,6 +359,10 @@ impl<
         {
             Ok(()) => {
                 // Synced a chunk of blocks, but may still be behind.
+                match self.ledger.num_blocks() {
+                    Ok(num_blocks) => self.tx_manager.blocks_appended(num_blocks),
+                    Err(err) => log::warn!(self.logger, "Could not read ledger height: {:?}", err),
+                }
                 LedgerSyncState::IsBehind {
                     attempt_sync_at: Instant::now(),
                     num_sync_attempts: 0,
@@ -517,6 +538,21 @@ impl<
             }
         }
 
+        // Record which of the transactions that were waiting for this block it
+        // included.
+        let candidates: Vec<_> = self
+            .pending_values
+            .iter()
+            .chain(externalized.iter())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter_map(|tx_hash| self.tx_manager.get_tx_context(tx_hash))
+            .collect();
+        self.fee_estimator
+            .lock()
+            .expect("mutex poisoned")
+            .record_block(&candidates, &externalized);
+
         // Invariant: pending_values only contains valid values that were not
         // externalized.
         self.pending_values
@@ -563,6 +599,7 @@ impl<
             assert_eq!(current_slot_index, self.current_slot_index + 1);
             current_slot_index
         };
+        self.tx_manager.blocks_appended(self.current_slot_index);
 
         // Purge transactions that can no longer be processed based on their tombstone
         // block.
@@ -777,6 +814,9 @@ mod tests {
             worker::ByzantineLedgerWorker,
             IS_BEHIND_GRACE_PERIOD, MAX_PENDING_VALUES_TO_NOMINATE,
         },
+        fee_estimator::{
+            FeeEstimator, DEFAULT_FEE_ESTIMATE_CONFIDENCE, DEFAULT_FEE_ESTIMATOR_DECAY,
+        },
         tx_manager::{MockTxManager, TxManagerError},
     };
     use mc_common::{
@@ -793,7 +833,9 @@ mod tests {
     use mc_ledger_sync::{LedgerSyncError, MockLedgerSync, SCPNetworkState};
     use mc_peers::{ConsensusMsg, MockBroadcast, VerifiedConsensusMsg};
     use mc_peers_test_utils::MockPeerConnection;
-    use mc_transaction_core::{tx::TxHash, validation::TransactionValidationError, Block};
+    use mc_transaction_core::{
+        constants::MINIMUM_FEE, tx::TxHash, validation::TransactionValidationError, Block,
+    };
     use mc_util_metered_channel::{Receiver, Sender};
     use mc_util_metrics::OpMetrics;
     use mockall::predicate::eq;
@@ -843,6 +885,14 @@ mod tests {
         )
     }
 
+    fn get_fee_estimator() -> Arc<Mutex<FeeEstimator>> {
+        Arc::new(Mutex::new(FeeEstimator::new(
+            MINIMUM_FEE,
+            DEFAULT_FEE_ESTIMATOR_DECAY,
+            DEFAULT_FEE_ESTIMATE_CONFIDENCE,
+        )))
+    }
+
     fn get_channel() -> (Sender<TaskMessage>, Receiver<TaskMessage>) {
         let gauge = OpMetrics::new("test").gauge("byzantine_ledger_msg_queue_size");
         mc_util_metered_channel::unbounded(&gauge)
@@ -895,6 +945,8 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            Arc::new(Mutex::new(Vec::new())),
+            get_fee_estimator(),
             logger,
         );
 
@@ -945,6 +997,8 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            Arc::new(Mutex::new(Vec::new())),
+            get_fee_estimator(),
             logger,
         );
 
@@ -1040,7 +1094,7 @@ mod tests {
             QuorumSet::new_with_node_ids(2, vec![peers[0].id.clone(), peers[1].id.clone()]);
 
         let num_blocks = 12;
-        let (scp_node, ledger, mut ledger_sync, tx_manager, broadcast) =
+        let (scp_node, ledger, mut ledger_sync, mut tx_manager, broadcast) =
             get_mocks(&node_id, &quorum_set, num_blocks);
         let connection_manager = get_connection_manager(&node_id, &peers, &logger);
         let (_task_sender, task_receiver) = get_channel();
@@ -1050,6 +1104,13 @@ mod tests {
             .expect_attempt_ledger_sync()
             .return_once(|_, _| Ok(())); // This is a hack because LedgerSyncError is not Clone.
 
+        // The transaction manager should be told about the synced blocks.
+        tx_manager
+            .expect_blocks_appended()
+            .with(eq(num_blocks))
+            .times(1)
+            .return_const(());
+
         let mut worker = ByzantineLedgerWorker::new(
             Box::new(scp_node),
             msg_signer_key,
@@ -1062,6 +1123,8 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            Arc::new(Mutex::new(Vec::new())),
+            get_fee_estimator(),
             logger,
         );
 
@@ -1121,6 +1184,8 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            Arc::new(Mutex::new(Vec::new())),
+            get_fee_estimator(),
             logger,
         );
 
@@ -1213,6 +1278,8 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            Arc::new(Mutex::new(Vec::new())),
+            get_fee_estimator(),
             logger,
         );
 
@@ -1316,6 +1383,8 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            Arc::new(Mutex::new(Vec::new())),
+            get_fee_estimator(),
             logger,
         );
 
@@ -132,6 +132,13 @@ pub struct NetworkConfig {
 
     /// Optional list of peers we are aware of.
     pub known_peers: Option<Vec<PeerUri>>,
+
+    /// Transactions paying less than this are left out of the blocks
+    /// `combine` builds. `combine` must give every node the same result, so
+    /// every node in the network must be configured with the same value.
+    /// Defaults to 0, which leaves no transaction out.
+    #[serde(default)]
+    pub min_combine_fee: u64,
 }
 
 impl NetworkConfig {
@@ -327,6 +334,7 @@ mod tests {
             assert_eq!(network.quorum_set.members.len(), 0);
             assert_eq!(network.broadcast_peers.len(), 0);
             assert!(network.known_peers.is_none());
+            assert_eq!(network.min_combine_fee, 0);
         }
 
         // Real world configuration.
@@ -341,6 +349,8 @@ mod tests {
                     "file:///tmp/dump"
                 ]
 
+                min_combine_fee = 100
+
                 known_peers = [
                     "insecure-mcp://0.0.0.0:8084?consensus-msg-key=MCowBQYDK2VwAyEAzxKNVxaVfJ4xELeA1bQ-aa-2HkcYyX2pDGcCqW9mzoo=",
                 ]
@@ -370,6 +380,8 @@ mod tests {
                     "file:///tmp/dump"
                 ],
 
+                "min_combine_fee": 100,
+
                 "known_peers": [
                     "insecure-mcp://0.0.0.0:8084?consensus-msg-key=MCowBQYDK2VwAyEAzxKNVxaVfJ4xELeA1bQ-aa-2HkcYyX2pDGcCqW9mzoo="
                 ],
@@ -386,6 +398,7 @@ mod tests {
                 serde_json::from_str(input_json).expect("failed parsing json");
 
             assert_eq!(network, network2);
+            assert_eq!(network.min_combine_fee, 100);
 
             let quorum_set = network.quorum_set();
             assert_eq!(
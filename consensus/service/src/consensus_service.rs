@@ -8,6 +8,7 @@ use crate::{
     byzantine_ledger::ByzantineLedger,
     config::Config,
     counters,
+    fee_estimator::{FeeEstimator, DEFAULT_FEE_ESTIMATE_CONFIDENCE, DEFAULT_FEE_ESTIMATOR_DECAY},
     peer_keepalive::PeerKeepalive,
     tx_manager::TxManager,
 };
@@ -30,7 +31,7 @@ use mc_crypto_keys::DistinguishedEncoding;
 use mc_ledger_db::{Error as LedgerDbError, Ledger, LedgerDB};
 use mc_peers::{PeerConnection, ThreadedBroadcaster, VerifiedConsensusMsg};
 use mc_sgx_report_cache_untrusted::{Error as ReportCacheError, ReportCacheThread};
-use mc_transaction_core::tx::TxHash;
+use mc_transaction_core::{constants::MINIMUM_FEE, tx::TxHash};
 use mc_util_grpc::{
     AdminServer, AnonymousAuthenticator, Authenticator, BuildInfoService,
     ConnectionUriGrpcioServer, GetConfigJsonFn, HealthCheckStatus, HealthService,
@@ -113,6 +114,9 @@ pub struct ConsensusService<
     // and the client and peer api services, via the ProposeTxCallback
     broadcaster: Arc<Mutex<ThreadedBroadcaster>>,
     tx_manager: Arc<TXM>,
+    // Fed by the ByzantineLedger worker with each externalized block, and queried
+    // by clients through the client api service.
+    fee_estimator: Arc<Mutex<FeeEstimator>>,
     // Option is only here because we need a way to drop the PeerKeepalive without mutex,
     // if we want to implement Stop as currently concieved
     peer_keepalive: Option<Arc<PeerKeepalive>>,
@@ -199,6 +203,16 @@ impl<
                 Arc::new(AnonymousAuthenticator::default())
             };
 
+        // Fee estimator
+        let fee_estimator = Arc::new(Mutex::new(FeeEstimator::new(
+            config
+                .minimum_fee()
+                .expect("Could not read minimum fee")
+                .unwrap_or(MINIMUM_FEE),
+            DEFAULT_FEE_ESTIMATOR_DECAY,
+            DEFAULT_FEE_ESTIMATE_CONFIDENCE,
+        )));
+
         // Return
         Self {
             config,
@@ -216,6 +230,7 @@ impl<
             peer_manager,
             broadcaster,
             tx_manager,
+            fee_estimator,
             peer_keepalive,
             client_authenticator,
 
@@ -322,7 +337,9 @@ impl<
                 self.create_scp_client_value_sender_fn(),
                 Arc::new(self.ledger_db.clone()),
                 self.tx_manager.clone(),
+                self.fee_estimator.clone(),
                 self.create_is_serving_user_requests_fn(),
+                self.create_get_pending_tx_hashes_fn(),
                 self.client_authenticator.clone(),
                 self.logger.clone(),
             ));
@@ -494,6 +511,7 @@ impl<
                 self.ledger_db.clone(),
                 self.tx_manager.clone(),
                 self.broadcaster.clone(),
+                self.fee_estimator.clone(),
                 self.config.msg_signer_key.clone(),
                 self.config.network().tx_source_urls,
                 self.config.scp_debug_dump.clone(),
@@ -541,6 +559,23 @@ impl<
         Ok(())
     }
 
+    /// Creates a function that returns the hashes of the transactions the
+    /// node has yet to get into a block.
+    fn create_get_pending_tx_hashes_fn(&self) -> Arc<dyn Fn() -> Vec<TxHash> + Sync + Send> {
+        let byzantine_ledger = self
+            .byzantine_ledger
+            .as_ref()
+            .map(Arc::downgrade)
+            .expect("Server was not initialized");
+
+        Arc::new(move || {
+            byzantine_ledger
+                .upgrade()
+                .and_then(|ledger| ledger.get().map(|ledger| ledger.pending_tx_hashes()))
+                .unwrap_or_default()
+        })
+    }
+
     /// Creates a function that returns true if the node is currently serving
     /// user requests.
     fn create_is_serving_user_requests_fn(&self) -> Arc<dyn Fn() -> bool + Sync + Send> {
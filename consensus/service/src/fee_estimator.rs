@@ -0,0 +1,204 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Fee estimates derived from which transactions recent blocks included.
+//!
+//! Each time a block is externalized, the transactions which were waiting for
+//! it are grouped into buckets of similar fee. For each bucket the estimator
+//! tracks how often its transactions made it into the block, weighting recent
+//! blocks more heavily than old ones. A fee is recommended for a target number
+//! of blocks if transactions paying it were likely enough to have been
+//! included within that many blocks.
+
+use mc_common::HashSet;
+use mc_consensus_enclave::WellFormedTxContext;
+use mc_transaction_core::tx::TxHash;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Default weight kept by past blocks each time a new block is recorded.
+pub const DEFAULT_FEE_ESTIMATOR_DECAY: f64 = 0.9;
+
+/// Default probability with which an estimated fee should be included within
+/// the target number of blocks.
+pub const DEFAULT_FEE_ESTIMATE_CONFIDENCE: f64 = 0.95;
+
+/// Ratio between the lowest fees of neighbouring buckets.
+const FEE_BUCKET_SPACING: f64 = 1.1;
+
+/// Buckets with less weight than this are too sparse to estimate from.
+const MIN_BUCKET_WEIGHT: f64 = 0.5;
+
+/// Buckets whose weight has decayed below this are forgotten.
+const FORGET_BUCKET_WEIGHT: f64 = 1e-3;
+
+/// Decayed counts of a bucket's transactions which were and were not selected.
+#[derive(Clone, Copy, Debug, Default)]
+struct BucketStats {
+    included: f64,
+    excluded: f64,
+}
+
+impl BucketStats {
+    fn weight(&self) -> f64 {
+        self.included + self.excluded
+    }
+}
+
+/// Estimates the fee needed for a transaction to be included within a given
+/// number of blocks.
+pub struct FeeEstimator {
+    /// The lowest fee the network accepts, and the lowest fee estimated.
+    minimum_fee: u64,
+
+    /// Weight kept by past blocks each time a new block is recorded.
+    decay: f64,
+
+    /// Probability with which an estimated fee should be included in time.
+    confidence: f64,
+
+    /// Selection statistics, keyed by bucket.
+    buckets: BTreeMap<u32, BucketStats>,
+}
+
+impl FeeEstimator {
+    /// Create an estimator with no history, which recommends `minimum_fee`
+    /// until blocks are recorded.
+    pub fn new(minimum_fee: u64, decay: f64, confidence: f64) -> Self {
+        Self {
+            minimum_fee,
+            decay: decay.max(0.0).min(1.0),
+            confidence: confidence.max(0.0).min(1.0),
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    /// Record an externalized block: the transactions which were waiting to be
+    /// included in it, and the hashes of those it included.
+    pub fn record_block(&mut self, candidates: &[Arc<WellFormedTxContext>], included: &[TxHash]) {
+        for stats in self.buckets.values_mut() {
+            stats.included *= self.decay;
+            stats.excluded *= self.decay;
+        }
+        self.buckets
+            .retain(|_bucket, stats| stats.weight() >= FORGET_BUCKET_WEIGHT);
+
+        let included: HashSet<&TxHash> = included.iter().collect();
+        for context in candidates {
+            let bucket = self.bucket(context.fee());
+            let stats = self.buckets.entry(bucket).or_default();
+            if included.contains(context.tx_hash()) {
+                stats.included += 1.0;
+            } else {
+                stats.excluded += 1.0;
+            }
+        }
+    }
+
+    /// The lowest fee which recent blocks suggest will be included within
+    /// `target_blocks` blocks. This is never below the minimum fee.
+    ///
+    /// If even the highest fees seen were not included reliably enough, the
+    /// estimate outbids them.
+    pub fn estimate_fee(&self, target_blocks: u64) -> u64 {
+        let target_blocks = target_blocks.max(1) as f64;
+        let mut estimate = None;
+        for (bucket, stats) in self.buckets.iter().rev() {
+            let weight = stats.weight();
+            if weight < MIN_BUCKET_WEIGHT {
+                continue;
+            }
+            // Treat each block as an independent chance to be included.
+            let miss_all = (stats.excluded / weight).powf(target_blocks);
+            if 1.0 - miss_all < self.confidence {
+                break;
+            }
+            estimate = Some(*bucket);
+        }
+
+        let bucket = match (estimate, self.buckets.keys().next_back()) {
+            (Some(bucket), _) => bucket,
+            (None, Some(highest)) => highest + 1,
+            (None, None) => return self.minimum_fee,
+        };
+        self.bucket_floor(bucket).max(self.minimum_fee)
+    }
+
+    // The bucket holding the given fee.
+    fn bucket(&self, fee: u64) -> u32 {
+        let ratio = fee as f64 / self.minimum_fee.max(1) as f64;
+        if ratio <= 1.0 {
+            return 0;
+        }
+        (ratio.ln() / FEE_BUCKET_SPACING.ln()).floor() as u32
+    }
+
+    // The lowest fee in the given bucket.
+    fn bucket_floor(&self, bucket: u32) -> u64 {
+        (self.minimum_fee.max(1) as f64 * FEE_BUCKET_SPACING.powi(bucket as i32)).ceil() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mc_transaction_core::ring_signature::KeyImage;
+
+    fn context(id: u8, fee: u64) -> Arc<WellFormedTxContext> {
+        Arc::new(WellFormedTxContext::new(
+            fee,
+            TxHash([id; 32]),
+            100,
+            vec![KeyImage::from(id as u64)],
+            vec![],
+            vec![],
+        ))
+    }
+
+    fn estimator() -> FeeEstimator {
+        FeeEstimator::new(
+            100,
+            DEFAULT_FEE_ESTIMATOR_DECAY,
+            DEFAULT_FEE_ESTIMATE_CONFIDENCE,
+        )
+    }
+
+    #[test]
+    // Without history, and when only the cheapest fees have been seen, the
+    // estimate should be the minimum fee.
+    fn estimate_defaults_to_minimum_fee() {
+        let mut estimator = estimator();
+        assert_eq!(estimator.estimate_fee(1), 100);
+
+        for _ in 0..10 {
+            let candidates = vec![context(1, 100), context(2, 50)];
+            estimator.record_block(&candidates, &[TxHash([1u8; 32]), TxHash([2u8; 32])]);
+        }
+        assert_eq!(estimator.estimate_fee(1), 100);
+    }
+
+    #[test]
+    // Fees which are always included should be recommended for the next
+    // block, and fees included every other block only for later ones.
+    fn estimate_tracks_inclusion_history() {
+        let mut estimator = estimator();
+        for block in 0..50 {
+            let candidates = vec![context(1, 10_000), context(2, 1_000), context(3, 100)];
+            let mut selected = vec![TxHash([1u8; 32])];
+            if block % 2 == 0 {
+                selected.push(TxHash([2u8; 32]));
+            }
+            estimator.record_block(&candidates, &selected);
+        }
+
+        let next_block = estimator.estimate_fee(1);
+        assert!(next_block > 1_000 && next_block <= 10_000, "{}", next_block);
+        let within_five = estimator.estimate_fee(5);
+        assert!(within_five > 100 && within_five <= 1_000, "{}", within_five);
+
+        // Once even the highest fee stops being included, the estimate should
+        // outbid it.
+        for _ in 0..50 {
+            estimator.record_block(&[context(1, 10_000)], &[]);
+        }
+        assert!(estimator.estimate_fee(5) > 10_000);
+    }
+}
@@ -9,11 +9,17 @@ extern crate test;
 
 pub mod config;
 pub mod consensus_service;
+pub mod fee_estimator;
+pub mod mempool;
 pub mod tx_manager;
 pub mod validators; // Public so that it can be benchmarked by the `benchmarks` crate.
 
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
+
 mod api;
 mod background_work_queue;
 mod byzantine_ledger;
 mod counters;
 mod peer_keepalive;
+mod public_key_filter;
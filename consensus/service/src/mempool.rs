@@ -0,0 +1,280 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A bounded pool of well-formed transactions awaiting inclusion in a block.
+//!
+//! The mempool sits between validation and block proposal: transactions are
+//! inserted once they have been found to be well-formed, expired transactions
+//! are dropped as the ledger grows, and `combine` is used to pick a
+//! conflict-free set of transactions for the next block.
+
+use crate::tx_manager::UntrustedInterfaces;
+use mc_common::HashMap;
+use mc_consensus_enclave::WellFormedTxContext;
+use mc_transaction_core::tx::TxHash;
+use std::{cmp::Ordering, collections::BTreeSet, sync::Arc};
+
+/// Default maximum number of transactions held by a mempool.
+pub const DEFAULT_MEMPOOL_MAX_SIZE: usize = 50_000;
+
+/// A size-bounded collection of well-formed transactions.
+pub struct Mempool<UI: UntrustedInterfaces> {
+    /// Used to select transactions for a block.
+    untrusted: UI,
+
+    /// Maximum number of transactions to hold.
+    max_size: usize,
+
+    /// Transactions, keyed by hash.
+    contexts: HashMap<TxHash, Arc<WellFormedTxContext>>,
+
+    /// (fee rate, hash) of every transaction, lowest fee rate first. Ties are
+    /// broken by hash so that eviction is deterministic.
+    by_fee_rate: BTreeSet<(FeeRate, TxHash)>,
+}
+
+/// A transaction's fee per unit of size. A transaction's size is taken to be
+/// its number of inputs and outputs, counting at least one.
+#[derive(Clone, Copy, Debug)]
+struct FeeRate {
+    fee: u64,
+    size: u64,
+}
+
+impl FeeRate {
+    fn of(context: &WellFormedTxContext) -> Self {
+        let size = context.key_images().len() + context.output_public_keys().len();
+        Self {
+            fee: context.fee(),
+            size: size.max(1) as u64,
+        }
+    }
+}
+
+impl Ord for FeeRate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Compare fee / size without rounding.
+        (self.fee as u128 * other.size as u128).cmp(&(other.fee as u128 * self.size as u128))
+    }
+}
+
+impl PartialOrd for FeeRate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for FeeRate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FeeRate {}
+
+impl<UI: UntrustedInterfaces> Mempool<UI> {
+    /// Create an empty mempool holding at most `max_size` transactions.
+    pub fn new(untrusted: UI, max_size: usize) -> Self {
+        Self {
+            untrusted,
+            max_size,
+            contexts: HashMap::default(),
+            by_fee_rate: BTreeSet::new(),
+        }
+    }
+
+    /// The number of transactions in the mempool.
+    pub fn len(&self) -> usize {
+        self.contexts.len()
+    }
+
+    /// Whether the mempool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    /// Whether the mempool holds the given transaction.
+    pub fn contains(&self, tx_hash: &TxHash) -> bool {
+        self.contexts.contains_key(tx_hash)
+    }
+
+    /// Retrieve a transaction by hash.
+    pub fn get(&self, tx_hash: &TxHash) -> Option<Arc<WellFormedTxContext>> {
+        self.contexts.get(tx_hash).cloned()
+    }
+
+    /// Insert a transaction, evicting the transaction with the lowest fee
+    /// rate if the mempool is over capacity.
+    ///
+    /// Returns the evicted transaction, if any. This is the inserted
+    /// transaction itself if it pays the lowest fee rate. Inserting a
+    /// transaction which is already present does nothing.
+    pub fn insert(
+        &mut self,
+        context: Arc<WellFormedTxContext>,
+    ) -> Option<Arc<WellFormedTxContext>> {
+        let tx_hash = *context.tx_hash();
+        if self.contexts.contains_key(&tx_hash) {
+            return None;
+        }
+
+        self.by_fee_rate.insert((FeeRate::of(&context), tx_hash));
+        self.contexts.insert(tx_hash, context);
+
+        if self.contexts.len() <= self.max_size {
+            return None;
+        }
+
+        let (_fee_rate, lowest) = *self
+            .by_fee_rate
+            .iter()
+            .next()
+            .expect("mempool is over capacity, so it cannot be empty");
+        self.remove(&lowest)
+    }
+
+    /// Remove a transaction, returning it if it was present.
+    pub fn remove(&mut self, tx_hash: &TxHash) -> Option<Arc<WellFormedTxContext>> {
+        let context = self.contexts.remove(tx_hash)?;
+        self.by_fee_rate.remove(&(FeeRate::of(&context), *tx_hash));
+        Some(context)
+    }
+
+    /// Remove every transaction whose tombstone block has been reached, given
+    /// the index of the block currently being built.
+    ///
+    /// Returns the hashes of the removed transactions.
+    pub fn evict_expired(&mut self, current_block_index: u64) -> Vec<TxHash> {
+        let expired: Vec<TxHash> = self
+            .contexts
+            .values()
+            .filter(|context| context.tombstone_block() <= current_block_index)
+            .map(|context| *context.tx_hash())
+            .collect();
+
+        for tx_hash in &expired {
+            self.remove(tx_hash);
+        }
+        expired
+    }
+
+    /// Select a conflict-free set of at most `max_elements` transactions for
+    /// the next block, using `combine`.
+    ///
+    /// The selected transactions remain in the mempool until they are removed.
+    pub fn select_for_block(&self, max_elements: usize) -> Vec<TxHash> {
+        // Present candidates in a deterministic order, highest fee rate first.
+        let candidates: Vec<_> = self
+            .by_fee_rate
+            .iter()
+            .rev()
+            .map(|(_fee_rate, tx_hash)| self.contexts[tx_hash].clone())
+            .collect();
+        self.untrusted.combine(&candidates, max_elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validators::DefaultTxManagerUntrustedInterfaces;
+    use mc_crypto_keys::CompressedRistrettoPublic;
+    use mc_ledger_db::test_utils::{get_mock_ledger, MockLedger};
+    use mc_transaction_core::ring_signature::KeyImage;
+
+    fn mempool(max_size: usize) -> Mempool<DefaultTxManagerUntrustedInterfaces<MockLedger>> {
+        let ledger = get_mock_ledger(10);
+        Mempool::new(DefaultTxManagerUntrustedInterfaces::new(ledger), max_size)
+    }
+
+    fn context(id: u8, fee: u64, tombstone_block: u64) -> Arc<WellFormedTxContext> {
+        Arc::new(WellFormedTxContext::new(
+            fee,
+            TxHash([id; 32]),
+            tombstone_block,
+            vec![KeyImage::from(id as u64)],
+            vec![],
+            vec![],
+        ))
+    }
+
+    fn context_with_outputs(id: u8, fee: u64, num_outputs: usize) -> Arc<WellFormedTxContext> {
+        Arc::new(WellFormedTxContext::new(
+            fee,
+            TxHash([id; 32]),
+            50,
+            vec![KeyImage::from(id as u64)],
+            vec![],
+            vec![CompressedRistrettoPublic::default(); num_outputs],
+        ))
+    }
+
+    #[test]
+    // Inserting beyond capacity should evict the lowest-fee transaction.
+    fn insert_evicts_lowest_fee() {
+        let mut mempool = mempool(2);
+
+        assert!(mempool.insert(context(1, 100, 50)).is_none());
+        assert!(mempool.insert(context(2, 300, 50)).is_none());
+
+        let evicted = mempool.insert(context(3, 200, 50)).unwrap();
+        assert_eq!(evicted.tx_hash(), &TxHash([1u8; 32]));
+        assert_eq!(mempool.len(), 2);
+
+        // A transaction paying less than everything in the pool is evicted itself.
+        let evicted = mempool.insert(context(4, 50, 50)).unwrap();
+        assert_eq!(evicted.tx_hash(), &TxHash([4u8; 32]));
+        assert!(mempool.contains(&TxHash([2u8; 32])));
+        assert!(mempool.contains(&TxHash([3u8; 32])));
+    }
+
+    #[test]
+    // Eviction should go by fee per input and output, not by absolute fee.
+    fn insert_evicts_lowest_fee_rate() {
+        let mut mempool = mempool(2);
+
+        // 300 over 3 inputs and outputs, 150 over 1, and 250 over 2.
+        assert!(mempool.insert(context_with_outputs(1, 300, 2)).is_none());
+        assert!(mempool.insert(context_with_outputs(2, 150, 0)).is_none());
+
+        let evicted = mempool.insert(context_with_outputs(3, 250, 1)).unwrap();
+        assert_eq!(evicted.tx_hash(), &TxHash([1u8; 32]));
+        assert!(mempool.contains(&TxHash([2u8; 32])));
+        assert!(mempool.contains(&TxHash([3u8; 32])));
+    }
+
+    #[test]
+    // Transactions whose tombstone block has been reached should be evicted.
+    fn evict_expired_drops_tombstoned_transactions() {
+        let mut mempool = mempool(10);
+        mempool.insert(context(1, 100, 10));
+        mempool.insert(context(2, 100, 11));
+        mempool.insert(context(3, 100, 20));
+
+        let mut expired = mempool.evict_expired(11);
+        expired.sort();
+        assert_eq!(expired, vec![TxHash([1u8; 32]), TxHash([2u8; 32])]);
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains(&TxHash([3u8; 32])));
+    }
+
+    #[test]
+    // Selection should delegate to combine and leave the mempool unchanged.
+    fn select_for_block_combines_transactions() {
+        let mut mempool = mempool(10);
+        mempool.insert(context(1, 100, 50));
+        mempool.insert(context(2, 300, 50));
+        mempool.insert(context(3, 200, 50));
+
+        assert_eq!(
+            mempool.select_for_block(2),
+            vec![TxHash([2u8; 32]), TxHash([3u8; 32])]
+        );
+        assert_eq!(mempool.len(), 3);
+
+        mempool.remove(&TxHash([2u8; 32]));
+        assert_eq!(
+            mempool.select_for_block(2),
+            vec![TxHash([3u8; 32]), TxHash([1u8; 32])]
+        );
+    }
+}
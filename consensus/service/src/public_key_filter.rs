@@ -0,0 +1,142 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! A bloom filter of the output public keys in the ledger.
+//!
+//! A key the filter has never seen is certainly not in the ledger, so
+//! `is_valid` can skip the ledger lookup for it. A key the filter reports as
+//! present may be a false positive, and is always looked up in the ledger.
+
+use mc_crypto_keys::CompressedRistrettoPublic;
+use mc_ledger_db::{Error as LedgerError, Ledger};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
+};
+
+/// A bloom filter holding the output public keys of the ledger's first
+/// `num_blocks` blocks.
+pub struct PublicKeyFilter {
+    /// The filter's bits, packed into words.
+    bits: Vec<u64>,
+
+    /// The number of bits in the filter.
+    num_bits: u64,
+
+    /// The number of bits set for each key.
+    num_hashes: u32,
+
+    /// Keys the hashes, so that keys chosen to collide on one node do not
+    /// collide on others.
+    hash_state: RandomState,
+
+    /// The number of blocks whose outputs have been added.
+    num_blocks: u64,
+}
+
+impl PublicKeyFilter {
+    /// Create an empty filter sized to hold `expected_keys` keys with the given
+    /// false positive rate. The rate rises as more keys are added, but the
+    /// filter never reports a key it holds as absent.
+    pub fn new(expected_keys: usize, false_positive_rate: f64) -> Self {
+        let expected_keys = expected_keys.max(1) as f64;
+        let false_positive_rate = false_positive_rate.max(f64::MIN_POSITIVE).min(1.0);
+        let ln2 = std::f64::consts::LN_2;
+
+        let num_bits = (-expected_keys * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / expected_keys) * ln2).round().max(1.0) as u32;
+
+        Self {
+            bits: vec![0; ((num_bits + 63) / 64) as usize],
+            num_bits,
+            num_hashes,
+            hash_state: RandomState::new(),
+            num_blocks: 0,
+        }
+    }
+
+    /// The number of blocks whose outputs have been added.
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    /// Add a key to the filter.
+    pub fn insert(&mut self, public_key: &CompressedRistrettoPublic) {
+        for bit in self.bit_indices(public_key) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Whether the key may have been added. False means it certainly has not.
+    pub fn may_contain(&self, public_key: &CompressedRistrettoPublic) -> bool {
+        self.bit_indices(public_key)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Add the outputs of the ledger's blocks up to `num_blocks` which have not
+    /// been added yet.
+    ///
+    /// If reading a block fails, the blocks before it remain added, and the
+    /// filter only describes the ledger up to them.
+    pub fn sync<L: Ledger>(&mut self, ledger: &L, num_blocks: u64) -> Result<(), LedgerError> {
+        while self.num_blocks < num_blocks {
+            let block_contents = ledger.get_block_contents(self.num_blocks)?;
+            for output in &block_contents.outputs {
+                self.insert(&output.public_key);
+            }
+            self.num_blocks += 1;
+        }
+        Ok(())
+    }
+
+    // The bits set for a key, by double hashing.
+    fn bit_indices(&self, public_key: &CompressedRistrettoPublic) -> impl Iterator<Item = u64> {
+        let hash = |seed: u8| {
+            let mut hasher = self.hash_state.build_hasher();
+            seed.hash(&mut hasher);
+            AsRef::<[u8]>::as_ref(public_key).hash(&mut hasher);
+            hasher.finish()
+        };
+        let (first, second) = (hash(0), hash(1) | 1);
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64)
+            .map(move |i| first.wrapping_add(i.wrapping_mul(second)) % num_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: u32) -> CompressedRistrettoPublic {
+        let mut bytes = [0u8; 32];
+        bytes[..4].copy_from_slice(&i.to_le_bytes());
+        CompressedRistrettoPublic::from(&bytes)
+    }
+
+    #[test]
+    // Every added key should be reported, even by a filter far over capacity.
+    fn added_keys_are_never_missed() {
+        for (expected_keys, false_positive_rate) in &[(1_000, 0.001), (1, 0.5)] {
+            let mut filter = PublicKeyFilter::new(*expected_keys, *false_positive_rate);
+            for i in 0..1_000 {
+                filter.insert(&key(i));
+            }
+            assert!((0..1_000).all(|i| filter.may_contain(&key(i))));
+        }
+    }
+
+    #[test]
+    // A filter within capacity should report few keys it has not seen.
+    fn false_positive_rate_is_bounded() {
+        let mut filter = PublicKeyFilter::new(1_000, 0.01);
+        for i in 0..1_000 {
+            filter.insert(&key(i));
+        }
+        let false_positives = (1_000..11_000)
+            .filter(|i| filter.may_contain(&key(*i)))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+}
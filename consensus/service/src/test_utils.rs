@@ -0,0 +1,88 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Utilities for testing implementations of the consensus service's traits.
+
+use crate::tx_manager::UntrustedInterfaces;
+use mc_consensus_enclave::WellFormedTxContext;
+use mc_transaction_core::tx::TxHash;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::sync::Arc;
+
+/// Asserts that `combine` gives the same hashes, in the same order, however
+/// its candidates are ordered.
+///
+/// Every node must build the same block from the same set of candidates,
+/// whatever order it received them in. This combines `tx_contexts` as given,
+/// then `runs` more times after shuffling them with a fixed seed, and panics
+/// if any result differs from the first. Returns that result.
+pub fn assert_combine_deterministic<UI: UntrustedInterfaces>(
+    untrusted: &UI,
+    tx_contexts: &[Arc<WellFormedTxContext>],
+    max_elements: usize,
+    runs: usize,
+) -> Vec<TxHash> {
+    let expected = untrusted.combine(tx_contexts, max_elements);
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut shuffled = tx_contexts.to_vec();
+    for run in 0..runs {
+        shuffled.shuffle(&mut rng);
+        let combined = untrusted.combine(&shuffled, max_elements);
+        assert_eq!(
+            combined, expected,
+            "combine was not deterministic on run {} of {}",
+            run, runs
+        );
+    }
+
+    expected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tx_manager::MockUntrustedInterfaces, validators::DefaultTxManagerUntrustedInterfaces,
+    };
+    use mc_ledger_db::test_utils::get_mock_ledger;
+
+    fn tx_contexts() -> Vec<Arc<WellFormedTxContext>> {
+        (1u8..=20)
+            .map(|id| {
+                Arc::new(WellFormedTxContext::new(
+                    100 + (id % 3) as u64,
+                    TxHash([id; 32]),
+                    0,
+                    vec![],
+                    vec![],
+                    vec![],
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    // The default combine should sort its candidates into one order.
+    fn default_combine_is_deterministic() {
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(get_mock_ledger(10));
+        let combined = assert_combine_deterministic(&untrusted, &tx_contexts(), 10, 20);
+        assert_eq!(combined.len(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "combine was not deterministic")]
+    // A combine which keeps its candidates' order should be caught.
+    fn order_preserving_combine_is_caught() {
+        let mut untrusted = MockUntrustedInterfaces::new();
+        untrusted
+            .expect_combine()
+            .returning(|tx_contexts, max_elements| {
+                tx_contexts
+                    .iter()
+                    .take(max_elements)
+                    .map(|tx_context| *tx_context.tx_hash())
+                    .collect()
+            });
+        assert_combine_deterministic(&untrusted, &tx_contexts(), 10, 20);
+    }
+}
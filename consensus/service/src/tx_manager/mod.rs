@@ -19,6 +19,7 @@ use mc_consensus_enclave::{
 use mc_transaction_core::{
     constants::MAX_TRANSACTIONS_PER_BLOCK,
     tx::{TxHash, TxOutMembershipProof},
+    validation::TransactionValidationResult,
     Block, BlockContents, BlockSignature,
 };
 use std::sync::{Arc, Mutex, MutexGuard};
@@ -34,6 +35,9 @@ pub use untrusted_interfaces::UntrustedInterfaces;
 #[cfg(test)]
 pub use tx_manager_trait::MockTxManager;
 
+#[cfg(test)]
+pub use untrusted_interfaces::MockUntrustedInterfaces;
+
 struct CacheEntry {
     /// An encrypted transaction that has been found to be well-formed.
     encrypted_tx: WellFormedEncryptedTx,
@@ -86,8 +90,19 @@ impl<E: ConsensusEnclave + Send, UI: UntrustedInterfaces + Send> TxManagerImpl<E
         let _metrics_timer = counters::WELL_FORMED_CHECK_TIME.start_timer();
 
         // The untrusted part of the well-formed check.
-        let (current_block_index, highest_index_proofs) =
-            self.untrusted.well_formed_check(&tx_context)?;
+        let untrusted_result = self.untrusted.well_formed_check(&tx_context);
+
+        self.is_well_formed_in_enclave(tx_context, untrusted_result)
+    }
+
+    /// Performs the enclave part of the well-formed checks, given the result
+    /// of the untrusted part.
+    fn is_well_formed_in_enclave(
+        &self,
+        tx_context: TxContext,
+        untrusted_result: TransactionValidationResult<(u64, Vec<TxOutMembershipProof>)>,
+    ) -> TxManagerResult<CacheEntry> {
+        let (current_block_index, highest_index_proofs) = untrusted_result?;
 
         // The enclave part of the well-formed check.
         let (well_formed_encrypted_tx, well_formed_tx_context) = self.enclave.tx_is_well_formed(
@@ -102,6 +117,23 @@ impl<E: ConsensusEnclave + Send, UI: UntrustedInterfaces + Send> TxManagerImpl<E
         })
     }
 
+    /// Add a well-formed transaction to the cache.
+    fn add_to_cache(&self, tx_hash: TxHash, entry: CacheEntry) -> TxHash {
+        {
+            let mut cache = self.lock_cache();
+            cache.insert(tx_hash, entry);
+            counters::TX_CACHE_NUM_ENTRIES.set(cache.len() as i64);
+        }
+
+        log::trace!(
+            self.logger,
+            "Cached well-formed transaction {hash}",
+            hash = tx_hash.to_string(),
+        );
+
+        tx_hash
+    }
+
     fn lock_cache(&self) -> MutexGuard<HashMap<TxHash, CacheEntry>> {
         self.cache.lock().expect("Lock poisoned")
     }
@@ -124,20 +156,39 @@ impl<E: ConsensusEnclave + Send, UI: UntrustedInterfaces + Send> TxManager
         }
 
         let new_entry = self.is_well_formed(tx_context)?;
+        Ok(self.add_to_cache(tx_hash, new_entry))
+    }
+
+    /// Insert a batch of transactions into the cache, checking them all
+    /// against the same ledger. Returns one result per transaction, in order.
+    fn insert_batch(&self, tx_contexts: Vec<TxContext>) -> Vec<TxManagerResult<TxHash>> {
+        let mut results: Vec<TxManagerResult<TxHash>> = tx_contexts
+            .iter()
+            .map(|tx_context| Ok(tx_context.tx_hash))
+            .collect();
+
+        // Transactions already in the cache are well-formed, and are not
+        // checked again.
+        let (indices, uncached): (Vec<usize>, Vec<TxContext>) = {
+            let cache = self.lock_cache();
+            tx_contexts
+                .into_iter()
+                .enumerate()
+                .filter(|(_index, tx_context)| !cache.contains_key(&tx_context.tx_hash))
+                .unzip()
+        };
 
+        let untrusted_results = self.untrusted.well_formed_check_batch(&uncached);
+        for ((index, tx_context), untrusted_result) in
+            indices.into_iter().zip(uncached).zip(untrusted_results)
         {
-            let mut cache = self.lock_cache();
-            cache.insert(tx_hash, new_entry);
-            counters::TX_CACHE_NUM_ENTRIES.set(cache.len() as i64);
+            let tx_hash = tx_context.tx_hash;
+            results[index] = self
+                .is_well_formed_in_enclave(tx_context, untrusted_result)
+                .map(|new_entry| self.add_to_cache(tx_hash, new_entry));
         }
 
-        log::trace!(
-            self.logger,
-            "Cached well-formed transaction {hash}",
-            hash = tx_hash.to_string(),
-        );
-
-        Ok(tx_hash)
+        results
     }
 
     /// Remove expired transactions from the cache and return their hashes.
@@ -172,6 +223,12 @@ impl<E: ConsensusEnclave + Send, UI: UntrustedInterfaces + Send> TxManager
         expired
     }
 
+    /// Called after blocks are appended to the ledger, which now contains
+    /// `num_blocks` blocks.
+    fn blocks_appended(&self, num_blocks: u64) {
+        self.untrusted.blocks_appended(num_blocks);
+    }
+
     /// Returns true if the cache contains the corresponding transaction.
     fn contains(&self, tx_hash: &TxHash) -> bool {
         self.lock_cache().contains_key(tx_hash)
@@ -204,6 +261,45 @@ impl<E: ConsensusEnclave + Send, UI: UntrustedInterfaces + Send> TxManager
         }
     }
 
+    /// Validate the transactions corresponding to the given hashes, all
+    /// against the same ledger. Returns one result per hash, in order.
+    fn validate_batch(&self, tx_hashes: &[TxHash]) -> Vec<TxManagerResult<()>> {
+        let mut results: Vec<TxManagerResult<()>> = tx_hashes
+            .iter()
+            .map(|tx_hash| Err(TxManagerError::NotInCache(vec![*tx_hash])))
+            .collect();
+
+        let (indices, contexts): (Vec<usize>, Vec<Arc<WellFormedTxContext>>) = {
+            let cache = self.lock_cache();
+            tx_hashes
+                .iter()
+                .enumerate()
+                .filter_map(|(index, tx_hash)| {
+                    cache
+                        .get(tx_hash)
+                        .map(|entry| (index, entry.context.clone()))
+                })
+                .unzip()
+        };
+        if indices.len() < tx_hashes.len() {
+            log::error!(
+                self.logger,
+                "attempting to validate {} non-existent tx hashes",
+                tx_hashes.len() - indices.len()
+            );
+        }
+
+        let _timer = counters::VALIDATE_TX_TIME.start_timer();
+        for (index, result) in indices
+            .into_iter()
+            .zip(self.untrusted.is_valid_batch(&contexts))
+        {
+            results[index] = result.map_err(TxManagerError::from);
+        }
+
+        results
+    }
+
     /// Combines the transactions that correspond to the given hashes.
     fn combine(&self, tx_hashes: &[TxHash]) -> TxManagerResult<Vec<TxHash>> {
         let tx_hashes: HashSet<&TxHash> = tx_hashes.iter().clone().collect(); // Dedup
@@ -347,6 +443,13 @@ impl<E: ConsensusEnclave + Send, UI: UntrustedInterfaces + Send> TxManager
             .get(tx_hash)
             .map(|entry| entry.encrypted_tx().clone())
     }
+
+    /// Get the context of the transaction corresponding to the given hash.
+    fn get_tx_context(&self, tx_hash: &TxHash) -> Option<Arc<WellFormedTxContext>> {
+        self.lock_cache()
+            .get(tx_hash)
+            .map(|entry| entry.context().clone())
+    }
 }
 
 #[cfg(test)]
@@ -646,6 +749,114 @@ mod tests {
         }
     }
 
+    #[test_with_logger]
+    // Should validate the cached transactions in a single untrusted call, and
+    // report the rest as not in the cache, in the order requested.
+    fn test_validate_batch(logger: Logger) {
+        let tx_hashes: Vec<_> = (0..3).map(|i| TxHash([i as u8; 32])).collect();
+
+        let mut mock_untrusted = MockUntrustedInterfaces::new();
+        mock_untrusted
+            .expect_is_valid_batch()
+            .withf(|contexts| contexts.len() == 2)
+            .times(1)
+            .return_const(vec![
+                Ok(()),
+                Err(TransactionValidationError::ContainsSpentKeyImage),
+            ]);
+
+        let mock_enclave = MockConsensusEnclave::new();
+        let tx_manager = TxManagerImpl::new(mock_enclave, mock_untrusted, logger.clone());
+
+        // Add the first and last transactions to the cache.
+        for tx_hash in [&tx_hashes[0], &tx_hashes[2]].iter() {
+            let cache_entry = CacheEntry {
+                encrypted_tx: Default::default(),
+                context: Arc::new(Default::default()),
+            };
+            tx_manager
+                .cache
+                .lock()
+                .unwrap()
+                .insert(**tx_hash, cache_entry);
+        }
+
+        let results = tx_manager.validate_batch(&tx_hashes);
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        match &results[1] {
+            Err(TxManagerError::NotInCache(not_found)) => {
+                assert_eq!(not_found, &vec![tx_hashes[1]])
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match &results[2] {
+            Err(TxManagerError::TransactionValidation(
+                TransactionValidationError::ContainsSpentKeyImage,
+            )) => {} // This is expected.
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test_with_logger]
+    // Should check the uncached transactions in a single untrusted call, and
+    // cache those which are well-formed.
+    fn test_insert_batch(logger: Logger) {
+        let tx_contexts: Vec<_> = (0..3)
+            .map(|i| {
+                let mut tx_context = TxContext::default();
+                tx_context.tx_hash = TxHash([i as u8; 32]);
+                tx_context
+            })
+            .collect();
+
+        // The first transaction is already cached, so only the others are
+        // checked. The last one is not well-formed.
+        let mut mock_untrusted = MockUntrustedInterfaces::new();
+        mock_untrusted
+            .expect_well_formed_check_batch()
+            .withf(|tx_contexts| tx_contexts.len() == 2)
+            .times(1)
+            .return_const(vec![
+                Ok((0, vec![])),
+                Err(TransactionValidationError::ContainsSpentKeyImage),
+            ]);
+
+        let mut mock_enclave = MockConsensusEnclave::new();
+        mock_enclave
+            .expect_tx_is_well_formed()
+            .times(1)
+            .return_const(Ok((
+                WellFormedEncryptedTx::default(),
+                WellFormedTxContext::default(),
+            )));
+
+        let tx_manager = TxManagerImpl::new(mock_enclave, mock_untrusted, logger.clone());
+        let cache_entry = CacheEntry {
+            encrypted_tx: Default::default(),
+            context: Arc::new(Default::default()),
+        };
+        tx_manager
+            .cache
+            .lock()
+            .unwrap()
+            .insert(tx_contexts[0].tx_hash, cache_entry);
+
+        let tx_hashes: Vec<_> = tx_contexts
+            .iter()
+            .map(|tx_context| tx_context.tx_hash)
+            .collect();
+        let results = tx_manager.insert_batch(tx_contexts);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().ok(), Some(&tx_hashes[0]));
+        assert_eq!(results[1].as_ref().ok(), Some(&tx_hashes[1]));
+        assert!(results[2].is_err());
+
+        assert_eq!(tx_manager.num_entries(), 2);
+        assert!(tx_manager.contains(&tx_hashes[1]));
+        assert!(!tx_manager.contains(&tx_hashes[2]));
+    }
+
     #[test_with_logger]
     // Should return Ok if the transactions are in the cache.
     fn test_combine_ok(logger: Logger) {
@@ -1102,6 +1313,40 @@ mod tests {
         assert_eq!(tx_manager.get_encrypted_tx(&TxHash([88u8; 32])), None);
     }
 
+    #[test_with_logger]
+    // Should return cache_entry.context if it is in the cache.
+    fn test_get_tx_context(logger: Logger) {
+        let mock_untrusted = MockUntrustedInterfaces::new();
+        let mock_enclave = MockConsensusEnclave::new();
+        let tx_manager = TxManagerImpl::new(mock_enclave, mock_untrusted, logger.clone());
+
+        // Add a transaction to the cache.
+        let tx_hash = TxHash([1u8; 32]);
+        let context = Arc::new(WellFormedTxContext::new(
+            1234,
+            tx_hash.clone(),
+            100,
+            vec![],
+            vec![],
+            vec![],
+        ));
+        let cache_entry = CacheEntry {
+            encrypted_tx: WellFormedEncryptedTx(vec![1, 2, 3]),
+            context: context.clone(),
+        };
+        tx_manager
+            .cache
+            .lock()
+            .unwrap()
+            .insert(tx_hash.clone(), cache_entry);
+
+        // Get something that is in the cache.
+        assert_eq!(tx_manager.get_tx_context(&tx_hash), Some(context));
+
+        // Get something that is not in the cache.
+        assert_eq!(tx_manager.get_tx_context(&TxHash([88u8; 32])), None);
+    }
+
     #[test_with_logger]
     // Should return the number of elements in the cache.
     fn test_get_num_entries(logger: Logger) {
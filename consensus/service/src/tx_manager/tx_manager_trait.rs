@@ -3,8 +3,9 @@
 use crate::tx_manager::TxManagerResult;
 use mc_attest_enclave_api::{EnclaveMessage, PeerSession};
 use mc_common::HashSet;
-use mc_consensus_enclave::{TxContext, WellFormedEncryptedTx};
+use mc_consensus_enclave::{TxContext, WellFormedEncryptedTx, WellFormedTxContext};
 use mc_transaction_core::{tx::TxHash, Block, BlockContents, BlockSignature};
+use std::sync::Arc;
 
 #[cfg(test)]
 use mockall::*;
@@ -15,12 +16,20 @@ pub trait TxManager: Send {
     /// well-formed.
     fn insert(&self, tx_context: TxContext) -> TxManagerResult<TxHash>;
 
+    /// Insert a batch of transactions into the cache, checking them all
+    /// against the same ledger. Returns one result per transaction, in order.
+    fn insert_batch(&self, tx_contexts: Vec<TxContext>) -> Vec<TxManagerResult<TxHash>>;
+
     /// Remove expired transactions from the cache and return their hashes.
     ///
     /// # Arguments
     /// * `block_index` - Current block index.
     fn remove_expired(&self, block_index: u64) -> HashSet<TxHash>;
 
+    /// Called after blocks are appended to the ledger, which now contains
+    /// `num_blocks` blocks.
+    fn blocks_appended(&self, num_blocks: u64);
+
     /// Returns true if the cache contains the corresponding transaction.
     fn contains(&self, tx_hash: &TxHash) -> bool;
 
@@ -31,6 +40,10 @@ pub trait TxManager: Send {
     /// current ledger.
     fn validate(&self, tx_hash: &TxHash) -> TxManagerResult<()>;
 
+    /// Validate the transactions corresponding to the given hashes, all
+    /// against the same ledger. Returns one result per hash, in order.
+    fn validate_batch(&self, tx_hashes: &[TxHash]) -> Vec<TxManagerResult<()>>;
+
     /// Combines the transactions that correspond to the given hashes.
     fn combine(&self, tx_hashes: &[TxHash]) -> TxManagerResult<Vec<TxHash>>;
 
@@ -58,4 +71,7 @@ pub trait TxManager: Send {
 
     /// Get the encrypted transaction corresponding to the given hash.
     fn get_encrypted_tx(&self, tx_hash: &TxHash) -> Option<WellFormedEncryptedTx>;
+
+    /// Get the context of the transaction corresponding to the given hash.
+    fn get_tx_context(&self, tx_hash: &TxHash) -> Option<Arc<WellFormedTxContext>>;
 }
@@ -26,6 +26,30 @@ pub trait UntrustedInterfaces: Send + Sync {
     /// Checks if a transaction is valid (see definition in validators.rs).
     fn is_valid(&self, context: Arc<WellFormedTxContext>) -> TransactionValidationResult<()>;
 
+    /// Performs the untrusted part of the well-formed check for each of a
+    /// batch of transactions, which should all see the same ledger.
+    fn well_formed_check_batch(
+        &self,
+        tx_contexts: &[TxContext],
+    ) -> Vec<TransactionValidationResult<(u64, Vec<TxOutMembershipProof>)>> {
+        tx_contexts
+            .iter()
+            .map(|tx_context| self.well_formed_check(tx_context))
+            .collect()
+    }
+
+    /// Checks if each of a batch of transactions is valid, all against the
+    /// same ledger.
+    fn is_valid_batch(
+        &self,
+        contexts: &[Arc<WellFormedTxContext>],
+    ) -> Vec<TransactionValidationResult<()>> {
+        contexts
+            .iter()
+            .map(|context| self.is_valid(context.clone()))
+            .collect()
+    }
+
     /// Combines a set of "candidate values" into a "composite value".
     /// This assumes all values are well-formed and safe to append to the ledger
     /// individually.
@@ -44,4 +68,8 @@ pub trait UntrustedInterfaces: Send + Sync {
         &self,
         indexes: &[u64],
     ) -> TransactionValidationResult<Vec<TxOutMembershipProof>>;
+
+    /// Called after blocks are appended to the ledger, which now contains
+    /// `num_blocks` blocks, to update any state derived from the ledger.
+    fn blocks_appended(&self, _num_blocks: u64) {}
 }
@@ -22,159 +22,1094 @@
 //! module - the check provided by it is actually the "Is well formed" check,
 //! and might be renamed in the future to match this.
 
-use crate::tx_manager::UntrustedInterfaces as TxManagerUntrustedInterfaces;
+use crate::{
+    public_key_filter::PublicKeyFilter,
+    tx_manager::UntrustedInterfaces as TxManagerUntrustedInterfaces,
+};
+use digest::Update;
+use mc_common::{
+    logger::{create_null_logger, log, Logger},
+    HashMap, LruCache,
+};
 use mc_consensus_enclave::{TxContext, WellFormedTxContext};
+use mc_crypto_hashes::Blake2b256;
 use mc_crypto_keys::CompressedRistrettoPublic;
 use mc_ledger_db::Ledger;
 use mc_transaction_core::{
+    constants::{MAX_INPUTS, RING_SIZE, TX_VERSION},
     ring_signature::KeyImage,
     tx::{TxHash, TxOutMembershipProof},
     validation::{validate_tombstone, TransactionValidationError, TransactionValidationResult},
+    BlockIndex,
+};
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    iter::FromIterator,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
 };
-use std::{collections::HashSet, iter::FromIterator, sync::Arc};
+
+/// Default maximum number of `is_valid` results retained at a given ledger
+/// height.
+pub const DEFAULT_IS_VALID_CACHE_SIZE: usize = 10_000;
+
+/// Default maximum number of elements in each of a transaction's rings.
+pub const DEFAULT_MAX_RING_SIZE: usize = RING_SIZE;
+
+/// Default number of remaining blocks at or below which
+/// `is_valid_with_warnings` warns that a transaction is about to expire.
+pub const DEFAULT_TOMBSTONE_WARNING_MARGIN: u64 = 1;
+
+/// Domain separator for the keys `BlockOrdering::SeededShuffle` sorts by.
+const BLOCK_SHUFFLE_DOMAIN_TAG: &[u8] = b"mc_consensus_block_shuffle";
+
+/// Results of `is_valid` computed against a single ledger height.
+struct IsValidCache {
+    /// The number of blocks in the ledger when these results were computed.
+    num_blocks: u64,
+
+    /// Validation results, keyed by transaction hash.
+    results: LruCache<TxHash, TransactionValidationResult<()>>,
+}
+
+/// Decides the order in which `combine` considers candidate transactions.
+///
+/// `combine` walks the candidates in this order, skipping any that conflict
+/// with an already-selected transaction, until it has selected the maximum
+/// number of transactions.
+pub trait CombineStrategy: Send + Sync {
+    fn order(&self, candidates: &[Arc<WellFormedTxContext>]) -> Vec<Arc<WellFormedTxContext>>;
+}
+
+/// Considers candidates in block sort order, i.e. highest fee first.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FeePriorityCombineStrategy;
+
+impl CombineStrategy for FeePriorityCombineStrategy {
+    fn order(&self, candidates: &[Arc<WellFormedTxContext>]) -> Vec<Arc<WellFormedTxContext>> {
+        // WellFormedTxContext defines the sort order of transactions within a block.
+        let mut candidates = candidates.to_vec();
+        candidates.sort();
+        candidates
+    }
+}
+
+/// Groups candidates into coarse fee tiers and, within each tier, round-robins
+/// across senders so that no single sender can crowd out the others.
+///
+/// Higher fee tiers are always considered before lower ones. Within a tier,
+/// each sender's transactions are taken in block sort order.
+pub struct FairCombineStrategy<F> {
+    /// The range of fees covered by a single tier.
+    tier_width: u64,
+
+    /// Maps a transaction to the group (e.g. sender) it belongs to.
+    group_key: F,
+}
+
+impl<F> FairCombineStrategy<F> {
+    /// Create a strategy with fee tiers `tier_width` wide, grouping
+    /// transactions by `group_key`.
+    pub fn new(tier_width: u64, group_key: F) -> Self {
+        Self {
+            tier_width: tier_width.max(1),
+            group_key,
+        }
+    }
+}
+
+impl<K, F> CombineStrategy for FairCombineStrategy<F>
+where
+    K: Ord,
+    F: Fn(&WellFormedTxContext) -> K + Send + Sync,
+{
+    fn order(&self, candidates: &[Arc<WellFormedTxContext>]) -> Vec<Arc<WellFormedTxContext>> {
+        let sorted = FeePriorityCombineStrategy.order(candidates);
+
+        let mut ordered = Vec::with_capacity(sorted.len());
+        let mut remaining = &sorted[..];
+        while let Some(first) = remaining.first() {
+            // Candidates are sorted by decreasing fee, so each tier is a contiguous run.
+            let tier = first.fee() / self.tier_width;
+            let tier_len = remaining
+                .iter()
+                .take_while(|candidate| candidate.fee() / self.tier_width == tier)
+                .count();
+            let (tier_candidates, rest) = remaining.split_at(tier_len);
+            remaining = rest;
+
+            // Queue each group's candidates, ordering groups by their best candidate.
+            let mut group_index: BTreeMap<K, usize> = BTreeMap::new();
+            let mut queues: Vec<VecDeque<Arc<WellFormedTxContext>>> = Vec::new();
+            for candidate in tier_candidates {
+                let index = *group_index
+                    .entry((self.group_key)(candidate))
+                    .or_insert_with(|| {
+                        queues.push(VecDeque::new());
+                        queues.len() - 1
+                    });
+                queues[index].push_back(candidate.clone());
+            }
+
+            // Take one candidate from each group in turn.
+            while ordered.len() < sorted.len() - remaining.len() {
+                for queue in queues.iter_mut() {
+                    if let Some(candidate) = queue.pop_front() {
+                        ordered.push(candidate);
+                    }
+                }
+            }
+        }
+
+        ordered
+    }
+}
+
+/// Why `combine` left a candidate transaction out of a block.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RejectReason {
+    /// The transaction pays less than the minimum fee for inclusion.
+    FeeBelowMinimum,
+
+    /// The block was already full, or had no room left for the transaction's
+    /// outputs.
+    SizeLimit,
+
+    /// The transaction uses a key image that an earlier candidate uses, or
+    /// that was excluded.
+    KeyImageConflict,
+
+    /// The transaction uses an output public key that an earlier candidate
+    /// uses, or that was excluded.
+    OutputPublicKeyConflict,
+}
+
+/// Something about a valid transaction which makes it unlikely to reach the
+/// ledger.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ValidationWarning {
+    /// The transaction can only be included in the next `blocks_remaining`
+    /// blocks.
+    TombstoneNearlyExpired { blocks_remaining: u64 },
+}
+
+/// The transactions `combine` selected for a block, and those it left out.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CombineResult {
+    /// Selected transactions, in block order.
+    pub selected: Vec<TxHash>,
+
+    /// Rejected transactions, in the order they were considered, with the
+    /// reason each was rejected.
+    pub rejected: Vec<(TxHash, RejectReason)>,
+
+    /// The total fee paid by the selected transactions, saturating at
+    /// `u64::MAX`.
+    pub selected_total_fee: u64,
+}
+
+/// The order in which `combine_with_ordering` lists the transactions it
+/// selects.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlockOrdering {
+    /// The order `combine` selects them in, i.e. highest fee first.
+    Fee,
+
+    /// A pseudorandom order determined by the seed and the transaction
+    /// hashes.
+    SeededShuffle([u8; 32]),
+}
 
 #[derive(Clone)]
 pub struct DefaultTxManagerUntrustedInterfaces<L: Ledger> {
     ledger: L,
+
+    /// Decides the order in which `combine` considers candidates.
+    combine_strategy: Arc<dyn CombineStrategy>,
+
+    /// `combine` leaves out transactions paying less than this fee.
+    min_combine_fee: u64,
+
+    /// The most outputs the transactions `combine` selects may have in total,
+    /// if limited.
+    max_block_outputs: Option<usize>,
+
+    /// Outputs added to each block after `combine` runs, which are subtracted
+    /// from `max_block_outputs`.
+    reserved_outputs: usize,
+
+    /// The largest ring `well_formed_check` will fetch membership proofs for.
+    max_ring_size: usize,
+
+    /// The transaction versions `well_formed_check` accepts.
+    supported_tx_versions: RangeInclusive<u32>,
+
+    /// `is_valid_with_warnings` warns about transactions which expire within
+    /// this many blocks.
+    tombstone_warning_margin: u64,
+
+    /// Cached `is_valid` results, keyed by (tx_hash, num_blocks). Entries are
+    /// discarded whenever the ledger's height changes.
+    is_valid_cache: Arc<Mutex<IsValidCache>>,
+
+    /// Output public keys in the ledger, if `is_valid` should screen keys
+    /// before looking them up. Filled in by `blocks_appended`, and only read
+    /// by `is_valid`.
+    public_key_filter: Option<Arc<RwLock<PublicKeyFilter>>>,
+
+    /// The number of transactions rejected for each reason, as reported by
+    /// `rejection_stats`.
+    rejection_counts: Arc<Mutex<HashMap<&'static str, u64>>>,
+
+    logger: Logger,
 }
 
 impl<L: Ledger + Sync> DefaultTxManagerUntrustedInterfaces<L> {
     pub fn new(ledger: L) -> Self {
-        Self { ledger }
+        Self::new_with_cache_size(ledger, DEFAULT_IS_VALID_CACHE_SIZE)
     }
-}
 
-impl<L: Ledger + Sync> TxManagerUntrustedInterfaces for DefaultTxManagerUntrustedInterfaces<L> {
-    /// Performs **only** the non-enclave part of the well-formed check.
+    /// Create an instance that retains at most `cache_size` `is_valid` results
+    /// per ledger height.
     ///
-    /// Returns the local ledger's block index and membership proofs for each
-    /// highest index.
-    fn well_formed_check(
-        &self,
-        tx_context: &TxContext,
-    ) -> TransactionValidationResult<(u64, Vec<TxOutMembershipProof>)> {
-        // The transaction's membership proofs must reference data contained in the
-        // ledger. This check could fail if the local ledger is behind the
-        // network's consensus ledger.
-        let membership_proofs =
-            self.get_tx_out_proof_of_memberships(&tx_context.highest_indices)?;
+    /// # Panics
+    /// If `cache_size` is zero. The cache cannot be disabled.
+    pub fn new_with_cache_size(ledger: L, cache_size: usize) -> Self {
+        assert!(cache_size > 0, "is_valid cache size must be non-zero");
+        Self {
+            ledger,
+            combine_strategy: Arc::new(FeePriorityCombineStrategy),
+            min_combine_fee: 0,
+            max_block_outputs: None,
+            reserved_outputs: 0,
+            max_ring_size: DEFAULT_MAX_RING_SIZE,
+            supported_tx_versions: TX_VERSION..=TX_VERSION,
+            tombstone_warning_margin: DEFAULT_TOMBSTONE_WARNING_MARGIN,
+            is_valid_cache: Arc::new(Mutex::new(IsValidCache {
+                num_blocks: 0,
+                results: LruCache::new(cache_size),
+            })),
+            public_key_filter: None,
+            rejection_counts: Arc::new(Mutex::new(HashMap::default())),
+            logger: create_null_logger(),
+        }
+    }
 
-        // Note: It is possible that the proofs above are obtained for a different block
-        // index as a new block could be written between getting the proofs and
-        // the call to num_blocks().
-        let num_blocks = self
-            .ledger
-            .num_blocks()
-            .map_err(|e| TransactionValidationError::Ledger(e.to_string()))?;
+    /// Log each rejected transaction to `logger`.
+    pub fn with_logger(mut self, logger: Logger) -> Self {
+        self.logger = logger;
+        self
+    }
 
-        Ok((num_blocks - 1, membership_proofs))
+    /// The number of transactions rejected by `well_formed_check` and
+    /// `is_valid` for each reason, keyed by the name of the
+    /// `TransactionValidationError` variant.
+    ///
+    /// A transaction rejected more than once is counted each time.
+    pub fn rejection_stats(&self) -> HashMap<&'static str, u64> {
+        self.rejection_counts.lock().expect("Lock poisoned").clone()
     }
 
-    /// Checks if a transaction is valid (see definition at top of this file).
-    fn is_valid(&self, context: Arc<WellFormedTxContext>) -> TransactionValidationResult<()> {
-        let current_block_index = self
-            .ledger
-            .num_blocks()
-            .map_err(|e| TransactionValidationError::Ledger(e.to_string()))?;
+    /// Log and count a rejection of the given transaction.
+    fn record_rejection(&self, tx_hash: &TxHash, err: &TransactionValidationError) {
+        log::debug!(
+            self.logger,
+            "Rejected transaction {hash}: {reason}",
+            hash = tx_hash.to_string(),
+            reason = err,
+        );
+        *self
+            .rejection_counts
+            .lock()
+            .expect("Lock poisoned")
+            .entry(rejection_reason(err))
+            .or_insert(0) += 1;
+    }
 
-        // The transaction must not have expired, and the tombstone block must not be
-        // too far in the future.
-        validate_tombstone(current_block_index, context.tombstone_block())?;
+    /// Use the given strategy to decide which transactions `combine` selects.
+    pub fn with_combine_strategy(
+        mut self,
+        combine_strategy: impl CombineStrategy + 'static,
+    ) -> Self {
+        self.combine_strategy = Arc::new(combine_strategy);
+        self
+    }
 
-        // The `key_images` must not have already been spent.
-        let contains_spent_key_image = context
-            .key_images()
-            .iter()
-            .any(|key_image| self.ledger.contains_key_image(key_image).unwrap_or(true));
+    /// Leave transactions paying less than `min_fee` out of the blocks
+    /// `combine` builds, even when there is room for them.
+    ///
+    /// This is consensus configuration, as `combine` must give every node the
+    /// same result: every node in the network must use the same `min_fee`.
+    /// Nodes take it from `NetworkConfig::min_combine_fee`.
+    pub fn with_min_combine_fee(mut self, min_fee: u64) -> Self {
+        self.min_combine_fee = min_fee;
+        self
+    }
 
-        if contains_spent_key_image {
-            // At least one key image was spent, or the ledger returned an error.
-            return Err(TransactionValidationError::ContainsSpentKeyImage);
+    /// Limit the total number of outputs of the transactions `combine` selects
+    /// for a block. Transactions whose outputs would not fit are left out, and
+    /// smaller ones considered after them may still be selected.
+    pub fn with_max_block_outputs(mut self, max_block_outputs: usize) -> Self {
+        self.max_block_outputs = Some(max_block_outputs);
+        self
+    }
+
+    /// Leave room for `reserved_outputs` outputs which are added to each block
+    /// after `combine` runs, such as the block's fee output. The reservation
+    /// is subtracted from the limit set by `with_max_block_outputs` before any
+    /// transaction is selected, and has no effect without one.
+    ///
+    /// `combine` only budgets transactions and outputs. It does not know the
+    /// size in bytes of the transactions it selects, so a byte limit on blocks
+    /// must allow for the reserved outputs' bytes wherever it is enforced.
+    pub fn with_reserved_outputs(mut self, reserved_outputs: usize) -> Self {
+        self.reserved_outputs = reserved_outputs;
+        self
+    }
+
+    /// Reject transactions whose rings hold more than `max_ring_size`
+    /// elements on average, before fetching any membership proofs.
+    pub fn with_max_ring_size(mut self, max_ring_size: usize) -> Self {
+        self.max_ring_size = max_ring_size;
+        self
+    }
+
+    /// Reject transactions built for a version outside `supported_tx_versions`,
+    /// before any other check. By default only `TX_VERSION` is accepted.
+    pub fn with_supported_tx_versions(
+        mut self,
+        supported_tx_versions: RangeInclusive<u32>,
+    ) -> Self {
+        self.supported_tx_versions = supported_tx_versions;
+        self
+    }
+
+    /// Screen output public keys with an in-memory bloom filter before looking
+    /// them up in the ledger, sized for `expected_keys` keys at the given false
+    /// positive rate.
+    ///
+    /// A key the filter has not seen is certainly not in the ledger, and is not
+    /// looked up. A key the filter has seen may be a false positive, so it is
+    /// always looked up, and the ledger's answer is used. The filter only
+    /// changes how many lookups are made, never the result.
+    ///
+    /// The filter is filled here from the blocks already in the ledger, and
+    /// then by `blocks_appended` as blocks are appended. `is_valid` never
+    /// updates the filter. While the filter is behind the ledger, for example
+    /// because a block could not be read, every key is looked up.
+    pub fn with_public_key_filter(
+        mut self,
+        expected_keys: usize,
+        false_positive_rate: f64,
+    ) -> Self {
+        let filter = Arc::new(RwLock::new(PublicKeyFilter::new(
+            expected_keys,
+            false_positive_rate,
+        )));
+        self.public_key_filter = Some(filter);
+        match self.ledger.num_blocks() {
+            Ok(num_blocks) => self.sync_public_key_filter(num_blocks),
+            Err(err) => log::warn!(self.logger, "Failed to read ledger height: {}", err),
         }
+        self
+    }
 
-        // The `output_public_keys` must not appear in the ledger.
-        let contains_existing_public_key = context.output_public_keys().iter().any(|public_key| {
-            self.ledger
-                .contains_tx_out_public_key(public_key)
-                .unwrap_or(true)
-        });
-        if contains_existing_public_key {
-            // At least one public key is already in the ledger, or the ledger returned an
-            // error.
-            return Err(TransactionValidationError::ContainsExistingOutputPublicKey);
+    /// Add the outputs of the ledger's first `num_blocks` blocks to the public
+    /// key filter, if there is one.
+    fn sync_public_key_filter(&self, num_blocks: u64) {
+        if let Some(public_key_filter) = &self.public_key_filter {
+            let mut filter = public_key_filter.write().expect("Lock poisoned");
+            if let Err(err) = filter.sync(&self.ledger, num_blocks) {
+                log::warn!(self.logger, "Failed to update public key filter: {}", err);
+            }
         }
+    }
 
-        // The transaction is valid w.r.t. the current ledger state.
-        Ok(())
+    /// Warn from `is_valid_with_warnings` about transactions which can only be
+    /// included in the next `tombstone_warning_margin` blocks.
+    pub fn with_tombstone_warning_margin(mut self, tombstone_warning_margin: u64) -> Self {
+        self.tombstone_warning_margin = tombstone_warning_margin;
+        self
     }
 
-    /// Combines a set of "candidate values" into a "composite value".
-    /// This assumes all values are well-formed and valid w.r.t the current
-    /// ledger.
+    /// Like `combine`, but also rejects any transaction which uses one of the
+    /// given key images or output public keys.
+    ///
+    /// This lets a caller building consecutive blocks exclude transactions that
+    /// conflict with recently-committed or in-flight blocks, which are not yet
+    /// visible in the candidate set or the ledger.
     ///
     /// # Arguments
     /// * `tx_contexts` - "Candidate" transactions. Each must be well-formed and
     ///   valid.
     /// * `max_elements` - Maximum number of elements to return.
+    /// * `excluded_key_images` - Key images which are already spent.
+    /// * `excluded_output_public_keys` - Output public keys which are already
+    ///   used.
+    pub fn combine_with_excluded(
+        &self,
+        tx_contexts: &[Arc<WellFormedTxContext>],
+        max_elements: usize,
+        excluded_key_images: &HashSet<KeyImage>,
+        excluded_output_public_keys: &HashSet<CompressedRistrettoPublic>,
+    ) -> Vec<TxHash> {
+        self.combine_detailed_with_excluded(
+            tx_contexts,
+            max_elements,
+            excluded_key_images,
+            excluded_output_public_keys,
+        )
+        .selected
+    }
+
+    /// Like `combine`, but also reports each candidate that was left out, and
+    /// why.
     ///
-    /// Returns a bounded, deterministically-ordered list of transactions that
-    /// are safe to append to the ledger.
-    fn combine(
+    /// This lets a caller decide whether a rejected transaction may fit in a
+    /// later block, or conflicts and should be dropped.
+    pub fn combine_detailed(
         &self,
         tx_contexts: &[Arc<WellFormedTxContext>],
         max_elements: usize,
+    ) -> CombineResult {
+        self.combine_detailed_with_excluded(
+            tx_contexts,
+            max_elements,
+            &HashSet::default(),
+            &HashSet::default(),
+        )
+    }
+
+    /// Like `combine`, but lists the selected transactions in the given
+    /// order.
+    ///
+    /// The same transactions are selected, with the same conflicts rejected,
+    /// whatever the ordering; only their order within the block changes.
+    /// Seeding the shuffle with the previous block's ID gives every node the
+    /// same order, without letting the proposer choose it.
+    pub fn combine_with_ordering(
+        &self,
+        tx_contexts: &[Arc<WellFormedTxContext>],
+        max_elements: usize,
+        ordering: BlockOrdering,
     ) -> Vec<TxHash> {
-        // WellFormedTxContext defines the sort order of transactions within a block.
-        let mut candidates: Vec<_> = tx_contexts.to_vec();
-        candidates.sort();
+        let mut selected = self.combine_detailed(tx_contexts, max_elements).selected;
+        if let BlockOrdering::SeededShuffle(seed) = ordering {
+            selected.sort_by_cached_key(|tx_hash| shuffle_key(&seed, tx_hash));
+        }
+        selected
+    }
+
+    fn combine_detailed_with_excluded(
+        &self,
+        tx_contexts: &[Arc<WellFormedTxContext>],
+        max_elements: usize,
+        excluded_key_images: &HashSet<KeyImage>,
+        excluded_output_public_keys: &HashSet<CompressedRistrettoPublic>,
+    ) -> CombineResult {
+        let candidates = self.combine_strategy.order(tx_contexts);
 
         // Allow transactions that do not cause duplicate key images or output public
         // keys.
-        let mut allowed_hashes = Vec::new();
-        let mut used_key_images: HashSet<&KeyImage> = HashSet::default();
-        let mut used_output_public_keys: HashSet<&CompressedRistrettoPublic> = HashSet::default();
+        let mut result = CombineResult::default();
+        let mut used_key_images: HashSet<&KeyImage> = excluded_key_images.iter().collect();
+        let mut used_output_public_keys: HashSet<&CompressedRistrettoPublic> =
+            excluded_output_public_keys.iter().collect();
+        let output_budget = self
+            .max_block_outputs
+            .map(|max_block_outputs| max_block_outputs.saturating_sub(self.reserved_outputs));
+        let mut selected_outputs = 0;
 
         for candidate in &candidates {
+            let tx_hash = *candidate.tx_hash();
+
+            // Leave out dust, however much room is left.
+            if candidate.fee() < self.min_combine_fee {
+                result
+                    .rejected
+                    .push((tx_hash, RejectReason::FeeBelowMinimum));
+                continue;
+            }
+
             // Enforce maximum size.
-            if allowed_hashes.len() >= max_elements {
-                break;
+            if result.selected.len() >= max_elements {
+                result.rejected.push((tx_hash, RejectReason::SizeLimit));
+                continue;
+            }
+
+            // Leave out transactions whose outputs do not fit alongside the
+            // reserved outputs.
+            let num_outputs = candidate.output_public_keys().len();
+            if let Some(output_budget) = output_budget {
+                if selected_outputs + num_outputs > output_budget {
+                    result.rejected.push((tx_hash, RejectReason::SizeLimit));
+                    continue;
+                }
             }
 
             // Reject a transaction that includes a previously used key image.
             let key_images: HashSet<&KeyImage> = HashSet::from_iter(candidate.key_images());
             if !used_key_images.is_disjoint(&key_images) {
+                result
+                    .rejected
+                    .push((tx_hash, RejectReason::KeyImageConflict));
                 continue;
             }
 
             // Reject a transaction that includes a previously used output public key.
             let output_public_keys = HashSet::from_iter(candidate.output_public_keys());
             if !used_output_public_keys.is_disjoint(&output_public_keys) {
+                result
+                    .rejected
+                    .push((tx_hash, RejectReason::OutputPublicKeyConflict));
                 continue;
             }
 
             // The transaction is allowed.
-            allowed_hashes.push(*candidate.tx_hash());
+            result.selected.push(tx_hash);
+            selected_outputs += num_outputs;
+            result.selected_total_fee = result.selected_total_fee.saturating_add(candidate.fee());
             used_key_images.extend(&key_images);
             used_output_public_keys.extend(&output_public_keys);
         }
 
-        allowed_hashes
+        result
+    }
+
+    /// Run `func` against a single view of the ledger's height.
+    ///
+    /// Every validation made through the snapshot uses the same `num_blocks`,
+    /// which is read once rather than per transaction, and membership proofs
+    /// obtained through it are checked to have been created at that height.
+    pub fn with_ledger_snapshot<T>(
+        &self,
+        func: impl FnOnce(&LedgerSnapshot<L>) -> T,
+    ) -> TransactionValidationResult<T> {
+        let ledger_error =
+            |e: mc_ledger_db::Error| TransactionValidationError::Ledger(e.to_string());
+
+        // num_txos is read first, so a block appended between the two reads
+        // leaves num_txos behind num_blocks. Every block adds TxOuts, so any
+        // membership proof obtained afterwards no longer matches num_txos, and
+        // is rejected by `LedgerSnapshot::well_formed_check`.
+        let num_txos = self.ledger.num_txos().map_err(ledger_error)?;
+        let num_blocks = self.ledger.num_blocks().map_err(ledger_error)?;
+        Ok(func(&LedgerSnapshot {
+            untrusted: self,
+            num_blocks,
+            num_txos,
+        }))
+    }
+
+    /// The checks made before touching the ledger: transactions built for an
+    /// unsupported version are turned away, and, as each ring element costs a
+    /// membership proof lookup, so are oversized rings.
+    fn check_tx_shape(&self, tx_context: &TxContext) -> TransactionValidationResult<()> {
+        check_tx_version(tx_context, &self.supported_tx_versions)
+            .and_then(|_| check_ring_size(tx_context, self.max_ring_size))
+    }
+
+    fn lock_is_valid_cache(&self) -> MutexGuard<IsValidCache> {
+        self.is_valid_cache.lock().expect("Lock poisoned")
+    }
+
+    /// Checks if a transaction is valid w.r.t. a ledger containing
+    /// `num_blocks` blocks, using the `is_valid` cache.
+    fn is_valid_cached(
+        &self,
+        context: &WellFormedTxContext,
+        num_blocks: u64,
+    ) -> TransactionValidationResult<()> {
+        {
+            let mut cache = self.lock_is_valid_cache();
+            if cache.num_blocks != num_blocks {
+                // The ledger has changed, so previous results no longer apply.
+                cache.results.clear();
+                cache.num_blocks = num_blocks;
+            } else if let Some(result) = cache.results.get(context.tx_hash()) {
+                return result.clone();
+            }
+        }
+
+        let result = self.is_valid_uncached(context, num_blocks);
+
+        // Ledger errors are transient and should not be remembered.
+        if !matches!(result, Err(TransactionValidationError::Ledger(_))) {
+            let mut cache = self.lock_is_valid_cache();
+            if cache.num_blocks == num_blocks {
+                cache.results.put(*context.tx_hash(), result.clone());
+            }
+        }
+
+        result
+    }
+
+    /// Checks if a transaction is valid w.r.t. a ledger containing
+    /// `num_blocks` blocks.
+    fn is_valid_uncached(
+        &self,
+        context: &WellFormedTxContext,
+        num_blocks: u64,
+    ) -> TransactionValidationResult<()> {
+        // The transaction must not have expired, and the tombstone block must not be
+        // too far in the future.
+        validate_tombstone(num_blocks, context.tombstone_block())?;
+
+        // The `key_images` must not have already been spent.
+        let contains_spent_key_image = context
+            .key_images()
+            .iter()
+            .any(|key_image| self.ledger.contains_key_image(key_image).unwrap_or(true));
+
+        if contains_spent_key_image {
+            // At least one key image was spent, or the ledger returned an error.
+            return Err(TransactionValidationError::ContainsSpentKeyImage);
+        }
+
+        // The `output_public_keys` must not appear in the ledger. Keys the filter
+        // has not seen are certainly absent, so only the rest are looked up. A
+        // failed lookup is a ledger error, which is not cached.
+        let ledger_error =
+            |e: mc_ledger_db::Error| TransactionValidationError::Ledger(e.to_string());
+        for public_key in self.screen_public_keys(context.output_public_keys(), num_blocks) {
+            if self
+                .ledger
+                .contains_tx_out_public_key(public_key)
+                .map_err(ledger_error)?
+            {
+                return Err(TransactionValidationError::ContainsExistingOutputPublicKey);
+            }
+        }
+
+        // The transaction is valid w.r.t. the current ledger state.
+        Ok(())
+    }
+
+    /// The given output public keys which may be in a ledger containing
+    /// `num_blocks` blocks, according to the public key filter.
+    ///
+    /// Every key is returned if there is no filter, or if the filter does not
+    /// yet cover `num_blocks` blocks. The filter is not updated here.
+    fn screen_public_keys<'a>(
+        &self,
+        public_keys: &'a [CompressedRistrettoPublic],
+        num_blocks: u64,
+    ) -> Vec<&'a CompressedRistrettoPublic> {
+        if let Some(public_key_filter) = &self.public_key_filter {
+            let filter = public_key_filter.read().expect("Lock poisoned");
+            // A filter ahead of the ledger only holds extra keys, which are
+            // looked up anyway.
+            if filter.num_blocks() >= num_blocks {
+                return public_keys
+                    .iter()
+                    .filter(|public_key| filter.may_contain(public_key))
+                    .collect();
+            }
+        }
+        public_keys.iter().collect()
+    }
+
+    /// Checks a transaction like `is_valid`, and if it is valid, reports
+    /// anything which makes it unlikely to reach the ledger.
+    ///
+    /// A proposer can use the warnings to deprioritize transactions which are
+    /// about to expire. Invalid transactions are rejected exactly as by
+    /// `is_valid`.
+    pub fn is_valid_with_warnings(
+        &self,
+        context: &WellFormedTxContext,
+    ) -> TransactionValidationResult<Vec<ValidationWarning>> {
+        let num_blocks = self
+            .ledger
+            .num_blocks()
+            .map_err(|e| TransactionValidationError::Ledger(e.to_string()))?;
+        self.is_valid_cached(context, num_blocks)?;
+
+        let mut warnings = Vec::new();
+
+        // A valid transaction's tombstone block is beyond the ledger, so this
+        // is at least one.
+        let blocks_remaining = context.tombstone_block() - num_blocks;
+        if blocks_remaining <= self.tombstone_warning_margin {
+            warnings.push(ValidationWarning::TombstoneNearlyExpired { blocks_remaining });
+        }
+
+        Ok(warnings)
+    }
+
+    /// Checks whether a transaction would have been valid when the ledger held
+    /// only its first `at_block_index` blocks, i.e. as a candidate for block
+    /// `at_block_index`.
+    ///
+    /// The tombstone block is checked against `at_block_index`, and key images
+    /// and output public keys count only if they appear in an earlier block.
+    /// This needs a ledger which can say which block a key image was spent
+    /// in (`Ledger::check_key_image`) and which block holds the output with a
+    /// given public key (`Ledger::get_tx_out_index_by_public_key` and
+    /// `Ledger::get_block_index_by_tx_out_index`). This is intended for
+    /// replaying and auditing, and does not use the `is_valid` cache.
+    ///
+    /// Fails with `TransactionValidationError::Ledger` if the ledger has not
+    /// yet reached `at_block_index`, or cannot answer one of these queries.
+    pub fn is_valid_at(
+        &self,
+        context: &WellFormedTxContext,
+        at_block_index: BlockIndex,
+    ) -> TransactionValidationResult<()> {
+        let ledger_error =
+            |e: mc_ledger_db::Error| TransactionValidationError::Ledger(e.to_string());
+
+        let num_blocks = self.ledger.num_blocks().map_err(ledger_error)?;
+        if at_block_index > num_blocks {
+            return Err(TransactionValidationError::Ledger(format!(
+                "Cannot validate at block {}, the ledger only has {} blocks",
+                at_block_index, num_blocks
+            )));
+        }
+
+        validate_tombstone(at_block_index, context.tombstone_block())?;
+
+        for key_image in context.key_images() {
+            let spent_at = self
+                .ledger
+                .check_key_image(key_image)
+                .map_err(ledger_error)?;
+            if spent_at.map_or(false, |block_index| block_index < at_block_index) {
+                return Err(TransactionValidationError::ContainsSpentKeyImage);
+            }
+        }
+
+        for public_key in context.output_public_keys() {
+            let tx_out_index = match self.ledger.get_tx_out_index_by_public_key(public_key) {
+                Ok(tx_out_index) => tx_out_index,
+                Err(mc_ledger_db::Error::NotFound) => continue,
+                Err(e) => return Err(ledger_error(e)),
+            };
+            let block_index = self
+                .ledger
+                .get_block_index_by_tx_out_index(tx_out_index)
+                .map_err(ledger_error)?;
+            if block_index < at_block_index {
+                return Err(TransactionValidationError::ContainsExistingOutputPublicKey);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks a transaction against the current ledger like `is_valid`, but
+    /// reports every problem found rather than only the first.
+    ///
+    /// The tombstone block, every key image and every output public key are
+    /// checked, and each violation is reported. Ledger errors are reported as
+    /// such, rather than as a spent key image or existing public key. This is
+    /// intended for diagnosing rejected transactions, and does not use the
+    /// `is_valid` cache.
+    ///
+    /// Returns an empty vector if the transaction is valid.
+    pub fn validate_verbose(
+        &self,
+        context: &WellFormedTxContext,
+    ) -> Vec<TransactionValidationError> {
+        let mut errors = Vec::new();
+
+        match self.ledger.num_blocks() {
+            Ok(num_blocks) => {
+                if let Err(err) = validate_tombstone(num_blocks, context.tombstone_block()) {
+                    errors.push(err);
+                }
+            }
+            Err(err) => errors.push(TransactionValidationError::Ledger(err.to_string())),
+        }
+
+        for key_image in context.key_images() {
+            match self.ledger.contains_key_image(key_image) {
+                Ok(false) => {}
+                Ok(true) => errors.push(TransactionValidationError::ContainsSpentKeyImage),
+                Err(err) => errors.push(TransactionValidationError::Ledger(err.to_string())),
+            }
+        }
+
+        for public_key in context.output_public_keys() {
+            match self.ledger.contains_tx_out_public_key(public_key) {
+                Ok(false) => {}
+                Ok(true) => {
+                    errors.push(TransactionValidationError::ContainsExistingOutputPublicKey)
+                }
+                Err(err) => errors.push(TransactionValidationError::Ledger(err.to_string())),
+            }
+        }
+
+        errors
+    }
+}
+
+/// A view of the ledger at a single height, shared by a batch of validations.
+///
+/// See `DefaultTxManagerUntrustedInterfaces::with_ledger_snapshot`.
+pub struct LedgerSnapshot<'a, L: Ledger> {
+    untrusted: &'a DefaultTxManagerUntrustedInterfaces<L>,
+
+    /// The number of blocks in the ledger when the snapshot was taken.
+    num_blocks: u64,
+
+    /// The number of TxOuts in the ledger when the snapshot was taken.
+    num_txos: u64,
+}
+
+impl<'a, L: Ledger + Sync> LedgerSnapshot<'a, L> {
+    /// The number of blocks in the ledger when the snapshot was taken.
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    /// Performs the untrusted part of the well-formed check at this snapshot's
+    /// height.
+    ///
+    /// Fails with a ledger error if a block was appended since the snapshot
+    /// was taken, as the membership proofs would not match the returned block
+    /// index.
+    pub fn well_formed_check(
+        &self,
+        tx_context: &TxContext,
+    ) -> TransactionValidationResult<(u64, Vec<TxOutMembershipProof>)> {
+        // The transaction's membership proofs must reference data contained in the
+        // ledger. This check could fail if the local ledger is behind the
+        // network's consensus ledger, so an index beyond the ledger is reported
+        // distinctly, and without asking the ledger for proofs it cannot have.
+        if let Some(&requested) = tx_context.highest_indices.iter().max() {
+            if requested >= self.num_txos {
+                return Err(
+                    TransactionValidationError::MembershipProofIndexOutOfBounds {
+                        requested,
+                        ledger_size: self.num_txos,
+                    },
+                );
+            }
+        }
+
+        let membership_proofs = self
+            .untrusted
+            .get_tx_out_proof_of_memberships(&tx_context.highest_indices)?;
+
+        if membership_proofs
+            .iter()
+            .any(|proof| proof.highest_index + 1 != self.num_txos)
+        {
+            return Err(TransactionValidationError::Ledger(
+                "Ledger changed since the snapshot was taken".to_string(),
+            ));
+        }
+
+        Ok((self.num_blocks - 1, membership_proofs))
+    }
+
+    /// Checks if a transaction is valid at this snapshot's height.
+    pub fn is_valid(&self, context: &WellFormedTxContext) -> TransactionValidationResult<()> {
+        self.untrusted.is_valid_cached(context, self.num_blocks)
+    }
+}
+
+impl<L: Ledger + Sync> TxManagerUntrustedInterfaces for DefaultTxManagerUntrustedInterfaces<L> {
+    /// Performs **only** the non-enclave part of the well-formed check.
+    ///
+    /// Returns the local ledger's block index and membership proofs for each
+    /// highest index.
+    fn well_formed_check(
+        &self,
+        tx_context: &TxContext,
+    ) -> TransactionValidationResult<(u64, Vec<TxOutMembershipProof>)> {
+        // A snapshot ensures the proofs were obtained at the returned block
+        // index.
+        let result = self.check_tx_shape(tx_context).and_then(|_| {
+            self.with_ledger_snapshot(|snapshot| snapshot.well_formed_check(tx_context))?
+        });
+        if let Err(err) = &result {
+            self.record_rejection(&tx_context.tx_hash, err);
+        }
+        result
+    }
+
+    /// Performs the untrusted part of the well-formed check for each
+    /// transaction, reading the ledger's height once for the whole batch.
+    fn well_formed_check_batch(
+        &self,
+        tx_contexts: &[TxContext],
+    ) -> Vec<TransactionValidationResult<(u64, Vec<TxOutMembershipProof>)>> {
+        let results = self
+            .with_ledger_snapshot(|snapshot| {
+                tx_contexts
+                    .iter()
+                    .map(|tx_context| {
+                        self.check_tx_shape(tx_context)
+                            .and_then(|_| snapshot.well_formed_check(tx_context))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(|err| vec![Err(err); tx_contexts.len()]);
+        for (tx_context, result) in tx_contexts.iter().zip(&results) {
+            if let Err(err) = result {
+                self.record_rejection(&tx_context.tx_hash, err);
+            }
+        }
+        results
+    }
+
+    /// Checks if a transaction is valid (see definition at top of this file).
+    ///
+    /// Results are cached per ledger height, so re-checking a transaction
+    /// before a new block is appended does not touch the ledger again.
+    fn is_valid(&self, context: Arc<WellFormedTxContext>) -> TransactionValidationResult<()> {
+        let result = self
+            .ledger
+            .num_blocks()
+            .map_err(|e| TransactionValidationError::Ledger(e.to_string()))
+            .and_then(|num_blocks| self.is_valid_cached(&context, num_blocks));
+        if let Err(err) = &result {
+            self.record_rejection(context.tx_hash(), err);
+        }
+        result
+    }
+
+    /// Checks if each transaction is valid, reading the ledger's height once
+    /// for the whole batch.
+    fn is_valid_batch(
+        &self,
+        contexts: &[Arc<WellFormedTxContext>],
+    ) -> Vec<TransactionValidationResult<()>> {
+        let results = self
+            .with_ledger_snapshot(|snapshot| {
+                contexts
+                    .iter()
+                    .map(|context| snapshot.is_valid(context))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(|err| vec![Err(err); contexts.len()]);
+        for (context, result) in contexts.iter().zip(&results) {
+            if let Err(err) = result {
+                self.record_rejection(context.tx_hash(), err);
+            }
+        }
+        results
+    }
+
+    /// Combines a set of "candidate values" into a "composite value".
+    /// This assumes all values are well-formed and valid w.r.t the current
+    /// ledger.
+    ///
+    /// # Arguments
+    /// * `tx_contexts` - "Candidate" transactions. Each must be well-formed and
+    ///   valid.
+    /// * `max_elements` - Maximum number of elements to return.
+    ///
+    /// Returns a bounded, deterministically-ordered list of transactions that
+    /// are safe to append to the ledger.
+    fn combine(
+        &self,
+        tx_contexts: &[Arc<WellFormedTxContext>],
+        max_elements: usize,
+    ) -> Vec<TxHash> {
+        self.combine_detailed(tx_contexts, max_elements).selected
+    }
+
+    fn get_tx_out_proof_of_memberships(
+        &self,
+        indexes: &[u64],
+    ) -> TransactionValidationResult<Vec<TxOutMembershipProof>> {
+        self.ledger
+            .get_tx_out_proof_of_memberships(indexes)
+            .map_err(|e| TransactionValidationError::Ledger(e.to_string()))
+    }
+
+    /// Adds the outputs of newly appended blocks to the public key filter.
+    fn blocks_appended(&self, num_blocks: u64) {
+        self.sync_public_key_filter(num_blocks);
+    }
+}
+
+/// The key a transaction is sorted by in a shuffle with the given seed.
+fn shuffle_key(seed: &[u8; 32], tx_hash: &TxHash) -> [u8; 32] {
+    let mut hasher = Blake2b256::new();
+    hasher.update(BLOCK_SHUFFLE_DOMAIN_TAG);
+    hasher.update(seed);
+    hasher.update(&tx_hash.0);
+    hasher.result().into()
+}
+
+/// Check that a transaction was built for one of the `supported` versions.
+fn check_tx_version(
+    tx_context: &TxContext,
+    supported: &RangeInclusive<u32>,
+) -> TransactionValidationResult<()> {
+    if !supported.contains(&tx_context.tx_version) {
+        return Err(TransactionValidationError::UnsupportedTransactionVersion {
+            found: tx_context.tx_version,
+            supported: (*supported.start(), *supported.end()),
+        });
+    }
+    Ok(())
+}
+
+/// Check that a transaction does not have more ring elements than
+/// `max_ring_size` for each of its inputs.
+///
+/// The untrusted side cannot see individual rings, only a highest index for
+/// every ring element, so this bounds the total. The input count is clamped to
+/// `[1, MAX_INPUTS]`, since the enclave rejects transactions outside it.
+fn check_ring_size(
+    tx_context: &TxContext,
+    max_ring_size: usize,
+) -> TransactionValidationResult<()> {
+    let num_inputs = tx_context.key_images.len().max(1).min(MAX_INPUTS as usize);
+    if tx_context.highest_indices.len() > max_ring_size.saturating_mul(num_inputs) {
+        return Err(TransactionValidationError::ExcessiveRingSize);
     }
+    Ok(())
+}
 
-    fn get_tx_out_proof_of_memberships(
-        &self,
-        indexes: &[u64],
-    ) -> TransactionValidationResult<Vec<TxOutMembershipProof>> {
-        self.ledger
-            .get_tx_out_proof_of_memberships(indexes)
-            .map_err(|e| TransactionValidationError::Ledger(e.to_string()))
+/// The name under which `rejection_stats` counts a rejection.
+fn rejection_reason(err: &TransactionValidationError) -> &'static str {
+    use TransactionValidationError::*;
+    match err {
+        InputsProofsLengthMismatch => "InputsProofsLengthMismatch",
+        NoInputs => "NoInputs",
+        TooManyInputs => "TooManyInputs",
+        InsufficientInputSignatures => "InsufficientInputSignatures",
+        InvalidInputSignature => "InvalidInputSignature",
+        InvalidTransactionSignature(_) => "InvalidTransactionSignature",
+        InvalidRangeProof => "InvalidRangeProof",
+        InsufficientRingSize => "InsufficientRingSize",
+        TombstoneBlockExceeded => "TombstoneBlockExceeded",
+        TombstoneBlockTooFar => "TombstoneBlockTooFar",
+        NoOutputs => "NoOutputs",
+        TooManyOutputs => "TooManyOutputs",
+        ExcessiveRingSize => "ExcessiveRingSize",
+        DuplicateRingElements => "DuplicateRingElements",
+        UnsortedRingElements => "UnsortedRingElements",
+        UnequalRingSizes => "UnequalRingSizes",
+        UnsortedInputs => "UnsortedInputs",
+        UnsortedKeyImages => "UnsortedKeyImages",
+        ContainsSpentKeyImage => "ContainsSpentKeyImage",
+        DuplicateKeyImages => "DuplicateKeyImages",
+        DuplicateOutputPublicKey => "DuplicateOutputPublicKey",
+        ContainsExistingOutputPublicKey => "ContainsExistingOutputPublicKey",
+        MissingTxOutMembershipProof => "MissingTxOutMembershipProof",
+        InvalidTxOutMembershipProof => "InvalidTxOutMembershipProof",
+        InvalidRistrettoPublicKey => "InvalidRistrettoPublicKey",
+        InvalidLedgerContext => "InvalidLedgerContext",
+        Ledger(_) => "Ledger",
+        MembershipProofIndexOutOfBounds { .. } => "MembershipProofIndexOutOfBounds",
+        MembershipProofValidationError => "MembershipProofValidationError",
+        TxFeeError => "TxFeeError",
+        KeyError => "KeyError",
+        UnsupportedTransactionVersion { .. } => "UnsupportedTransactionVersion",
     }
 }
 
 #[cfg(test)]
 pub mod well_formed_tests {
     use super::*;
-    use mc_ledger_db::{Error as LedgerError, MockLedger};
+    use mc_ledger_db::MockLedger;
 
     #[test]
     // `is_well_formed` should accept a well-formed transaction.
@@ -183,21 +1118,23 @@ pub mod well_formed_tests {
 
         // Untrusted should request a proof of membership for each highest index.
         let highest_index_proofs = vec![
-            TxOutMembershipProof::new(1, 1, vec![]),
-            TxOutMembershipProof::new(1, 1, vec![]),
-            TxOutMembershipProof::new(1, 1, vec![]),
+            TxOutMembershipProof::new(33, 44, vec![]),
+            TxOutMembershipProof::new(44, 44, vec![]),
+            TxOutMembershipProof::new(33, 44, vec![]),
         ];
         ledger
             .expect_get_tx_out_proof_of_memberships()
             .times(1)
             .return_const(Ok(highest_index_proofs));
 
-        // Untrusted should request num_blocks.
+        // Untrusted should snapshot num_blocks and num_txos. The proofs above were
+        // created when the ledger held 45 TxOuts.
         let num_blocks = 53;
         ledger
             .expect_num_blocks()
             .times(1)
             .return_const(Ok(num_blocks));
+        ledger.expect_num_txos().times(1).return_const(Ok(45));
 
         let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
 
@@ -207,6 +1144,7 @@ pub mod well_formed_tests {
             highest_indices: vec![33, 44, 33],
             key_images: vec![KeyImage::default(), KeyImage::default()],
             output_public_keys: vec![CompressedRistrettoPublic::default()],
+            tx_version: TX_VERSION,
         };
 
         match untrusted.well_formed_check(&tx_context) {
@@ -223,12 +1161,11 @@ pub mod well_formed_tests {
     /// proof-of-membership with highest index outside the ledger, i.e. a
     /// transaction "from the future".
     fn is_well_formed_rejects_excessive_highest_index() {
-        // The ledger cannot provide membership proofs for highest indices.
+        // Membership proofs must not be fetched for indices beyond the ledger.
         let mut ledger = MockLedger::new();
-        ledger
-            .expect_get_tx_out_proof_of_memberships()
-            .times(1)
-            .return_const(Err(LedgerError::CapacityExceeded));
+        ledger.expect_get_tx_out_proof_of_memberships().times(0);
+        ledger.expect_num_blocks().return_const(Ok(53));
+        ledger.expect_num_txos().return_const(Ok(100));
 
         let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
 
@@ -245,21 +1182,208 @@ pub mod well_formed_tests {
                 // This is expected.
                 assert_eq!(
                     e,
-                    TransactionValidationError::Ledger("CapacityExceeded".to_string())
+                    TransactionValidationError::MembershipProofIndexOutOfBounds {
+                        requested: 10002,
+                        ledger_size: 100,
+                    }
                 );
             }
         }
     }
+
+    #[test]
+    /// `is_well_formed` should reject oversized rings without fetching any
+    /// membership proofs.
+    fn is_well_formed_rejects_excessive_ring_size() {
+        // The ledger must not be read.
+        let ledger = MockLedger::new();
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger).with_max_ring_size(2);
+
+        let mut tx_context = TxContext::default();
+        tx_context.key_images = vec![KeyImage::default(), KeyImage::default()];
+        tx_context.highest_indices = vec![1, 2, 3, 4, 5];
+
+        match untrusted.well_formed_check(&tx_context) {
+            Err(TransactionValidationError::ExcessiveRingSize) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // Up to two elements per input is allowed.
+        tx_context.highest_indices.truncate(4);
+        assert_eq!(check_ring_size(&tx_context, 2), Ok(()));
+    }
+
+    #[test]
+    /// `is_well_formed` should reject transaction versions outside the
+    /// supported range without reading the ledger.
+    fn is_well_formed_rejects_unsupported_tx_version() {
+        // The ledger must not be read.
+        let ledger = MockLedger::new();
+        let untrusted =
+            DefaultTxManagerUntrustedInterfaces::new(ledger).with_supported_tx_versions(2..=3);
+
+        let mut tx_context = TxContext::default();
+
+        // Below the supported range.
+        tx_context.tx_version = 1;
+        match untrusted.well_formed_check(&tx_context) {
+            Err(TransactionValidationError::UnsupportedTransactionVersion {
+                found: 1,
+                supported: (2, 3),
+            }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // Above the supported range.
+        tx_context.tx_version = 4;
+        match untrusted.well_formed_check(&tx_context) {
+            Err(TransactionValidationError::UnsupportedTransactionVersion {
+                found: 4,
+                supported: (2, 3),
+            }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // Within the supported range, including both ends.
+        for tx_version in 2..=3 {
+            tx_context.tx_version = tx_version;
+            assert_eq!(check_tx_version(&tx_context, &(2..=3)), Ok(()));
+        }
+    }
+
+    #[test]
+    /// A batch of checks through a snapshot should read the ledger's height
+    /// once, and reject proofs created after a block was appended.
+    fn snapshot_reads_height_once_and_detects_new_blocks() {
+        let mut ledger = MockLedger::new();
+        let num_blocks = 53;
+        ledger
+            .expect_num_blocks()
+            .times(1)
+            .return_const(Ok(num_blocks));
+        ledger.expect_num_txos().times(1).return_const(Ok(2));
+
+        // The first proof matches the snapshot, the second was created after
+        // another TxOut was appended.
+        let mut proofs = vec![
+            Ok(vec![TxOutMembershipProof::new(1, 1, vec![])]),
+            Ok(vec![TxOutMembershipProof::new(1, 2, vec![])]),
+        ]
+        .into_iter();
+        ledger
+            .expect_get_tx_out_proof_of_memberships()
+            .times(2)
+            .returning(move |_| proofs.next().unwrap());
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+        let mut tx_context = TxContext::default();
+        tx_context.highest_indices = vec![1];
+
+        let (first, second) = untrusted
+            .with_ledger_snapshot(|snapshot| {
+                (
+                    snapshot.well_formed_check(&tx_context),
+                    snapshot.well_formed_check(&tx_context),
+                )
+            })
+            .unwrap();
+
+        assert_eq!(first.unwrap().0, num_blocks - 1);
+        assert_eq!(
+            second,
+            Err(TransactionValidationError::Ledger(
+                "Ledger changed since the snapshot was taken".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    /// A batch of well-formed checks should read the ledger's height once,
+    /// for the whole batch.
+    fn well_formed_check_batch_takes_one_snapshot() {
+        let mut ledger = MockLedger::new();
+        let num_blocks = 53;
+        ledger
+            .expect_num_blocks()
+            .times(1)
+            .return_const(Ok(num_blocks));
+        ledger.expect_num_txos().times(1).return_const(Ok(2));
+        ledger
+            .expect_get_tx_out_proof_of_memberships()
+            .times(3)
+            .return_const(Ok(vec![TxOutMembershipProof::new(1, 1, vec![])]));
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+        let mut tx_context = TxContext::default();
+        tx_context.highest_indices = vec![1];
+
+        let tx_contexts: Vec<_> = (0..3).map(|_| tx_context.clone()).collect();
+
+        let results = untrusted.well_formed_check_batch(&tx_contexts);
+        assert_eq!(results.len(), 3);
+        for result in results {
+            assert_eq!(result.unwrap().0, num_blocks - 1);
+        }
+    }
 }
 
 #[cfg(test)]
 mod is_valid_tests {
     use super::*;
-    use mc_ledger_db::{Error as LedgerError, MockLedger};
+    use mc_ledger_db::{test_utils::get_test_ledger_blocks, Error as LedgerError, MockLedger};
     use mc_transaction_core::{
-        constants::MAX_TOMBSTONE_BLOCKS, validation::TransactionValidationError,
+        constants::MAX_TOMBSTONE_BLOCKS, validation::TransactionValidationError, BlockContents,
     };
 
+    #[test]
+    /// `is_valid_at` should only count key images and outputs from blocks
+    /// before the given one, and should refuse heights the ledger has not
+    /// reached.
+    fn is_valid_at_uses_historical_ledger() {
+        let key_image = KeyImage::from(7);
+        let public_key = CompressedRistrettoPublic::default();
+
+        let mut ledger = MockLedger::new();
+        ledger.expect_num_blocks().return_const(Ok(20));
+        // The key image was spent in block 10.
+        ledger.expect_check_key_image().return_const(Ok(Some(10)));
+        // The output public key is in block 12.
+        ledger
+            .expect_get_tx_out_index_by_public_key()
+            .return_const(Ok(30));
+        ledger
+            .expect_get_block_index_by_tx_out_index()
+            .return_const(Ok(12));
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+
+        let spends =
+            WellFormedTxContext::new(0, Default::default(), 18, vec![key_image], vec![], vec![]);
+        assert_eq!(untrusted.is_valid_at(&spends, 10), Ok(()));
+        assert_eq!(
+            untrusted.is_valid_at(&spends, 11),
+            Err(TransactionValidationError::ContainsSpentKeyImage)
+        );
+
+        let outputs =
+            WellFormedTxContext::new(0, Default::default(), 18, vec![], vec![], vec![public_key]);
+        assert_eq!(untrusted.is_valid_at(&outputs, 12), Ok(()));
+        assert_eq!(
+            untrusted.is_valid_at(&outputs, 13),
+            Err(TransactionValidationError::ContainsExistingOutputPublicKey)
+        );
+
+        // The tombstone block is checked against the given height.
+        assert_eq!(
+            untrusted.is_valid_at(&outputs, 18),
+            Err(TransactionValidationError::TombstoneBlockExceeded)
+        );
+
+        match untrusted.is_valid_at(&spends, 21) {
+            Err(TransactionValidationError::Ledger(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
     #[test]
     /// `is_valid` should accept a valid transaction.
     fn is_valid_ok() {
@@ -346,6 +1470,93 @@ mod is_valid_tests {
         );
     }
 
+    #[test]
+    /// `is_valid_with_warnings` should warn about a valid transaction only once
+    /// its tombstone block is within the margin.
+    fn is_valid_with_warnings_warns_near_tombstone() {
+        // Number of blocks in the local ledger.
+        let num_blocks = 53;
+
+        // Each context needs its own hash, as `is_valid` results are cached.
+        let context = |tombstone_block: u64| {
+            WellFormedTxContext::new(
+                Default::default(),
+                TxHash([tombstone_block as u8; 32]),
+                tombstone_block,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            )
+        };
+
+        // Mock the local ledger.
+        let mut ledger = MockLedger::new();
+        ledger.expect_num_blocks().return_const(Ok(num_blocks));
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+
+        // The transaction can only be included in the next block.
+        assert_eq!(
+            untrusted.is_valid_with_warnings(&context(54)),
+            Ok(vec![ValidationWarning::TombstoneNearlyExpired {
+                blocks_remaining: 1
+            }]),
+        );
+        assert_eq!(untrusted.is_valid_with_warnings(&context(60)), Ok(vec![]));
+
+        // Expired transactions are still rejected outright.
+        assert_eq!(
+            untrusted.is_valid_with_warnings(&context(53)),
+            Err(TransactionValidationError::TombstoneBlockExceeded),
+        );
+
+        let untrusted = untrusted.with_tombstone_warning_margin(10);
+        assert_eq!(
+            untrusted.is_valid_with_warnings(&context(60)),
+            Ok(vec![ValidationWarning::TombstoneNearlyExpired {
+                blocks_remaining: 7
+            }]),
+        );
+    }
+
+    #[test]
+    /// Each rejection should be counted under its reason, including repeated
+    /// rejections served from the cache.
+    fn rejection_stats_count_each_reason() {
+        // Number of blocks in the local ledger.
+        let num_blocks = 53;
+
+        let context = |tombstone_block: u64| {
+            Arc::new(WellFormedTxContext::new(
+                Default::default(),
+                TxHash([tombstone_block as u8; 32]),
+                tombstone_block,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            ))
+        };
+
+        // Mock the local ledger.
+        let mut ledger = MockLedger::new();
+        ledger.expect_num_blocks().return_const(Ok(num_blocks));
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+        assert!(untrusted.rejection_stats().is_empty());
+
+        assert!(untrusted.is_valid(context(17)).is_err());
+        assert!(untrusted.is_valid(context(17)).is_err());
+        assert!(untrusted
+            .is_valid(context(num_blocks + MAX_TOMBSTONE_BLOCKS + 1))
+            .is_err());
+        assert_eq!(untrusted.is_valid(context(60)), Ok(()));
+
+        let stats = untrusted.rejection_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats.get("TombstoneBlockExceeded"), Some(&2));
+        assert_eq!(stats.get("TombstoneBlockTooFar"), Some(&1));
+    }
+
     #[test]
     /// `is_valid` should reject a transaction if tombstone_block is too far in
     /// the future.
@@ -411,76 +1622,383 @@ mod is_valid_tests {
             .times(1)
             .return_const(Ok(num_blocks));
 
-        // A key image has been spent.
+        // A key image has been spent.
+        ledger
+            .expect_contains_key_image()
+            .times(1)
+            .return_const(Err(LedgerError::KeyImageAlreadySpent));
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+
+        assert_eq!(
+            untrusted.is_valid(Arc::new(well_formed_tx_context)),
+            Err(TransactionValidationError::ContainsSpentKeyImage),
+        );
+    }
+
+    #[test]
+    /// `is_valid` should reject a transaction with an already used output
+    /// public key.
+    fn is_valid_rejects_non_unique_output_public_key() {
+        // Number of blocks in the local ledger.
+        let num_blocks = 53;
+
+        let well_formed_tx_context = {
+            let key_images = vec![
+                KeyImage::default(),
+                KeyImage::default(),
+                KeyImage::default(),
+            ];
+
+            let output_public_keys = vec![
+                CompressedRistrettoPublic::default(),
+                CompressedRistrettoPublic::default(),
+            ];
+
+            WellFormedTxContext::new(
+                Default::default(),
+                Default::default(),
+                num_blocks + 17,
+                key_images,
+                vec![9, 10, 8],
+                output_public_keys,
+            )
+        };
+
+        // Mock the local ledger.
+        let mut ledger = MockLedger::new();
+
+        // Untrusted should request num_blocks.
+        ledger
+            .expect_num_blocks()
+            .times(1)
+            .return_const(Ok(num_blocks));
+
+        // Key images must not be in the ledger.
+        ledger
+            .expect_contains_key_image()
+            .times(well_formed_tx_context.key_images().len())
+            .return_const(Ok(false));
+
+        // Output public keys must not be in the ledger.
+        ledger
+            .expect_contains_tx_out_public_key()
+            .times(1)
+            .return_const(Ok(true)); // The output public key is in the ledger.
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+
+        assert_eq!(
+            untrusted.is_valid(Arc::new(well_formed_tx_context)),
+            Err(TransactionValidationError::ContainsExistingOutputPublicKey),
+        );
+    }
+
+    #[test]
+    /// `validate_verbose` should report every problem with a transaction, not
+    /// just the first.
+    fn validate_verbose_reports_all_violations() {
+        // Number of blocks in the local ledger.
+        let num_blocks = 53;
+
+        let well_formed_tx_context = WellFormedTxContext::new(
+            Default::default(),
+            Default::default(),
+            17, // The local ledger has advanced beyond the tombstone block.
+            vec![KeyImage::from(1), KeyImage::from(2)],
+            Default::default(),
+            vec![CompressedRistrettoPublic::default()],
+        );
+
+        // Mock the local ledger.
+        let mut ledger = MockLedger::new();
+        ledger
+            .expect_num_blocks()
+            .times(1)
+            .return_const(Ok(num_blocks));
+
+        // The first key image has been spent, the second has not.
+        ledger
+            .expect_contains_key_image()
+            .times(2)
+            .returning(|key_image| Ok(*key_image == KeyImage::from(1)));
+
+        ledger
+            .expect_contains_tx_out_public_key()
+            .times(1)
+            .return_const(Ok(false));
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+
+        assert_eq!(
+            untrusted.validate_verbose(&well_formed_tx_context),
+            vec![
+                TransactionValidationError::TombstoneBlockExceeded,
+                TransactionValidationError::ContainsSpentKeyImage,
+            ],
+        );
+    }
+
+    #[test]
+    /// Re-checking a transaction at the same ledger height should be served
+    /// from the cache without querying the ledger again.
+    fn is_valid_cache_hit_at_same_height() {
+        // Number of blocks in the local ledger.
+        let num_blocks = 53;
+
+        let well_formed_tx_context = Arc::new(WellFormedTxContext::new(
+            Default::default(),
+            TxHash([7u8; 32]),
+            num_blocks + 17,
+            vec![KeyImage::default()],
+            Default::default(),
+            vec![CompressedRistrettoPublic::default()],
+        ));
+
+        // Mock the local ledger.
+        let mut ledger = MockLedger::new();
+
+        // Untrusted should request num_blocks for each call.
+        ledger
+            .expect_num_blocks()
+            .times(2)
+            .return_const(Ok(num_blocks));
+
+        // The key image and output public key are only checked once.
         ledger
             .expect_contains_key_image()
             .times(1)
-            .return_const(Err(LedgerError::KeyImageAlreadySpent));
+            .return_const(Ok(false));
+
+        ledger
+            .expect_contains_tx_out_public_key()
+            .times(1)
+            .return_const(Ok(false));
 
         let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
 
-        assert_eq!(
-            untrusted.is_valid(Arc::new(well_formed_tx_context)),
-            Err(TransactionValidationError::ContainsSpentKeyImage),
-        );
+        assert_eq!(untrusted.is_valid(well_formed_tx_context.clone()), Ok(()));
+        assert_eq!(untrusted.is_valid(well_formed_tx_context), Ok(()));
     }
 
     #[test]
-    /// `is_valid` should reject a transaction with an already used output
-    /// public key.
-    fn is_valid_rejects_non_unique_output_public_key() {
+    /// A failed output public key lookup should be reported as a ledger error,
+    /// and not cached, so the next check queries the ledger again.
+    fn is_valid_does_not_cache_public_key_lookup_errors() {
         // Number of blocks in the local ledger.
         let num_blocks = 53;
 
-        let well_formed_tx_context = {
-            let key_images = vec![
-                KeyImage::default(),
-                KeyImage::default(),
-                KeyImage::default(),
-            ];
-
-            let output_public_keys = vec![
-                CompressedRistrettoPublic::default(),
-                CompressedRistrettoPublic::default(),
-            ];
-
-            WellFormedTxContext::new(
-                Default::default(),
-                Default::default(),
-                num_blocks + 17,
-                key_images,
-                vec![9, 10, 8],
-                output_public_keys,
-            )
-        };
+        let well_formed_tx_context = Arc::new(WellFormedTxContext::new(
+            Default::default(),
+            TxHash([7u8; 32]),
+            num_blocks + 17,
+            vec![KeyImage::default()],
+            Default::default(),
+            vec![CompressedRistrettoPublic::default()],
+        ));
 
         // Mock the local ledger.
         let mut ledger = MockLedger::new();
 
-        // Untrusted should request num_blocks.
+        // Untrusted should request num_blocks for each call.
         ledger
             .expect_num_blocks()
-            .times(1)
+            .times(2)
             .return_const(Ok(num_blocks));
 
-        // Key images must not be in the ledger.
         ledger
             .expect_contains_key_image()
-            .times(well_formed_tx_context.key_images().len())
+            .times(2)
             .return_const(Ok(false));
 
-        // Output public keys must not be in the ledger.
+        // The output public key lookup fails both times.
         ledger
             .expect_contains_tx_out_public_key()
-            .times(1)
-            .return_const(Ok(true)); // The output public key is in the ledger.
+            .times(2)
+            .return_const(Err(LedgerError::NotFound));
 
         let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
 
-        assert_eq!(
-            untrusted.is_valid(Arc::new(well_formed_tx_context)),
-            Err(TransactionValidationError::ContainsExistingOutputPublicKey),
+        for _ in 0..2 {
+            match untrusted.is_valid(well_formed_tx_context.clone()) {
+                Err(TransactionValidationError::Ledger(_)) => {}
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "is_valid cache size must be non-zero")]
+    /// A zero-sized `is_valid` cache should be rejected.
+    fn new_with_cache_size_rejects_zero() {
+        DefaultTxManagerUntrustedInterfaces::new_with_cache_size(MockLedger::new(), 0);
+    }
+
+    #[test]
+    /// A cached negative result should be returned at the same height, and
+    /// discarded once the ledger height changes.
+    fn is_valid_revalidates_after_height_change() {
+        let well_formed_tx_context = Arc::new(WellFormedTxContext::new(
+            Default::default(),
+            TxHash([7u8; 32]),
+            100,
+            vec![KeyImage::default()],
+            Default::default(),
+            Default::default(),
+        ));
+
+        // Mock the local ledger.
+        let mut ledger = MockLedger::new();
+
+        // The ledger grows by one block between the second and third calls.
+        let mut heights = vec![54, 53, 53];
+        ledger
+            .expect_num_blocks()
+            .times(3)
+            .returning(move || Ok(heights.pop().unwrap()));
+
+        // The key image is checked at each distinct height.
+        ledger
+            .expect_contains_key_image()
+            .times(2)
+            .return_const(Ok(true));
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+
+        for _ in 0..3 {
+            assert_eq!(
+                untrusted.is_valid(well_formed_tx_context.clone()),
+                Err(TransactionValidationError::ContainsSpentKeyImage),
+            );
+        }
+    }
+
+    #[test]
+    /// The public key filter should never change the result of `is_valid`,
+    /// even when it is far over capacity, and should spare the ledger lookups
+    /// of keys it has not seen.
+    fn public_key_filter_preserves_correctness() {
+        let num_blocks = 3;
+        let block_contents: Vec<BlockContents> = get_test_ledger_blocks(num_blocks as usize)
+            .into_iter()
+            .map(|(_block, block_contents)| block_contents)
+            .collect();
+        let existing_keys: Vec<CompressedRistrettoPublic> = block_contents
+            .iter()
+            .flat_map(|block_contents| block_contents.outputs.iter())
+            .map(|output| output.public_key)
+            .collect();
+        let new_keys: Vec<CompressedRistrettoPublic> = (0..100u8)
+            .map(|i| CompressedRistrettoPublic::from(&[i; 32]))
+            .collect();
+
+        // A filter with room to spare, and one so small it is mostly false
+        // positives.
+        for (expected_keys, false_positive_rate) in &[(1_000, 1e-9), (1, 0.99)] {
+            let mut ledger = MockLedger::new();
+            ledger.expect_num_blocks().return_const(Ok(num_blocks));
+
+            // The filter reads each block once.
+            let contents = block_contents.clone();
+            ledger
+                .expect_get_block_contents()
+                .times(contents.len())
+                .returning(move |block_index| Ok(contents[block_index as usize].clone()));
+
+            let ledger_keys: HashSet<CompressedRistrettoPublic> =
+                existing_keys.iter().cloned().collect();
+            let lookups = Arc::new(Mutex::new(0));
+            let ledger_lookups = lookups.clone();
+            ledger
+                .expect_contains_tx_out_public_key()
+                .returning(move |public_key| {
+                    *ledger_lookups.lock().unwrap() += 1;
+                    Ok(ledger_keys.contains(public_key))
+                });
+
+            let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger)
+                .with_public_key_filter(*expected_keys, *false_positive_rate);
+            let context = |public_key: &CompressedRistrettoPublic| {
+                WellFormedTxContext::new(
+                    Default::default(),
+                    Default::default(),
+                    num_blocks + 10,
+                    Default::default(),
+                    Default::default(),
+                    vec![*public_key],
+                )
+            };
+
+            for public_key in &existing_keys {
+                assert_eq!(
+                    untrusted.is_valid_uncached(&context(public_key), num_blocks),
+                    Err(TransactionValidationError::ContainsExistingOutputPublicKey),
+                );
+            }
+            for public_key in &new_keys {
+                assert_eq!(
+                    untrusted.is_valid_uncached(&context(public_key), num_blocks),
+                    Ok(())
+                );
+            }
+
+            // Every existing key is looked up, and with room to spare, no
+            // other key is.
+            let lookups = *lookups.lock().unwrap();
+            assert!(lookups >= existing_keys.len());
+            if *expected_keys > 1 {
+                assert_eq!(lookups, existing_keys.len());
+            }
+        }
+    }
+
+    #[test]
+    /// `is_valid` should never update the public key filter. Until
+    /// `blocks_appended` adds a new block, every key is looked up.
+    fn public_key_filter_is_updated_by_blocks_appended() {
+        let block_contents: Vec<BlockContents> = get_test_ledger_blocks(2)
+            .into_iter()
+            .map(|(_block, block_contents)| block_contents)
+            .collect();
+        let new_key = CompressedRistrettoPublic::from(&[7u8; 32]);
+
+        // The filter is built while the ledger holds one block, and reads the
+        // second only once it is appended.
+        let mut ledger = MockLedger::new();
+        ledger.expect_num_blocks().times(1).return_const(Ok(1));
+        let contents = block_contents.clone();
+        ledger
+            .expect_get_block_contents()
+            .times(2)
+            .returning(move |block_index| Ok(contents[block_index as usize].clone()));
+        let lookups = Arc::new(Mutex::new(0));
+        let ledger_lookups = lookups.clone();
+        ledger
+            .expect_contains_tx_out_public_key()
+            .returning(move |_public_key| {
+                *ledger_lookups.lock().unwrap() += 1;
+                Ok(false)
+            });
+
+        let untrusted =
+            DefaultTxManagerUntrustedInterfaces::new(ledger).with_public_key_filter(1_000, 1e-9);
+        let context = WellFormedTxContext::new(
+            Default::default(),
+            Default::default(),
+            10,
+            Default::default(),
+            Default::default(),
+            vec![new_key],
         );
+
+        // The filter is behind a ledger of two blocks, so the key is looked up.
+        assert_eq!(untrusted.is_valid_uncached(&context, 2), Ok(()));
+        assert_eq!(*lookups.lock().unwrap(), 1);
+
+        // Once the second block is appended, the filter spares the lookup.
+        untrusted.blocks_appended(2);
+        assert_eq!(untrusted.is_valid_uncached(&context, 2), Ok(()));
+        assert_eq!(*lookups.lock().unwrap(), 1);
     }
 }
 
@@ -488,17 +2006,62 @@ mod is_valid_tests {
 mod combine_tests {
     use super::*;
     use mc_crypto_keys::{RistrettoPrivate, RistrettoPublic};
-    use mc_ledger_db::test_utils::get_mock_ledger;
+    use mc_ledger_db::{test_utils::get_mock_ledger, LedgerDB};
     use mc_transaction_core::{
         onetime_keys::recover_onetime_private_key,
         tx::{TxOut, TxOutMembershipProof},
+        Block, BlockContents, BLOCK_VERSION,
+    };
+    use mc_transaction_core_test_utils::{
+        create_ledger, valid_membership_proofs_for, AccountKey, MockFogResolver,
     };
-    use mc_transaction_core_test_utils::{AccountKey, MockFogResolver};
     use mc_transaction_std::{InputCredentials, TransactionBuilder};
     use mc_util_from_random::FromRandom;
     use rand::SeedableRng;
     use rand_hc::Hc128Rng;
-    use std::convert::TryFrom;
+    use std::{convert::TryFrom, sync::PoisonError};
+
+    lazy_static::lazy_static! {
+        /// A ledger holding the TxOuts of every ring the tests build, so that
+        /// they all share a single LMDB environment.
+        static ref RING_LEDGER: Mutex<LedgerDB> = Mutex::new(create_ledger());
+    }
+
+    /// Valid membership proofs for each TxOut in `ring`, against the shared
+    /// ring ledger. TxOuts it does not yet hold are appended in a new block.
+    fn ring_membership_proofs(ring: &[TxOut]) -> Vec<TxOutMembershipProof> {
+        let mut ledger = RING_LEDGER.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let missing: Vec<TxOut> = ring
+            .iter()
+            .filter(|tx_out| ledger.get_tx_out_index_by_hash(&tx_out.hash()).is_err())
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            let (block, block_contents) = match ledger.num_blocks().unwrap() {
+                0 => (
+                    Block::new_origin_block(&missing),
+                    BlockContents::new(vec![], missing),
+                ),
+                num_blocks => {
+                    let parent = ledger.get_block(num_blocks - 1).unwrap();
+                    // Each block after the origin block must spend something.
+                    let block_contents =
+                        BlockContents::new(vec![KeyImage::from(num_blocks)], missing);
+                    let block = Block::new_with_parent(
+                        BLOCK_VERSION,
+                        &parent,
+                        &Default::default(),
+                        &block_contents,
+                    );
+                    (block, block_contents)
+                }
+            };
+            ledger.append_block(&block, &block_contents, None).unwrap();
+        }
+
+        valid_membership_proofs_for(&*ledger, ring)
+    }
 
     fn combine(tx_contexts: Vec<WellFormedTxContext>, max_elements: usize) -> Vec<TxHash> {
         let ledger = get_mock_ledger(10);
@@ -551,13 +2114,7 @@ mod combine_tests {
         );
 
         let ring: Vec<TxOut> = vec![tx_out];
-        let membership_proofs: Vec<TxOutMembershipProof> = ring
-            .iter()
-            .map(|_tx_out| {
-                // TODO: provide valid proofs for each tx_out.
-                TxOutMembershipProof::new(0, 0, Default::default())
-            })
-            .collect();
+        let membership_proofs = ring_membership_proofs(&ring);
 
         let input_credentials = InputCredentials::new(
             ring,
@@ -625,13 +2182,7 @@ mod combine_tests {
 
                 // Create InputCredentials to spend the TxOut.
                 let ring: Vec<TxOut> = vec![tx_out.clone()];
-                let membership_proofs: Vec<TxOutMembershipProof> = ring
-                    .iter()
-                    .map(|_tx_out| {
-                        // TODO: provide valid proofs for each tx_out.
-                        TxOutMembershipProof::new(0, 0, Default::default())
-                    })
-                    .collect();
+                let membership_proofs = ring_membership_proofs(&ring);
 
                 let input_credentials = InputCredentials::new(
                     ring,
@@ -688,13 +2239,7 @@ mod combine_tests {
         // Create a transaction that sends the full value of  `tx_out` to bob.
         let first_client_tx: WellFormedTxContext = {
             let ring = vec![tx_out.clone()];
-            let membership_proofs: Vec<TxOutMembershipProof> = ring
-                .iter()
-                .map(|_tx_out| {
-                    // TODO: provide valid proofs for each tx_out.
-                    TxOutMembershipProof::new(0, 0, Default::default())
-                })
-                .collect();
+            let membership_proofs = ring_membership_proofs(&ring);
 
             let input_credentials = InputCredentials::new(
                 ring,
@@ -720,13 +2265,7 @@ mod combine_tests {
         let second_client_tx: WellFormedTxContext = {
             let recipient_account = AccountKey::random(&mut rng);
             let ring: Vec<TxOut> = vec![tx_out];
-            let membership_proofs: Vec<TxOutMembershipProof> = ring
-                .iter()
-                .map(|_tx_out| {
-                    // TODO: provide valid proofs for each tx_out.
-                    TxOutMembershipProof::new(0, 0, Default::default())
-                })
-                .collect();
+            let membership_proofs = ring_membership_proofs(&ring);
 
             let input_credentials = InputCredentials::new(
                 ring,
@@ -775,13 +2314,7 @@ mod combine_tests {
             );
 
             let ring: Vec<TxOut> = vec![tx_out];
-            let membership_proofs: Vec<TxOutMembershipProof> = ring
-                .iter()
-                .map(|_tx_out| {
-                    // TODO: provide valid proofs for each tx_out.
-                    TxOutMembershipProof::new(0, 0, Default::default())
-                })
-                .collect();
+            let membership_proofs = ring_membership_proofs(&ring);
 
             let input_credentials = InputCredentials::new(
                 ring,
@@ -855,13 +2388,7 @@ mod combine_tests {
         // Create a transaction that sends the full value of  `tx_out1` to bob.
         let first_client_tx: WellFormedTxContext = {
             let ring = vec![tx_out1.clone()];
-            let membership_proofs: Vec<TxOutMembershipProof> = ring
-                .iter()
-                .map(|_tx_out| {
-                    // TODO: provide valid proofs for each tx_out.
-                    TxOutMembershipProof::new(0, 0, Default::default())
-                })
-                .collect();
+            let membership_proofs = ring_membership_proofs(&ring);
 
             let input_credentials = InputCredentials::new(
                 ring,
@@ -888,13 +2415,7 @@ mod combine_tests {
         let second_client_tx: WellFormedTxContext = {
             let recipient_account = AccountKey::random(&mut rng);
             let ring: Vec<TxOut> = vec![tx_out2];
-            let membership_proofs: Vec<TxOutMembershipProof> = ring
-                .iter()
-                .map(|_tx_out| {
-                    // TODO: provide valid proofs for each tx_out.
-                    TxOutMembershipProof::new(0, 0, Default::default())
-                })
-                .collect();
+            let membership_proofs = ring_membership_proofs(&ring);
 
             let input_credentials = InputCredentials::new(
                 ring,
@@ -944,13 +2465,7 @@ mod combine_tests {
             );
 
             let ring: Vec<TxOut> = vec![tx_out];
-            let membership_proofs: Vec<TxOutMembershipProof> = ring
-                .iter()
-                .map(|_tx_out| {
-                    // TODO: provide valid proofs for each tx_out.
-                    TxOutMembershipProof::new(0, 0, Default::default())
-                })
-                .collect();
+            let membership_proofs = ring_membership_proofs(&ring);
 
             let input_credentials = InputCredentials::new(
                 ring,
@@ -996,4 +2511,379 @@ mod combine_tests {
         let expected_hashes = vec![TxHash([2u8; 32]), TxHash([1u8; 32]), TxHash([3u8; 32])];
         assert_eq!(hashes, expected_hashes);
     }
+
+    #[test]
+    // Transactions paying equal fees should be ordered by hash, whatever order
+    // they are given in, so that every node builds the same block.
+    fn combine_breaks_fee_ties_by_hash() {
+        let hashes = [4u8, 1, 5, 3, 2];
+        let contexts = |order: &[u8]| -> Vec<WellFormedTxContext> {
+            order
+                .iter()
+                .map(|id| {
+                    WellFormedTxContext::new(100, TxHash([*id; 32]), 0, vec![], vec![], vec![])
+                })
+                .collect()
+        };
+
+        let expected: Vec<_> = (1u8..=5).map(|id| TxHash([id; 32])).collect();
+        assert_eq!(combine(contexts(&hashes), 10), expected);
+
+        let mut reversed = hashes;
+        reversed.reverse();
+        assert_eq!(combine(contexts(&reversed), 10), expected);
+
+        let mut sorted = hashes;
+        sorted.sort_unstable();
+        assert_eq!(combine(contexts(&sorted), 10), expected);
+    }
+
+    #[test]
+    // `combine_with_excluded` should omit a transaction whose key image is
+    // excluded, even though it does not conflict with any other candidate.
+    fn combine_with_excluded_rejects_excluded_key_image() {
+        let a = WellFormedTxContext::new(
+            100,
+            TxHash([1u8; 32]),
+            0,
+            vec![KeyImage::from(1)],
+            vec![],
+            vec![],
+        );
+        let b = WellFormedTxContext::new(
+            557,
+            TxHash([2u8; 32]),
+            0,
+            vec![KeyImage::from(2)],
+            vec![],
+            vec![],
+        );
+        let c = WellFormedTxContext::new(
+            88,
+            TxHash([3u8; 32]),
+            0,
+            vec![KeyImage::from(3)],
+            vec![],
+            vec![],
+        );
+        let tx_contexts: Vec<_> = vec![a, b, c].into_iter().map(Arc::new).collect();
+
+        let ledger = get_mock_ledger(10);
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+
+        let excluded_key_images = HashSet::from_iter(vec![KeyImage::from(2)]);
+        let hashes = untrusted.combine_with_excluded(
+            &tx_contexts,
+            10,
+            &excluded_key_images,
+            &HashSet::default(),
+        );
+
+        // The remaining transactions should keep their usual order.
+        assert_eq!(hashes, vec![TxHash([1u8; 32]), TxHash([3u8; 32])]);
+    }
+
+    #[test]
+    // `combine_detailed` should report why each left-out transaction was
+    // rejected.
+    fn combine_detailed_reports_rejection_reasons() {
+        let output_public_key = CompressedRistrettoPublic::from(&[7u8; 32]);
+        let tx_contexts: Vec<_> = vec![
+            // Highest fee, so selected first.
+            WellFormedTxContext::new(
+                500,
+                TxHash([1u8; 32]),
+                0,
+                vec![KeyImage::from(1)],
+                vec![],
+                vec![output_public_key],
+            ),
+            WellFormedTxContext::new(
+                400,
+                TxHash([2u8; 32]),
+                0,
+                vec![KeyImage::from(1)],
+                vec![],
+                vec![],
+            ),
+            WellFormedTxContext::new(
+                300,
+                TxHash([3u8; 32]),
+                0,
+                vec![KeyImage::from(3)],
+                vec![],
+                vec![output_public_key],
+            ),
+            WellFormedTxContext::new(
+                200,
+                TxHash([4u8; 32]),
+                0,
+                vec![KeyImage::from(4)],
+                vec![],
+                vec![],
+            ),
+            WellFormedTxContext::new(
+                100,
+                TxHash([5u8; 32]),
+                0,
+                vec![KeyImage::from(5)],
+                vec![],
+                vec![],
+            ),
+        ]
+        .into_iter()
+        .map(Arc::new)
+        .collect();
+
+        let ledger = get_mock_ledger(10);
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+        let result = untrusted.combine_detailed(&tx_contexts, 2);
+
+        assert_eq!(result.selected, vec![TxHash([1u8; 32]), TxHash([4u8; 32])]);
+        assert_eq!(
+            result.rejected,
+            vec![
+                (TxHash([2u8; 32]), RejectReason::KeyImageConflict),
+                (TxHash([3u8; 32]), RejectReason::OutputPublicKeyConflict),
+                (TxHash([5u8; 32]), RejectReason::SizeLimit),
+            ]
+        );
+        assert_eq!(untrusted.combine(&tx_contexts, 2), result.selected);
+    }
+
+    #[test]
+    // `combine_detailed` should report the total fee of the selected
+    // transactions, and only those.
+    fn combine_detailed_sums_selected_fees() {
+        let tx_contexts: Vec<Arc<WellFormedTxContext>> = vec![
+            (1, 500, 1),
+            // Conflicts with the first transaction's key image.
+            (2, 400, 1),
+            // Lowest fee, so considered once the block is already full.
+            (3, 0, 3),
+            (4, 250, 4),
+            (5, 125, 5),
+            (6, 1, 6),
+        ]
+        .into_iter()
+        .map(|(id, fee, key_image)| {
+            Arc::new(WellFormedTxContext::new(
+                fee,
+                TxHash([id; 32]),
+                0,
+                vec![KeyImage::from(key_image)],
+                vec![],
+                vec![],
+            ))
+        })
+        .collect();
+
+        let ledger = get_mock_ledger(10);
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+        let result = untrusted.combine_detailed(&tx_contexts, 4);
+
+        let expected_total_fee: u64 = tx_contexts
+            .iter()
+            .filter(|context| result.selected.contains(context.tx_hash()))
+            .map(|context| context.fee())
+            .sum();
+        assert_eq!(result.selected.len(), 4);
+        assert_eq!(result.selected_total_fee, expected_total_fee);
+        assert_eq!(result.selected_total_fee, 500 + 250 + 125 + 1);
+    }
+
+    #[test]
+    // Transactions paying less than the minimum fee should be left out even
+    // when the block has room for them.
+    fn combine_skips_transactions_below_min_fee() {
+        let tx_contexts: Vec<Arc<WellFormedTxContext>> = vec![(1, 0), (2, 99), (3, 100), (4, 101)]
+            .into_iter()
+            .map(|(id, fee)| {
+                Arc::new(WellFormedTxContext::new(
+                    fee,
+                    TxHash([id; 32]),
+                    0,
+                    vec![KeyImage::from(id as u64)],
+                    vec![],
+                    vec![],
+                ))
+            })
+            .collect();
+
+        let ledger = get_mock_ledger(10);
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger).with_min_combine_fee(100);
+        let result = untrusted.combine_detailed(&tx_contexts, 100);
+
+        assert_eq!(result.selected, vec![TxHash([4; 32]), TxHash([3; 32])]);
+        assert_eq!(
+            result.rejected,
+            vec![
+                (TxHash([2; 32]), RejectReason::FeeBelowMinimum),
+                (TxHash([1; 32]), RejectReason::FeeBelowMinimum),
+            ]
+        );
+        assert_eq!(
+            untrusted.combine(&tx_contexts, 100),
+            vec![TxHash([4; 32]), TxHash([3; 32])]
+        );
+    }
+
+    #[test]
+    // The selected transactions' outputs and the reserved outputs together
+    // should stay within the block's output limit.
+    fn combine_leaves_room_for_reserved_outputs() {
+        // (id, fee, number of outputs) for each transaction.
+        let tx_contexts: Vec<Arc<WellFormedTxContext>> =
+            vec![(1, 400, 2), (2, 300, 2), (3, 200, 2), (4, 100, 1)]
+                .into_iter()
+                .map(|(id, fee, num_outputs)| {
+                    let output_public_keys = (0..num_outputs)
+                        .map(|i| CompressedRistrettoPublic::from(&[id * 10 + i; 32]))
+                        .collect();
+                    Arc::new(WellFormedTxContext::new(
+                        fee,
+                        TxHash([id; 32]),
+                        0,
+                        vec![KeyImage::from(id as u64)],
+                        vec![],
+                        output_public_keys,
+                    ))
+                })
+                .collect();
+
+        let max_block_outputs = 6;
+        let reserved_outputs = 1;
+        let ledger = get_mock_ledger(10);
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger)
+            .with_max_block_outputs(max_block_outputs)
+            .with_reserved_outputs(reserved_outputs);
+        let result = untrusted.combine_detailed(&tx_contexts, 100);
+
+        // The third transaction would fill the last slot, which is reserved, but
+        // the smaller fourth still fits.
+        assert_eq!(
+            result.selected,
+            vec![TxHash([1; 32]), TxHash([2; 32]), TxHash([4; 32])]
+        );
+        assert_eq!(
+            result.rejected,
+            vec![(TxHash([3; 32]), RejectReason::SizeLimit)]
+        );
+        let selected_outputs: usize = tx_contexts
+            .iter()
+            .filter(|context| result.selected.contains(context.tx_hash()))
+            .map(|context| context.output_public_keys().len())
+            .sum();
+        assert!(selected_outputs + reserved_outputs <= max_block_outputs);
+
+        // Without a reservation the whole limit is available.
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(get_mock_ledger(10))
+            .with_max_block_outputs(max_block_outputs);
+        assert_eq!(
+            untrusted.combine(&tx_contexts, 100),
+            vec![TxHash([1; 32]), TxHash([2; 32]), TxHash([3; 32])]
+        );
+    }
+
+    #[test]
+    // A seeded shuffle should select the same transactions as fee ordering, in
+    // an order fixed by the seed alone.
+    fn combine_with_ordering_shuffles_deterministically() {
+        let mut tx_contexts: Vec<Arc<WellFormedTxContext>> = (1..=8u8)
+            .map(|id| {
+                Arc::new(WellFormedTxContext::new(
+                    100 * id as u64,
+                    TxHash([id; 32]),
+                    0,
+                    // The last transaction conflicts with the first.
+                    vec![KeyImage::from((id % 7) as u64)],
+                    vec![],
+                    vec![],
+                ))
+            })
+            .collect();
+
+        let ledger = get_mock_ledger(10);
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger);
+        let by_fee = untrusted.combine_with_ordering(&tx_contexts, 6, BlockOrdering::Fee);
+        assert_eq!(by_fee, untrusted.combine(&tx_contexts, 6));
+
+        let ordering = BlockOrdering::SeededShuffle([7u8; 32]);
+        let shuffled = untrusted.combine_with_ordering(&tx_contexts, 6, ordering);
+        let mut sorted_by_fee = by_fee.clone();
+        let mut sorted_shuffled = shuffled.clone();
+        sorted_by_fee.sort();
+        sorted_shuffled.sort();
+        assert_eq!(sorted_shuffled, sorted_by_fee);
+
+        // The order depends only on the seed, not on how candidates arrive.
+        tx_contexts.reverse();
+        assert_eq!(
+            untrusted.combine_with_ordering(&tx_contexts, 6, ordering),
+            shuffled
+        );
+        assert_ne!(
+            untrusted.combine_with_ordering(
+                &tx_contexts,
+                6,
+                BlockOrdering::SeededShuffle([8u8; 32])
+            ),
+            shuffled
+        );
+    }
+
+    #[test]
+    // The fair strategy should interleave senders within a fee tier, rather than
+    // taking all of the highest-paying sender's transactions first.
+    fn combine_fair_strategy_interleaves_senders() {
+        // (sender, fee) for each transaction. Alice outbids Bob within the same tier,
+        // and Carol is alone in a higher tier.
+        let txs = vec![
+            ("alice", 109),
+            ("alice", 108),
+            ("alice", 107),
+            ("bob", 101),
+            ("bob", 100),
+            ("carol", 200),
+        ];
+
+        let tx_contexts: Vec<_> = txs
+            .iter()
+            .enumerate()
+            .map(|(i, (_sender, fee))| {
+                Arc::new(WellFormedTxContext::new(
+                    *fee,
+                    TxHash([i as u8; 32]),
+                    0,
+                    vec![KeyImage::from(i as u64)],
+                    vec![],
+                    vec![],
+                ))
+            })
+            .collect();
+
+        let senders: Vec<_> = txs.iter().map(|(sender, _fee)| *sender).collect();
+        let sender = move |context: &WellFormedTxContext| senders[context.tx_hash().0[0] as usize];
+
+        let ledger = get_mock_ledger(10);
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger)
+            .with_combine_strategy(FairCombineStrategy::new(100, sender));
+        let combined = untrusted.combine(&tx_contexts, 10);
+
+        let combined_senders: Vec<_> = combined
+            .iter()
+            .map(|tx_hash| txs[tx_hash.0[0] as usize])
+            .collect();
+        assert_eq!(
+            combined_senders,
+            vec![
+                ("carol", 200),
+                ("alice", 109),
+                ("bob", 101),
+                ("alice", 108),
+                ("bob", 100),
+                ("alice", 107),
+            ]
+        );
+    }
 }
@@ -24,6 +24,8 @@ pub struct MockLedgerInner {
     pub membership_proofs: HashMap<u64, TxOutMembershipProof>,
     pub key_images_by_block_number: HashMap<u64, Vec<KeyImage>>,
     pub key_images: HashMap<KeyImage, u64>,
+    /// If set, `get_block` fails with this error.
+    pub get_block_error: Option<Error>,
 }
 
 #[derive(Clone)]
@@ -101,7 +103,11 @@ impl Ledger for MockLedger {
     }
 
     fn get_block(&self, block_number: u64) -> Result<Block, Error> {
-        self.lock()
+        let inner = self.lock();
+        if let Some(err) = &inner.get_block_error {
+            return Err(err.clone());
+        }
+        inner
             .blocks_by_block_number
             .get(&block_number)
             .cloned()
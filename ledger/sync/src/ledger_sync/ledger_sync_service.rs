@@ -389,8 +389,16 @@ fn get_blocks<BC: BlockchainConnection + 'static>(
                 match conn
                     .fetch_blocks(start..end, Fibonacci::from_millis(10).take(5))
                     .map_err(LedgerSyncError::Consensus)
-                    .and_then(|blocks| verify_block_ids(blocks, &thread_append_after_block))
-                {
+                    .and_then(|success| {
+                        log::debug!(
+                            logger,
+                            "Fetched blocks from {} after {} attempt(s) in {:?}",
+                            conn,
+                            success.attempts,
+                            success.elapsed
+                        );
+                        verify_block_ids(success.value, &thread_append_after_block)
+                    }) {
                     Ok(mut blocks) => {
                         log::debug!(logger, "Received {} blocks from {}", blocks.len(), conn);
                         blocks_result.append(&mut blocks);
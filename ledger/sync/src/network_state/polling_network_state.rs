@@ -10,7 +10,8 @@ use mc_common::{
     ResponderId,
 };
 use mc_connection::{
-    BlockchainConnection, Connection, ConnectionManager, RetryableBlockchainConnection,
+    BlockchainConnection, Connection, ConnectionManager, RetrySuccess,
+    RetryableBlockchainConnection,
 };
 use mc_consensus_scp::{
     core_types::Ballot, msg::ExternalizePayload, Msg, QuorumSet, SlotIndex, Topic,
@@ -82,7 +83,9 @@ impl<BC: BlockchainConnection + 'static> PollingNetworkState<BC> {
 
                     let &(ref lock, ref condvar) = &*thread_results_and_condvar;
 
-                    let block_height_result = conn.fetch_block_height(Self::get_retry_iterator());
+                    let block_height_result = conn
+                        .fetch_block_height(Self::get_retry_iterator())
+                        .map(RetrySuccess::into_inner);
 
                     let mut results = lock.lock().expect("mutex poisoned");
 
@@ -751,6 +751,8 @@ pub struct JsonTxPrefix {
     pub outputs: Vec<JsonTxOut>,
     pub fee: String,
     tombstone_block: String,
+    #[serde(default)]
+    version: u32,
 }
 
 impl From<&TxPrefix> for JsonTxPrefix {
@@ -760,6 +762,7 @@ impl From<&TxPrefix> for JsonTxPrefix {
             outputs: src.get_outputs().iter().map(JsonTxOut::from).collect(),
             fee: src.get_fee().to_string(),
             tombstone_block: src.get_tombstone_block().to_string(),
+            version: src.get_version(),
         }
     }
 }
@@ -795,6 +798,7 @@ impl TryFrom<&JsonTxPrefix> for TxPrefix {
                 .parse::<u64>()
                 .map_err(|err| format!("Failed to parse u64 from tombstone_block: {}", err))?,
         );
+        prefix.set_version(src.version);
 
         Ok(prefix)
     }
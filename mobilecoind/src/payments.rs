@@ -140,7 +140,7 @@ fn get_fee<T: BlockchainConnection + UserTxConnection + 'static>(
             .conns()
             .par_iter()
             .filter_map(|conn| conn.fetch_block_info(empty()).ok())
-            .map(|block_info| block_info.minimum_fee)
+            .map(|block_info| block_info.value.minimum_fee)
             .max()
             .unwrap_or(MINIMUM_FEE)
     }
@@ -480,7 +480,9 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             .conn(responder_id)
             .ok_or(Error::NodeNotFound)?
             .propose_tx(&tx_proposal.tx, empty())
-            .map_err(Error::from)?;
+            .map_err(Error::from)?
+            .value
+            .block_index;
 
         log::info!(
             self.logger,
@@ -208,11 +208,10 @@ impl<Enclave: ConsensusEnclave + Clone + Send + Sync> BlockchainConnection
     fn fetch_blocks(&mut self, range: Range<BlockIndex>) -> ConnectionResult<Vec<Block>> {
         trace_time!(self.logger, "PeerConnection::get_blocks");
 
-        let mut request = BlocksRequest::new();
-        request.set_offset(range.start);
-        let limit =
-            u32::try_from(range.end - range.start).or(Err(ConnectionError::RequestTooLarge))?;
-        request.set_limit(limit);
+        let request = match blocks_request(&range)? {
+            Some(request) => request,
+            None => return Ok(Vec::new()),
+        };
 
         self.log_attested_call("fetch_blocks", |this| {
             this.blockchain_api_client.get_blocks(&request)
@@ -226,11 +225,10 @@ impl<Enclave: ConsensusEnclave + Clone + Send + Sync> BlockchainConnection
     fn fetch_block_ids(&mut self, range: Range<BlockIndex>) -> ConnectionResult<Vec<BlockID>> {
         trace_time!(self.logger, "PeerConnection::get_blocks");
 
-        let mut request = BlocksRequest::new();
-        request.set_offset(range.start);
-        let limit =
-            u32::try_from(range.end - range.start).or(Err(ConnectionError::RequestTooLarge))?;
-        request.set_limit(limit);
+        let request = match blocks_request(&range)? {
+            Some(request) => request,
+            None => return Ok(Vec::new()),
+        };
 
         self.attested_call(|this| this.blockchain_api_client.get_blocks(&request))?
             .get_blocks()
@@ -359,3 +357,63 @@ impl<Enclave: ConsensusEnclave + Clone + Send + Sync> ConsensusConnection
         }
     }
 }
+
+/// Build the request for the blocks in `range`, or `None` if the range is
+/// empty and there is nothing to ask for.
+fn blocks_request(range: &Range<BlockIndex>) -> ConnectionResult<Option<BlocksRequest>> {
+    if range.end < range.start {
+        return Err(ConnectionError::InvalidRange(range.start, range.end));
+    }
+    if range.is_empty() {
+        return Ok(None);
+    }
+
+    let mut request = BlocksRequest::new();
+    request.set_offset(range.start);
+    let limit = u32::try_from(range.end - range.start).or(Err(ConnectionError::RequestTooLarge))?;
+    request.set_limit(limit);
+    Ok(Some(request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grpcio::EnvBuilder;
+    use mc_common::logger::create_null_logger;
+    use mc_consensus_enclave_mock::ConsensusServiceMockEnclave;
+    use mc_peers_test_utils::{test_node_id, test_peer_uri};
+
+    fn peer_connection() -> PeerConnection<ConsensusServiceMockEnclave> {
+        PeerConnection::new(
+            ConsensusServiceMockEnclave::default(),
+            test_node_id(1),
+            test_peer_uri(2),
+            Arc::new(EnvBuilder::new().build()),
+            create_null_logger(),
+        )
+    }
+
+    #[test]
+    // An empty range should be answered without asking the peer.
+    fn empty_ranges_fetch_nothing() {
+        let mut conn = peer_connection();
+        assert_eq!(conn.fetch_blocks(5..5).unwrap(), Vec::new());
+        assert_eq!(conn.fetch_block_ids(5..5).unwrap(), Vec::new());
+    }
+
+    #[test]
+    // A range which ends before it starts should be rejected, not wrap around.
+    fn inverted_ranges_are_rejected() {
+        let mut conn = peer_connection();
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = 10..5;
+        match conn.fetch_blocks(inverted.clone()) {
+            Err(ConnectionError::InvalidRange(10, 5)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match conn.fetch_block_ids(inverted) {
+            Err(ConnectionError::InvalidRange(10, 5)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
@@ -5,7 +5,7 @@
 use crate::ConsensusMsgError;
 use failure::Fail;
 use grpcio::Error as GrpcError;
-use mc_connection::AttestationError;
+use mc_connection::{AttestationError, Cancelled, ConnectionBusy};
 use mc_consensus_api::ConversionError;
 use mc_consensus_enclave_api::Error as EnclaveError;
 use mc_transaction_core::tx::TxHash;
@@ -60,6 +60,12 @@ pub enum Error {
     /// Tx hashes not in cache.
     #[fail(display = "Tx hashes not in cache: {:?}", _0)]
     TxHashesNotInCache(Vec<TxHash>),
+    /// The connection is already running as many operations as it allows.
+    #[fail(display = "Connection busy")]
+    Busy,
+    /// The call was cancelled before it succeeded.
+    #[fail(display = "Call cancelled")]
+    Cancelled,
     /// Some other error.
     #[fail(display = "Unknown peering issue")]
     Other,
@@ -74,6 +80,18 @@ impl Error {
     }
 }
 
+impl From<ConnectionBusy> for Error {
+    fn from(_src: ConnectionBusy) -> Self {
+        Error::Busy
+    }
+}
+
+impl From<Cancelled> for Error {
+    fn from(_src: Cancelled) -> Self {
+        Error::Cancelled
+    }
+}
+
 impl From<ConversionError> for Error {
     fn from(src: ConversionError) -> Self {
         Error::Conversion(src)
@@ -8,7 +8,7 @@ use crate::{
     traits::{ConsensusConnection, RetryableConsensusConnection},
 };
 use mc_common::{NodeID, ResponderId};
-use mc_connection::{impl_sync_connection_retry, SyncConnection};
+use mc_connection::{impl_sync_connection_retry, RetrySuccess, SyncConnection};
 use mc_consensus_api::consensus_peer::ConsensusMsgResponse;
 use mc_consensus_enclave_api::{TxContext, WellFormedEncryptedTx};
 use mc_transaction_core::tx::TxHash;
@@ -26,13 +26,8 @@ impl<CC: ConsensusConnection> RetryableConsensusConnection for SyncConnection<CC
         msg: &ConsensusMsg,
         retry_iterator: impl IntoIterator<Item = Duration>,
     ) -> RetryResult<ConsensusMsgResponse> {
-        impl_sync_connection_retry!(
-            self.write(),
-            self.logger(),
-            send_consensus_msg,
-            retry_iterator,
-            msg
-        )
+        impl_sync_connection_retry!(self, send_consensus_msg, retry_iterator, msg)
+            .map(RetrySuccess::into_inner)
     }
 
     fn send_propose_tx(
@@ -42,13 +37,13 @@ impl<CC: ConsensusConnection> RetryableConsensusConnection for SyncConnection<CC
         retry_iterator: impl IntoIterator<Item = Duration>,
     ) -> RetryResult<()> {
         impl_sync_connection_retry!(
-            self.write(),
-            self.logger(),
+            self,
             send_propose_tx,
             retry_iterator,
             encrypted_tx,
             origin_node
         )
+        .map(RetrySuccess::into_inner)
     }
 
     fn fetch_txs(
@@ -56,24 +51,15 @@ impl<CC: ConsensusConnection> RetryableConsensusConnection for SyncConnection<CC
         hashes: &[TxHash],
         retry_iterator: impl IntoIterator<Item = Duration>,
     ) -> RetryResult<Vec<TxContext>> {
-        impl_sync_connection_retry!(
-            self.write(),
-            self.logger(),
-            fetch_txs,
-            retry_iterator,
-            hashes
-        )
+        impl_sync_connection_retry!(self, fetch_txs, retry_iterator, hashes)
+            .map(RetrySuccess::into_inner)
     }
 
     fn fetch_latest_msg(
         &self,
         retry_iterator: impl IntoIterator<Item = Duration>,
     ) -> RetryResult<Option<ConsensusMsg>> {
-        impl_sync_connection_retry!(
-            self.write(),
-            self.logger(),
-            fetch_latest_msg,
-            retry_iterator
-        )
+        impl_sync_connection_retry!(self, fetch_latest_msg, retry_iterator)
+            .map(RetrySuccess::into_inner)
     }
 }
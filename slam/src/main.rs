@@ -9,8 +9,8 @@ use mc_common::{
     HashMap, HashSet, ResponderId,
 };
 use mc_connection::{
-    HardcodedCredentialsProvider, RetryError, RetryableBlockchainConnection,
-    RetryableUserTxConnection, SyncConnection, ThickClient,
+    HardcodedCredentialsProvider, ProposeTxResponse, RetryError, RetrySuccess,
+    RetryableBlockchainConnection, RetryableUserTxConnection, SyncConnection, ThickClient,
 };
 use mc_consensus_scp::QuorumSet;
 use mc_crypto_keys::{CompressedRistrettoPublic, RistrettoPublic};
@@ -130,7 +130,7 @@ fn main() {
         get_conns(&config, &logger)
             .par_iter()
             .filter_map(|conn| conn.fetch_block_info(empty()).ok())
-            .map(|block_info| block_info.minimum_fee)
+            .map(|block_info| block_info.value.minimum_fee)
             .max()
             .unwrap_or(MINIMUM_FEE),
         Ordering::SeqCst,
@@ -423,8 +423,11 @@ fn submit_tx(
             max_retries
         );
         thread::sleep(Duration::from_millis(config.add_tx_delay_ms));
-        match conn.propose_tx(&tx, empty()) {
-            Ok(block_height) => {
+        match conn.propose_tx(&tx, empty()).map(RetrySuccess::into_inner) {
+            Ok(ProposeTxResponse {
+                block_index: block_height,
+                ..
+            }) => {
                 log::debug!(
                     logger,
                     "Successfully submitted {:?}, at block height {:?} (attempt {} / {})",
@@ -7,7 +7,10 @@
 
 #![cfg_attr(test, allow(clippy::unnecessary_operation))]
 
-use crate::domain_separators::{AMOUNT_BLINDING_DOMAIN_TAG, AMOUNT_VALUE_DOMAIN_TAG};
+use crate::domain_separators::{
+    AMOUNT_BLINDING_DOMAIN_TAG, AMOUNT_BLINDING_DOMAIN_TAG_V1, AMOUNT_VALUE_DOMAIN_TAG,
+    AMOUNT_VALUE_DOMAIN_TAG_V1,
+};
 use blake2::{Blake2b, Digest};
 use curve25519_dalek::scalar::Scalar;
 use mc_crypto_digestible::Digestible;
@@ -23,6 +26,41 @@ pub use commitment::Commitment;
 pub use compressed_commitment::CompressedCommitment;
 pub use error::AmountError;
 
+/// Selects the domain separators used to mask and unmask an Amount.
+///
+/// An amount can only be unmasked under the version it was masked with.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AmountDomainVersion {
+    /// The original amount domain separators.
+    V0,
+    /// Amount domain separators for the next protocol version.
+    V1,
+}
+
+impl Default for AmountDomainVersion {
+    fn default() -> Self {
+        AmountDomainVersion::V0
+    }
+}
+
+impl AmountDomainVersion {
+    /// Domain separator for the value mask hash function.
+    fn value_domain_tag(self) -> &'static str {
+        match self {
+            AmountDomainVersion::V0 => AMOUNT_VALUE_DOMAIN_TAG,
+            AmountDomainVersion::V1 => AMOUNT_VALUE_DOMAIN_TAG_V1,
+        }
+    }
+
+    /// Domain separator for the blinding mask hash function.
+    fn blinding_domain_tag(self) -> &'static str {
+        match self {
+            AmountDomainVersion::V0 => AMOUNT_BLINDING_DOMAIN_TAG,
+            AmountDomainVersion::V1 => AMOUNT_BLINDING_DOMAIN_TAG_V1,
+        }
+    }
+}
+
 /// A commitment to an amount of MobileCoin, denominated in picoMOB.
 #[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Message, Digestible)]
 pub struct Amount {
@@ -46,15 +84,31 @@ impl Amount {
     ///   key `r` and recipient public key `B`.
     #[inline]
     pub fn new(value: u64, shared_secret: &RistrettoPublic) -> Result<Amount, AmountError> {
+        Self::new_with_version(value, shared_secret, AmountDomainVersion::default())
+    }
+
+    /// Creates a commitment `value*H + blinding*G`, masked using the domain
+    /// separators of the given version.
+    ///
+    /// # Arguments
+    /// * `value` - The committed value `v`, in picoMOB.
+    /// * `shared_secret` - The shared secret, e.g. `rB` for transaction private
+    ///   key `r` and recipient public key `B`.
+    /// * `version` - The amount domain separator version.
+    pub fn new_with_version(
+        value: u64,
+        shared_secret: &RistrettoPublic,
+        version: AmountDomainVersion,
+    ) -> Result<Amount, AmountError> {
         // The blinding is `Blake2B("blinding" | shared_secret)`
-        let blinding: Scalar = get_blinding(shared_secret);
+        let blinding: Scalar = get_blinding_with_version(shared_secret, version);
 
         // Pedersen commitment `v*H + b*G`.
         let commitment = CompressedCommitment::new(value, blinding);
 
         // The value is XORed with the first 8 bytes of the mask.
         // `v XOR_8 Blake2B(value_mask | shared_secret)`
-        let masked_value: u64 = value ^ get_value_mask(&shared_secret);
+        let masked_value: u64 = value ^ get_value_mask_with_version(&shared_secret, version);
 
         Ok(Amount {
             commitment,
@@ -69,8 +123,22 @@ impl Amount {
     /// # Arguments
     /// * `shared_secret` - The shared secret, e.g. `rB`.
     pub fn get_value(&self, shared_secret: &RistrettoPublic) -> Result<(u64, Scalar), AmountError> {
-        let value: u64 = self.unmask_value(shared_secret);
-        let blinding = get_blinding(shared_secret);
+        self.get_value_with_version(shared_secret, AmountDomainVersion::default())
+    }
+
+    /// Returns the value `v` and blinding `b` in the commitment `v*H + b*G`,
+    /// unmasking with the domain separators of the given version.
+    ///
+    /// # Arguments
+    /// * `shared_secret` - The shared secret, e.g. `rB`.
+    /// * `version` - The amount domain separator version.
+    pub fn get_value_with_version(
+        &self,
+        shared_secret: &RistrettoPublic,
+        version: AmountDomainVersion,
+    ) -> Result<(u64, Scalar), AmountError> {
+        let value: u64 = self.unmask_value(shared_secret, version);
+        let blinding = get_blinding_with_version(shared_secret, version);
 
         let expected_commitment = CompressedCommitment::new(value, blinding);
         if self.commitment != expected_commitment {
@@ -85,8 +153,8 @@ impl Amount {
     }
 
     /// Reveals `masked_value`.
-    fn unmask_value(&self, shared_secret: &RistrettoPublic) -> u64 {
-        self.masked_value ^ get_value_mask(shared_secret)
+    fn unmask_value(&self, shared_secret: &RistrettoPublic, version: AmountDomainVersion) -> u64 {
+        self.masked_value ^ get_value_mask_with_version(shared_secret, version)
     }
 }
 
@@ -97,8 +165,20 @@ impl Amount {
 /// # Arguments
 /// * `shared_secret` - The shared secret, e.g. `rB`.
 pub fn get_value_mask(shared_secret: &RistrettoPublic) -> u64 {
+    get_value_mask_with_version(shared_secret, AmountDomainVersion::default())
+}
+
+/// Computes the value mask using the domain separator of the given version.
+///
+/// # Arguments
+/// * `shared_secret` - The shared secret, e.g. `rB`.
+/// * `version` - The amount domain separator version.
+pub fn get_value_mask_with_version(
+    shared_secret: &RistrettoPublic,
+    version: AmountDomainVersion,
+) -> u64 {
     let mut hasher = Blake2b::new();
-    hasher.update(&AMOUNT_VALUE_DOMAIN_TAG);
+    hasher.update(&version.value_domain_tag());
     hasher.update(&shared_secret.to_bytes());
     let scalar = Scalar::from_hash(hasher);
     let mut temp = [0u8; 8];
@@ -110,9 +190,22 @@ pub fn get_value_mask(shared_secret: &RistrettoPublic) -> u64 {
 ///
 /// # Arguments
 /// * `shared_secret` - The shared secret, e.g. `rB`.
+#[cfg(test)]
 fn get_blinding(shared_secret: &RistrettoPublic) -> Scalar {
+    get_blinding_with_version(shared_secret, AmountDomainVersion::default())
+}
+
+/// Computes the blinding using the domain separator of the given version.
+///
+/// # Arguments
+/// * `shared_secret` - The shared secret, e.g. `rB`.
+/// * `version` - The amount domain separator version.
+fn get_blinding_with_version(
+    shared_secret: &RistrettoPublic,
+    version: AmountDomainVersion,
+) -> Scalar {
     let mut hasher = Blake2b::new();
-    hasher.update(&AMOUNT_BLINDING_DOMAIN_TAG);
+    hasher.update(&version.blinding_domain_tag());
     hasher.update(&shared_secret.to_bytes());
     Scalar::from_hash(hasher)
 }
@@ -120,7 +213,7 @@ fn get_blinding(shared_secret: &RistrettoPublic) -> Scalar {
 #[cfg(test)]
 mod amount_tests {
     use crate::{
-        amount::{get_blinding, Amount, AmountError},
+        amount::{get_blinding, Amount, AmountDomainVersion, AmountError},
         proptest_fixtures::*,
         CompressedCommitment,
     };
@@ -157,7 +250,7 @@ mod amount_tests {
                 let amount = Amount::new(value, &shared_secret).unwrap();
                 assert_eq!(
                     value,
-                    amount.unmask_value(&shared_secret)
+                    amount.unmask_value(&shared_secret, AmountDomainVersion::V0)
                 );
             }
 
@@ -202,5 +295,38 @@ mod amount_tests {
                 let expected = Err(AmountError::InconsistentCommitment);
                 assert_eq!(result, expected);
             }
+
+            #[test]
+            /// An amount should round-trip under the version it was masked with.
+            fn test_get_value_with_version_round_trip(
+                value in any::<u64>(),
+                shared_secret in arbitrary_ristretto_public())
+            {
+                for version in &[AmountDomainVersion::V0, AmountDomainVersion::V1] {
+                    let amount = Amount::new_with_version(value, &shared_secret, *version).unwrap();
+                    let (unmasked_value, _blinding) =
+                        amount.get_value_with_version(&shared_secret, *version).unwrap();
+                    assert_eq!(unmasked_value, value);
+                }
+            }
+
+            #[test]
+            /// An amount masked under one version should not unmask under the other.
+            fn test_get_value_with_mismatched_version(
+                value in any::<u64>(),
+                shared_secret in arbitrary_ristretto_public())
+            {
+                let v0_amount = Amount::new_with_version(value, &shared_secret, AmountDomainVersion::V0).unwrap();
+                assert_eq!(
+                    v0_amount.get_value_with_version(&shared_secret, AmountDomainVersion::V1),
+                    Err(AmountError::InconsistentCommitment)
+                );
+
+                let v1_amount = Amount::new_with_version(value, &shared_secret, AmountDomainVersion::V1).unwrap();
+                assert_eq!(
+                    v1_amount.get_value_with_version(&shared_secret, AmountDomainVersion::V0),
+                    Err(AmountError::InconsistentCommitment)
+                );
+            }
     }
 }
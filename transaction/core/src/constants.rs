@@ -4,6 +4,10 @@
 
 use crate::ring_signature::Scalar;
 
+/// The version of the transaction format built by this release. Each
+/// transaction records its version in `TxPrefix::version`.
+pub const TX_VERSION: u32 = 0;
+
 /// Maximum number of transactions that may be included in a Block.
 pub const MAX_TRANSACTIONS_PER_BLOCK: usize = 5000;
 
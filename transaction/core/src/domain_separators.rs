@@ -20,6 +20,12 @@ pub const AMOUNT_VALUE_DOMAIN_TAG: &str = "mc_amount_value";
 /// Domain separator for Amount's blinding mask hash function.
 pub const AMOUNT_BLINDING_DOMAIN_TAG: &str = "mc_amount_blinding";
 
+/// Domain separator for Amount's value mask hash function, version 1.
+pub const AMOUNT_VALUE_DOMAIN_TAG_V1: &str = "mc_amount_value_v1";
+
+/// Domain separator for Amount's blinding mask hash function, version 1.
+pub const AMOUNT_BLINDING_DOMAIN_TAG_V1: &str = "mc_amount_blinding_v1";
+
 /// Domain separator for Bulletproof transcript.
 pub const BULLETPROOF_DOMAIN_TAG: &str = "mc_bulletproof_transcript";
 
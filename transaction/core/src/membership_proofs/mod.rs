@@ -30,9 +30,14 @@ lazy_static! {
 
 /// Merkle tree hash function for a leaf node.
 pub fn hash_leaf(tx_out: &TxOut) -> [u8; 32] {
+    hash_leaf_from_tx_out_hash(&tx_out.hash())
+}
+
+/// Merkle tree hash function for a leaf node, given the hash of its TxOut.
+pub fn hash_leaf_from_tx_out_hash(tx_out_hash: &[u8; 32]) -> [u8; 32] {
     let mut hasher = Blake2b256::new();
     hasher.update(&TXOUT_MERKLE_LEAF_DOMAIN_TAG);
-    hasher.update(&tx_out.hash());
+    hasher.update(tx_out_hash);
     hasher.result().try_into().unwrap()
 }
 
@@ -46,7 +51,7 @@ pub fn hash_nodes(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
 }
 
 /// Merkle tree Hash function for hashing a "nil" value.
-fn hash_nil() -> [u8; 32] {
+pub fn hash_nil() -> [u8; 32] {
     let mut hasher = Blake2b256::new();
     hasher.update(&TXOUT_MERKLE_NIL_DOMAIN_TAG);
     hasher.result().try_into().unwrap()
@@ -313,4 +318,47 @@ pub fn derive_proof_at_index(
 mod tests {
     // TODO: the tests for derive_proof_at_index are currently in
     // ledger_db/tx_out_store.rs.
+    use super::*;
+
+    #[test]
+    // The root of an empty tree is the nil hash.
+    fn empty_tree_root() {
+        assert_eq!(
+            hash_nil(),
+            [
+                255, 218, 175, 67, 5, 227, 101, 196, 195, 12, 161, 229, 251, 244, 245, 230, 43, 8,
+                20, 65, 238, 148, 235, 45, 9, 128, 71, 11, 94, 112, 89, 104
+            ]
+        );
+        assert_eq!(*NIL_HASH, hash_nil());
+    }
+
+    #[test]
+    // The root of a single-leaf tree is the leaf's hash.
+    fn single_leaf_tree_root() {
+        assert_eq!(
+            hash_leaf_from_tx_out_hash(&[1u8; 32]),
+            [
+                172, 249, 24, 237, 155, 159, 38, 206, 78, 171, 15, 227, 168, 9, 148, 128, 122, 91,
+                159, 96, 152, 198, 216, 96, 195, 34, 63, 190, 146, 96, 7, 244
+            ]
+        );
+    }
+
+    #[test]
+    // The root of a two-leaf tree is the node hash of its leaves.
+    fn two_leaf_tree_root() {
+        let left = hash_leaf_from_tx_out_hash(&[1u8; 32]);
+        let right = hash_leaf_from_tx_out_hash(&[2u8; 32]);
+        let root = hash_nodes(&left, &right);
+        assert_eq!(
+            root,
+            [
+                150, 184, 86, 106, 206, 25, 153, 79, 166, 33, 100, 19, 232, 39, 181, 203, 238, 188,
+                34, 49, 111, 8, 179, 220, 141, 99, 194, 220, 117, 74, 27, 138
+            ]
+        );
+
+        assert_ne!(root, hash_leaf_from_tx_out_hash(&root));
+    }
 }
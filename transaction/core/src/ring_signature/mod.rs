@@ -40,8 +40,56 @@ lazy_static! {
 
 /// Applies a hash function and returns a RistrettoPoint.
 pub fn hash_to_point(ristretto_public: &RistrettoPublic) -> RistrettoPoint {
+    hash_bytes_to_point(&ristretto_public.to_bytes())
+}
+
+/// Hashes arbitrary bytes to a RistrettoPoint, domain-separated by
+/// `HASH_TO_POINT_DOMAIN_TAG`.
+///
+/// This is the canonical hash-to-point used by key images, MLSAG, and the
+/// Pedersen generators. Its output is consensus-critical and must not change.
+pub fn hash_bytes_to_point(data: &[u8]) -> RistrettoPoint {
     let mut hasher = Blake2b::new();
     hasher.update(&HASH_TO_POINT_DOMAIN_TAG);
-    hasher.update(&ristretto_public.to_bytes());
+    hasher.update(data);
     RistrettoPoint::from_hash(hasher)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
+
+    #[test]
+    // hash_bytes_to_point should match known-answer vectors.
+    fn hash_bytes_to_point_known_answers() {
+        let basepoint_hash = hash_bytes_to_point(RISTRETTO_BASEPOINT_COMPRESSED.as_bytes());
+        assert_eq!(
+            basepoint_hash.compress().to_bytes(),
+            [
+                128, 96, 36, 216, 244, 31, 37, 226, 128, 160, 202, 69, 250, 47, 162, 140, 5, 41,
+                247, 176, 203, 175, 24, 45, 193, 149, 250, 35, 204, 88, 200, 43
+            ]
+        );
+
+        let empty_hash = hash_bytes_to_point(&[]);
+        assert_eq!(
+            empty_hash.compress().to_bytes(),
+            [
+                60, 193, 80, 94, 55, 26, 215, 223, 158, 151, 23, 74, 117, 18, 88, 48, 195, 121,
+                137, 228, 141, 234, 225, 136, 49, 65, 175, 18, 164, 128, 157, 58
+            ]
+        );
+    }
+
+    #[test]
+    // hash_to_point should agree with hash_bytes_to_point on the key's bytes.
+    fn hash_to_point_matches_bytes() {
+        let basepoint = RistrettoPublic::from(RISTRETTO_BASEPOINT_POINT);
+        assert_eq!(
+            hash_to_point(&basepoint),
+            hash_bytes_to_point(RISTRETTO_BASEPOINT_COMPRESSED.as_bytes())
+        );
+        assert_eq!(hash_to_point(&basepoint), GENERATORS.B);
+    }
+}
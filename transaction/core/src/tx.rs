@@ -17,6 +17,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     amount::{Amount, AmountError},
+    constants::TX_VERSION,
     domain_separators::TXOUT_CONFIRMATION_NUMBER_DOMAIN_TAG,
     encrypted_fog_hint::EncryptedFogHint,
     get_tx_out_shared_secret,
@@ -162,6 +163,13 @@ pub struct TxPrefix {
     /// The block index at which this transaction is no longer valid.
     #[prost(uint64, tag = "4")]
     pub tombstone_block: u64,
+
+    /// The version of the transaction format, if later than version 0.
+    ///
+    /// Version 0 predates this field and is stored as `None`, so that the
+    /// hashes of version 0 transactions are unchanged.
+    #[prost(uint32, optional, tag = "5")]
+    pub version: Option<u32>,
 }
 
 impl TxPrefix {
@@ -173,13 +181,29 @@ impl TxPrefix {
     /// * `fee` - Transaction fee.
     /// * `tombstone_block` - The block index at which this transaction is no
     ///   longer valid.
+    ///
+    /// The prefix is of version `TX_VERSION`.
     pub fn new(inputs: Vec<TxIn>, outputs: Vec<TxOut>, fee: u64, tombstone_block: u64) -> TxPrefix {
-        TxPrefix {
+        let mut prefix = TxPrefix {
             inputs,
             outputs,
             fee,
             tombstone_block,
-        }
+            version: None,
+        };
+        prefix.set_version(TX_VERSION);
+        prefix
+    }
+
+    /// The version of the transaction format.
+    pub fn version(&self) -> u32 {
+        self.version.unwrap_or(0)
+    }
+
+    /// Set the version of the transaction format. The version is covered by
+    /// the prefix hash.
+    pub fn set_version(&mut self, version: u32) {
+        self.version = if version == 0 { None } else { Some(version) };
     }
 
     /// Blake2b256 hash of `self`.
@@ -521,6 +545,7 @@ mod tests {
             outputs: vec![tx_out],
             fee: MINIMUM_FEE,
             tombstone_block: 23,
+            version: None,
         };
 
         let mut buf = Vec::new();
@@ -540,4 +565,30 @@ mod tests {
         let recovered_tx: Tx = Tx::decode(&buf[..]).unwrap();
         assert_eq!(tx, recovered_tx);
     }
+    #[test]
+    // The version should be covered by the prefix hash, and version 0 should be
+    // omitted so that the hashes of unversioned transactions are unchanged.
+    fn test_version_is_covered_by_hash() {
+        let mut prefix = TxPrefix::new(vec![], vec![], MINIMUM_FEE, 23);
+        let unversioned = TxPrefix {
+            version: None,
+            ..prefix.clone()
+        };
+
+        prefix.set_version(0);
+        assert_eq!(prefix.version, None);
+        assert_eq!(prefix.version(), 0);
+        assert_eq!(prefix.hash(), unversioned.hash());
+
+        prefix.set_version(1);
+        assert_eq!(prefix.version(), 1);
+        assert_ne!(prefix.hash(), unversioned.hash());
+
+        // The version survives encoding.
+        let mut buf = Vec::new();
+        prefix
+            .encode(&mut buf)
+            .expect("failed to serialize into slice");
+        assert_eq!(TxPrefix::decode(&buf[..]).unwrap().version(), 1);
+    }
 }
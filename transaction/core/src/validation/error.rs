@@ -122,6 +122,18 @@ pub enum TransactionValidationError {
 
     /// Public keys must be valid Ristretto points.
     KeyError,
+
+    /**
+     * Transaction version {found} is not supported, expected a version in
+     * {supported:?}.
+     */
+    UnsupportedTransactionVersion { found: u32, supported: (u32, u32) },
+
+    /**
+     * Membership proof requested for TxOut index {requested}, but the
+     * ledger contains {ledger_size} TxOuts.
+     */
+    MembershipProofIndexOutOfBounds { requested: u64, ledger_size: u64 },
 }
 
 impl From<mc_crypto_keys::KeyError> for TransactionValidationError {
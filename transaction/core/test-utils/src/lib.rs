@@ -11,7 +11,7 @@ pub use mc_transaction_core::{
     get_tx_out_shared_secret,
     onetime_keys::recover_onetime_private_key,
     ring_signature::KeyImage,
-    tx::{Tx, TxOut, TxOutMembershipElement, TxOutMembershipHash},
+    tx::{Tx, TxOut, TxOutMembershipElement, TxOutMembershipHash, TxOutMembershipProof},
     Block, BlockID, BlockIndex, BLOCK_VERSION,
 };
 use mc_transaction_core::{constants::RING_SIZE, membership_proofs::Range, BlockContents};
@@ -141,6 +141,29 @@ pub fn create_transaction_with_amount<L: Ledger, R: RngCore + CryptoRng>(
     transaction_builder.build(rng).unwrap()
 }
 
+/// Get valid membership proofs for each of the given TxOuts.
+///
+/// Every TxOut must already be in the ledger. A `MockLedger` does not track
+/// TxOut indices, so use a real ledger, e.g. one from `create_ledger()`.
+///
+/// # Arguments
+/// * `ledger` - A ledger containing every TxOut in `tx_outs`.
+/// * `tx_outs` - The TxOuts to prove membership of.
+pub fn valid_membership_proofs_for<L: Ledger>(
+    ledger: &L,
+    tx_outs: &[TxOut],
+) -> Vec<TxOutMembershipProof> {
+    let indexes = tx_outs
+        .iter()
+        .map(|tx_out| {
+            ledger
+                .get_tx_out_index_by_hash(&tx_out.hash())
+                .expect("TxOut is not in the ledger")
+        })
+        .collect::<Vec<u64>>();
+    ledger.get_tx_out_proof_of_memberships(&indexes).unwrap()
+}
+
 /// Populates the LedgerDB with initial data.
 ///
 /// Creates a number of blocks, each of which contains a single transaction.
@@ -242,6 +242,21 @@ mod consensus_client_uri_tests {
         assert!(ClientUri::from_str(&uri.addr()).is_err());
     }
 
+    #[test]
+    fn test_supported_schemes() {
+        let uri = ClientUri::from_str("insecure-mc://localhost:3223/").unwrap();
+        assert_eq!(uri.supported_schemes(), &["mc", "insecure-mc"]);
+        assert!(uri.has_supported_scheme());
+
+        // The parser accepts schemes which only start with a supported one.
+        let uri = ClientUri::from_str("mcp://localhost:3223/").unwrap();
+        assert!(!uri.has_supported_scheme());
+
+        let uri = PeerUri::from_str("mcp://localhost:8443/").unwrap();
+        assert_eq!(uri.supported_schemes(), &["mcp", "insecure-mcp"]);
+        assert!(uri.has_supported_scheme());
+    }
+
     #[test]
     fn test_invalid_client_uris() {
         assert!(ClientUri::from_str("http://127.0.0.1/").is_err());
@@ -79,6 +79,18 @@ pub trait ConnectionUri:
     /// Whether TLS should be used for this connection.
     fn use_tls(&self) -> bool;
 
+    /// The schemes this kind of URI may be written with, or none if any
+    /// scheme is accepted.
+    fn supported_schemes(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Whether this URI was written with one of `supported_schemes` exactly.
+    fn has_supported_scheme(&self) -> bool {
+        let supported_schemes = self.supported_schemes();
+        supported_schemes.is_empty() || supported_schemes.contains(&self.url().scheme())
+    }
+
     /// Retrieve the username part of the URI, or an empty string if one is not
     /// available.
     fn username(&self) -> String;
@@ -197,6 +209,9 @@ pub trait UriScheme:
     const DEFAULT_SECURE_PORT: u16;
     const DEFAULT_INSECURE_PORT: u16;
 
+    /// Both schemes, the secure one first.
+    const SCHEMES: &'static [&'static str] = &[Self::SCHEME_SECURE, Self::SCHEME_INSECURE];
+
     /// When true, ensure the path components of a URI ends with a slash.
     /// This is genenerally the desired behavior for our URIs since we currently
     /// do not use any of them to point at a specific file. Having a
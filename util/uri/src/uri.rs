@@ -67,6 +67,10 @@ impl<Scheme: UriScheme> ConnectionUri for Uri<Scheme> {
         self.use_tls
     }
 
+    fn supported_schemes(&self) -> &'static [&'static str] {
+        Scheme::SCHEMES
+    }
+
     fn username(&self) -> String {
         self.username.clone()
     }